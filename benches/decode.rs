@@ -0,0 +1,83 @@
+use std::hint::black_box;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use protoshark::{decode, ProtobufBytes, VarInt};
+
+/// Builds a message resembling a typical game packet.
+fn small_message() -> Vec<u8> {
+    let mut position = vec![];
+    position.write_f32(1, 12.5);
+    position.write_f32(2, -3.25);
+    position.write_f32(3, 100.0);
+
+    let mut bytes = vec![];
+    bytes.write_u32(1, 150);
+    bytes.write_str(2, "PlayerEnterSceneNotify");
+    bytes.write_bytes(3, &position);
+    bytes.write_u64(4, 1_700_000_000_000);
+    bytes.write_u32(5, 1);
+
+    bytes
+}
+
+/// Builds a message of several megabytes, made of many repeated small messages.
+fn large_message() -> Vec<u8> {
+    let entry = small_message();
+
+    let mut bytes = vec![];
+    while bytes.len() < 4 * 1024 * 1024 {
+        bytes.write_bytes(1, &entry);
+        bytes.write_u32(2, bytes.len() as u32);
+    }
+
+    bytes
+}
+
+fn decoding(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for (name, bytes) in [("small", small_message()), ("large", large_message())] {
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &bytes, |b, bytes| {
+            b.iter(|| decode(black_box(bytes)).unwrap());
+        });
+    }
+    group.finish();
+}
+
+fn encoding(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for (name, bytes) in [("small", small_message()), ("large", large_message())] {
+        let message = decode(&bytes).unwrap();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &message, |b, message| {
+            b.iter(|| black_box(message).encode());
+        });
+    }
+    group.finish();
+}
+
+fn varints(c: &mut Criterion) {
+    let mut bytes = vec![];
+    for value in [1u64, 300, 70_000, 1 << 40, u64::MAX] {
+        bytes.write_u64(1, value);
+    }
+
+    c.bench_function("varint/read_at", |b| {
+        b.iter(|| {
+            let mut index = 0;
+            while let Some((_, len)) = VarInt::read_at(black_box(&bytes), index) {
+                index += len;
+            }
+        });
+    });
+    c.bench_function("varint/decode_at", |b| {
+        b.iter(|| {
+            let mut index = 0;
+            while index < bytes.len() {
+                index += VarInt::decode_at(black_box(&bytes), index).1;
+            }
+        });
+    });
+}
+
+criterion_group!(benches, decoding, encoding, varints);
+criterion_main!(benches);
@@ -0,0 +1,110 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, LitInt};
+
+/// The largest valid field number, as `protoshark::MAX_FIELD_NUMBER`.
+const MAX_FIELD_NUMBER: u32 = (1 << 29) - 1;
+
+/// Derives `FromSerializedMessage` and `FromValue` for a struct with named fields.
+///
+/// Every field is annotated with its field number from 1 to 2^29 - 1, as in `#[field(1)]`,
+/// and its type implements `FromField`.
+#[proc_macro_derive(FromSerializedMessage, attributes(field))]
+pub fn derive_from_serialized_message(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_serialized_message(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn from_serialized_message(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = numbered_fields(&input)?;
+    let initializers = fields.iter().map(|(ident, number)| quote! {
+        #ident: ::protoshark::FromField::from_field(#number, ::protoshark::FieldAccess::get_ref(message, #number))?
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::protoshark::FromSerializedMessage for #name #type_generics #where_clause {
+            fn from_message(message: &::protoshark::SerializedMessage) -> ::protoshark::Result<Self> {
+                Ok(Self { #(#initializers),* })
+            }
+        }
+
+        impl #impl_generics ::protoshark::FromValue for #name #type_generics #where_clause {
+            fn from_value(value: &::protoshark::Value) -> ::protoshark::Result<Self> {
+                match value {
+                    ::protoshark::Value::Message(message) => ::protoshark::FromSerializedMessage::from_message(message),
+                    ::protoshark::Value::Empty => ::protoshark::FromSerializedMessage::from_message(&::protoshark::SerializedMessage::new()),
+                    value => Err(::protoshark::ConversionError::new(stringify!(#name), value).into())
+                }
+            }
+        }
+    })
+}
+
+/// Derives `ToSerializedMessage` and `ToValue` for a struct with named fields.
+///
+/// Every field is annotated with its field number from 1 to 2^29 - 1, as in `#[field(1)]`,
+/// and its type implements `ToField`.
+#[proc_macro_derive(ToSerializedMessage, attributes(field))]
+pub fn derive_to_serialized_message(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    to_serialized_message(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn to_serialized_message(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = numbered_fields(&input)?;
+    let writes = fields.iter().map(|(ident, number)| quote! {
+        ::protoshark::ToField::to_field(&self.#ident, #number, &mut message);
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::protoshark::ToSerializedMessage for #name #type_generics #where_clause {
+            fn to_message(&self) -> ::protoshark::SerializedMessage {
+                let mut message = ::protoshark::SerializedMessage::new();
+                #(#writes)*
+                message
+            }
+        }
+
+        impl #impl_generics ::protoshark::ToValue for #name #type_generics #where_clause {
+            fn to_value(&self) -> ::protoshark::Value {
+                ::protoshark::Value::Message(::protoshark::ToSerializedMessage::to_message(self))
+            }
+        }
+    })
+}
+
+/// Returns the identifier and field number of every field of a struct.
+fn numbered_fields(input: &DeriveInput) -> syn::Result<Vec<(syn::Ident, u32)>> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(input, "Only structs can be derived."));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(Error::new_spanned(&data.fields, "Only structs with named fields can be derived."));
+    };
+
+    fields.named.iter()
+        .map(|field| {
+            let ident = field.ident.clone().expect("Named fields have identifiers.");
+            let attribute = field.attrs.iter()
+                .find(|attribute| attribute.path().is_ident("field"))
+                .ok_or_else(|| Error::new_spanned(field, "Missing the field number, as in `#[field(1)]`."))?;
+
+            let number: LitInt = attribute.parse_args()?;
+            match number.base10_parse()? {
+                field @ 1..=MAX_FIELD_NUMBER => Ok((ident, field)),
+                _ => Err(Error::new_spanned(number, format!("Field numbers must be between 1 and {MAX_FIELD_NUMBER}.")))
+            }
+        })
+        .collect()
+}
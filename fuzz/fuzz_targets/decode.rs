@@ -0,0 +1,21 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use protoshark::{classify, DecodeConfig, Decoder};
+
+// Decoding must never panic, only fail with an `Err`.
+fuzz_target!(|bytes: &[u8]| {
+    if let Ok(message) = protoshark::decode(bytes) {
+        // Anything decoded must encode and decode again.
+        protoshark::decode(&message.encode()).expect("Re-encoded message failed to decode.");
+    }
+
+    let strict = Decoder::new(DecodeConfig {
+        strict: true,
+        max_fields: Some(16),
+        fold_maps: true,
+        ..Default::default()
+    });
+    let _ = strict.decode_value(bytes);
+    let _ = classify::validate(bytes);
+});
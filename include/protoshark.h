@@ -0,0 +1,48 @@
+#ifndef PROTOSHARK_H
+#define PROTOSHARK_H
+
+/* The C ABI of protoshark, available with the `ffi` feature. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Returned when a function succeeds. */
+#define PROTOSHARK_OK 0
+
+/* Returned when a function fails. */
+#define PROTOSHARK_ERROR -1
+
+/*
+ * Decodes a protobuf message into JSON.
+ *
+ * On success, `out` holds the JSON; on failure, the error message.
+ * Either must be released with `protoshark_free_string`.
+ */
+int protoshark_decode_json(const uint8_t *data, size_t len, char **out);
+
+/* Decodes a protobuf message into indented text, like `protoshark_decode_json`. */
+int protoshark_decode_text(const uint8_t *data, size_t len, char **out);
+
+/*
+ * Dissects a protobuf message into JSON with a stable, versioned schema.
+ *
+ * `options` is a JSON object of options, or NULL for the defaults.
+ * `out` is set like `protoshark_decode_json`.
+ */
+int protoshark_dissect_json(const uint8_t *data, size_t len, const char *options, char **out);
+
+/* Frees a string returned by this library. Null is ignored. */
+void protoshark_free_string(char *string);
+
+/* Returns the version of the library. The string must not be freed. */
+const char *protoshark_version(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* PROTOSHARK_H */
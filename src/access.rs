@@ -0,0 +1,94 @@
+use std::slice;
+use crate::{SerializedMessage, Value};
+
+/// Typed accessors for the fields of a message.
+///
+/// Fields which are ambiguous after decoding (a string which also
+/// decodes as a message) are matched by both `get_str` and `get_message`.
+pub trait FieldAccess {
+    /// Gets the value at the given field without cloning it.
+    fn get_ref(&self, field: u32) -> Option<&Value>;
+
+    /// Gets a string field.
+    fn get_str(&self, field: u32) -> Option<&str> {
+        match self.get_ref(field)? {
+            Value::String(value) => Some(value),
+            Value::Repeated(values) => match values.as_slice() {
+                [Value::String(value), Value::Message(_)] => Some(value),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    /// Gets a varint field which fits in a `u32`.
+    fn get_u32(&self, field: u32) -> Option<u32> {
+        match self.get_ref(field)? {
+            Value::VarInt(value) => value.as_u32(),
+            _ => None
+        }
+    }
+
+    /// Gets a nested message field.
+    fn get_message(&self, field: u32) -> Option<&SerializedMessage> {
+        match self.get_ref(field)? {
+            Value::Message(message) => Some(message),
+            Value::Repeated(values) => match values.as_slice() {
+                [Value::String(_), Value::Message(message)] => Some(message),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    /// Gets every value of a field; a field which occurs once has one value.
+    fn get_repeated(&self, field: u32) -> Option<&[Value]> {
+        match self.get_ref(field)? {
+            Value::Repeated(values) => Some(values),
+            value => Some(slice::from_ref(value))
+        }
+    }
+
+    /// Gets a bytes field.
+    fn get_bytes(&self, field: u32) -> Option<&[u8]> {
+        match self.get_ref(field)? {
+            Value::Bytes(value) => Some(value),
+            _ => None
+        }
+    }
+}
+
+impl FieldAccess for SerializedMessage {
+    fn get_ref(&self, field: u32) -> Option<&Value> {
+        self.backing.get(&field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn typed_access() {
+        let mut inner = vec![];
+        inner.write_u32(1, 150);
+
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+        bytes.write_str(2, "Hello, World!");
+        bytes.write_bytes(3, &inner);
+        bytes.write_bytes(4, &[0xff, 0xfe]);
+        bytes.write_u32(5, 1);
+        bytes.write_u32(5, 2);
+
+        let message = decode(&bytes).unwrap();
+        assert_eq!(message.get_u32(1), Some(150));
+        assert_eq!(message.get_str(2), Some("Hello, World!"));
+        assert_eq!(message.get_message(3).and_then(|inner| inner.get_u32(1)), Some(150));
+        assert_eq!(message.get_bytes(4), Some(&[0xff, 0xfe][..]));
+        assert_eq!(message.get_repeated(5).map(<[Value]>::len), Some(2));
+        assert_eq!(message.get_repeated(1).map(<[Value]>::len), Some(1));
+        assert_eq!(message.get_str(1), None);
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use serde::{Serialize, Serializer};
+use serde::ser::SerializeStruct;
+use crate::wellknown::EpochUnit;
+use crate::{FieldPath, SerializedMessage, Value};
+
+/// The number of distinct values tracked per field, bounding memory use.
+pub const MAX_DISTINCT: usize = 10_000;
+
+/// The most distinct values a field guessed to be an enum may have.
+const MAX_ENUM_VALUES: usize = 16;
+
+/// The largest value a field guessed to be an enum may have.
+const MAX_ENUM_VALUE: i64 = 255;
+
+/// A guess at the type of a varint field, from the values seen across a corpus.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Guess {
+    /// Every value is 0 or 1.
+    Bool,
+    /// The values are few, small, and repeat.
+    Enum,
+    /// Every value is a Unix timestamp from 2000 until 2100, in the same unit.
+    Timestamp
+}
+
+/// Guesses are formatted with a question mark, as `bool?`.
+impl fmt::Display for Guess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Guess::Bool => "bool?",
+            Guess::Enum => "enum?",
+            Guess::Timestamp => "timestamp?"
+        })
+    }
+}
+
+impl Serialize for Guess {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Statistics of a single field across a corpus of messages.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FieldStats {
+    /// The number of root messages in which the field occurs.
+    pub present: usize,
+    /// The total number of values of the field, counting repeated values.
+    pub occurrences: usize,
+    /// The number of values of each kind; see `Value::kind`.
+    pub types: BTreeMap<&'static str, usize>,
+    /// The shortest string, bytes, or message payload, in bytes.
+    pub min_len: Option<usize>,
+    /// The longest string, bytes, or message payload, in bytes.
+    pub max_len: Option<usize>,
+    /// The smallest varint value, as a signed 64-bit integer.
+    pub min_value: Option<i64>,
+    /// The largest varint value, as a signed 64-bit integer.
+    pub max_value: Option<i64>,
+    distinct: HashSet<Value>,
+    saturated: bool
+}
+
+impl FieldStats {
+    /// Returns the number of distinct values of the field.
+    ///
+    /// Counting stops at `MAX_DISTINCT`; see `is_saturated`.
+    pub fn cardinality(&self) -> usize {
+        self.distinct.len()
+    }
+
+    /// Returns true if the field has more distinct values than are tracked.
+    pub fn is_saturated(&self) -> bool {
+        self.saturated
+    }
+
+    /// Returns the most common kind of value of the field.
+    pub fn dominant_type(&self) -> Option<&'static str> {
+        self.types.iter()
+            .max_by_key(|(_, count)| **count)
+            .map(|(kind, _)| *kind)
+    }
+
+    /// Guesses the type of the field, if every value is a varint.
+    ///
+    /// A guess is only a hint: a counter which has only reached 1 looks like
+    /// a bool, so guesses are more reliable over larger corpora.
+    pub fn guess(&self) -> Option<Guess> {
+        if self.types.len() != 1 || !self.types.contains_key("varint") {
+            return None;
+        }
+
+        let (min, max) = (self.min_value?, self.max_value?);
+        if min >= 0 && max <= 1 {
+            Some(Guess::Bool)
+        } else if EpochUnit::detect(min).is_some() && EpochUnit::detect(min) == EpochUnit::detect(max) {
+            Some(Guess::Timestamp)
+        } else if min >= 0 && max <= MAX_ENUM_VALUE && !self.saturated
+            && (2..=MAX_ENUM_VALUES).contains(&self.cardinality())
+            && self.occurrences >= 2 * self.cardinality() {
+            Some(Guess::Enum)
+        } else {
+            None
+        }
+    }
+
+    /// Records a single value.
+    fn record(&mut self, kind: &'static str, value: &Value, len: Option<usize>) {
+        self.occurrences += 1;
+        *self.types.entry(kind).or_default() += 1;
+
+        if let Some(len) = len {
+            self.min_len = Some(self.min_len.map_or(len, |min| min.min(len)));
+            self.max_len = Some(self.max_len.map_or(len, |max| max.max(len)));
+        }
+
+        if let Value::VarInt(varint) = value {
+            let varint = varint.as_i64();
+            self.min_value = Some(self.min_value.map_or(varint, |min| min.min(varint)));
+            self.max_value = Some(self.max_value.map_or(varint, |max| max.max(varint)));
+        }
+
+        if self.distinct.len() < MAX_DISTINCT {
+            self.distinct.insert(value.clone());
+        } else if !self.distinct.contains(value) {
+            self.saturated = true;
+        }
+    }
+}
+
+/// Per-field statistics over a corpus of decoded messages.
+///
+/// Useful for telling fields apart when reversing a protocol: a session id is
+/// always present with one value per session, while a counter is always present
+/// with a value per message, and an optional flag has low presence and cardinality.
+#[derive(Clone, Debug, Default)]
+pub struct Analysis {
+    messages: usize,
+    fields: BTreeMap<FieldPath, FieldStats>
+}
+
+impl Analysis {
+    /// Creates a new, empty analysis.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a message to the corpus.
+    pub fn add(&mut self, message: &SerializedMessage) {
+        self.messages += 1;
+
+        let mut seen = BTreeSet::new();
+        self.add_message(message, &mut vec![], &mut seen);
+        for path in seen {
+            if let Some(stats) = self.fields.get_mut(&path) {
+                stats.present += 1;
+            }
+        }
+    }
+
+    /// Returns the number of messages in the corpus.
+    pub fn messages(&self) -> usize {
+        self.messages
+    }
+
+    /// Returns the statistics of every field, keyed by path.
+    pub fn fields(&self) -> &BTreeMap<FieldPath, FieldStats> {
+        &self.fields
+    }
+
+    /// Returns the statistics of the field at the given path.
+    pub fn get(&self, path: &[u32]) -> Option<&FieldStats> {
+        self.fields.get(path)
+    }
+
+    /// Returns the fraction of messages in which the field occurs, from `0.0` to `1.0`.
+    pub fn presence_rate(&self, path: &[u32]) -> f64 {
+        match (self.get(path), self.messages) {
+            (Some(stats), messages) if messages > 0 => stats.present as f64 / messages as f64,
+            _ => 0.0
+        }
+    }
+
+    /// Returns the guessed type of every varint field which has one.
+    pub fn guesses(&self) -> BTreeMap<FieldPath, Guess> {
+        self.fields.iter()
+            .filter_map(|(path, stats)| Some((path.clone(), stats.guess()?)))
+            .collect()
+    }
+
+    /// Wraps a message to serialize it with the guessed types of its fields.
+    pub fn annotate<'a>(&'a self, message: &'a SerializedMessage) -> Annotated<'a> {
+        Annotated { message, analysis: self }
+    }
+
+    /// Records the fields of a message.
+    fn add_message(&mut self, message: &SerializedMessage, path: &mut FieldPath, seen: &mut BTreeSet<FieldPath>) {
+        for (field, value) in message {
+            path.push(*field);
+            seen.insert(path.clone());
+            self.add_value(value, path, seen);
+            path.pop();
+        }
+    }
+
+    /// Records a value, and the fields of any messages nested in it.
+    fn add_value(&mut self, value: &Value, path: &mut FieldPath, seen: &mut BTreeSet<FieldPath>) {
+        let stats = self.fields.entry(path.clone()).or_default();
+        match value {
+            Value::Repeated(values) => match values.as_slice() {
+                // A single payload which is both a string and a message.
+                [Value::String(string), Value::Message(message)] => {
+                    stats.record("message", value, Some(string.len()));
+                    self.add_message(message, path, seen);
+                }
+                values => {
+                    for value in values {
+                        self.add_value(value, path, seen);
+                    }
+                }
+            },
+            Value::Message(message) => {
+                stats.record(value.kind(), value, Some(message.encode().len()));
+                self.add_message(message, path, seen);
+            }
+            Value::String(string) => stats.record(value.kind(), value, Some(string.len())),
+            Value::Bytes(bytes) => stats.record(value.kind(), value, Some(bytes.len())),
+            Value::Empty => stats.record(value.kind(), value, Some(0)),
+            value => stats.record(value.kind(), value, None)
+        }
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "analyzed {} messages", self.messages)?;
+
+        for (path, stats) in &self.fields {
+            let presence = self.presence_rate(path) * 100.0;
+            let path: Vec<String> = path.iter().map(u32::to_string).collect();
+            let lengths = match (stats.min_len, stats.max_len) {
+                (Some(min), Some(max)) => format!("{min}..={max} bytes"),
+                _ => String::new()
+            };
+            let cardinality = format!("{}{}", stats.cardinality(), if stats.saturated { "+" } else { "" });
+            let guess = stats.guess().map(|guess| guess.to_string()).unwrap_or_default();
+
+            write!(f, "\n  {:<16} {:>5.1}% present {:>8} values {:>8} distinct  {:<10} {:<11} {lengths}",
+                path.join("."), presence, stats.occurrences,
+                cardinality, stats.dominant_type().unwrap_or_default(), guess)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A message whose varint fields are annotated with their guessed types.
+///
+/// Serializes like `SerializedMessage`, except that a varint with a guess
+/// is written as `{"value": 1, "guess": "bool?"}`.
+#[derive(Copy, Clone, Debug)]
+pub struct Annotated<'a> {
+    message: &'a SerializedMessage,
+    analysis: &'a Analysis
+}
+
+impl Serialize for Annotated<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        AnnotatedMessage { message: self.message, analysis: self.analysis, path: vec![] }.serialize(serializer)
+    }
+}
+
+struct AnnotatedMessage<'a> {
+    message: &'a SerializedMessage,
+    analysis: &'a Analysis,
+    path: FieldPath
+}
+
+impl Serialize for AnnotatedMessage<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.message.iter().map(|(field, value)| {
+            let mut path = self.path.clone();
+            path.push(*field);
+            (field.to_string(), AnnotatedValue { value, analysis: self.analysis, path })
+        }))
+    }
+}
+
+struct AnnotatedValue<'a> {
+    value: &'a Value,
+    analysis: &'a Analysis,
+    path: FieldPath
+}
+
+impl AnnotatedValue<'_> {
+    fn child<'a>(&'a self, value: &'a Value) -> AnnotatedValue<'a> {
+        AnnotatedValue { value, analysis: self.analysis, path: self.path.clone() }
+    }
+}
+
+impl Serialize for AnnotatedValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.value {
+            Value::VarInt(varint) => match self.analysis.get(&self.path).and_then(FieldStats::guess) {
+                Some(guess) => {
+                    let mut state = serializer.serialize_struct("AnnotatedValue", 2)?;
+                    state.serialize_field("value", varint)?;
+                    state.serialize_field("guess", &guess)?;
+                    state.end()
+                }
+                None => varint.serialize(serializer)
+            },
+            Value::Message(message) => {
+                AnnotatedMessage { message, analysis: self.analysis, path: self.path.clone() }.serialize(serializer)
+            }
+            Value::Repeated(values) => serializer.collect_seq(values.iter().map(|value| self.child(value))),
+            Value::Map(entries) => serializer.collect_map(entries.iter().map(|(key, value)| (key, self.child(value)))),
+            value => value.serialize(serializer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyze() {
+        let mut analysis = Analysis::new();
+        for counter in 0..10 {
+            let mut nested = SerializedMessage::new();
+            nested.insert(1, Value::String("abc".repeat(counter % 3 + 1)));
+
+            let mut message = SerializedMessage::new();
+            message.insert(1, Value::VarInt(42.into()));
+            message.insert(2, Value::VarInt((counter as i64).into()));
+            if counter % 2 == 0 {
+                message.insert(3, Value::Message(nested));
+            }
+            analysis.add(&message);
+        }
+
+        let session = analysis.get(&[1]).unwrap();
+        assert_eq!((session.present, session.cardinality()), (10, 1));
+        assert_eq!(analysis.get(&[2]).unwrap().cardinality(), 10);
+        assert_eq!(analysis.presence_rate(&[3]), 0.5);
+
+        let name = analysis.get(&[3, 1]).unwrap();
+        assert_eq!((name.min_len, name.max_len), (Some(3), Some(9)));
+        assert_eq!(name.dominant_type(), Some("string"));
+        assert!(analysis.to_string().contains("3.1"));
+    }
+
+    #[test]
+    fn guesses() {
+        let mut analysis = Analysis::new();
+        let mut last = SerializedMessage::new();
+        for counter in 0..10i64 {
+            last = SerializedMessage::new();
+            last.insert(1, Value::VarInt((counter % 2).into()));
+            last.insert(2, Value::VarInt((counter % 3 + 1).into()));
+            last.insert(3, Value::VarInt((1_700_000_000_000 + counter * 1000).into()));
+            last.insert(4, Value::VarInt(counter.into()));
+            analysis.add(&last);
+        }
+
+        assert_eq!(analysis.guesses(), BTreeMap::from([
+            (vec![1], Guess::Bool),
+            (vec![2], Guess::Enum),
+            (vec![3], Guess::Timestamp)
+        ]));
+        assert_eq!(
+            serde_json::to_string(&analysis.annotate(&last)).unwrap(),
+            r#"{"1":{"value":1,"guess":"bool?"},"2":{"value":1,"guess":"enum?"},"3":{"value":[-807040216,1700000009000],"guess":"timestamp?"},"4":9}"#
+        );
+    }
+}
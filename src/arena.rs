@@ -0,0 +1,203 @@
+use bumpalo::Bump;
+use bumpalo::collections::Vec as BumpVec;
+use crate::decoder::field_number;
+use crate::{DecodeConfig, Decoder, EmptyPayload, Result, SerializedMessage, Value, VarInt};
+
+/// A message decoded into an arena, borrowing everything from it.
+///
+/// Fields are kept in wire order, with one entry per occurrence.
+/// A payload which is both valid UTF-8 and a valid message appears
+/// twice: first as a string, then as a message.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ArenaMessage<'a> {
+    fields: &'a [(u32, ArenaValue<'a>)]
+}
+
+/// A value of a message decoded into an arena.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArenaValue<'a> {
+    VarInt(VarInt),
+    Float(f32),
+    Double(f64),
+    String(&'a str),
+    Bytes(&'a [u8]),
+    Message(ArenaMessage<'a>),
+    /// An empty payload; see `DecodeConfig::empty`.
+    Empty
+}
+
+impl<'a> ArenaMessage<'a> {
+    /// Returns the fields of the message, in wire order.
+    pub fn fields(&self) -> &'a [(u32, ArenaValue<'a>)] {
+        self.fields
+    }
+
+    /// Returns the first value of a field.
+    pub fn get(&self, field: u32) -> Option<&'a ArenaValue<'a>> {
+        self.get_all(field).next()
+    }
+
+    /// Returns every value of a field, in wire order.
+    pub fn get_all(&self, field: u32) -> impl Iterator<Item = &'a ArenaValue<'a>> {
+        self.fields.iter()
+            .filter(move |(number, _)| *number == field)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the number of field entries.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns true if the message has no fields.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Copies the message out of the arena.
+    pub fn to_message(&self) -> SerializedMessage {
+        let mut message = SerializedMessage::new();
+        for (field, value) in self.fields {
+            message.insert(*field, match value {
+                ArenaValue::VarInt(value) => Value::VarInt(value.clone()),
+                ArenaValue::Float(value) => Value::Float(*value),
+                ArenaValue::Double(value) => Value::Double(*value),
+                ArenaValue::String(value) => Value::String(value.to_string()),
+                ArenaValue::Bytes(value) => Value::Bytes(value.to_vec()),
+                ArenaValue::Message(value) => Value::Message(value.to_message()),
+                ArenaValue::Empty => Value::Empty
+            });
+        }
+
+        message
+    }
+}
+
+impl Decoder {
+    /// Decodes a protobuf-encoded message into an arena.
+    ///
+    /// All strings, bytes, and nested messages are allocated in the arena,
+    /// and freed at once when it is reset or dropped.
+    /// Honors `max_depth`, `max_field_bytes`, `strict`, and `tolerate_field_numbers`; the other options
+    /// only apply to `decode`.
+    pub fn decode_in<'a>(&self, bytes: &[u8], arena: &'a Bump) -> Result<ArenaMessage<'a>> {
+        decode_message(bytes, arena, self.config(), 0)
+    }
+}
+
+/// Decodes a protobuf-encoded message into an arena, using the default limits.
+pub fn decode_in<'a>(bytes: &[u8], arena: &'a Bump) -> Result<ArenaMessage<'a>> {
+    Decoder::default().decode_in(bytes, arena)
+}
+
+/// Decodes the message at the given depth.
+fn decode_message<'a>(bytes: &[u8], arena: &'a Bump, config: &DecodeConfig, depth: usize) -> Result<ArenaMessage<'a>> {
+    let mut fields = BumpVec::new_in(arena);
+    let mut index = 0usize;
+
+    while index < bytes.len() {
+        let Some((tag, len)) = VarInt::read_at(bytes, index) else {
+            return Err("Invalid message; malformed field header.".into());
+        };
+        index += len;
+
+        let field = field_number(tag, config)?;
+
+        match tag & 0b111 {
+            0 => {
+                let Some((varint, len)) = VarInt::try_decode_at(bytes, index) else {
+                    return Err("Invalid message; malformed varint field.".into());
+                };
+                index += len;
+                fields.push((field, ArenaValue::VarInt(varint)));
+            }
+            1 => {
+                let Some(value) = bytes.get(index..index.saturating_add(8)) else {
+                    return Err("Invalid message; not enough bytes for a fixed64 field.".into());
+                };
+                index += 8;
+                fields.push((field, ArenaValue::Double(f64::from_le_bytes(value.try_into()?))));
+            }
+            2 => {
+                let Some((data_len, len)) = VarInt::read_at(bytes, index) else {
+                    return Err("Invalid message; malformed length of a length-delimited field.".into());
+                };
+                index += len;
+
+                let data_len = usize::try_from(data_len).ok().filter(|len| *len <= config.max_field_bytes);
+                let Some(data) = data_len.and_then(|len| bytes.get(index..index.checked_add(len)?)) else {
+                    return Err("Invalid message; not enough bytes for a length-delimited field.".into());
+                };
+                index += data.len();
+
+                push_nested(&mut fields, field, data, arena, config, depth);
+            }
+            5 => {
+                let Some(value) = bytes.get(index..index.saturating_add(4)) else {
+                    return Err("Invalid message; not enough bytes for a fixed32 field.".into());
+                };
+                index += 4;
+                fields.push((field, ArenaValue::Float(f32::from_le_bytes(value.try_into()?))));
+            }
+            wire_type => return Err(format!("Invalid message; wire type {wire_type} is not supported.").into())
+        }
+    }
+
+    Ok(ArenaMessage { fields: fields.into_bump_slice() })
+}
+
+/// Pushes a length-delimited field as a string, a message, both, or bytes.
+fn push_nested<'a>(
+    fields: &mut BumpVec<'a, (u32, ArenaValue<'a>)>, field: u32, data: &[u8],
+    arena: &'a Bump, config: &DecodeConfig, depth: usize
+) {
+    if data.is_empty() {
+        fields.push((field, match config.empty {
+            EmptyPayload::Empty => ArenaValue::Empty,
+            EmptyPayload::String => ArenaValue::String(""),
+            EmptyPayload::Bytes => ArenaValue::Bytes(&[]),
+            EmptyPayload::Message => ArenaValue::Message(ArenaMessage { fields: &[] })
+        }));
+        return;
+    }
+
+    let message = (depth < config.max_depth)
+        .then(|| decode_message(data, arena, config, depth + 1).ok())
+        .flatten();
+    let string = config.strings.accept(data);
+
+    if let Some(string) = string {
+        fields.push((field, ArenaValue::String(arena.alloc_str(string))));
+    }
+    match message {
+        Some(message) => fields.push((field, ArenaValue::Message(message))),
+        None if string.is_none() => fields.push((field, ArenaValue::Bytes(arena.alloc_slice_copy(data)))),
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn arena() {
+        let mut nested = vec![];
+        nested.write_f32(1, 1.5);
+        nested.write_bytes(2, &[0xff]);
+
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+        bytes.write_str(2, "Hello, World!");
+        bytes.write_bytes(3, &nested);
+        bytes.write_u32(1, 300);
+
+        let arena = Bump::new();
+        let message = decode_in(&bytes, &arena).unwrap();
+        assert_eq!(message.len(), 4);
+        assert_eq!(message.get(2), Some(&ArenaValue::String("Hello, World!")));
+        assert_eq!(message.get_all(1).count(), 2);
+        assert_eq!(message.to_message(), decode(&bytes).unwrap());
+    }
+}
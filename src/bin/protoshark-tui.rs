@@ -0,0 +1,353 @@
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::process::ExitCode;
+use std::{env, fs};
+use protoshark::{encoding, Decoder, Spanned, Value};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+const USAGE: &str = "Usage: protoshark-tui [FILE] [OPTIONS]
+
+Browses a protobuf message from FILE, or from stdin if FILE is omitted or '-'.
+
+Input:
+  --raw        Read raw bytes (default)
+  --base64     Read Base64 text
+  --hex        Read hex text; whitespace is ignored
+
+Keys:
+  Up, Down, j, k      Select a field
+  Right, Enter, l     Expand a nested message, or select its first field
+  Left, h             Collapse a nested message, or select its parent
+  Space               Switch between the readings of an ambiguous field
+  c                   Copy the bytes of the selected field as hex
+  p                   Copy the payload of the selected field as hex
+  q, Esc              Quit";
+
+/// The number of bytes in each row of the hex dump.
+const ROW_LEN: usize = 16;
+
+/// A field of the decoded tree.
+struct Node {
+    path: Vec<u32>,
+    header: Range<usize>,
+    payload: Range<usize>,
+    /// Every reading of the field, such as a string and a message;
+    /// strings and messages can always be read as bytes too.
+    readings: Vec<Value>,
+    /// The index of the reading shown.
+    reading: usize,
+    children: Vec<Node>,
+    expanded: bool
+}
+
+impl Node {
+    /// Creates the node of a located field, and of its nested fields.
+    fn new(spanned: Spanned<Value>, parent: &[u32], bytes: &[u8]) -> Self {
+        let path = [parent, &[spanned.field]].concat();
+        let mut readings = match spanned.value {
+            Value::Repeated(values) => values,
+            value => vec![value]
+        };
+        if readings.iter().all(|value| matches!(value, Value::String(_) | Value::Message(_))) {
+            readings.push(Value::Bytes(bytes[spanned.payload.clone()].to_vec()));
+        }
+
+        let children = spanned.children.into_iter()
+            .map(|child| Node::new(child, &path, bytes))
+            .collect();
+
+        Self { path, header: spanned.header, payload: spanned.payload, readings, reading: 0, children, expanded: false }
+    }
+
+    /// Returns the reading shown.
+    fn value(&self) -> &Value {
+        &self.readings[self.reading]
+    }
+
+    /// Returns true if the nested fields can be shown under the current reading.
+    fn is_expandable(&self) -> bool {
+        !self.children.is_empty() && matches!(self.value(), Value::Message(_))
+    }
+
+    /// Returns true if the nested fields are shown.
+    fn is_open(&self) -> bool {
+        self.expanded && self.is_expandable()
+    }
+
+    /// Describes the field on a single line.
+    fn label(&self) -> String {
+        let marker = match (self.is_expandable(), self.expanded) {
+            (false, _) => ' ',
+            (true, false) => '▸',
+            (true, true) => '▾'
+        };
+        let value = match self.value() {
+            Value::Message(_) => format!("message ({} fields)", self.children.len()),
+            value => value.to_string()
+        };
+        let field = self.path.last().copied().unwrap_or_default();
+        let indent = "  ".repeat(self.path.len() - 1);
+
+        match self.readings.len() {
+            1 => format!("{indent}{marker} {field}: {value}"),
+            count => format!("{indent}{marker} {field}: {value} [{}/{count}]", self.reading + 1)
+        }
+    }
+}
+
+/// The state of the viewer.
+struct App {
+    bytes: Vec<u8>,
+    roots: Vec<Node>,
+    /// The index of the selected node among the shown nodes.
+    selected: usize,
+    /// A message for the status line, such as the result of copying.
+    status: String
+}
+
+impl App {
+    /// Decodes the message to browse.
+    fn new(bytes: Vec<u8>) -> Result<Self, String> {
+        let spans = Decoder::default().decode_with_spans(&bytes).map_err(|error| error.to_string())?;
+        let roots = spans.into_iter().map(|spanned| Node::new(spanned, &[], &bytes)).collect();
+
+        Ok(Self { bytes, roots, selected: 0, status: String::new() })
+    }
+
+    /// Handles keys until the viewer is closed.
+    fn run(mut self, terminal: &mut DefaultTerminal) -> Result<(), String> {
+        loop {
+            terminal.draw(|frame| self.draw(frame)).map_err(|error| error.to_string())?;
+
+            let Event::Key(key) = event::read().map_err(|error| error.to_string())? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            self.status.clear();
+            let shown = self.shown().len();
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => self.selected = (self.selected + 1).min(shown.saturating_sub(1)),
+                KeyCode::Right | KeyCode::Enter | KeyCode::Char('l') => self.expand(),
+                KeyCode::Left | KeyCode::Char('h') => self.collapse(),
+                KeyCode::Char(' ') => {
+                    if let Some(node) = self.selected_mut() {
+                        node.reading = (node.reading + 1) % node.readings.len();
+                    }
+                }
+                KeyCode::Char('c') => self.copy(false),
+                KeyCode::Char('p') => self.copy(true),
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the address in the tree of every shown node, in order.
+    fn shown(&self) -> Vec<Vec<usize>> {
+        let mut shown = vec![];
+        list(&self.roots, &mut vec![], &mut shown);
+        shown
+    }
+
+    /// Returns the selected node.
+    fn selected(&self) -> Option<&Node> {
+        let address = self.shown().into_iter().nth(self.selected)?;
+        let (first, rest) = address.split_first()?;
+        Some(rest.iter().fold(&self.roots[*first], |node, index| &node.children[*index]))
+    }
+
+    /// Returns the selected node for changing.
+    fn selected_mut(&mut self) -> Option<&mut Node> {
+        let address = self.shown().into_iter().nth(self.selected)?;
+        let (first, rest) = address.split_first()?;
+        Some(rest.iter().fold(&mut self.roots[*first], |node, index| &mut node.children[*index]))
+    }
+
+    /// Expands the selected message, or selects its first field if it is already expanded.
+    fn expand(&mut self) {
+        let Some(node) = self.selected_mut() else {
+            return;
+        };
+
+        if node.is_open() {
+            self.selected += 1;
+        } else if node.is_expandable() {
+            node.expanded = true;
+        }
+    }
+
+    /// Collapses the selected message, or selects its parent if it is not expanded.
+    fn collapse(&mut self) {
+        let shown = self.shown();
+        let Some(node) = self.selected_mut() else {
+            return;
+        };
+
+        if node.is_open() {
+            node.expanded = false;
+        } else if let Some(address) = shown.get(self.selected).filter(|address| address.len() > 1) {
+            let parent = &address[..address.len() - 1];
+            self.selected = shown.iter().position(|shown| shown == parent).unwrap_or(self.selected);
+        }
+    }
+
+    /// Copies the selected field, or only its payload, to the clipboard as hex.
+    ///
+    /// The terminal sets the clipboard, given an OSC 52 escape sequence.
+    fn copy(&mut self, payload: bool) {
+        let Some(node) = self.selected() else {
+            return;
+        };
+
+        let range = if payload { node.payload.clone() } else { node.header.start..node.payload.end };
+        let hex = encoding::hex_encode(&self.bytes[range.clone()]);
+        let sequence = format!("\x1b]52;c;{}\x07", encoding::base64_encode(hex.as_bytes()));
+
+        let mut stdout = io::stdout();
+        self.status = match stdout.write_all(sequence.as_bytes()).and_then(|()| stdout.flush()) {
+            Ok(()) => format!("Copied {} bytes at {}..{} as hex.", range.len(), range.start, range.end),
+            Err(error) => format!("Unable to copy: {error}")
+        };
+    }
+
+    /// Draws the tree, the hex dump, and the status line.
+    fn draw(&self, frame: &mut Frame) {
+        let [main, status] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [tree, dump] = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(main);
+
+        let mut labels = vec![];
+        for address in self.shown() {
+            let (first, rest) = address.split_first().expect("Addresses are never empty.");
+            let node = rest.iter().fold(&self.roots[*first], |node, index| &node.children[*index]);
+            labels.push(ListItem::new(node.label()));
+        }
+
+        let list = List::new(labels)
+            .block(Block::bordered().title(" Fields "))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(list, tree, &mut state);
+
+        let selected = self.selected();
+        let (header, payload) = selected.map_or((0..0, 0..0), |node| (node.header.clone(), node.payload.clone()));
+
+        // Keep the selected field in view, a few rows from the top.
+        let rows = dump.height.saturating_sub(2) as usize;
+        let scroll = (header.start / ROW_LEN).saturating_sub(rows / 4);
+        let lines: Vec<Line> = self.bytes.chunks(ROW_LEN)
+            .enumerate()
+            .skip(scroll)
+            .take(rows)
+            .map(|(row, chunk)| dump_row(row * ROW_LEN, chunk, &header, &payload))
+            .collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Bytes ")), dump);
+
+        let line = match selected {
+            Some(node) if self.status.is_empty() => format!(
+                " {} — header {}..{}, payload {}..{}, read as {}",
+                node.path.iter().map(u32::to_string).collect::<Vec<_>>().join("."),
+                node.header.start, node.header.end, node.payload.start, node.payload.end, node.value().kind()
+            ),
+            _ => format!(" {}", self.status)
+        };
+        frame.render_widget(Paragraph::new(line), status);
+    }
+}
+
+/// Lists the addresses of the shown nodes, prefixed by `address`.
+fn list(nodes: &[Node], address: &mut Vec<usize>, shown: &mut Vec<Vec<usize>>) {
+    for (index, node) in nodes.iter().enumerate() {
+        address.push(index);
+        shown.push(address.clone());
+        if node.is_open() {
+            list(&node.children, address, shown);
+        }
+        address.pop();
+    }
+}
+
+/// Formats a row of the hex dump, highlighting the header and payload of the selected field.
+fn dump_row<'a>(offset: usize, chunk: &[u8], header: &Range<usize>, payload: &Range<usize>) -> Line<'a> {
+    let style = |index: usize| {
+        if header.contains(&index) {
+            Style::default().fg(Color::Black).bg(Color::Yellow)
+        } else if payload.contains(&index) {
+            Style::default().fg(Color::Black).bg(Color::Cyan)
+        } else {
+            Style::default()
+        }
+    };
+
+    let mut spans = vec![Span::raw(format!("{offset:08x}  "))];
+    for (i, byte) in chunk.iter().enumerate() {
+        spans.push(Span::styled(format!("{byte:02x}"), style(offset + i)));
+        spans.push(Span::raw(" "));
+    }
+    spans.push(Span::raw(" ".repeat((ROW_LEN - chunk.len()) * 3 + 1)));
+    for (i, byte) in chunk.iter().enumerate() {
+        let c = if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' };
+        spans.push(Span::styled(c.to_string(), style(offset + i)));
+    }
+
+    Line::from(spans)
+}
+
+/// Reads and decodes the input bytes, given the command line.
+fn read_input(args: &[String]) -> Result<Vec<u8>, String> {
+    let (mut file, mut input) = (None, "--raw");
+    for arg in args {
+        match arg.as_str() {
+            "--raw" | "--base64" | "--hex" => input = arg,
+            flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'.")),
+            path if file.is_none() => file = Some(path),
+            _ => return Err("Only one input file may be given.".to_string())
+        }
+    }
+
+    let mut bytes = vec![];
+    match file {
+        None | Some("-") => io::stdin().read_to_end(&mut bytes).map(|_| ()),
+        Some(path) => fs::read(path).map(|file| bytes = file)
+    }.map_err(|error| format!("Unable to read input: {error}"))?;
+
+    match input {
+        "--base64" => encoding::base64_decode_any(&String::from_utf8_lossy(&bytes))
+            .map_err(|error| format!("Invalid Base64 input: {error}")),
+        "--hex" => encoding::hex_decode(&String::from_utf8_lossy(&bytes))
+            .map_err(|error| format!("Invalid hex input: {error}")),
+        _ => Ok(bytes)
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    let result = read_input(&args).and_then(App::new).and_then(|app| {
+        let mut terminal = ratatui::init();
+        let result = app.run(&mut terminal);
+        ratatui::restore();
+        result
+    });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            eprintln!("Run 'protoshark-tui --help' for usage.");
+            ExitCode::FAILURE
+        }
+    }
+}
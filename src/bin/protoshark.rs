@@ -0,0 +1,327 @@
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+use std::{env, fs};
+use protoshark::encoding;
+use protoshark::names::{EnumNameMap, FieldNameMap, Named};
+use protoshark::project::Project;
+use protoshark::schema::Schema;
+use protoshark::table::Exporter;
+use protoshark::tail::Tail;
+use protoshark::{fold_maps_with_schema, Decoder};
+
+const USAGE: &str = "Usage: protoshark decode [FILE] [OPTIONS]
+       protoshark tail FILE [--text | --json] [--from-end]
+
+Decodes a protobuf message from FILE, or from stdin if FILE is omitted or '-'.
+
+Tail follows FILE, a file or FIFO of length-delimited messages, and prints
+each message with its offset as it is written, until interrupted.
+
+Output:
+  --text       Print the message as indented text (default)
+  --json       Print the message as JSON
+  --hexdump    Print a hex dump of the input
+  --csv PATH   Print the repeated message field at PATH (e.g. 1.4) as CSV
+  --tsv PATH   Print the repeated message field at PATH as TSV
+
+Input:
+  --raw        Read raw bytes (default)
+  --base64     Read Base64 text
+  --hex        Read hex text; whitespace is ignored
+
+Options:
+  --schema FILE     Use a JSON schema to fold map fields
+  --project DIR     Load schemas from a project directory
+  --message NAME    Use the project schema of the named message
+  --names FILE      Label fields using a JSON or CSV field name map
+  --enums FILE      Label enum values using a JSON or CSV enum map
+  --timestamps      Comment values which look like Unix timestamps with their date
+  --identifiers     Annotate bytes which look like UUIDs or IP addresses
+  --from-end        Tail only the messages written after starting
+  -h, --help        Print this message";
+
+/// How the decoded message is printed.
+#[derive(Copy, Clone, PartialEq)]
+enum Output {
+    Text,
+    Json,
+    Hexdump,
+    Csv,
+    Tsv
+}
+
+/// How the input is encoded.
+#[derive(Copy, Clone, PartialEq)]
+enum Input {
+    Raw,
+    Base64,
+    Hex
+}
+
+/// The parsed command line.
+struct Options {
+    file: Option<String>,
+    output: Output,
+    table: Vec<u32>,
+    input: Input,
+    schema: Option<String>,
+    project: Option<String>,
+    message: Option<String>,
+    names: Option<String>,
+    enums: Option<String>,
+    timestamps: bool,
+    identifiers: bool
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    let result = match args[0].as_str() {
+        "decode" => parse_options(&args[1..]).and_then(|options| decode(&options)),
+        "tail" => tail(&args[1..]),
+        command => Err(format!("Unknown command '{command}'."))
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            eprintln!("Run 'protoshark --help' for usage.");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Parses the options of the `decode` command.
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        file: None,
+        output: Output::Text,
+        table: vec![],
+        input: Input::Raw,
+        schema: None,
+        project: None,
+        message: None,
+        names: None,
+        enums: None,
+        timestamps: false,
+        identifiers: false
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--text" => options.output = Output::Text,
+            "--json" => options.output = Output::Json,
+            "--hexdump" => options.output = Output::Hexdump,
+            "--csv" | "--tsv" => {
+                options.output = if arg == "--csv" { Output::Csv } else { Output::Tsv };
+                options.table = parse_path(&value(&mut args, arg)?)?;
+            }
+            "--raw" => options.input = Input::Raw,
+            "--base64" => options.input = Input::Base64,
+            "--hex" => options.input = Input::Hex,
+            "--schema" => options.schema = Some(value(&mut args, arg)?),
+            "--project" => options.project = Some(value(&mut args, arg)?),
+            "--message" => options.message = Some(value(&mut args, arg)?),
+            "--names" => options.names = Some(value(&mut args, arg)?),
+            "--enums" => options.enums = Some(value(&mut args, arg)?),
+            "--timestamps" => options.timestamps = true,
+            "--identifiers" => options.identifiers = true,
+            flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'.")),
+            file if options.file.is_none() => options.file = Some(file.to_string()),
+            _ => return Err("Only one input file may be given.".to_string())
+        }
+    }
+
+    if options.message.is_some() != options.project.is_some() {
+        return Err("'--project' and '--message' must be used together.".to_string());
+    }
+
+    Ok(options)
+}
+
+/// Reads the value of an option.
+fn value<'a, I: Iterator<Item = &'a String>>(args: &mut I, option: &str) -> Result<String, String> {
+    args.next().cloned().ok_or_else(|| format!("Missing value after '{option}'."))
+}
+
+/// Parses a dot-separated path of field numbers.
+fn parse_path(path: &str) -> Result<Vec<u32>, String> {
+    path.split('.')
+        .map(|field| field.parse().map_err(|_| format!("Invalid field path '{path}'.")))
+        .collect()
+}
+
+/// Runs the `decode` command.
+fn decode(options: &Options) -> Result<(), String> {
+    let bytes = read_input(options)?;
+
+    if options.output == Output::Hexdump {
+        return write_stdout(&hexdump(&bytes));
+    }
+
+    let mut message = Decoder::default().decode(&bytes).map_err(|error| error.to_string())?;
+    if let Some(path) = &options.schema {
+        fold_maps_with_schema(&mut message, &read_schema(path)?);
+    }
+    if let (Some(root), Some(name)) = (&options.project, &options.message) {
+        let project = Project::open(root).map_err(|error| format!("Unable to open project: {error}"))?;
+        let Some(schema) = project.schemas.get(name) else {
+            return Err(format!("Project has no schema for '{name}'."));
+        };
+
+        fold_maps_with_schema(&mut message, schema);
+    }
+
+    let names = options.names.as_deref().map(read_names).transpose()?;
+    let enums = options.enums.as_deref().map(read_enums).transpose()?;
+    let mut named = Named::new(&message);
+    if let Some(names) = &names {
+        named = named.with_names(names);
+    }
+    if let Some(enums) = &enums {
+        named = named.with_enums(enums);
+    }
+    if options.timestamps {
+        named = named.with_timestamps();
+    }
+    if options.identifiers {
+        named = named.with_identifiers();
+    }
+
+    let output = match options.output {
+        Output::Json => serde_json::to_string_pretty(&named).map_err(|error| error.to_string())?,
+        Output::Csv | Output::Tsv => {
+            let mut exporter = if options.output == Output::Csv { Exporter::csv() } else { Exporter::tsv() };
+            if let Some(names) = &names {
+                exporter = exporter.with_names(names);
+            }
+
+            // Lines are already terminated.
+            let Some(table) = exporter.export(&message, &options.table) else {
+                return Err("No repeated message field at the given path.".to_string());
+            };
+            return write_stdout(&table);
+        }
+        _ => format!("{named:#}")
+    };
+
+    write_stdout(&format!("{output}\n"))
+}
+
+/// Runs the `tail` command.
+fn tail(args: &[String]) -> Result<(), String> {
+    let (mut file, mut output, mut from_end) = (None, Output::Text, false);
+    for arg in args {
+        match arg.as_str() {
+            "--text" => output = Output::Text,
+            "--json" => output = Output::Json,
+            "--from-end" => from_end = true,
+            flag if flag.starts_with("--") => return Err(format!("Unknown option '{flag}'.")),
+            path if file.is_none() => file = Some(path),
+            _ => return Err("Only one input file may be given.".to_string())
+        }
+    }
+
+    let Some(path) = file else {
+        return Err("Missing the file to tail.".to_string());
+    };
+    let tail = if from_end { Tail::open_at_end(path) } else { Tail::open(path) }
+        .map_err(|error| format!("Unable to read input: {error}"))?;
+
+    for record in tail {
+        let record = record.map_err(|error| error.to_string())?;
+        let message = match record.message {
+            Ok(message) => message,
+            Err(error) => {
+                eprintln!("@{} {error}", record.offset);
+                continue;
+            }
+        };
+        let named = Named::new(&message);
+        let output = match output {
+            Output::Json => serde_json::to_string(&named).map_err(|error| error.to_string())?,
+            _ => format!("{named:#}")
+        };
+
+        // Stop once the reader is gone, rather than waiting for messages no one reads.
+        match io::stdout().write_all(format!("@{} {output}\n", record.offset).as_bytes()) {
+            Err(error) if error.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            result => result.map_err(|error| error.to_string())?
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads and decodes the input bytes.
+fn read_input(options: &Options) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![];
+    match options.file.as_deref() {
+        None | Some("-") => io::stdin().read_to_end(&mut bytes).map(|_| ()),
+        Some(path) => fs::read(path).map(|file| bytes = file)
+    }.map_err(|error| format!("Unable to read input: {error}"))?;
+
+    match options.input {
+        Input::Raw => Ok(bytes),
+        Input::Base64 => encoding::base64_decode_any(&String::from_utf8_lossy(&bytes))
+            .map_err(|error| format!("Invalid Base64 input: {error}")),
+        Input::Hex => encoding::hex_decode(&String::from_utf8_lossy(&bytes))
+            .map_err(|error| format!("Invalid hex input: {error}"))
+    }
+}
+
+/// Reads a JSON schema file.
+fn read_schema(path: &str) -> Result<Schema, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("Unable to read schema: {error}"))?;
+    serde_json::from_str(&text).map_err(|error| format!("Invalid schema: {error}"))
+}
+
+/// Reads a field name map, as CSV if the file ends with `.csv` and as JSON otherwise.
+fn read_names(path: &str) -> Result<FieldNameMap, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("Unable to read field names: {error}"))?;
+    if path.ends_with(".csv") {
+        FieldNameMap::from_csv(&text).map_err(|error| format!("Invalid field names: {error}"))
+    } else {
+        serde_json::from_str(&text).map_err(|error| format!("Invalid field names: {error}"))
+    }
+}
+
+/// Reads an enum map, as CSV if the file ends with `.csv` and as JSON otherwise.
+fn read_enums(path: &str) -> Result<EnumNameMap, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("Unable to read enum names: {error}"))?;
+    if path.ends_with(".csv") {
+        EnumNameMap::from_csv(&text).map_err(|error| format!("Invalid enum names: {error}"))
+    } else {
+        serde_json::from_str(&text).map_err(|error| format!("Invalid enum names: {error}"))
+    }
+}
+
+/// Formats bytes as a hex dump, with 16 bytes and their ASCII form per line.
+fn hexdump(bytes: &[u8]) -> String {
+    let mut output = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{byte:02x}")).collect();
+        let ascii: String = chunk.iter()
+            .map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' })
+            .collect();
+
+        output += &format!("{:08x}  {:<47}  |{ascii}|\n", i * 16, hex.join(" "));
+    }
+
+    output
+}
+
+/// Writes to stdout, ignoring a closed pipe.
+fn write_stdout(output: &str) -> Result<(), String> {
+    match io::stdout().write_all(output.as_bytes()) {
+        Err(error) if error.kind() != io::ErrorKind::BrokenPipe => Err(error.to_string()),
+        _ => Ok(())
+    }
+}
+
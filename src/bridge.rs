@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+use crate::{MapKey, Number, Result, SerializedMessage, Value, VarInt};
+
+/// CBOR and MessagePack values convert to and from messages structurally:
+/// messages become maps keyed by field number, bytes stay binary, and
+/// numbers keep their closest type. Converting back, maps keyed by
+/// field numbers become messages and any other map a map field;
+/// summaries are converted like maps, so they do not convert back.
+impl SerializedMessage {
+    /// Encodes the message as CBOR.
+    #[cfg(feature = "cbor")]
+    pub fn to_cbor(&self) -> Result<Vec<u8>> {
+        let mut bytes = vec![];
+        ciborium::into_writer(&ciborium::Value::from(self), &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Decodes a message from CBOR, such as one encoded by `to_cbor`.
+    #[cfg(feature = "cbor")]
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self> {
+        ciborium::from_reader::<ciborium::Value, _>(bytes)?.try_into()
+    }
+
+    /// Encodes the message as MessagePack.
+    #[cfg(feature = "msgpack")]
+    pub fn to_msgpack(&self) -> Result<Vec<u8>> {
+        let mut bytes = vec![];
+        rmpv::encode::write_value(&mut bytes, &rmpv::Value::from(self))?;
+        Ok(bytes)
+    }
+
+    /// Decodes a message from MessagePack, such as one encoded by `to_msgpack`.
+    #[cfg(feature = "msgpack")]
+    pub fn from_msgpack(mut bytes: &[u8]) -> Result<Self> {
+        rmpv::decode::read_value(&mut bytes)?.try_into()
+    }
+}
+
+/// Builds a message from map entries keyed by field number, or a map field otherwise.
+fn from_entries(entries: Vec<(MapKey, Value)>) -> Value {
+    let is_message = entries.iter()
+        .all(|(key, _)| matches!(key, MapKey::Integer(field) if u32::try_from(*field).is_ok()));
+
+    if is_message && !entries.is_empty() {
+        let mut message = SerializedMessage::new();
+        for (key, value) in entries {
+            if let MapKey::Integer(field) = key {
+                message.set_field(field as u32, value);
+            }
+        }
+        Value::Message(message)
+    } else {
+        Value::Map(entries.into_iter().collect())
+    }
+}
+
+/// Creates a varint from an integer of either sign.
+fn varint(signed: Option<i64>, unsigned: Option<u64>) -> Result<VarInt> {
+    match (signed, unsigned) {
+        (Some(value), _) => Ok(value.into()),
+        (None, Some(value)) => Ok(VarInt::decode(&VarInt::encode_minimal(value))),
+        (None, None) => Err("Invalid value; the integer does not fit in 64 bits.".into())
+    }
+}
+
+/// The fields of a summary, which has no wire representation.
+fn summary_entries(field_count: usize, type_histogram: &BTreeMap<String, usize>) -> (u64, Vec<(String, u64)>) {
+    let histogram = type_histogram.iter()
+        .map(|(wire_type, count)| (wire_type.clone(), *count as u64))
+        .collect();
+
+    (field_count as u64, histogram)
+}
+
+#[cfg(feature = "cbor")]
+mod cbor {
+    use ciborium::value::Integer;
+    use ciborium::Value as Cbor;
+    use super::*;
+    use crate::Error;
+
+    impl From<&SerializedMessage> for Cbor {
+        fn from(message: &SerializedMessage) -> Self {
+            Cbor::Map(message.iter()
+                .map(|(field, value)| (Cbor::Integer((*field).into()), value.into()))
+                .collect())
+        }
+    }
+
+    impl From<&Value> for Cbor {
+        fn from(value: &Value) -> Self {
+            match value {
+                Value::VarInt(varint) => Cbor::Integer(match Number::closest(varint.clone()) {
+                    Number::Integer(value) => value.into(),
+                    Number::Long(value) => value.into(),
+                    Number::UnsignedInteger(value) => value.into(),
+                    Number::UnsignedLong(value) => value.into()
+                }),
+                Value::Float(value) => Cbor::Float(*value as f64),
+                Value::Double(value) => Cbor::Float(*value),
+                Value::String(value) => Cbor::Text(value.clone()),
+                Value::Bytes(bytes) => Cbor::Bytes(bytes.clone()),
+                Value::Empty => Cbor::Bytes(vec![]),
+                Value::Message(message) => message.into(),
+                Value::Repeated(values) => Cbor::Array(values.iter().map(Cbor::from).collect()),
+                Value::Summary { field_count, type_histogram } => {
+                    let (field_count, histogram) = summary_entries(*field_count, type_histogram);
+                    Cbor::Map(vec![
+                        (Cbor::Text("field_count".into()), Cbor::Integer(field_count.into())),
+                        (Cbor::Text("type_histogram".into()), Cbor::Map(histogram.into_iter()
+                            .map(|(wire_type, count)| (Cbor::Text(wire_type), Cbor::Integer(count.into())))
+                            .collect()))
+                    ])
+                }
+                Value::Map(entries) => Cbor::Map(entries.iter()
+                    .map(|(key, value)| {
+                        let key = match key {
+                            MapKey::Integer(key) => Cbor::Integer((*key).into()),
+                            MapKey::String(key) => Cbor::Text(key.clone())
+                        };
+                        (key, value.into())
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    impl TryFrom<Cbor> for SerializedMessage {
+        type Error = Error;
+
+        fn try_from(value: Cbor) -> Result<Self> {
+            match Value::try_from(value)? {
+                Value::Message(message) => Ok(message),
+                Value::Map(entries) if entries.is_empty() => Ok(SerializedMessage::new()),
+                _ => Err("Invalid message; expected a map keyed by field number.".into())
+            }
+        }
+    }
+
+    impl TryFrom<Cbor> for Value {
+        type Error = Error;
+
+        fn try_from(value: Cbor) -> Result<Self> {
+            Ok(match value {
+                Cbor::Integer(value) => Value::VarInt(integer(value)?),
+                Cbor::Float(value) => Value::Double(value),
+                Cbor::Bool(value) => Value::VarInt((value as i32).into()),
+                Cbor::Text(value) => Value::String(value),
+                Cbor::Bytes(bytes) => Value::Bytes(bytes),
+                Cbor::Array(values) => Value::Repeated(values.into_iter()
+                    .map(Value::try_from)
+                    .collect::<Result<_>>()?),
+                Cbor::Map(entries) => from_entries(entries.into_iter()
+                    .map(|(key, value)| {
+                        let key = match key {
+                            Cbor::Integer(key) => MapKey::Integer(i64::try_from(key)
+                                .map_err(|_| "Invalid map key; the integer does not fit in 64 bits.")?),
+                            Cbor::Text(key) => MapKey::String(key),
+                            _ => return Err("Invalid map key; expected an integer or string.".into())
+                        };
+                        Ok((key, value.try_into()?))
+                    })
+                    .collect::<Result<_>>()?),
+                Cbor::Tag(_, value) => (*value).try_into()?,
+                _ => return Err("Invalid value; CBOR nulls and simple values have no protobuf equivalent.".into())
+            })
+        }
+    }
+
+    fn integer(value: Integer) -> Result<VarInt> {
+        varint(i64::try_from(value).ok(), u64::try_from(value).ok())
+    }
+
+    impl From<ciborium::ser::Error<std::io::Error>> for Error {
+        fn from(error: ciborium::ser::Error<std::io::Error>) -> Self {
+            Error::Invalid(error.to_string())
+        }
+    }
+
+    impl From<ciborium::de::Error<std::io::Error>> for Error {
+        fn from(error: ciborium::de::Error<std::io::Error>) -> Self {
+            Error::Invalid(error.to_string())
+        }
+    }
+}
+
+#[cfg(feature = "msgpack")]
+mod msgpack {
+    use rmpv::Value as MessagePack;
+    use super::*;
+    use crate::Error;
+
+    impl From<&SerializedMessage> for MessagePack {
+        fn from(message: &SerializedMessage) -> Self {
+            MessagePack::Map(message.iter()
+                .map(|(field, value)| (MessagePack::from(*field), value.into()))
+                .collect())
+        }
+    }
+
+    impl From<&Value> for MessagePack {
+        fn from(value: &Value) -> Self {
+            match value {
+                Value::VarInt(varint) => match Number::closest(varint.clone()) {
+                    Number::Integer(value) => value.into(),
+                    Number::Long(value) => value.into(),
+                    Number::UnsignedInteger(value) => value.into(),
+                    Number::UnsignedLong(value) => value.into()
+                },
+                Value::Float(value) => MessagePack::F32(*value),
+                Value::Double(value) => MessagePack::F64(*value),
+                Value::String(value) => value.as_str().into(),
+                Value::Bytes(bytes) => MessagePack::Binary(bytes.clone()),
+                Value::Empty => MessagePack::Binary(vec![]),
+                Value::Message(message) => message.into(),
+                Value::Repeated(values) => MessagePack::Array(values.iter().map(MessagePack::from).collect()),
+                Value::Summary { field_count, type_histogram } => {
+                    let (field_count, histogram) = summary_entries(*field_count, type_histogram);
+                    MessagePack::Map(vec![
+                        ("field_count".into(), field_count.into()),
+                        ("type_histogram".into(), MessagePack::Map(histogram.into_iter()
+                            .map(|(wire_type, count)| (wire_type.into(), count.into()))
+                            .collect()))
+                    ])
+                }
+                Value::Map(entries) => MessagePack::Map(entries.iter()
+                    .map(|(key, value)| {
+                        let key = match key {
+                            MapKey::Integer(key) => (*key).into(),
+                            MapKey::String(key) => key.as_str().into()
+                        };
+                        (key, value.into())
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    impl TryFrom<MessagePack> for SerializedMessage {
+        type Error = Error;
+
+        fn try_from(value: MessagePack) -> Result<Self> {
+            match Value::try_from(value)? {
+                Value::Message(message) => Ok(message),
+                Value::Map(entries) if entries.is_empty() => Ok(SerializedMessage::new()),
+                _ => Err("Invalid message; expected a map keyed by field number.".into())
+            }
+        }
+    }
+
+    impl TryFrom<MessagePack> for Value {
+        type Error = Error;
+
+        fn try_from(value: MessagePack) -> Result<Self> {
+            Ok(match value {
+                MessagePack::Integer(value) => Value::VarInt(varint(value.as_i64(), value.as_u64())?),
+                MessagePack::F32(value) => Value::Float(value),
+                MessagePack::F64(value) => Value::Double(value),
+                MessagePack::Boolean(value) => Value::VarInt((value as i32).into()),
+                MessagePack::String(value) => match value.into_str() {
+                    Some(value) => Value::String(value),
+                    None => return Err("Invalid value; the string is not valid UTF-8.".into())
+                },
+                MessagePack::Binary(bytes) => Value::Bytes(bytes),
+                MessagePack::Array(values) => Value::Repeated(values.into_iter()
+                    .map(Value::try_from)
+                    .collect::<Result<_>>()?),
+                MessagePack::Map(entries) => from_entries(entries.into_iter()
+                    .map(|(key, value)| {
+                        let key = match key {
+                            MessagePack::Integer(key) => MapKey::Integer(key.as_i64()
+                                .ok_or("Invalid map key; the integer does not fit in 64 bits.")?),
+                            MessagePack::String(key) => MapKey::String(key.into_str()
+                                .ok_or("Invalid map key; the string is not valid UTF-8.")?),
+                            _ => return Err("Invalid map key; expected an integer or string.".into())
+                        };
+                        Ok((key, value.try_into()?))
+                    })
+                    .collect::<Result<_>>()?),
+                _ => return Err("Invalid value; MessagePack nils and extensions have no protobuf equivalent.".into())
+            })
+        }
+    }
+
+    impl From<rmpv::encode::Error> for Error {
+        fn from(error: rmpv::encode::Error) -> Self {
+            Error::Invalid(error.to_string())
+        }
+    }
+
+    impl From<rmpv::decode::Error> for Error {
+        fn from(error: rmpv::decode::Error) -> Self {
+            Error::Invalid(error.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn round_trip() {
+        let mut inner = vec![];
+        inner.write_i64(1, -5);
+
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+        bytes.write_str(2, "Hello, World!");
+        bytes.write_bytes(3, &[0xff; 4]);
+        bytes.write_f64(4, 1.5);
+        bytes.write_bytes(5, &inner);
+        bytes.write_bytes(5, &inner);
+
+        let message = decode(&bytes).unwrap();
+
+        #[cfg(feature = "cbor")]
+        {
+            let cbor = message.to_cbor().unwrap();
+            assert_eq!(SerializedMessage::from_cbor(&cbor).unwrap(), message);
+            assert!(SerializedMessage::from_cbor(&cbor[..cbor.len() - 1]).is_err());
+        }
+
+        #[cfg(feature = "msgpack")]
+        {
+            let msgpack = message.to_msgpack().unwrap();
+            assert_eq!(SerializedMessage::from_msgpack(&msgpack).unwrap(), message);
+            assert!(SerializedMessage::from_msgpack(&msgpack[..msgpack.len() - 1]).is_err());
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use paste::paste;
-use crate::{Header, IntoVarInt, VarInt, WireType};
+use crate::{Header, IntoVarInt, SerializedMessage, Value, VarInt, WireType};
 
 /// A macro to write a header to the byte array.
 macro_rules! h {
@@ -49,6 +49,15 @@ pub trait ProtobufBytes {
 
     /// Writes a `f64` fixed-length floating point decimal to the byte array.
     fn write_f64(&mut self, field: u32, value: f64);
+
+    /// Writes a decoded value to the byte array.
+    ///
+    /// Repeated values are written as one field per element.
+    /// Summaries cannot be encoded and are skipped.
+    fn write_value(&mut self, field: u32, value: &Value);
+
+    /// Writes a nested message to the byte array.
+    fn write_message(&mut self, field: u32, value: &SerializedMessage);
 }
 
 impl ProtobufBytes for Vec<u8> {
@@ -73,4 +82,34 @@ impl ProtobufBytes for Vec<u8> {
         self.extend(h!(field, WireType::Fixed64));
         self.extend(value.to_le_bytes());
     }
+
+    fn write_value(&mut self, field: u32, value: &Value) {
+        match value {
+            Value::VarInt(value) => {
+                self.extend(h!(field, WireType::VarInt));
+                self.extend(value.to_bytes());
+            }
+            Value::Float(value) => self.write_f32(field, *value),
+            Value::Double(value) => self.write_f64(field, *value),
+            Value::String(value) => self.write_str(field, value),
+            Value::Bytes(value) => self.write_bytes(field, value),
+            Value::Empty => self.write_bytes(field, &[]),
+            Value::Message(value) => self.write_message(field, value),
+            Value::Repeated(values) => {
+                for value in values {
+                    self.write_value(field, value);
+                }
+            }
+            Value::Summary { .. } => {}
+            Value::Map(entries) => {
+                for (key, value) in entries {
+                    self.write_message(field, &key.entry(value.clone()));
+                }
+            }
+        }
+    }
+
+    fn write_message(&mut self, field: u32, value: &SerializedMessage) {
+        self.write_bytes(field, &value.encode());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,235 @@
+use crate::decoder::field_number;
+use crate::{Decoder, Result, VarInt, WireType};
+
+/// A field as read from the wire, borrowing its payload.
+#[derive(Clone, Debug)]
+enum Field<'a> {
+    VarInt(u64),
+    Fixed32(&'a [u8]),
+    Fixed64(&'a [u8]),
+    LengthDelimited(&'a [u8])
+}
+
+/// A length-delimited payload, read as canonically as it can be.
+#[derive(Clone, Debug)]
+enum Payload {
+    /// A nested message, already canonicalized.
+    Message(Vec<u8>),
+    /// Minimal packed varints which are neither a message nor a string.
+    Packed(Vec<u64>),
+    /// Anything else, such as a string, kept as it is.
+    Raw
+}
+
+impl Decoder {
+    /// Re-encodes a message canonically, so that messages which differ only in
+    /// their encoding produce identical bytes, for hashing and deduplication.
+    ///
+    /// Field headers, varints, and lengths are encoded minimally, and fields
+    /// are sorted by number, keeping the order of each field's occurrences.
+    /// Payloads which are messages are canonicalized up to `max_depth`.
+    /// Payloads of packed varints (which are neither messages nor strings
+    /// under the string policy) are merged with the other occurrences of their
+    /// field into a single packed payload. Only payloads whose varints are all
+    /// minimal are read as packed, since padding a varint in a payload of bytes
+    /// changes its contents; other payloads are kept as they are, so messages
+    /// which differ never canonicalize alike.
+    ///
+    /// Without a schema, a field set several times cannot be told from an
+    /// unpacked repeated field, so such fields are left unpacked. Sorting
+    /// also forgets which member of a oneof was set last.
+    pub fn canonicalize(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        self.canonicalize_message(bytes, 0)
+    }
+
+    /// Canonicalizes a message at the given depth, failing if it is not one.
+    fn canonicalize_message(&self, bytes: &[u8], depth: usize) -> Result<Vec<u8>> {
+        let mut fields = self.read_fields(bytes)?;
+        fields.sort_by_key(|(number, _)| *number);
+
+        let mut output = vec![];
+        for occurrences in fields.chunk_by(|(a, _), (b, _)| a == b) {
+            let number = occurrences[0].0;
+            let fields: Vec<(&Field, Option<Payload>)> = occurrences.iter()
+                .map(|(_, field)| match field {
+                    Field::LengthDelimited(payload) => (field, Some(self.canonicalize_payload(payload, depth))),
+                    field => (field, None)
+                })
+                .collect();
+
+            // Packed varints merge with the other varints of their field, which must be repeated.
+            let packed = fields.iter().any(|(_, payload)| matches!(payload, Some(Payload::Packed(_))));
+            let mergeable = fields.iter().all(|(field, payload)| {
+                matches!((field, payload), (Field::VarInt(_), _) | (_, Some(Payload::Packed(_))))
+            });
+            if packed && mergeable {
+                let values = fields.iter().flat_map(|(field, payload)| match (field, payload) {
+                    (Field::VarInt(value), _) => vec![*value],
+                    (_, Some(Payload::Packed(values))) => values.clone(),
+                    _ => vec![]
+                });
+                write_length_delimited(&mut output, number, &values.flat_map(VarInt::encode_minimal).collect::<Vec<_>>());
+                continue;
+            }
+
+            for (field, payload) in fields {
+                match (field, payload) {
+                    (Field::VarInt(value), _) => {
+                        write_header(&mut output, number, WireType::VarInt);
+                        output.extend(VarInt::encode_minimal(*value));
+                    }
+                    (Field::Fixed32(value), _) => {
+                        write_header(&mut output, number, WireType::Fixed32);
+                        output.extend_from_slice(value);
+                    }
+                    (Field::Fixed64(value), _) => {
+                        write_header(&mut output, number, WireType::Fixed64);
+                        output.extend_from_slice(value);
+                    }
+                    (_, Some(Payload::Message(message))) => write_length_delimited(&mut output, number, &message),
+                    (_, Some(Payload::Packed(values))) => {
+                        write_length_delimited(&mut output, number, &values.into_iter().flat_map(VarInt::encode_minimal).collect::<Vec<_>>());
+                    }
+                    (Field::LengthDelimited(payload), _) => write_length_delimited(&mut output, number, payload)
+                }
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Reads a length-delimited payload as a message, as packed varints, or as it is.
+    fn canonicalize_payload(&self, payload: &[u8], depth: usize) -> Payload {
+        let config = self.config();
+        if depth < config.max_depth {
+            if let Ok(message) = self.canonicalize_message(payload, depth + 1) {
+                return Payload::Message(message);
+            }
+        }
+        if config.strings.accept(payload).is_some() {
+            return Payload::Raw;
+        }
+
+        let mut values = vec![];
+        let mut index = 0;
+        while index < payload.len() {
+            let Some((value, len)) = VarInt::read_at(payload, index) else {
+                return Payload::Raw;
+            };
+            if len != VarInt::minimal_length(value) {
+                return Payload::Raw;
+            }
+            values.push(value);
+            index += len;
+        }
+
+        Payload::Packed(values)
+    }
+
+    /// Reads the fields of a message in wire order, failing if it is malformed.
+    fn read_fields<'a>(&self, bytes: &'a [u8]) -> Result<Vec<(u32, Field<'a>)>> {
+        let config = self.config();
+        let mut fields = vec![];
+        let mut index = 0;
+
+        while index < bytes.len() {
+            let Some((tag, tag_len)) = VarInt::read_at(bytes, index) else {
+                return Err("Invalid message; malformed field header.".into());
+            };
+            let number = field_number(tag, config)?;
+            let Ok(wire_type) = WireType::try_from((tag & 0b111) as u8) else {
+                return Err("Invalid wire type specified".into());
+            };
+            index += tag_len;
+
+            let (field, len) = match wire_type {
+                WireType::VarInt => {
+                    let Some((value, len)) = VarInt::read_at(bytes, index) else {
+                        return Err("Invalid message; malformed varint field.".into());
+                    };
+                    (Field::VarInt(value), len)
+                }
+                WireType::Fixed32 | WireType::Fixed64 => {
+                    let len = if wire_type == WireType::Fixed32 { 4 } else { 8 };
+                    let Some(value) = bytes.get(index..index + len) else {
+                        return Err(format!("Invalid message; not enough bytes for a {} field.", wire_type.name()).into());
+                    };
+                    (if len == 4 { Field::Fixed32(value) } else { Field::Fixed64(value) }, len)
+                }
+                WireType::StartGroup => return Err("Start group wire type is not supported.".into()),
+                WireType::EndGroup => return Err("End group wire type is not supported.".into()),
+                WireType::LengthDelimited => {
+                    let Some((len, prefix)) = VarInt::read_at(bytes, index) else {
+                        return Err("Invalid message; malformed length of a length-delimited field.".into());
+                    };
+                    index += prefix;
+
+                    let Some(len) = usize::try_from(len).ok().filter(|len| *len <= config.max_field_bytes) else {
+                        return Err(format!(
+                            "Invalid message; field length exceeds the limit of {} bytes.",
+                            config.max_field_bytes
+                        ).into());
+                    };
+                    let Some(payload) = bytes.get(index..index + len) else {
+                        return Err("Invalid message; not enough bytes for a length-delimited field.".into());
+                    };
+                    (Field::LengthDelimited(payload), len)
+                }
+            };
+
+            fields.push((number, field));
+            index += len;
+        }
+
+        Ok(fields)
+    }
+}
+
+/// Writes a minimal field header.
+fn write_header(output: &mut Vec<u8>, number: u32, wire_type: WireType) {
+    output.extend(VarInt::encode_minimal(((number as u64) << 3) | wire_type as u64));
+}
+
+/// Writes a length-delimited field with a minimal header and length.
+fn write_length_delimited(output: &mut Vec<u8>, number: u32, payload: &[u8]) {
+    write_header(output, number, WireType::LengthDelimited);
+    output.extend(VarInt::encode_minimal(payload.len() as u64));
+    output.extend_from_slice(payload);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::canonicalize;
+
+    #[test]
+    fn canonicalize_message() {
+        let canonical = [
+            0x08, 0x96, 0x01,
+            0x08, 0x01,
+            0x12, 0x03, 0x61, 0x62, 0x63,
+            0x1a, 0x02, 0x08, 0x07,
+            0x22, 0x03, 0x01, 0x80, 0x01
+        ];
+
+        // Padded headers, varints, and lengths; fields out of order;
+        // and a packed field split into a varint and a packed chunk.
+        let padded = [
+            0x9a, 0x00, 0x83, 0x80, 0x00, 0x88, 0x00, 0x07,
+            0x12, 0x83, 0x00, 0x61, 0x62, 0x63,
+            0x08, 0x96, 0x81, 0x80, 0x00,
+            0xa0, 0x00, 0x01,
+            0x08, 0x01,
+            0x22, 0x82, 0x00, 0x80, 0x01
+        ];
+
+        assert_eq!(canonicalize(&canonical).unwrap(), canonical);
+        assert_eq!(canonicalize(&padded).unwrap(), canonical);
+        assert!(canonicalize(&[0x08]).is_err());
+
+        // Padded varints in a payload may be bytes, so they are kept as they are.
+        assert_eq!(canonicalize(&[0x0a, 0x01, 0x00]).unwrap(), [0x0a, 0x01, 0x00]);
+        assert_eq!(canonicalize(&[0x0a, 0x02, 0x80, 0x00]).unwrap(), [0x0a, 0x02, 0x80, 0x00]);
+        let blob = [0x0a, 0x08, 0xde, 0xad, 0xbe, 0xef, 0x80, 0x80, 0x00, 0x11];
+        assert_eq!(canonicalize(&blob).unwrap(), blob);
+    }
+}
@@ -0,0 +1,142 @@
+use std::io::{self, Read};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use crate::{Result, SerializedMessage};
+
+pub use crate::reassembly::{Framing, Reassembler};
+
+/// The largest UDP payload.
+const MAX_DATAGRAM_LEN: usize = 65_535;
+
+/// A callback receiving every captured message, with the address of its sender.
+pub type Callback = dyn Fn(SocketAddr, Result<SerializedMessage>) + Send + Sync;
+
+/// Binds a UDP socket and captures the messages of every datagram it receives, forever.
+pub fn capture_udp<A, F>(address: A, framing: Framing, callback: F) -> io::Result<()>
+where
+    A: ToSocketAddrs,
+    F: Fn(SocketAddr, Result<SerializedMessage>) + Send + Sync + 'static
+{
+    serve_udp(UdpSocket::bind(address)?, framing, callback)
+}
+
+/// Binds a TCP listener and captures the messages of every connection, forever.
+pub fn capture_tcp<A, F>(address: A, framing: Framing, callback: F) -> io::Result<()>
+where
+    A: ToSocketAddrs,
+    F: Fn(SocketAddr, Result<SerializedMessage>) + Send + Sync + 'static
+{
+    serve_tcp(TcpListener::bind(address)?, framing, callback)
+}
+
+/// Receives datagrams forever, passing their messages to the callback.
+///
+/// Each datagram is framed on its own; messages may not span datagrams.
+pub fn serve_udp<F>(socket: UdpSocket, framing: Framing, callback: F) -> io::Result<()>
+where
+    F: Fn(SocketAddr, Result<SerializedMessage>) + Send + Sync + 'static
+{
+    let mut buffer = vec![0u8; MAX_DATAGRAM_LEN];
+    loop {
+        let (len, sender) = socket.recv_from(&mut buffer)?;
+
+        let mut reassembler = Reassembler::new(framing.clone());
+        for message in reassembler.feed(&buffer[..len]) {
+            callback(sender, message);
+        }
+        if let Some(message) = reassembler.finish() {
+            callback(sender, message);
+        }
+    }
+}
+
+/// Accepts connections forever, reading each on its own thread
+/// and passing its messages to the callback.
+///
+/// A connection which fails to be accepted is skipped.
+pub fn serve_tcp<F>(listener: TcpListener, framing: Framing, callback: F) -> io::Result<()>
+where
+    F: Fn(SocketAddr, Result<SerializedMessage>) + Send + Sync + 'static
+{
+    let callback: Arc<Callback> = Arc::new(callback);
+    for stream in listener.incoming() {
+        // Such as when out of file descriptors, which only loses this connection.
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let (framing, callback) = (framing.clone(), callback.clone());
+        thread::spawn(move || receive(stream, framing, &*callback));
+    }
+
+    Ok(())
+}
+
+/// Reads a connection until it is closed, passing its messages to the callback.
+fn receive(mut stream: TcpStream, framing: Framing, callback: &Callback) -> io::Result<()> {
+    let sender = stream.peer_addr()?;
+    let mut reassembler = Reassembler::new(framing);
+    let mut buffer = [0u8; 16 * 1024];
+
+    loop {
+        let len = stream.read(&mut buffer)?;
+        if len == 0 {
+            if let Some(message) = reassembler.finish() {
+                callback(sender, message);
+            }
+            return Ok(());
+        }
+
+        for message in reassembler.feed(&buffer[..len]) {
+            callback(sender, message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::mpsc;
+    use crate::ProtobufBytes;
+
+    #[test]
+    fn capture() {
+        let mut message = vec![];
+        message.write_str(1, "hey");
+        let mut delimited = vec![message.len() as u8];
+        delimited.extend(&message);
+
+        let (sender, receiver) = mpsc::channel();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve_tcp(listener, Framing::LengthDelimited, move |_, message| {
+            sender.send(message).unwrap();
+        }));
+
+        let mut client = TcpStream::connect(address).unwrap();
+        client.write_all(&delimited[..3]).unwrap();
+        client.flush().unwrap();
+        client.write_all(&[&delimited[3..], &[0xff; 10]].concat()).unwrap();
+        drop(client);
+
+        let message = receiver.recv().unwrap().unwrap();
+        assert_eq!(message.get(1).unwrap().as_string().unwrap(), "hey");
+        assert!(receiver.recv().unwrap().is_err());
+
+        let (sender, receiver) = mpsc::channel();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        thread::spawn(move || serve_udp(socket, Framing::Raw, move |from, message| {
+            sender.send((from, message)).unwrap();
+        }));
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut datagram = vec![];
+        datagram.write_u32(1, 150);
+        client.send_to(&datagram, address).unwrap();
+        let (from, message) = receiver.recv().unwrap();
+        assert_eq!(from, client.local_addr().unwrap());
+        assert_eq!(message.unwrap().get(1).unwrap().as_u32(), Some(150));
+    }
+}
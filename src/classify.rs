@@ -0,0 +1,344 @@
+use std::error::Error;
+use std::fmt;
+use crate::{decode, SerializedMessage, VarInt, MAX_FIELD_NUMBER};
+
+/// How likely a buffer is to be a protobuf message, from `0.0` to `1.0`.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct Confidence(f32);
+
+impl Confidence {
+    /// The buffer is not a valid protobuf message.
+    pub const NONE: Confidence = Confidence(0.0);
+
+    /// Returns the confidence score, from `0.0` to `1.0`.
+    pub fn score(&self) -> f32 {
+        self.0
+    }
+
+    /// Returns true if the buffer is more likely than not a protobuf message.
+    pub fn is_likely(&self) -> bool {
+        self.0 >= 0.5
+    }
+}
+
+/// The highest field number considered common in real schemas.
+const COMMON_FIELD_NUMBER: u64 = 1000;
+
+/// Scores how likely a buffer is to be a protobuf message.
+///
+/// Only the top-level fields are walked and nothing is allocated,
+/// so this is much cheaper than `decode` for triaging payloads.
+/// Any structural error (invalid header, overrunning length) scores `NONE`.
+pub fn looks_like_protobuf(bytes: &[u8]) -> Confidence {
+    let mut index = 0usize;
+    let mut fields = 0usize;
+    let mut common = 0usize;
+    let mut ordered = 0usize;
+    let mut last_field = 0u64;
+
+    while index < bytes.len() {
+        let Some((field, end)) = read_field(bytes, index) else {
+            return Confidence::NONE;
+        };
+        index = end;
+
+        fields += 1;
+        if field <= COMMON_FIELD_NUMBER {
+            common += 1;
+        }
+        // Encoders usually emit fields in ascending order.
+        if field >= last_field {
+            ordered += 1;
+        }
+        last_field = field;
+    }
+
+    if fields == 0 {
+        return Confidence::NONE;
+    }
+
+    let common = common as f32 / fields as f32;
+    let ordered = ordered as f32 / fields as f32;
+    let count = fields.min(4) as f32 / 4.0;
+
+    Confidence(0.4 + 0.3 * common + 0.2 * ordered + 0.1 * count)
+}
+
+/// The first field number reserved for the protobuf implementation.
+const FIRST_RESERVED_FIELD_NUMBER: u64 = 19000;
+
+/// The last field number reserved for the protobuf implementation.
+const LAST_RESERVED_FIELD_NUMBER: u64 = 19999;
+
+/// Field numbers above this are legal, but almost never used by real schemas.
+const IMPLAUSIBLE_FIELD_NUMBER: u64 = 1 << 20;
+
+/// Why a field number is rejected in strict mode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DesyncReason {
+    /// Field number zero is never valid.
+    Zero,
+    /// Field numbers 19000 to 19999 are reserved by protobuf.
+    Reserved,
+    /// The field number is too large to be legal, or to be used in practice.
+    Implausible
+}
+
+impl DesyncReason {
+    /// Checks a field number, returning why it is rejected, if it is.
+    pub fn of(field: u64) -> Option<Self> {
+        match field {
+            0 => Some(DesyncReason::Zero),
+            FIRST_RESERVED_FIELD_NUMBER..=LAST_RESERVED_FIELD_NUMBER => Some(DesyncReason::Reserved),
+            field if field > IMPLAUSIBLE_FIELD_NUMBER => Some(DesyncReason::Implausible),
+            _ => None
+        }
+    }
+}
+
+/// A field header which suggests the buffer is not aligned with a message.
+///
+/// This usually means the framing is wrong, or the data is encrypted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Desync {
+    /// The offset of the rejected header.
+    pub offset: usize,
+    /// The rejected field number.
+    pub field: u64,
+    /// Why the field number was rejected.
+    pub reason: DesyncReason,
+    /// The offset at which the message most likely starts, if one was found
+    /// within the first `MAX_SUGGESTED_START` bytes.
+    pub suggested_start: Option<usize>
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.reason {
+            DesyncReason::Zero => "is zero",
+            DesyncReason::Reserved => "is reserved",
+            DesyncReason::Implausible => "is implausibly large"
+        };
+
+        write!(f, "Probable desync; field number {} at offset {} {reason}.", self.field, self.offset)?;
+        match self.suggested_start {
+            Some(start) => write!(f, " The message most likely starts at offset {start}."),
+            None => write!(f, " Check the framing, or whether the data is encrypted.")
+        }
+    }
+}
+
+impl Error for Desync {}
+
+/// Validates the top-level field numbers of a buffer in strict mode.
+///
+/// Headers with zero, reserved, or implausibly large field numbers are
+/// reported as a probable desync, along with the most likely true start.
+/// Other structural errors are not reported; see `looks_like_protobuf`.
+pub fn validate(bytes: &[u8]) -> Result<(), Desync> {
+    let Some((offset, field)) = first_rejected(bytes) else {
+        return Ok(());
+    };
+
+    Err(Desync {
+        offset,
+        field,
+        reason: DesyncReason::of(field).unwrap(),
+        suggested_start: suggest_start(bytes)
+    })
+}
+
+/// Returns the offset and field number of the first rejected header.
+fn first_rejected(bytes: &[u8]) -> Option<(usize, u64)> {
+    let mut index = 0usize;
+    while index < bytes.len() {
+        let (header, _) = VarInt::read_at(bytes, index)?;
+        let field = header >> 3;
+        if DesyncReason::of(field).is_some() {
+            return Some((index, field));
+        }
+
+        index = read_field(bytes, index)?.1;
+    }
+
+    None
+}
+
+/// The furthest offset `validate` suggests as the start of a message.
+pub const MAX_SUGGESTED_START: usize = 4096;
+
+/// The most bytes read from each offset tried as the start of a message.
+const SUGGEST_WINDOW: usize = 64 * 1024;
+
+/// Finds the offset whose remainder is the longest run of strictly valid fields.
+///
+/// Only offsets up to `MAX_SUGGESTED_START` are tried, reading at most
+/// `SUGGEST_WINDOW` bytes from each, so large captures take bounded time.
+/// Offsets whose remainder is valid to the end of that window are preferred.
+fn suggest_start(bytes: &[u8]) -> Option<usize> {
+    let mut best: Option<(usize, usize)> = None;
+
+    for offset in 1..bytes.len().min(MAX_SUGGESTED_START + 1) {
+        let remainder = &bytes[offset..bytes.len().min(offset + SUGGEST_WINDOW)];
+        let len = strict_prefix(remainder);
+        if len == 0 || !looks_like_protobuf(&remainder[..len]).is_likely() {
+            continue;
+        }
+
+        if len == remainder.len() {
+            return Some(offset);
+        }
+        if best.is_none_or(|(_, best)| len > best) {
+            best = Some((offset, len));
+        }
+    }
+
+    best.map(|(offset, _)| offset)
+}
+
+/// Returns the length of the longest prefix of complete fields with accepted field numbers.
+fn strict_prefix(bytes: &[u8]) -> usize {
+    let mut index = 0usize;
+    while let Some((field, end)) = read_field(bytes, index) {
+        if DesyncReason::of(field).is_some() {
+            break;
+        }
+        index = end;
+    }
+
+    index
+}
+
+/// The shortest range `scan` reports by default.
+const MIN_SCAN_LEN: usize = 8;
+
+/// Finds every range of the buffer which decodes cleanly as a protobuf message.
+///
+/// Useful when captured buffers have non-protobuf prefixes or suffixes.
+/// Returns the offset, length, and decoded message of every hit.
+pub fn scan(bytes: &[u8]) -> Vec<(usize, usize, SerializedMessage)> {
+    scan_with(bytes, MIN_SCAN_LEN)
+}
+
+/// Finds every range of at least `min_len` bytes which decodes cleanly.
+///
+/// Short ranges are frequently valid by chance, so `min_len` filters noise.
+/// Hits never overlap; scanning resumes after the end of each hit.
+pub fn scan_with(bytes: &[u8], min_len: usize) -> Vec<(usize, usize, SerializedMessage)> {
+    let mut hits = vec![];
+    let mut offset = 0usize;
+
+    while offset < bytes.len() {
+        let len = valid_prefix(&bytes[offset..]);
+        let range = &bytes[offset..offset + len];
+
+        if len > 0 && len >= min_len && looks_like_protobuf(range).is_likely() {
+            if let Ok(message) = decode(range) {
+                hits.push((offset, len, message));
+                offset += len;
+                continue;
+            }
+        }
+
+        offset += 1;
+    }
+
+    hits
+}
+
+/// Returns the length of the longest prefix made of complete, valid fields.
+fn valid_prefix(bytes: &[u8]) -> usize {
+    let mut index = 0usize;
+    while let Some((_, end)) = read_field(bytes, index) {
+        index = end;
+    }
+
+    index
+}
+
+/// Reads the field starting at the given index without allocating.
+///
+/// Returns the field number and the index after the field,
+/// or `None` if the field is invalid or overruns the buffer.
+pub(crate) fn read_field(bytes: &[u8], index: usize) -> Option<(u64, usize)> {
+    let (header, len) = VarInt::read_at(bytes, index)?;
+    let index = index + len;
+
+    let field = header >> 3;
+    if field == 0 || field > MAX_FIELD_NUMBER as u64 {
+        return None;
+    }
+
+    let size = match header & 0b111 {
+        0 => VarInt::read_at(bytes, index)?.1,
+        1 => 8,
+        2 => {
+            let (length, len) = VarInt::read_at(bytes, index)?;
+            len.checked_add(usize::try_from(length).ok()?)?
+        }
+        5 => 4,
+        // Groups are unsupported, and 6/7 are not wire types.
+        _ => return None
+    };
+
+    match index.checked_add(size) {
+        Some(end) if end <= bytes.len() => Some((field, end)),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProtobufBytes;
+
+    #[test]
+    fn classify() {
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+        bytes.write_str(2, "Hello, World!");
+        bytes.write_f32(3, 1.5);
+
+        assert!(looks_like_protobuf(&bytes).is_likely());
+        assert_eq!(looks_like_protobuf(&bytes[..bytes.len() - 1]), Confidence::NONE);
+        assert_eq!(looks_like_protobuf(b"\x00\x01"), Confidence::NONE);
+        assert_eq!(looks_like_protobuf(&[]), Confidence::NONE);
+    }
+
+    #[test]
+    fn scan_prefixed() {
+        let mut message = vec![];
+        message.write_u32(1, 150);
+        message.write_str(2, "Hello, World!");
+
+        let mut bytes = vec![0xff, 0xff, 0x07];
+        bytes.extend(&message);
+
+        let hits = scan(&bytes);
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].0, hits[0].1), (3, message.len()));
+        assert_eq!(hits[0].2.get(2).unwrap().as_string().unwrap(), "Hello, World!");
+    }
+
+    #[test]
+    fn desync() {
+        let mut message = vec![];
+        message.write_u32(1, 150);
+        message.write_str(2, "Hello, World!");
+        assert!(validate(&message).is_ok());
+
+        // A header for reserved field 19001, followed by the real message.
+        let mut bytes = vec![0xc8, 0xa3, 0x09, 0x01];
+        bytes.extend(&message);
+
+        let desync = validate(&bytes).unwrap_err();
+        assert_eq!((desync.offset, desync.field), (0, 19001));
+        assert_eq!(desync.reason, DesyncReason::Reserved);
+        assert_eq!(desync.suggested_start, Some(4));
+
+        // Only the start of a large capture is searched.
+        let padded = |len: usize| [&bytes[..4], &vec![0; len], &message].concat();
+        assert_eq!(validate(&padded(MAX_SUGGESTED_START - 4)).unwrap_err().suggested_start, Some(MAX_SUGGESTED_START));
+        assert_ne!(validate(&padded(MAX_SUGGESTED_START - 3)).unwrap_err().suggested_start, Some(MAX_SUGGESTED_START + 1));
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{IntoVarInt, SerializedMessage, Value, VarInt};
+
+/// A type which can be composed into a message, the inverse of `FromSerializedMessage`.
+///
+/// Derive this with the `derive` feature, annotating each field with its number:
+///
+/// ```ignore
+/// #[derive(ToSerializedMessage)]
+/// struct Login {
+///     #[field(1)]
+///     user: String,
+///     #[field(2)]
+///     token: Vec<u8>
+/// }
+/// ```
+pub trait ToSerializedMessage {
+    /// Composes the fields of a message.
+    fn to_message(&self) -> SerializedMessage;
+
+    /// Encodes the message into protobuf wire format.
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_message().encode()
+    }
+}
+
+/// A type which can be converted into a single value.
+///
+/// Integers are written as the `write_*` methods of `ProtobufBytes` write them.
+pub trait ToValue {
+    /// Converts the type into a value.
+    fn to_value(&self) -> Value;
+}
+
+/// A type which can be written to a field of a message.
+///
+/// Any `ToValue` type is written once, `Option` only if it is `Some`,
+/// and `Vec` once for each of its elements.
+pub trait ToField {
+    /// Inserts the values of the field into a message.
+    fn to_field(&self, field: u32, message: &mut SerializedMessage);
+}
+
+impl<T: ToValue> ToField for T {
+    fn to_field(&self, field: u32, message: &mut SerializedMessage) {
+        message.insert(field, self.to_value());
+    }
+}
+
+impl<T: ToValue> ToField for Option<T> {
+    fn to_field(&self, field: u32, message: &mut SerializedMessage) {
+        if let Some(value) = self {
+            value.to_field(field, message);
+        }
+    }
+}
+
+impl<T: ToValue> ToField for Vec<T> {
+    fn to_field(&self, field: u32, message: &mut SerializedMessage) {
+        for value in self {
+            value.to_field(field, message);
+        }
+    }
+}
+
+macro_rules! to_varint {
+    ($($t:ty => $wide:ty),*) => {
+        $(
+            impl ToValue for $t {
+                fn to_value(&self) -> Value {
+                    Value::VarInt(VarInt::decode(&<$wide>::from(*self).into_varint()))
+                }
+            }
+        )*
+    };
+}
+
+// Narrow integers are encoded as the 32-bit integer of the same signedness.
+to_varint!(i32 => i32, i64 => i64, u32 => u32, u64 => u64, i8 => i32, i16 => i32, u16 => u32);
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::from(*self)
+    }
+}
+
+impl ToValue for f32 {
+    fn to_value(&self) -> Value {
+        Value::Float(*self)
+    }
+}
+
+impl ToValue for f64 {
+    fn to_value(&self) -> Value {
+        Value::Double(*self)
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        Value::String(self.clone())
+    }
+}
+
+impl ToValue for Vec<u8> {
+    fn to_value(&self) -> Value {
+        Value::Bytes(self.clone())
+    }
+}
+
+impl ToValue for SerializedMessage {
+    fn to_value(&self) -> Value {
+        Value::Message(self.clone())
+    }
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProtobufBytes;
+
+    #[test]
+    fn compose() {
+        let mut message = SerializedMessage::new();
+        "alice".to_string().to_field(1, &mut message);
+        vec![0xde_u8, 0xad].to_field(2, &mut message);
+        vec![7u32, 8].to_field(4, &mut message);
+        None::<u32>.to_field(5, &mut message);
+
+        let mut bytes = vec![];
+        bytes.write_str(1, "alice");
+        bytes.write_bytes(2, &[0xde, 0xad]);
+        bytes.write_u32(4, 7);
+        bytes.write_u32(4, 8);
+        assert_eq!(message.encode(), bytes);
+
+        #[cfg(feature = "derive")]
+        {
+            use crate::{decode, FromSerializedMessage, ToSerializedMessage};
+
+            #[derive(Debug, PartialEq, FromSerializedMessage, ToSerializedMessage)]
+            struct Login {
+                #[field(1)]
+                user: String,
+                #[field(2)]
+                token: Vec<u8>,
+                #[field(4)]
+                attempts: Vec<u32>,
+                #[field(5)]
+                session: Option<u32>
+            }
+
+            let login = Login { user: "alice".to_string(), token: vec![0xde, 0xad], attempts: vec![7, 8], session: None };
+            assert_eq!(login.to_bytes(), bytes);
+            assert_eq!(Login::from_message(&decode(&login.to_bytes()).unwrap()).unwrap(), login);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+use crate::{decode, verify_roundtrip, Difference, Value, VarInt};
+
+/// What a conforming parser does with a test vector.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Expectation {
+    /// The payload is rejected as malformed.
+    Reject,
+    /// The payload is accepted.
+    Accept,
+    /// The payload is accepted, and re-encodes to the same bytes.
+    ///
+    /// Field headers and length prefixes may be written with a different
+    /// width, since the encoder pads them and every parser accepts padding.
+    RoundTrip,
+    /// The payload is accepted, and the field holds the varint.
+    VarInt { field: u32, value: i64 }
+}
+
+/// A wire-format payload with the behavior expected of a conforming parser.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TestVector {
+    /// The name of the behavior, grouped by the feature it exercises (e.g. `Varint.Simple`).
+    pub name: &'static str,
+    pub bytes: &'static [u8],
+    pub expectation: Expectation
+}
+
+/// Hand-written payloads modeled on the binary cases of the protobuf conformance
+/// suite, covering the wire format rather than any particular schema.
+pub const VECTORS: &[TestVector] = &[
+    TestVector { name: "Varint.Simple", bytes: &[0x08, 0x96, 0x01], expectation: Expectation::VarInt { field: 1, value: 150 } },
+    TestVector {
+        name: "Varint.MaxLength",
+        bytes: &[0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+        expectation: Expectation::VarInt { field: 1, value: -1 }
+    },
+    TestVector { name: "Varint.Overlong", bytes: &[0x08, 0x96, 0x81, 0x80, 0x00], expectation: Expectation::RoundTrip },
+    TestVector {
+        name: "Varint.TooLong",
+        bytes: &[0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+        expectation: Expectation::Reject
+    },
+    TestVector { name: "Varint.Truncated", bytes: &[0x08, 0x96], expectation: Expectation::Reject },
+    TestVector { name: "FieldNumber.Zero", bytes: &[0x00, 0x01], expectation: Expectation::Reject },
+    TestVector {
+        name: "FieldNumber.Max",
+        bytes: &[0xf8, 0xff, 0xff, 0xff, 0x0f, 0x01],
+        expectation: Expectation::VarInt { field: (1 << 29) - 1, value: 1 }
+    },
+    TestVector { name: "FieldNumber.TooLarge", bytes: &[0x80, 0x80, 0x80, 0x80, 0x10, 0x01], expectation: Expectation::Reject },
+    TestVector { name: "WireType.Invalid6", bytes: &[0x0e, 0x00], expectation: Expectation::Reject },
+    TestVector { name: "WireType.Invalid7", bytes: &[0x0f, 0x00], expectation: Expectation::Reject },
+    TestVector { name: "LengthDelimited.Overrun", bytes: &[0x0a, 0x05, 0x61], expectation: Expectation::Reject },
+    TestVector {
+        name: "LengthDelimited.NegativeLength",
+        bytes: &[0x0a, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, 0x61],
+        expectation: Expectation::Reject
+    },
+    TestVector { name: "Fixed32.RoundTrip", bytes: &[0x0d, 0x00, 0x00, 0x80, 0x3f], expectation: Expectation::RoundTrip },
+    TestVector { name: "Fixed32.Truncated", bytes: &[0x0d, 0x01, 0x02], expectation: Expectation::Reject },
+    TestVector {
+        name: "Fixed64.NanPayload",
+        bytes: &[0x09, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf8, 0x7f],
+        expectation: Expectation::RoundTrip
+    },
+    TestVector { name: "Fixed64.Truncated", bytes: &[0x09, 0x01, 0x02, 0x03], expectation: Expectation::Reject },
+    TestVector { name: "Group.Simple", bytes: &[0x0b, 0x08, 0x01, 0x0c], expectation: Expectation::RoundTrip },
+    TestVector { name: "Group.UnmatchedEnd", bytes: &[0x0c], expectation: Expectation::Reject },
+    TestVector { name: "Packed.Varints", bytes: &[0x0a, 0x03, 0x01, 0x02, 0x03], expectation: Expectation::RoundTrip },
+    TestVector { name: "Repeated.Interleaved", bytes: &[0x08, 0x01, 0x10, 0x02, 0x08, 0x03], expectation: Expectation::RoundTrip },
+    TestVector { name: "Message.Empty", bytes: &[], expectation: Expectation::Accept }
+];
+
+/// A test vector the crate handled differently than expected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Failure {
+    pub name: &'static str,
+    /// What went wrong.
+    pub reason: String
+}
+
+/// The outcome of running test vectors.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConformanceReport {
+    /// The number of vectors which behaved as expected.
+    pub passed: usize,
+    pub failures: Vec<Failure>
+}
+
+impl ConformanceReport {
+    /// Returns true if every vector behaved as expected.
+    pub fn is_conformant(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Lists each failure, followed by a summary line.
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for failure in &self.failures {
+            writeln!(f, "FAIL {}: {}", failure.name, failure.reason)?;
+        }
+
+        write!(f, "{} passed, {} failed", self.passed, self.failures.len())
+    }
+}
+
+/// Runs the vendored test vectors.
+pub fn run() -> ConformanceReport {
+    run_vectors(VECTORS)
+}
+
+/// Runs the given test vectors against the default decoder and the encoder.
+pub fn run_vectors(vectors: &[TestVector]) -> ConformanceReport {
+    let mut report = ConformanceReport::default();
+    for vector in vectors {
+        match check(vector) {
+            Ok(()) => report.passed += 1,
+            Err(reason) => report.failures.push(Failure { name: vector.name, reason })
+        }
+    }
+
+    report
+}
+
+/// Checks a single test vector, describing how it failed.
+fn check(vector: &TestVector) -> Result<(), String> {
+    let decoded = decode(vector.bytes);
+    match (vector.expectation, decoded) {
+        (Expectation::Reject, Ok(_)) => Err("The malformed payload was accepted.".to_string()),
+        (Expectation::Reject, Err(_)) => Ok(()),
+        (_, Err(error)) => Err(format!("The payload was rejected; {error}")),
+        (Expectation::Accept, Ok(_)) => Ok(()),
+        (Expectation::RoundTrip, Ok(_)) => {
+            let report = verify_roundtrip(vector.bytes).map_err(|error| error.to_string())?;
+            match report.differences.iter().find(|difference| !matches!(difference, Difference::Header { .. } | Difference::LengthPrefix { .. })) {
+                Some(difference) => Err(difference.to_string()),
+                None => Ok(())
+            }
+        }
+        (Expectation::VarInt { field, value }, Ok(message)) => match message.get(field) {
+            Some(Value::VarInt(varint)) if varint.as_i64() == value => Ok(()),
+            Some(found) => Err(format!("Field {field} was decoded as {found:?}, not {}.", VarInt::from(value))),
+            None => Err(format!("Field {field} is missing."))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conformance() {
+        let report = run();
+        assert_eq!(report.passed + report.failures.len(), VECTORS.len());
+
+        // Groups are unsupported, and interleaved occurrences are merged when encoding.
+        let failures: Vec<_> = report.failures.iter().map(|failure| failure.name).collect();
+        assert_eq!(failures, ["Group.Simple", "Repeated.Interleaved"]);
+    }
+}
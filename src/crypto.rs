@@ -0,0 +1,189 @@
+use aes::cipher::{generic_array::GenericArray, BlockDecrypt, KeyInit};
+use aes::{Aes128, Aes192, Aes256};
+use crate::{PayloadTransform, Result};
+
+/// The AES block size, in bytes.
+pub const BLOCK_LEN: usize = 16;
+
+/// Deobfuscates payloads by XOR-ing them with a repeating key.
+///
+/// As a `PayloadTransform`, only the root message is transformed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Xor {
+    /// The repeating key.
+    pub key: Vec<u8>
+}
+
+impl Xor {
+    /// Creates a new XOR transform with the given key.
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self { key: key.into() }
+    }
+
+    /// XORs the bytes with the key, repeating it as needed.
+    pub fn apply(&self, bytes: &[u8]) -> Vec<u8> {
+        if self.key.is_empty() {
+            return bytes.to_vec();
+        }
+
+        bytes.iter()
+            .zip(self.key.iter().cycle())
+            .map(|(byte, key)| byte ^ key)
+            .collect()
+    }
+}
+
+impl PayloadTransform for Xor {
+    fn transform(&self, path: &[u32], bytes: &[u8]) -> Option<Vec<u8>> {
+        path.is_empty().then(|| self.apply(bytes))
+    }
+}
+
+/// The block cipher mode used by an `Aes` transform.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AesMode {
+    /// Each block is decrypted independently.
+    Ecb,
+    /// Each block is chained with the previous, starting with the IV.
+    Cbc([u8; BLOCK_LEN])
+}
+
+#[derive(Clone)]
+enum Cipher {
+    Aes128(Aes128),
+    Aes192(Aes192),
+    Aes256(Aes256)
+}
+
+impl Cipher {
+    fn decrypt_block(&self, block: &mut [u8]) {
+        let block = GenericArray::from_mut_slice(block);
+        match self {
+            Cipher::Aes128(cipher) => cipher.decrypt_block(block),
+            Cipher::Aes192(cipher) => cipher.decrypt_block(block),
+            Cipher::Aes256(cipher) => cipher.decrypt_block(block)
+        }
+    }
+}
+
+/// Decrypts payloads with AES in ECB or CBC mode.
+///
+/// Keys may be 16, 24 or 32 bytes long. Plaintexts are expected to use
+/// PKCS#7 padding; as a `PayloadTransform`, only the root message is
+/// transformed, and payloads that fail to decrypt are left unchanged.
+#[derive(Clone)]
+pub struct Aes {
+    cipher: Cipher,
+    mode: AesMode
+}
+
+impl Aes {
+    /// Creates a new AES transform with the given key and mode.
+    pub fn new(key: &[u8], mode: AesMode) -> Result<Self> {
+        let cipher = match key.len() {
+            16 => Cipher::Aes128(Aes128::new(GenericArray::from_slice(key))),
+            24 => Cipher::Aes192(Aes192::new(GenericArray::from_slice(key))),
+            32 => Cipher::Aes256(Aes256::new(GenericArray::from_slice(key))),
+            len => return Err(format!("Invalid AES key length {len}.").into())
+        };
+
+        Ok(Self { cipher, mode })
+    }
+
+    /// Creates a new AES transform in ECB mode.
+    pub fn ecb(key: &[u8]) -> Result<Self> {
+        Self::new(key, AesMode::Ecb)
+    }
+
+    /// Creates a new AES transform in CBC mode.
+    pub fn cbc(key: &[u8], iv: [u8; BLOCK_LEN]) -> Result<Self> {
+        Self::new(key, AesMode::Cbc(iv))
+    }
+
+    /// Returns the block cipher mode.
+    pub fn mode(&self) -> AesMode {
+        self.mode
+    }
+
+    /// Decrypts the bytes and removes their PKCS#7 padding.
+    pub fn decrypt(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        if bytes.is_empty() || !bytes.len().is_multiple_of(BLOCK_LEN) {
+            return Err("Invalid ciphertext; not a whole number of AES blocks.".into());
+        }
+
+        let mut plaintext = bytes.to_vec();
+        for block in plaintext.chunks_exact_mut(BLOCK_LEN) {
+            self.cipher.decrypt_block(block);
+        }
+
+        if let AesMode::Cbc(iv) = self.mode {
+            let previous = std::iter::once(&iv[..]).chain(bytes.chunks_exact(BLOCK_LEN));
+            for (block, previous) in plaintext.chunks_exact_mut(BLOCK_LEN).zip(previous) {
+                block.iter_mut().zip(previous).for_each(|(byte, key)| *byte ^= key);
+            }
+        }
+
+        let padding = plaintext[plaintext.len() - 1] as usize;
+        let valid = (1..=BLOCK_LEN).contains(&padding)
+            && plaintext[plaintext.len() - padding..].iter().all(|&byte| byte as usize == padding);
+        if !valid {
+            return Err("Invalid plaintext; bad PKCS#7 padding.".into());
+        }
+
+        plaintext.truncate(plaintext.len() - padding);
+        Ok(plaintext)
+    }
+}
+
+impl std::fmt::Debug for Aes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Aes").field("mode", &self.mode).finish_non_exhaustive()
+    }
+}
+
+impl PayloadTransform for Aes {
+    fn transform(&self, path: &[u32], bytes: &[u8]) -> Option<Vec<u8>> {
+        path.is_empty().then(|| self.decrypt(bytes).ok()).flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DecodeConfig, Decoder, ProtobufBytes, Transformer};
+
+    #[test]
+    fn decrypt() {
+        // FIPS-197 appendix C.1, followed by a full block of padding.
+        let key: Vec<u8> = (0..16).collect();
+        let mut ciphertext = vec![
+            0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30,
+            0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4, 0xc5, 0x5a
+        ];
+        let aes = Aes::ecb(&key).unwrap();
+        assert!(aes.decrypt(&ciphertext).is_err());
+
+        use aes::cipher::BlockEncrypt;
+        let mut padding = GenericArray::from([16u8; 16]);
+        Aes128::new(GenericArray::from_slice(&key)).encrypt_block(&mut padding);
+        ciphertext.extend(padding);
+
+        let plaintext: Vec<u8> = (0..16).map(|i| i * 0x11).collect();
+        assert_eq!(aes.decrypt(&ciphertext).unwrap(), plaintext);
+
+        // In CBC mode, the padding block is chained with the first block.
+        let mut chained = GenericArray::from([16u8; 16]);
+        chained.iter_mut().zip(&ciphertext[..BLOCK_LEN]).for_each(|(byte, key)| *byte ^= key);
+        Aes128::new(GenericArray::from_slice(&key)).encrypt_block(&mut chained);
+        ciphertext.truncate(BLOCK_LEN);
+        ciphertext.extend(chained);
+        assert_eq!(Aes::cbc(&key, [0; BLOCK_LEN]).unwrap().decrypt(&ciphertext).unwrap(), plaintext);
+
+        let mut message = vec![];
+        message.write_str(1, "Hello, World!");
+        let xor = Xor::new(*b"key");
+        let config = DecodeConfig { transform: Some(Transformer::new(xor.clone())), ..DecodeConfig::default() };
+        let decoded = Decoder::new(config).decode(&xor.apply(&message)).unwrap();
+        assert_eq!(decoded.get(1).unwrap().as_string().unwrap(), "Hello, World!");
+    }
+}
@@ -0,0 +1,1302 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use crate::{classify, encoding, fold_maps, DesyncReason, Error, FieldPath, Header, Profiler, Result, SerializedMessage, Value, VarInt, WireType};
+
+/// The largest valid field number.
+pub const MAX_FIELD_NUMBER: u32 = (1 << 29) - 1;
+
+/// How many fields are decoded between progress reports; see `Decoder::decode_with_progress`.
+pub const PROGRESS_INTERVAL: usize = 4096;
+
+/// Options which control how messages are decoded.
+#[derive(Clone, Debug)]
+pub struct DecodeConfig {
+    /// The maximum nesting depth of messages.
+    ///
+    /// Length-delimited fields nested deeper than this are not
+    /// decoded as messages, and are kept as strings or bytes instead.
+    pub max_depth: usize,
+
+    /// The maximum length of a single length-delimited field.
+    ///
+    /// Fields which declare a longer length are rejected.
+    pub max_field_bytes: usize,
+
+    /// The maximum number of fields of a single message, if any.
+    ///
+    /// Messages with more fields are not decoded into a tree;
+    /// they become a `Value::Summary` of their field counts instead.
+    pub max_fields: Option<usize>,
+
+    /// Whether to reject zero, reserved, and implausibly large field numbers.
+    ///
+    /// Such headers usually mean the framing is wrong or the data is encrypted;
+    /// decoding the root message fails with a `Desync` suggesting the true start.
+    pub strict: bool,
+
+    /// Whether to accept field number zero, and field numbers above `MAX_FIELD_NUMBER`.
+    ///
+    /// Such field numbers only occur in malformed traffic, so they fail
+    /// with `Error::FieldNumber` by default. When tolerated, they are decoded
+    /// like any other field; `classify::validate` finds where they occur.
+    /// Field numbers which do not fit in a `u32` are always rejected.
+    pub tolerate_field_numbers: bool,
+
+    /// Decodes fields of wire types the decoder does not support, if set.
+    ///
+    /// Without a handler, groups (wire types 3 and 4) and
+    /// wire types 6 and 7 fail to decode.
+    pub wire_type_handler: Option<WireTypeHandler>,
+
+    /// Transforms length-delimited payloads before they are interpreted, if set.
+    ///
+    /// Used to decrypt or deobfuscate payloads, including the root message.
+    pub transform: Option<Transformer>,
+
+    /// Overrides how individual fields are decoded, if set.
+    ///
+    /// The interpreter is consulted for every field of a standard wire type.
+    pub interpreter: Option<Interpreter>,
+
+    /// Whether to fold repeated key/value entries into `Value::Map`.
+    ///
+    /// Disabled by default, since the folding is heuristic; see `fold_maps`.
+    pub fold_maps: bool,
+
+    /// Whether to record the wire order of fields, so messages re-encode in that order.
+    ///
+    /// Needed for byte-exact replay of messages whose fields are out of order
+    /// or interleaved; see `SerializedMessage::fields_in_order`.
+    pub preserve_order: bool,
+
+    /// Which length-delimited payloads are decoded as strings.
+    ///
+    /// By default any valid UTF-8 is a string, which mislabels some binary
+    /// tokens; payloads which are not strings are kept as bytes instead.
+    pub strings: StringPolicy,
+
+    /// How empty length-delimited fields are decoded.
+    ///
+    /// An empty payload is a valid string, bytes, and message alike,
+    /// so by default it is kept as `Value::Empty` rather than guessed.
+    pub empty: EmptyPayload
+}
+
+impl Default for DecodeConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: 100,
+            max_field_bytes: 64 * 1024 * 1024,
+            max_fields: None,
+            strict: false,
+            tolerate_field_numbers: false,
+            wire_type_handler: None,
+            transform: None,
+            interpreter: None,
+            fold_maps: false,
+            preserve_order: false,
+            strings: StringPolicy::default(),
+            empty: EmptyPayload::default()
+        }
+    }
+}
+
+/// How an empty length-delimited field is decoded, when its type is unknown.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum EmptyPayload {
+    /// As `Value::Empty`.
+    #[default]
+    Empty,
+    /// As an empty `Value::String`.
+    String,
+    /// As an empty `Value::Bytes`.
+    Bytes,
+    /// As an empty `Value::Message`.
+    Message
+}
+
+impl EmptyPayload {
+    /// Returns the value of an empty field.
+    pub fn value(self) -> Value {
+        match self {
+            EmptyPayload::Empty => Value::Empty,
+            EmptyPayload::String => Value::String(String::new()),
+            EmptyPayload::Bytes => Value::Bytes(vec![]),
+            EmptyPayload::Message => Value::Message(SerializedMessage::new())
+        }
+    }
+}
+
+/// The heuristic deciding which valid UTF-8 payloads are strings.
+///
+/// The default accepts any valid UTF-8, as the decoder always has.
+/// Deserializing fills in missing options with their defaults.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StringPolicy {
+    /// The minimum fraction of printable characters, from `0.0` to `1.0`.
+    ///
+    /// Characters other than control characters are printable,
+    /// as are the control characters in `allowed_controls`.
+    pub min_printable_ratio: f64,
+
+    /// The minimum length of a string, in bytes.
+    pub min_len: usize,
+
+    /// Control characters which count as printable.
+    pub allowed_controls: Vec<char>,
+
+    /// Whether strings must be ASCII.
+    pub require_ascii: bool
+}
+
+impl Default for StringPolicy {
+    fn default() -> Self {
+        Self {
+            min_printable_ratio: 0.0,
+            min_len: 0,
+            allowed_controls: vec!['\t', '\n', '\r'],
+            require_ascii: false
+        }
+    }
+}
+
+impl StringPolicy {
+    /// A policy for text: at least 90% printable, with only tabs and line breaks allowed.
+    pub fn text() -> Self {
+        Self { min_printable_ratio: 0.9, ..Self::default() }
+    }
+
+    /// Returns the payload as a string, if it is one under this policy.
+    pub fn accept<'a>(&self, bytes: &'a [u8]) -> Option<&'a str> {
+        if bytes.len() < self.min_len || (self.require_ascii && !bytes.is_ascii()) {
+            return None;
+        }
+
+        let string = std::str::from_utf8(bytes).ok()?;
+        if self.min_printable_ratio > 0.0 && !string.is_empty() {
+            let (mut chars, mut printable) = (0usize, 0usize);
+            for c in string.chars() {
+                chars += 1;
+                if !c.is_control() || self.allowed_controls.contains(&c) {
+                    printable += 1;
+                }
+            }
+            if (printable as f64) < self.min_printable_ratio * chars as f64 {
+                return None;
+            }
+        }
+
+        Some(string)
+    }
+}
+
+/// Decodes fields of non-standard wire types, for protocol dialects.
+///
+/// Given the field number, the wire type, and the bytes after the header,
+/// the handler returns the value and the number of bytes it consumed,
+/// or `None` if the field is invalid.
+#[derive(Clone)]
+pub struct WireTypeHandler(Arc<HandlerFn>);
+
+/// The signature of a wire type handler.
+type HandlerFn = dyn Fn(u32, u8, &[u8]) -> Option<(Value, usize)> + Send + Sync;
+
+impl WireTypeHandler {
+    /// Creates a new handler from a closure.
+    pub fn new<F>(handler: F) -> Self
+    where
+        F: Fn(u32, u8, &[u8]) -> Option<(Value, usize)> + Send + Sync + 'static
+    {
+        Self(Arc::new(handler))
+    }
+}
+
+impl fmt::Debug for WireTypeHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WireTypeHandler")
+    }
+}
+
+/// Overrides how individual fields are decoded, for protocol-specific dissectors.
+///
+/// Implemented for closures taking the same arguments as `interpret`.
+pub trait FieldInterpreter: Send + Sync {
+    /// Interprets a field, or returns `None` to decode it as usual.
+    ///
+    /// Given the path of the field, its wire type, and its raw value: the
+    /// varint, the fixed-size bytes, or the payload of a length-delimited field.
+    fn interpret(&self, path: &[u32], wire_type: WireType, bytes: &[u8]) -> Option<Interpretation>;
+}
+
+impl<F> FieldInterpreter for F
+where
+    F: Fn(&[u32], WireType, &[u8]) -> Option<Interpretation> + Send + Sync
+{
+    fn interpret(&self, path: &[u32], wire_type: WireType, bytes: &[u8]) -> Option<Interpretation> {
+        self(path, wire_type, bytes)
+    }
+}
+
+/// How a `FieldInterpreter` decodes a field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Interpretation {
+    /// The field has this value.
+    Value(Value),
+    /// The field is a length-delimited payload of these bytes, such as a
+    /// decrypted blob, and is decoded as a message, string, or bytes as usual.
+    Payload(Vec<u8>)
+}
+
+/// A shared `FieldInterpreter`, for use in a `DecodeConfig`.
+#[derive(Clone)]
+pub struct Interpreter(Arc<dyn FieldInterpreter>);
+
+impl Interpreter {
+    /// Creates a new shared interpreter.
+    pub fn new<I: FieldInterpreter + 'static>(interpreter: I) -> Self {
+        Self(Arc::new(interpreter))
+    }
+}
+
+impl fmt::Debug for Interpreter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Interpreter")
+    }
+}
+
+/// Transforms payloads before they are decoded, such as to decrypt them.
+///
+/// Implemented for closures taking the same arguments as `transform`.
+pub trait PayloadTransform: Send + Sync {
+    /// Transforms a payload, or returns `None` to leave it unchanged.
+    ///
+    /// Given the path of the length-delimited field, which is empty
+    /// for the root message, and the payload without its length.
+    fn transform(&self, path: &[u32], bytes: &[u8]) -> Option<Vec<u8>>;
+}
+
+impl<F> PayloadTransform for F
+where
+    F: Fn(&[u32], &[u8]) -> Option<Vec<u8>> + Send + Sync
+{
+    fn transform(&self, path: &[u32], bytes: &[u8]) -> Option<Vec<u8>> {
+        self(path, bytes)
+    }
+}
+
+/// A shared `PayloadTransform`, for use in a `DecodeConfig`.
+#[derive(Clone)]
+pub struct Transformer(Arc<dyn PayloadTransform>);
+
+impl Transformer {
+    /// Creates a new shared transform.
+    pub fn new<T: PayloadTransform + 'static>(transform: T) -> Self {
+        Self(Arc::new(transform))
+    }
+}
+
+impl fmt::Debug for Transformer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Transformer")
+    }
+}
+
+/// A flag for cancelling a decode from another thread, such as a GUI's.
+///
+/// Clones share the flag, so one can be kept to cancel while another is decoding.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a token which is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every decode using this token or its clones.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// How far a decode has progressed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// The offset in the message of the field being decoded, which never decreases.
+    pub bytes_processed: usize,
+    /// The length of the message.
+    pub total_bytes: usize,
+    /// The number of fields decoded so far, at any depth.
+    pub fields_emitted: usize
+}
+
+/// Reports the progress of a decode, and stops it once cancelled.
+struct Monitor<'m> {
+    token: &'m CancellationToken,
+    callback: &'m mut dyn FnMut(Progress),
+    progress: Progress
+}
+
+/// A protobuf decoder with configurable limits.
+///
+/// Nested messages are decoded using an explicit stack,
+/// so deeply nested input cannot overflow the call stack.
+///
+/// Decoding never panics on arbitrary input, only fails with an `Err`,
+/// unless a `WireTypeHandler` panics. This is checked by the fuzz
+/// target in `fuzz/` and by property tests.
+#[derive(Clone, Debug, Default)]
+pub struct Decoder {
+    config: DecodeConfig
+}
+
+/// A message being decoded.
+struct Frame<'a> {
+    /// The encoded message.
+    bytes: &'a [u8],
+    /// The offset of `bytes` in the root message.
+    offset: usize,
+    /// The index of the next field in `bytes`.
+    index: usize,
+    /// The fields decoded so far.
+    message: SerializedMessage,
+    /// The field number of this message in its parent.
+    field: u32,
+    /// The nesting depth of this message; the root is at zero.
+    depth: usize,
+    /// The path of this message, tracked only for an interpreter or transform.
+    path: Option<FieldPath>,
+    /// The number of fields of each wire type.
+    counts: [usize; 8],
+    /// Whether the message has too many fields and is only being counted.
+    summarized: bool,
+    /// The span of this message, nested in the span of its parent.
+    #[cfg(feature = "trace")]
+    span: tracing::Span
+}
+
+/// The outcome of decoding a single field.
+enum Step<'a> {
+    /// The field of the given wire type was decoded into the current message.
+    Done(u8),
+    /// The field is length-delimited and may be a nested message.
+    Nested(u32, &'a [u8]),
+    /// The field was interpreted as a payload which may be a nested message.
+    Interpreted(u32, Vec<u8>)
+}
+
+impl Decoder {
+    /// Creates a new decoder using the given options.
+    pub fn new(config: DecodeConfig) -> Self {
+        Self { config }
+    }
+
+    /// Returns the options of the decoder.
+    pub fn config(&self) -> &DecodeConfig {
+        &self.config
+    }
+
+    /// Decodes a protobuf-encoded message.
+    ///
+    /// Fails if the message has more than `max_fields` fields;
+    /// use `decode_value` to get a summary instead.
+    pub fn decode(&self, bytes: &[u8]) -> Result<SerializedMessage> {
+        into_message(self.decode_inner(bytes, None, None, &[])?)
+    }
+
+    /// Decodes a protobuf-encoded message from Base64 text.
+    ///
+    /// Standard and URL-safe alphabets are accepted, with or without padding,
+    /// and whitespace is ignored.
+    pub fn decode_base64(&self, text: &str) -> Result<SerializedMessage> {
+        self.decode(&encoding::base64_decode_any(text)?)
+    }
+
+    /// Decodes a protobuf-encoded message from hex text, ignoring whitespace.
+    pub fn decode_hex(&self, text: &str) -> Result<SerializedMessage> {
+        self.decode(&encoding::hex_decode(text)?)
+    }
+
+    /// Decodes a batch of protobuf-encoded messages concurrently.
+    ///
+    /// The results are in the same order as the messages.
+    #[cfg(feature = "parallel")]
+    pub fn decode_many(&self, messages: &[&[u8]]) -> Vec<Result<SerializedMessage>> {
+        use rayon::prelude::*;
+
+        messages.par_iter()
+            .map(|bytes| self.decode(bytes))
+            .collect()
+    }
+
+    /// Decodes several messages concatenated back to back without framing.
+    ///
+    /// Serializers write fields in ascending order, so the only candidate
+    /// boundaries are where a field number is lower than the one before it;
+    /// messages are never split anywhere else. A candidate is kept only if
+    /// the bytes on either side of it, up to the neighbouring boundaries, both
+    /// decode; otherwise the bytes around it are decoded as one message.
+    /// Consecutive messages whose field numbers only ascend, such as several
+    /// messages of a single field, cannot be told apart and are decoded as one.
+    ///
+    /// Returns the offset and decoded message of each.
+    pub fn decode_concatenated(&self, bytes: &[u8]) -> Result<Vec<(usize, SerializedMessage)>> {
+        let mut candidates = vec![];
+        let (mut index, mut previous) = (0usize, 0u64);
+        while index < bytes.len() {
+            let Some((field, end)) = classify::read_field(bytes, index) else {
+                return Err(format!("Invalid message; no valid field at offset {index}.").into());
+            };
+
+            if index > 0 && field < previous {
+                candidates.push(index);
+            }
+            (index, previous) = (end, field);
+        }
+        if bytes.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut messages = vec![];
+        let mut start = 0;
+        // The message from `start` up to the next candidate, if already decoded.
+        let mut current = None;
+        for (i, candidate) in candidates.iter().copied().enumerate() {
+            let end = candidates.get(i + 1).copied().unwrap_or(bytes.len());
+            let before = current.take().map_or_else(|| self.decode(&bytes[start..candidate]), Ok);
+            if let (Ok(before), Ok(after)) = (before, self.decode(&bytes[candidate..end])) {
+                messages.push((start, before));
+                (start, current) = (candidate, Some(after));
+            }
+        }
+
+        let last = current.map_or_else(|| self.decode(&bytes[start..]), Ok)?;
+        messages.push((start, last));
+        Ok(messages)
+    }
+
+    /// Decodes a protobuf-encoded message into a `Value::Message`,
+    /// or a `Value::Summary` if it has more than `max_fields` fields.
+    pub fn decode_value(&self, bytes: &[u8]) -> Result<Value> {
+        self.decode_inner(bytes, None, None, &[])
+    }
+
+    /// Decodes a protobuf-encoded message, recording timings in the profiler.
+    pub fn decode_profiled(&self, bytes: &[u8], profiler: &mut Profiler) -> Result<SerializedMessage> {
+        let start = Instant::now();
+        let result = self.decode_inner(bytes, Some(profiler), None, &[]);
+        profiler.record_message(bytes.len(), start.elapsed());
+
+        into_message(result?)
+    }
+
+    /// Decodes a protobuf-encoded message, reporting progress to the callback
+    /// and stopping with `Error::Cancelled` once the token is cancelled.
+    ///
+    /// Progress is reported every `PROGRESS_INTERVAL` fields, and once more
+    /// when decoding succeeds. The token is checked before every field, so
+    /// cancelling takes effect promptly even within nested messages.
+    pub fn decode_with_progress<F>(&self, bytes: &[u8], token: &CancellationToken, mut callback: F) -> Result<SerializedMessage>
+    where
+        F: FnMut(Progress)
+    {
+        let mut monitor = Monitor {
+            token,
+            callback: &mut callback,
+            progress: Progress { total_bytes: bytes.len(), ..Progress::default() }
+        };
+        let message = into_message(self.decode_inner(bytes, None, Some(&mut monitor), &[])?)?;
+
+        let progress = Progress { bytes_processed: bytes.len(), ..monitor.progress };
+        callback(progress);
+        Ok(message)
+    }
+
+    /// Decodes a message at the given path, optionally recording timings and reporting progress.
+    fn decode_inner(
+        &self,
+        bytes: &[u8],
+        mut profiler: Option<&mut Profiler>,
+        mut monitor: Option<&mut Monitor>,
+        path: &[u32]
+    ) -> Result<Value> {
+        #[cfg(feature = "trace")]
+        let _span = tracing::debug_span!("decode", bytes = bytes.len()).entered();
+
+        // Interpreted payloads were already transformed by their parent.
+        let transformed = match &self.config.transform {
+            Some(transform) if path.is_empty() => transform.0.transform(path, bytes),
+            _ => None
+        };
+        let bytes = transformed.as_deref().unwrap_or(bytes);
+
+        let field = path.last().copied().unwrap_or(0);
+        let tracked = (self.config.interpreter.is_some() || self.config.transform.is_some()).then(|| path.to_vec());
+        let mut stack = vec![Frame::new(bytes, 0, field, path.len(), tracked, self.config.preserve_order)];
+
+        loop {
+            let Some(frame) = stack.last_mut() else {
+                unreachable!("The root frame is never popped without returning.");
+            };
+
+            // Events of this step, and the spans of messages it starts, belong to the message.
+            #[cfg(feature = "trace")]
+            let _entered = frame.span.clone().entered();
+
+            if let Some(monitor) = monitor.as_deref_mut() {
+                if monitor.token.is_cancelled() {
+                    return Err(Error::Cancelled);
+                }
+                // Offsets within interpreted payloads restart at zero, so they never move progress back.
+                monitor.progress.bytes_processed = monitor.progress.bytes_processed.max(frame.offset + frame.index);
+            }
+
+            // The message is complete; hand it to its parent.
+            if frame.index >= frame.bytes.len() {
+                let frame = stack.pop().unwrap();
+                let (field, bytes, depth) = (frame.field, frame.bytes, frame.depth);
+                let value = frame.into_value();
+
+                match (stack.last_mut(), value) {
+                    (Some(parent), value) => parent.insert_nested(field, bytes, Some(value), &self.config, &mut profiler),
+                    (None, Value::Message(mut message)) if self.config.fold_maps && depth == 0 => {
+                        fold_maps(&mut message);
+                        return Ok(Value::Message(message));
+                    }
+                    (None, value) => return Ok(value)
+                }
+
+                continue;
+            }
+
+            let start = profiler.is_some().then(Instant::now);
+            let step = frame.step(&self.config);
+
+            if let (Some(profiler), Some(start)) = (profiler.as_deref_mut(), start) {
+                let wire_type = match &step {
+                    Ok(Step::Done(wire_type)) => Some(*wire_type),
+                    Ok(Step::Nested(..) | Step::Interpreted(..)) => Some(u32::from(WireType::LengthDelimited) as u8),
+                    Err(_) => None
+                };
+                profiler.record_field(wire_type, frame.depth, start.elapsed());
+            }
+
+            if let (Some(monitor), Ok(_)) = (monitor.as_deref_mut(), &step) {
+                monitor.progress.fields_emitted += 1;
+                if monitor.progress.fields_emitted % PROGRESS_INTERVAL == 0 {
+                    (monitor.callback)(monitor.progress);
+                }
+            }
+
+            if let Some(max_fields) = self.config.max_fields {
+                if !frame.summarized && frame.counts.iter().sum::<usize>() > max_fields {
+                    #[cfg(feature = "trace")]
+                    tracing::debug!(max_fields, "summarizing the message, which has too many fields");
+
+                    // Stop building the tree; only count the remaining fields.
+                    frame.summarized = true;
+                    frame.message = SerializedMessage::new();
+                }
+            }
+
+            match step {
+                Ok(Step::Done(_)) => {}
+                // Fields of summarized messages are only counted.
+                Ok(Step::Nested(..) | Step::Interpreted(..)) if frame.summarized => {}
+                Ok(Step::Nested(field, bytes)) => {
+                    if frame.depth < self.config.max_depth {
+                        // The payload ends where the next field of its parent starts.
+                        let offset = frame.offset + frame.index - bytes.len();
+                        let (depth, path) = (frame.depth + 1, frame.child_path(field));
+                        stack.push(Frame::new(bytes, offset, field, depth, path, self.config.preserve_order));
+                    } else {
+                        #[cfg(feature = "trace")]
+                        tracing::debug!(field, max_depth = self.config.max_depth, "not decoding a nested message beyond the maximum depth");
+
+                        frame.insert_nested(field, bytes, None, &self.config, &mut profiler);
+                    }
+                }
+                Ok(Step::Interpreted(field, bytes)) => {
+                    // The payload is owned, so it is decoded separately rather than on the stack.
+                    let message = match frame.child_path(field) {
+                        Some(path) if frame.depth < self.config.max_depth => {
+                            match self.decode_inner(&bytes, profiler.as_deref_mut(), monitor.as_deref_mut(), &path) {
+                                Ok(message) => Some(message),
+                                Err(Error::Cancelled) => return Err(Error::Cancelled),
+                                Err(_) => None
+                            }
+                        }
+                        _ => None
+                    };
+                    frame.insert_nested(field, &bytes, message, &self.config, &mut profiler);
+                }
+                Err(error) => {
+                    #[cfg(feature = "trace")]
+                    tracing::debug!(offset = frame.index, %error, "not a message");
+
+                    // A nested message failing to decode is not an error;
+                    // the field is kept as a string or bytes instead.
+                    let frame = stack.pop().unwrap();
+                    match stack.last_mut() {
+                        Some(parent) => parent.insert_nested(frame.field, frame.bytes, None, &self.config, &mut profiler),
+                        None if self.config.strict => {
+                            return Err(match classify::validate(bytes) {
+                                Err(desync) => desync.into(),
+                                Ok(()) => error
+                            });
+                        }
+                        None => return Err(error)
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Frame<'a> {
+    /// Creates a new frame for the given message bytes.
+    fn new(bytes: &'a [u8], offset: usize, field: u32, depth: usize, path: Option<FieldPath>, ordered: bool) -> Self {
+        Self {
+            bytes,
+            offset,
+            index: 0,
+            message: if ordered { SerializedMessage::new_ordered() } else { SerializedMessage::new() },
+            field,
+            depth,
+            path,
+            counts: [0; 8],
+            summarized: false,
+            #[cfg(feature = "trace")]
+            span: tracing::debug_span!("message", field, depth, bytes = bytes.len())
+        }
+    }
+
+    /// Converts the decoded message into a value.
+    fn into_value(self) -> Value {
+        if !self.summarized {
+            return Value::Message(self.message);
+        }
+
+        let type_histogram = self.counts.iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(wire_type, count)| {
+                let name = match WireType::try_from(wire_type as u8) {
+                    Ok(wire_type) => wire_type.name().to_string(),
+                    Err(()) => format!("wire type {wire_type}")
+                };
+                (name, *count)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        Value::Summary { field_count: self.counts.iter().sum(), type_histogram }
+    }
+
+    /// Returns the path of a field of this message, if paths are tracked.
+    fn child_path(&self, field: u32) -> Option<FieldPath> {
+        self.path.as_ref().map(|path| [path.as_slice(), &[field]].concat())
+    }
+
+    /// Interprets a field with the interpreter, if one is set and overrides the field.
+    fn interpret(&mut self, config: &DecodeConfig, field: u32, wire_type: WireType, bytes: &[u8]) -> Option<Step<'a>> {
+        let (interpreter, path) = (config.interpreter.as_ref()?, self.child_path(field)?);
+        match interpreter.0.interpret(&path, wire_type, bytes)? {
+            Interpretation::Value(value) => {
+                self.insert(field, value);
+                Some(Step::Done(wire_type as u8))
+            }
+            Interpretation::Payload(bytes) => Some(Step::Interpreted(field, bytes))
+        }
+    }
+
+    /// Inserts a decoded field, unless the message is being summarized.
+    fn insert(&mut self, field: u32, value: Value) {
+        if !self.summarized {
+            self.message.insert(field, value);
+        }
+    }
+
+    /// Decodes the next field of the message.
+    fn step(&mut self, config: &DecodeConfig) -> Result<Step<'a>> {
+        let bytes = self.bytes;
+        let bytes_len = bytes.len();
+
+        let Some((tag, tag_len)) = VarInt::read_at(bytes, self.index) else {
+            return Err("Invalid message; malformed field header.".into());
+        };
+        let (field_number, wire_type) = (field_number(tag, config)?, (tag & 0b111) as u8);
+
+        #[cfg(feature = "trace")]
+        tracing::trace!(field = field_number, wire_type, offset = self.index, "field");
+
+        let header = match (WireType::try_from(wire_type), &config.wire_type_handler) {
+            (Ok(WireType::StartGroup | WireType::EndGroup) | Err(()), Some(handler)) => {
+                self.index += tag_len;
+                self.counts[wire_type as usize] += 1;
+
+                return self.step_custom(handler, field_number, wire_type);
+            }
+            (Ok(wire_type), _) => Header::new(field_number, wire_type),
+            (Err(()), None) => return Err("Invalid wire type specified".into())
+        };
+
+        self.index += tag_len;
+        self.counts[wire_type as usize] += 1;
+
+        match header.wire_type {
+            WireType::VarInt => {
+                let Some((varint, len)) = VarInt::try_decode_at(bytes, self.index) else {
+                    return Err("Invalid message; malformed varint field.".into());
+                };
+                let raw = &bytes[self.index..self.index + len];
+                self.index += len;
+
+                if let Some(step) = self.interpret(config, header.field_number, header.wire_type, raw) {
+                    return Ok(step);
+                }
+                self.insert(header.field_number, Value::VarInt(varint));
+            }
+            WireType::Fixed64 => {
+                let Some(bytes) = bytes.get(self.index..self.index.saturating_add(8)) else {
+                    return Err("Invalid message; not enough bytes for a fixed64 field.".into());
+                };
+                self.index += 8;
+
+                if let Some(step) = self.interpret(config, header.field_number, header.wire_type, bytes) {
+                    return Ok(step);
+                }
+                let value = f64::from_le_bytes(bytes.try_into()?);
+                self.insert(header.field_number, Value::Double(value));
+            }
+            WireType::LengthDelimited => {
+                let Some((data_len, varint_len)) = VarInt::read_at(bytes, self.index) else {
+                    return Err("Invalid message; malformed length of a length-delimited field.".into());
+                };
+                self.index += varint_len;
+
+                let data_len = usize::try_from(data_len).ok();
+                let Some(data_len) = data_len.filter(|len| *len <= config.max_field_bytes) else {
+                    return Err(format!(
+                        "Invalid message; field length exceeds the limit of {} bytes.",
+                        config.max_field_bytes
+                    ).into());
+                };
+
+                let end = self.index.checked_add(data_len).filter(|end| *end <= bytes_len);
+                let Some(end) = end else {
+                    return Err("Invalid message; not enough bytes for a length-delimited field.".into());
+                };
+
+                let bytes = &bytes[self.index..end];
+                self.index = end;
+
+                let transformed = match (&config.transform, self.child_path(header.field_number)) {
+                    (Some(transform), Some(path)) => transform.0.transform(&path, bytes),
+                    _ => None
+                };
+                if let Some(bytes) = transformed {
+                    let step = self.interpret(config, header.field_number, header.wire_type, &bytes);
+                    return Ok(step.unwrap_or(Step::Interpreted(header.field_number, bytes)));
+                }
+
+                if let Some(step) = self.interpret(config, header.field_number, header.wire_type, bytes) {
+                    return Ok(step);
+                }
+                return Ok(Step::Nested(header.field_number, bytes));
+            }
+            WireType::StartGroup => {
+                return Err("Start group wire type is not supported.".into());
+            }
+            WireType::EndGroup => {
+                return Err("End group wire type is not supported.".into());
+            }
+            WireType::Fixed32 => {
+                let Some(bytes) = bytes.get(self.index..self.index.saturating_add(4)) else {
+                    return Err("Invalid message; not enough bytes for a fixed32 field.".into());
+                };
+                self.index += 4;
+
+                if let Some(step) = self.interpret(config, header.field_number, header.wire_type, bytes) {
+                    return Ok(step);
+                }
+                let value = f32::from_le_bytes(bytes.try_into()?);
+                self.insert(header.field_number, Value::Float(value));
+            }
+        }
+
+        Ok(Step::Done(wire_type))
+    }
+
+    /// Decodes a field of a non-standard wire type using a custom handler.
+    fn step_custom(&mut self, handler: &WireTypeHandler, field: u32, wire_type: u8) -> Result<Step<'a>> {
+        let bytes = &self.bytes[self.index..];
+        let Some((value, len)) = (handler.0)(field, wire_type, bytes) else {
+            return Err(format!("Invalid message; field {field} of wire type {wire_type} was rejected by the handler.").into());
+        };
+
+        if len > bytes.len() {
+            return Err(format!("Invalid message; wire type {wire_type} handler consumed more bytes than are available.").into());
+        }
+
+        self.index += len;
+        self.insert(field, value);
+
+        Ok(Step::Done(wire_type))
+    }
+
+    /// Inserts a length-delimited field, given the result of decoding it as a message.
+    fn insert_nested(
+        &mut self, field: u32, bytes: &[u8], message: Option<Value>,
+        config: &DecodeConfig, profiler: &mut Option<&mut Profiler>
+    ) {
+        let start = profiler.is_some().then(Instant::now);
+        let string = config.strings.accept(bytes);
+
+        #[cfg(feature = "trace")]
+        tracing::debug!(
+            field,
+            bytes = bytes.len(),
+            utf8 = std::str::from_utf8(bytes).is_ok(),
+            string = string.is_some(),
+            message = message.is_some(),
+            decision = match (bytes.is_empty(), string.is_some(), message.is_some()) {
+                (true, ..) => "empty",
+                (false, false, false) => "bytes",
+                (false, true, false) => "string",
+                (false, false, true) => "message",
+                (false, true, true) => "string and message"
+            },
+            "interpreted a length-delimited field"
+        );
+
+        if bytes.is_empty() {
+            self.message.insert(field, config.empty.value());
+        } else if message.is_none() && string.is_none() {
+            self.message.insert(field, Value::Bytes(bytes.to_vec()));
+        } else {
+            if let Some(string) = string {
+                self.message.insert(field, Value::String(string.to_string()));
+            }
+            if let Some(message) = message {
+                self.message.insert(field, message);
+            }
+        }
+
+        if let (Some(profiler), Some(start)) = (profiler, start) {
+            profiler.record_heuristic(start.elapsed());
+        }
+    }
+}
+
+/// Reads and validates the field number of a tag.
+pub(crate) fn field_number(tag: u64, config: &DecodeConfig) -> Result<u32> {
+    let field = tag >> 3;
+    if config.strict && DesyncReason::of(field).is_some() {
+        return Err(format!("Invalid message; field number {field} is not allowed in strict mode.").into());
+    }
+
+    match u32::try_from(field) {
+        Ok(field) if config.tolerate_field_numbers || (1..=MAX_FIELD_NUMBER).contains(&field) => Ok(field),
+        _ => Err(Error::FieldNumber(field))
+    }
+}
+
+/// Unwraps a decoded root message, failing if it was summarized.
+fn into_message(value: Value) -> Result<SerializedMessage> {
+    match value {
+        Value::Message(message) => Ok(message),
+        Value::Summary { field_count, .. } => {
+            Err(format!("Message has {field_count} fields, exceeding the field limit.").into())
+        }
+        _ => unreachable!("Decoded messages are always messages or summaries.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use super::*;
+    use crate::{decode, ProtobufBytes};
+
+    /// A value which encodes and decodes back to itself.
+    ///
+    /// Bytes start with an invalid UTF-8 prefix which is also an invalid
+    /// message header, so they cannot be decoded as strings or messages.
+    fn leaf() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            any::<i64>().prop_map(|value| Value::VarInt(value.into())),
+            any::<f32>().prop_map(Value::Float),
+            any::<f64>().prop_map(Value::Double),
+            proptest::collection::vec(any::<u8>(), 0..16)
+                .prop_map(|bytes| Value::Bytes([&[0xff, 0x01][..], &bytes].concat()))
+        ]
+    }
+
+    /// A message of leaves, with possibly repeated fields.
+    fn message(value: impl Strategy<Value = Value>) -> impl Strategy<Value = SerializedMessage> {
+        proptest::collection::vec((1..1000u32, value), 0..8).prop_map(|fields| {
+            let mut message = SerializedMessage::new();
+            for (field, value) in fields {
+                message.insert(field, value);
+            }
+            message
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics(bytes in proptest::collection::vec(any::<u8>(), 0..256)) {
+            let _ = Decoder::default().decode(&bytes);
+            let _ = Decoder::new(DecodeConfig { strict: true, max_fields: Some(4), ..Default::default() })
+                .decode_value(&bytes);
+        }
+
+        #[test]
+        fn round_trip(message in message(prop_oneof![
+            leaf(),
+            // Nested messages contain invalid UTF-8, so they are not decoded as strings.
+            message(leaf()).prop_map(|mut message| {
+                message.insert(0x7fff, Value::Bytes(vec![0xff, 0x01]));
+                Value::Message(message)
+            })
+        ])) {
+            prop_assert_eq!(Decoder::default().decode(&message.encode()).unwrap(), message);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn decode_many() {
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+
+        let results = Decoder::default().decode_many(&[&bytes, &[0x0a, 0x05], &bytes]);
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok() && results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap().get(1), Some(Value::VarInt(150.into())));
+    }
+
+    #[test]
+    fn interpreter() {
+        let mut secret = vec![];
+        secret.write_str(1, "Hello, World!");
+
+        let mut inner = vec![];
+        inner.write_bytes(3, &secret.iter().map(|byte| byte ^ 0x55).collect::<Vec<_>>());
+
+        let mut bytes = vec![];
+        bytes.write_u32(1, 7);
+        bytes.write_bytes(2, &inner);
+
+        // Field 2.3 is an obfuscated message, and field 1 is an enum.
+        let interpreter = |path: &[u32], _: WireType, bytes: &[u8]| match path {
+            [2, 3] => Some(Interpretation::Payload(bytes.iter().map(|byte| byte ^ 0x55).collect())),
+            [1] => Some(Interpretation::Value(Value::String("LOGIN".to_string()))),
+            _ => None
+        };
+
+        let decoder = Decoder::new(DecodeConfig { interpreter: Some(Interpreter::new(interpreter)), ..Default::default() });
+        let message = decoder.decode(&bytes).unwrap();
+        assert_eq!(message.get(1), Some(Value::String("LOGIN".to_string())));
+        assert_eq!(message.get_path(&[2, 3]).unwrap().as_message().unwrap(), decode(&secret).unwrap());
+    }
+
+    #[test]
+    fn transform() {
+        let mut secret = vec![];
+        secret.write_str(1, "Hello, World!");
+        let obfuscate = |bytes: &[u8]| bytes.iter().map(|byte| byte ^ 0x55).collect::<Vec<_>>();
+
+        let mut bytes = vec![];
+        bytes.write_bytes(2, &obfuscate(&secret));
+
+        // The whole message is obfuscated, as is field 2 within it.
+        let transform = move |path: &[u32], bytes: &[u8]| matches!(path, [] | [2]).then(|| obfuscate(bytes));
+        let decoder = Decoder::new(DecodeConfig { transform: Some(Transformer::new(transform)), ..Default::default() });
+        let message = decoder.decode(&bytes.iter().map(|byte| byte ^ 0x55).collect::<Vec<_>>()).unwrap();
+        assert_eq!(message.get(2).unwrap().as_message().unwrap(), decode(&secret).unwrap());
+    }
+
+    #[test]
+    fn strings() {
+        let mut bytes = vec![];
+        bytes.write_bytes(1, b"\x01\x02\x03\x7f");
+        bytes.write_str(2, "caf\u{e9}\n");
+        bytes.write_str(3, "ok");
+
+        let message = decode(&bytes).unwrap();
+        assert!(matches!(message.get(1), Some(Value::String(_))));
+
+        let policy = StringPolicy { min_len: 3, ..StringPolicy::text() };
+        let message = Decoder::new(DecodeConfig { strings: policy.clone(), ..Default::default() }).decode(&bytes).unwrap();
+        assert_eq!(message.get(1), Some(Value::Bytes(vec![0x01, 0x02, 0x03, 0x7f])));
+        assert_eq!(message.get(2), Some(Value::String("caf\u{e9}\n".to_string())));
+        assert_eq!(message.get(3), Some(Value::Bytes(b"ok".to_vec())));
+
+        let ascii = StringPolicy { require_ascii: true, ..policy };
+        assert_eq!(ascii.accept("caf\u{e9}".as_bytes()), None);
+        assert_eq!(ascii.accept(b"cafe"), Some("cafe"));
+    }
+
+    #[test]
+    fn empty() {
+        let message = decode(&[0x0a, 0x00]).unwrap();
+        assert_eq!(message.get(1), Some(Value::Empty));
+        assert_eq!(decode(&message.encode()).unwrap(), message);
+
+        let decoder = Decoder::new(DecodeConfig { empty: EmptyPayload::String, ..Default::default() });
+        assert_eq!(decoder.decode(&[0x0a, 0x00]).unwrap().get(1), Some(Value::String(String::new())));
+        let decoder = Decoder::new(DecodeConfig { empty: EmptyPayload::Message, ..Default::default() });
+        assert_eq!(decoder.decode(&[0x0a, 0x00]).unwrap().get(1), Some(Value::Message(SerializedMessage::new())));
+    }
+
+    #[test]
+    fn text() {
+        let decoder = Decoder::default();
+        let expected = decoder.decode(&[0x08, 0x96, 0x01]).unwrap();
+        assert_eq!(decoder.decode_hex("08 96\n01").unwrap(), expected);
+        assert_eq!(decoder.decode_base64("CJYB").unwrap(), expected);
+        assert_eq!(decoder.decode_base64("CJ\nYB\n").unwrap(), expected);
+
+        assert!(decoder.decode_hex("08 9").is_err());
+        assert!(decoder.decode_hex("0x089601").is_err());
+        assert!(decoder.decode_base64("CJ*B").is_err());
+    }
+
+    #[test]
+    fn concatenated() {
+        let mut first = vec![];
+        first.write_u32(1, 7);
+        first.write_str(2, "a");
+        first.write_str(2, "b");
+        let mut second = vec![];
+        second.write_u32(1, 8);
+        second.write_u32(3, 9);
+
+        let bytes = [first.as_slice(), &second, &first].concat();
+        let messages = Decoder::default().decode_concatenated(&bytes).unwrap();
+        let offsets: Vec<usize> = messages.iter().map(|(offset, _)| *offset).collect();
+        assert_eq!(offsets, [0, first.len(), first.len() + second.len()]);
+        assert_eq!(messages[1].1, decode(&second).unwrap());
+
+        assert!(Decoder::default().decode_concatenated(&[]).unwrap().is_empty());
+        assert!(Decoder::default().decode_concatenated(&[0x08]).is_err());
+
+        // A boundary before a message which fails to decode is not kept.
+        let mut reserved = vec![];
+        reserved.write_u32(1, 8);
+        reserved.write_u32(19_000, 9);
+        let strict = Decoder::new(DecodeConfig { strict: true, ..Default::default() });
+        assert_eq!(strict.decode_concatenated(&[first.as_slice(), &second].concat()).unwrap().len(), 2);
+        assert!(strict.decode_concatenated(&[first.as_slice(), &reserved].concat()).is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        // A varint truncated by the end of the buffer.
+        assert!(decode(&[0x08, 0x96]).is_err());
+        // A field length of 2^32 + 1, which must not wrap around to 1.
+        assert!(decode(&[0x0a, 0x81, 0x80, 0x80, 0x80, 0x10, 0x01]).is_err());
+        // Field number zero, which is only accepted when tolerated.
+        assert!(matches!(decode(&[0x00, 0x01]), Err(Error::FieldNumber(0))));
+        let tolerant = Decoder::new(DecodeConfig { tolerate_field_numbers: true, ..Default::default() });
+        assert_eq!(tolerant.decode(&[0x00, 0x01]).unwrap().get(0), Some(Value::VarInt(1.into())));
+        // A field number of 2^29, one above the maximum.
+        assert!(matches!(decode(&[0x80, 0x80, 0x80, 0x80, 0x10, 0x01]), Err(Error::FieldNumber(0x2000_0000))));
+        // An 11 byte varint.
+        assert!(decode(&[[0x08].as_slice(), &[0x80; 10], &[0x00]].concat()).is_err());
+    }
+
+    #[test]
+    fn limits() {
+        // A message nested far deeper than the default limit.
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+        for _ in 0..1000 {
+            let mut outer = vec![];
+            outer.write_bytes(1, &bytes);
+            bytes = outer;
+        }
+
+        let decoder = Decoder::new(DecodeConfig { max_depth: 1, ..Default::default() });
+        let message = decoder.decode(&bytes).unwrap();
+        let Some(Value::Message(nested)) = message.get(1) else {
+            panic!("Expected a nested message.");
+        };
+        assert!(matches!(nested.get(1), Some(Value::Bytes(_))));
+        assert!(Decoder::default().decode(&bytes).is_ok());
+
+        let decoder = Decoder::new(DecodeConfig { max_field_bytes: 16, ..Default::default() });
+        assert!(decoder.decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn summarize() {
+        let mut inner = vec![];
+        for _ in 0..1000 {
+            inner.write_u32(1, 1);
+        }
+        inner.write_f32(2, 1.5);
+
+        let mut bytes = vec![];
+        bytes.write_bytes(1, &inner);
+
+        let decoder = Decoder::new(DecodeConfig { max_fields: Some(100), ..Default::default() });
+        let Some(Value::Summary { field_count, type_histogram }) = decoder.decode(&bytes).unwrap().get(1) else {
+            panic!("Expected a summary.");
+        };
+        assert_eq!(field_count, 1001);
+        assert_eq!(type_histogram["varint"], 1000);
+        assert_eq!(type_histogram["fixed32"], 1);
+
+        assert!(decoder.decode(&inner).is_err());
+        assert!(matches!(decoder.decode_value(&inner), Ok(Value::Summary { .. })));
+    }
+
+    #[test]
+    fn strict() {
+        let mut bytes = vec![0xc8, 0xa3, 0x09, 0x01];
+        bytes.write_u32(1, 150);
+        bytes.write_str(2, "Hello, World!");
+
+        assert!(Decoder::default().decode(&bytes).is_ok());
+
+        let decoder = Decoder::new(DecodeConfig { strict: true, ..Default::default() });
+        let Err(Error::Desync(desync)) = decoder.decode(&bytes) else {
+            panic!("Expected a desync.");
+        };
+        assert_eq!(desync.suggested_start, Some(4));
+    }
+
+    #[test]
+    fn custom_wire_type() {
+        // Field 1 with wire type 3, followed by a single byte value.
+        let mut bytes = vec![0x0b, 0x2a];
+        bytes.write_u32(2, 150);
+        assert!(Decoder::default().decode(&bytes).is_err());
+
+        let handler = WireTypeHandler::new(|_, wire_type, bytes| match wire_type {
+            3 => Some((Value::VarInt((*bytes.first()? as i32).into()), 1)),
+            _ => None
+        });
+        let decoder = Decoder::new(DecodeConfig { wire_type_handler: Some(handler), ..Default::default() });
+
+        let message = decoder.decode(&bytes).unwrap();
+        assert_eq!(message.get(1).and_then(|value| value.as_u32()), Some(42));
+        assert_eq!(message.get(2).and_then(|value| value.as_u32()), Some(150));
+    }
+
+    #[test]
+    fn profile() {
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+        bytes.write_str(2, "Hello, World!");
+
+        let mut profiler = Profiler::new();
+        for _ in 0..10 {
+            Decoder::default().decode_profiled(&bytes, &mut profiler).unwrap();
+        }
+
+        assert_eq!(profiler.total().count, 10);
+        assert_eq!(profiler.wire_type(2).count, 10);
+        assert_eq!(profiler.heuristics().count, 10);
+        assert_eq!(profiler.bytes(), bytes.len() * 10);
+    }
+
+    #[test]
+    fn progress() {
+        let fields = [0x08, 0x01].repeat(3000);
+        let mut bytes = vec![];
+        bytes.write_bytes(1, &fields);
+        bytes.extend(&fields);
+
+        let mut reports = vec![];
+        let message = Decoder::default()
+            .decode_with_progress(&bytes, &CancellationToken::new(), |progress| reports.push(progress))
+            .unwrap();
+        assert_eq!(message, decode(&bytes).unwrap());
+
+        // The outer field, the fields nested in it, and those following it.
+        assert_eq!(reports.iter().map(|progress| progress.fields_emitted).collect::<Vec<_>>(), [4096, 6001]);
+        assert!(reports[0].bytes_processed > fields.len() && reports[0].bytes_processed < bytes.len());
+        assert_eq!(reports[1], Progress { bytes_processed: bytes.len(), total_bytes: bytes.len(), fields_emitted: 6001 });
+
+        // Cancelling stops decoding before the next field.
+        let token = CancellationToken::new();
+        let result = Decoder::default().decode_with_progress(&fields.repeat(2), &token, |_| token.cancel());
+        assert!(matches!(result, Err(Error::Cancelled)));
+    }
+
+    #[test]
+    fn progress_within_interpreted_payloads() {
+        let fields = [0x08, 0x01].repeat(5000);
+        let mut bytes = vec![];
+        bytes.write_bytes(1, &fields.iter().map(|byte| byte ^ 0x55).collect::<Vec<_>>());
+
+        let interpreter = |path: &[u32], _: WireType, bytes: &[u8]| match path {
+            [1] => Some(Interpretation::Payload(bytes.iter().map(|byte| byte ^ 0x55).collect())),
+            _ => None
+        };
+        let decoder = Decoder::new(DecodeConfig { interpreter: Some(Interpreter::new(interpreter)), ..Default::default() });
+
+        // Fields of the payload are counted, and cancelling stops within it.
+        let mut reports = vec![];
+        decoder.decode_with_progress(&bytes, &CancellationToken::new(), |progress| reports.push(progress)).unwrap();
+        assert_eq!(reports.last().unwrap().fields_emitted, 5001);
+
+        let token = CancellationToken::new();
+        let result = decoder.decode_with_progress(&bytes, &token, |_| token.cancel());
+        assert!(matches!(result, Err(Error::Cancelled)));
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn trace() {
+        use std::sync::Mutex;
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        /// Records the decision of every event which has one.
+        #[derive(Default)]
+        struct Decisions(Mutex<Vec<String>>);
+
+        impl Visit for &Decisions {
+            fn record_str(&mut self, field: &Field, value: &str) {
+                if field.name() == "decision" {
+                    self.0.lock().unwrap().push(value.to_string());
+                }
+            }
+
+            fn record_debug(&mut self, _: &Field, _: &dyn fmt::Debug) {}
+        }
+
+        impl Subscriber for &'static Decisions {
+            fn enabled(&self, _: &Metadata<'_>) -> bool { true }
+            fn new_span(&self, _: &Attributes<'_>) -> Id { Id::from_u64(1) }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, event: &Event<'_>) { event.record(&mut &**self) }
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let mut bytes = vec![];
+        bytes.write_str(1, "hey");
+        bytes.write_bytes(2, &[0xff]);
+
+        let decisions: &'static Decisions = Box::leak(Box::default());
+        tracing::subscriber::with_default(decisions, || decode(&bytes).unwrap());
+        assert_eq!(*decisions.0.lock().unwrap(), ["string", "bytes"]);
+    }
+}
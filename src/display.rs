@@ -0,0 +1,194 @@
+use std::fmt::{self, Write};
+use crate::identifier::Identifier;
+use crate::names::Labels;
+use crate::wellknown::EpochUnit;
+use crate::{FieldPath, SerializedMessage, Value};
+
+/// The number of bytes shown in the hex preview of a bytes field.
+const BYTES_PREVIEW_LEN: usize = 16;
+
+/// Writes a message, indenting nested values if `indent` is set.
+///
+/// Fields and enum values are named using `labels`, tracking their paths in `path`.
+pub(crate) fn write_message(
+    f: &mut fmt::Formatter<'_>,
+    message: &SerializedMessage,
+    indent: Option<usize>,
+    labels: Labels<'_>,
+    path: &mut FieldPath
+) -> fmt::Result {
+    if message.iter().next().is_none() {
+        return f.write_str("{}");
+    }
+
+    f.write_char('{')?;
+    for (i, (field, value)) in message.iter().enumerate() {
+        match indent {
+            Some(indent) => write!(f, "\n{:width$}", "", width = (indent + 1) * 2)?,
+            None if i > 0 => f.write_str(", ")?,
+            None => {}
+        }
+
+        path.push(*field);
+        match labels.fields.and_then(|names| names.get(path)) {
+            Some(name) => write!(f, "{name}: ")?,
+            None => write!(f, "{field}: ")?
+        }
+        write_value(f, value, indent.map(|indent| indent + 1), labels, path)?;
+        path.pop();
+    }
+
+    match indent {
+        Some(indent) => write!(f, "\n{:width$}}}", "", width = indent * 2),
+        None => f.write_char('}')
+    }
+}
+
+/// Writes a value, indenting nested values if `indent` is set.
+fn write_value(
+    f: &mut fmt::Formatter<'_>,
+    value: &Value,
+    indent: Option<usize>,
+    labels: Labels<'_>,
+    path: &mut FieldPath
+) -> fmt::Result {
+    match value {
+        Value::VarInt(varint) => {
+            if let Some(name) = labels.enums.and_then(|enums| enums.get(path, varint.as_i64())) {
+                return write!(f, "{name} ({varint})");
+            }
+            write!(f, "{varint}")?;
+            write_timestamp(f, varint.as_i64(), labels)
+        }
+        Value::Float(value) => write!(f, "{value}f"),
+        // Fixed64 timestamps decode as tiny doubles, so their bits are written instead.
+        Value::Double(value) if labels.timestamps && EpochUnit::detect(value.to_bits() as i64).is_some() => {
+            write!(f, "{}", value.to_bits())?;
+            write_timestamp(f, value.to_bits() as i64, labels)
+        }
+        Value::Double(value) => write!(f, "{value}"),
+        Value::String(value) => {
+            write!(f, "{value:?}")?;
+            write_identifier(f, value.as_bytes(), labels)
+        }
+        Value::Bytes(bytes) => {
+            write!(f, "<{} bytes:", bytes.len())?;
+            for byte in bytes.iter().take(BYTES_PREVIEW_LEN) {
+                write!(f, " {byte:02x}")?;
+            }
+            if bytes.len() > BYTES_PREVIEW_LEN {
+                write!(f, " …")?;
+            }
+            f.write_char('>')?;
+            write_identifier(f, bytes, labels)
+        }
+        Value::Empty => f.write_str("<empty>"),
+        Value::Message(message) => write_message(f, message, indent, labels, path),
+        Value::Repeated(values) => {
+            f.write_char('[')?;
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write_value(f, value, indent, labels, path)?;
+            }
+            f.write_char(']')
+        }
+        Value::Summary { field_count, type_histogram } => {
+            write!(f, "<summary of {field_count} fields:")?;
+            for (i, (wire_type, count)) in type_histogram.iter().enumerate() {
+                let separator = if i > 0 { "," } else { "" };
+                write!(f, "{separator} {count} {wire_type}")?;
+            }
+            f.write_char('>')
+        }
+        Value::Map(entries) => {
+            f.write_str("map{")?;
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{key}: ")?;
+                write_value(f, value, indent, labels, path)?;
+            }
+            f.write_char('}')
+        }
+    }
+}
+
+/// Writes a comment with the date of a value which looks like a Unix timestamp, if enabled.
+fn write_timestamp(f: &mut fmt::Formatter<'_>, value: i64, labels: Labels<'_>) -> fmt::Result {
+    match EpochUnit::detect(value).filter(|_| labels.timestamps) {
+        Some(unit) => write!(f, " /* {} */", unit.timestamp(value)),
+        None => Ok(())
+    }
+}
+
+/// Writes a comment with the identifier or address a payload looks like, if enabled.
+fn write_identifier(f: &mut fmt::Formatter<'_>, bytes: &[u8], labels: Labels<'_>) -> fmt::Result {
+    match Identifier::detect(bytes).filter(|_| labels.identifiers) {
+        Some(identifier) => write!(f, " /* {} {identifier} */", identifier.kind()),
+        None => Ok(())
+    }
+}
+
+/// Messages are formatted on one line, or indented with `{:#}`.
+///
+/// Long bytes fields are truncated to a short hex preview.
+impl fmt::Display for SerializedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let indent = f.alternate().then_some(0);
+        write_message(f, self, indent, Labels::default(), &mut vec![])
+    }
+}
+
+/// Values are formatted on one line, or indented with `{:#}`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let indent = f.alternate().then_some(0);
+        write_value(f, self, indent, Labels::default(), &mut vec![])
+    }
+}
+
+impl fmt::Debug for SerializedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Message(message) => fmt::Debug::fmt(message, f),
+            Value::Repeated(values) => f.debug_list().entries(values).finish(),
+            Value::Map(entries) => f.debug_map().entries(entries).finish(),
+            value => fmt::Display::fmt(value, f)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn display() {
+        let mut inner = vec![];
+        inner.write_u32(1, 150);
+        inner.write_f32(2, 1.5);
+
+        let mut bytes = vec![];
+        bytes.write_i32(1, -5);
+        bytes.write_str(2, "Hello, World!");
+        bytes.write_bytes(3, &inner);
+        bytes.write_bytes(3, &inner);
+        bytes.write_bytes(4, &[0xff; 20]);
+
+        let message = decode(&bytes).unwrap();
+        assert_eq!(
+            message.to_string(),
+            r#"{1: -5, 2: "Hello, World!", 3: [{1: 150, 2: 1.5f}, {1: 150, 2: 1.5f}], 4: <20 bytes: ff ff ff ff ff ff ff ff ff ff ff ff ff ff ff ff …>}"#
+        );
+        assert_eq!(format!("{:#}", message.get(3).unwrap()), "[{\n  1: 150\n  2: 1.5f\n}, {\n  1: 150\n  2: 1.5f\n}]");
+    }
+}
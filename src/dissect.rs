@@ -0,0 +1,202 @@
+use serde::{Deserialize, Serialize};
+use crate::names::{EnumNameMap, FieldNameMap};
+use crate::{encoding, DecodeConfig, Decoder, Spanned, StringPolicy, Value};
+
+/// The version of the output of `dissect_to_json`.
+///
+/// It only changes when existing output changes meaning or is removed;
+/// new keys may be added to objects without changing it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Options of `dissect_to_json`, given as a JSON object.
+///
+/// Every key is optional, and unknown keys are rejected, as in
+/// `{"max_depth": 8, "strings": {"min_printable_ratio": 0.9}, "names": {"1": "id"}}`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DissectOptions {
+    /// See `DecodeConfig::max_depth`.
+    pub max_depth: Option<usize>,
+    /// See `DecodeConfig::max_field_bytes`.
+    pub max_field_bytes: Option<usize>,
+    /// See `DecodeConfig::strings`; missing keys keep their defaults.
+    pub strings: Option<StringPolicy>,
+    /// Names for fields, keyed by path; see `FieldNameMap`.
+    pub names: FieldNameMap,
+    /// Names for enum values, keyed by path and value; see `EnumNameMap`.
+    pub enums: EnumNameMap
+}
+
+impl DissectOptions {
+    /// Returns the decoder these options configure.
+    pub fn decoder(&self) -> Decoder {
+        let mut config = DecodeConfig::default();
+        if let Some(max_depth) = self.max_depth {
+            config.max_depth = max_depth;
+        }
+        if let Some(max_field_bytes) = self.max_field_bytes {
+            config.max_field_bytes = max_field_bytes;
+        }
+        if let Some(strings) = &self.strings {
+            config.strings = strings.clone();
+        }
+
+        Decoder::new(config)
+    }
+}
+
+/// The output of `dissect_to_json`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Dissection {
+    /// Always `SCHEMA_VERSION`.
+    pub version: u32,
+    /// The length of the message, in bytes.
+    pub length: usize,
+    pub fields: Vec<Field>
+}
+
+/// A single occurrence of a field, in wire order.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Field {
+    pub number: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The offset of the field header in the message.
+    pub offset: usize,
+    /// The length of the field, including its header and any length prefix.
+    pub length: usize,
+    #[serde(flatten)]
+    pub value: FieldValue
+}
+
+/// The value of a field, tagged with its `type`.
+///
+/// 64-bit integers are written as strings, since JavaScript numbers
+/// cannot hold them exactly.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FieldValue {
+    /// A varint, as unsigned and as two's complement signed decimals.
+    Varint {
+        value: String,
+        signed: String,
+        #[serde(rename = "enum", skip_serializing_if = "Option::is_none")]
+        enum_name: Option<String>
+    },
+    /// A fixed32 value, as a float and as its bits.
+    ///
+    /// Non-finite floats are written as `null`.
+    Fixed32 { value: f32, bits: u32 },
+    /// A fixed64 value, as a double and as its bits in decimal.
+    Fixed64 { value: f64, bits: String },
+    String { value: String },
+    /// Bytes in standard, padded Base64.
+    Bytes { value: String },
+    /// A nested message, and the payload as a string if it is also valid text.
+    Message {
+        fields: Vec<Field>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        text: Option<String>
+    },
+    /// An empty length-delimited payload.
+    Empty
+}
+
+/// Dissects a message into JSON with a stable, versioned schema, for embedding
+/// in instrumentation scripts such as Frida hooks.
+///
+/// `options` is a JSON object of `DissectOptions`; an empty string uses the
+/// defaults. The output is a `Dissection`, as in `{"version": 1, "length": 3,
+/// "fields": [{"number": 1, "offset": 0, "length": 3, "type": "varint",
+/// "value": "150", "signed": "150"}]}`. Errors are described in plain text.
+pub fn dissect_to_json(bytes: &[u8], options: &str) -> Result<String, String> {
+    let options: DissectOptions = match options.trim() {
+        "" => DissectOptions::default(),
+        options => serde_json::from_str(options).map_err(|error| format!("Invalid options; {error}"))?
+    };
+
+    let spans = options.decoder().decode_with_spans(bytes).map_err(|error| error.to_string())?;
+    let dissection = Dissection {
+        version: SCHEMA_VERSION,
+        length: bytes.len(),
+        fields: spans.iter().map(|spanned| field(spanned, bytes, &options, &mut vec![])).collect()
+    };
+
+    serde_json::to_string(&dissection).map_err(|error| error.to_string())
+}
+
+/// Converts a located field, labelling it with the names in the options.
+fn field(spanned: &Spanned<Value>, bytes: &[u8], options: &DissectOptions, path: &mut Vec<u32>) -> Field {
+    path.push(spanned.field);
+
+    let payload = &bytes[spanned.payload.clone()];
+    let value = match &spanned.value {
+        Value::VarInt(varint) => FieldValue::Varint {
+            value: (varint.as_i64() as u64).to_string(),
+            signed: varint.as_i64().to_string(),
+            enum_name: options.enums.get(path, varint.as_i64()).map(str::to_string)
+        },
+        Value::Float(value) => FieldValue::Fixed32 { value: *value, bits: value.to_bits() },
+        Value::Double(value) => FieldValue::Fixed64 { value: *value, bits: value.to_bits().to_string() },
+        Value::String(value) => FieldValue::String { value: value.clone() },
+        Value::Empty => FieldValue::Empty,
+        // A message, possibly also decoded as a string.
+        Value::Message(_) | Value::Repeated(_) => FieldValue::Message {
+            fields: spanned.children.iter().map(|child| field(child, bytes, options, path)).collect(),
+            text: match &spanned.value {
+                Value::Repeated(values) => values.iter().find_map(Value::as_string),
+                _ => None
+            }
+        },
+        // Bytes, and anything else the decoder may be configured to produce, as the raw payload.
+        _ => FieldValue::Bytes { value: encoding::base64_encode(payload) }
+    };
+
+    let field = Field {
+        number: spanned.field,
+        name: options.names.get(path).map(str::to_string),
+        offset: spanned.header.start,
+        length: spanned.span().len(),
+        value
+    };
+
+    path.pop();
+    field
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dissect() {
+        let bytes = [
+            0x08, 0x96, 0x01,
+            0x12, 0x02, 0x08, 0x07,
+            0x1a, 0x02, 0xff, 0x01,
+            0x20, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01
+        ];
+        let options = r#"{"names": {"1": "id", "2.1": "state"}, "enums": {"2.1": {"7": "DONE"}}}"#;
+
+        let json: serde_json::Value = serde_json::from_str(&dissect_to_json(&bytes, options).unwrap()).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["fields"][0], serde_json::json!({
+            "number": 1, "name": "id", "offset": 0, "length": 3, "type": "varint", "value": "150", "signed": "150"
+        }));
+        assert_eq!(json["fields"][1]["type"], "message");
+        assert_eq!(json["fields"][1]["text"], "\u{8}\u{7}");
+        assert_eq!(json["fields"][1]["fields"][0]["name"], "state");
+        assert_eq!(json["fields"][1]["fields"][0]["enum"], "DONE");
+        assert_eq!(json["fields"][1]["fields"][0]["offset"], 5);
+        assert_eq!(json["fields"][2], serde_json::json!({"number": 3, "offset": 7, "length": 4, "type": "bytes", "value": "/wE="}));
+        assert_eq!(json["fields"][3]["value"], "18446744073709551615");
+        assert_eq!(json["fields"][3]["signed"], "-1");
+
+        // The string policy rejects the control characters, so the payload is only a message.
+        let strict = dissect_to_json(&bytes, r#"{"strings": {"min_printable_ratio": 1.0}}"#).unwrap();
+        assert!(!strict.contains("\"text\""));
+
+        assert!(dissect_to_json(&bytes, r#"{"depth": 1}"#).unwrap_err().starts_with("Invalid options; unknown field `depth`"));
+        assert!(dissect_to_json(&[0x08], "").is_err());
+    }
+}
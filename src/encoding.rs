@@ -0,0 +1,104 @@
+use base64::Engine;
+use base64::engine::GeneralPurpose;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD};
+use crate::Result;
+
+/// A Base64 alphabet and padding convention.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Base64 {
+    /// The standard alphabet, padded with `=`; used by protobuf JSON.
+    Standard,
+    StandardNoPad,
+    /// The URL-safe alphabet, with `-` and `_` in place of `+` and `/`.
+    UrlSafe,
+    UrlSafeNoPad
+}
+
+impl Base64 {
+    /// Every alphabet and padding convention.
+    pub const ALL: [Base64; 4] = [Base64::Standard, Base64::StandardNoPad, Base64::UrlSafe, Base64::UrlSafeNoPad];
+
+    /// Encodes bytes as Base64 text.
+    pub fn encode(self, bytes: &[u8]) -> String {
+        self.engine().encode(bytes)
+    }
+
+    /// Decodes Base64 text, failing if it is not in this alphabet and convention.
+    pub fn decode(self, text: &str) -> Result<Vec<u8>> {
+        Ok(self.engine().decode(text)?)
+    }
+
+    /// Returns the engine implementing the alphabet.
+    fn engine(self) -> &'static GeneralPurpose {
+        match self {
+            Base64::Standard => &STANDARD,
+            Base64::StandardNoPad => &STANDARD_NO_PAD,
+            Base64::UrlSafe => &URL_SAFE,
+            Base64::UrlSafeNoPad => &URL_SAFE_NO_PAD
+        }
+    }
+}
+
+/// Encodes bytes as standard, padded Base64 text.
+pub fn base64_encode(bytes: &[u8]) -> String {
+    Base64::Standard.encode(bytes)
+}
+
+/// Decodes standard, padded Base64 text.
+pub fn base64_decode(text: &str) -> Result<Vec<u8>> {
+    Base64::Standard.decode(text)
+}
+
+/// Decodes Base64 text in any of the common alphabets, with or without padding.
+///
+/// Whitespace, such as line breaks in wrapped text, is ignored.
+pub fn base64_decode_any(text: &str) -> Result<Vec<u8>> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    Base64::ALL.iter()
+        .find_map(|alphabet| alphabet.decode(&text).ok())
+        .ok_or_else(|| "Invalid Base64; the text is not in any known alphabet.".into())
+}
+
+/// Encodes bytes as lowercase hex text, without separators.
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Decodes hex text, such as `08 96 01` or `089601`.
+///
+/// Whitespace is ignored, including between the digits of a byte.
+pub fn hex_decode(text: &str) -> Result<Vec<u8>> {
+    let digits = text.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16).map(|digit| digit as u8).ok_or(c))
+        .collect::<std::result::Result<Vec<u8>, char>>()
+        .map_err(|c| format!("Invalid hex; {c:?} is not a hex digit."))?;
+
+    if !digits.len().is_multiple_of(2) {
+        return Err("Invalid hex; the text has an odd number of digits.".into());
+    }
+
+    Ok(digits.chunks(2).map(|pair| (pair[0] << 4) | pair[1]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings() {
+        let bytes = [0xfb, 0xff, 0x01];
+        assert_eq!(base64_encode(&bytes), "+/8B");
+        assert_eq!(Base64::UrlSafe.encode(&bytes), "-_8B");
+        assert_eq!(Base64::StandardNoPad.encode(&[0xff]), "/w");
+
+        assert_eq!(base64_decode("+/8B").unwrap(), bytes);
+        assert!(base64_decode("-_8B").is_err());
+        assert!(base64_decode("not base64!").is_err());
+        assert_eq!(base64_decode_any("-_8\nB").unwrap(), bytes);
+
+        assert_eq!(hex_encode(&bytes), "fbff01");
+        assert_eq!(hex_decode("FB ff\t01").unwrap(), bytes);
+        assert!(hex_decode("fbf").is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use std::array::TryFromSliceError;
+use std::{error, fmt, io};
+use crate::{ConversionError, Desync};
+
+/// A result using the crate's error type.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// An error from any of the crate's subsystems.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The input is malformed.
+    Invalid(String),
+    /// A field number is zero or above `MAX_FIELD_NUMBER`;
+    /// see `DecodeConfig::tolerate_field_numbers`.
+    FieldNumber(u64),
+    /// The input is misaligned with the message; see `DecodeConfig::strict`.
+    Desync(Desync),
+    /// A value is not of the requested type.
+    Conversion(ConversionError),
+    /// Reading or writing failed.
+    Io(io::Error),
+    /// Decoding was cancelled through a `CancellationToken`.
+    Cancelled,
+    /// A value cannot be converted to or from YAML.
+    #[cfg(feature = "yaml")]
+    Yaml(serde_yaml::Error),
+    /// A value cannot be converted to TOML.
+    #[cfg(feature = "toml")]
+    TomlSerialize(toml::ser::Error),
+    /// A TOML document is not valid.
+    #[cfg(feature = "toml")]
+    TomlDeserialize(toml::de::Error),
+    /// A capture database query failed.
+    #[cfg(feature = "storage")]
+    Sqlite(rusqlite::Error),
+    /// A value cannot be converted to or from JSON.
+    #[cfg(feature = "json")]
+    Json(serde_json::Error)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Invalid(message) => f.write_str(message),
+            Error::FieldNumber(field) => write!(f, "Invalid message; field number {field} is out of range."),
+            Error::Desync(error) => error.fmt(f),
+            Error::Conversion(error) => error.fmt(f),
+            Error::Io(error) => error.fmt(f),
+            Error::Cancelled => f.write_str("Decoding was cancelled."),
+            #[cfg(feature = "yaml")]
+            Error::Yaml(error) => error.fmt(f),
+            #[cfg(feature = "toml")]
+            Error::TomlSerialize(error) => error.fmt(f),
+            #[cfg(feature = "toml")]
+            Error::TomlDeserialize(error) => error.fmt(f),
+            #[cfg(feature = "storage")]
+            Error::Sqlite(error) => error.fmt(f),
+            #[cfg(feature = "json")]
+            Error::Json(error) => error.fmt(f)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Invalid(_) | Error::FieldNumber(_) | Error::Cancelled => None,
+            Error::Desync(error) => Some(error),
+            Error::Conversion(error) => Some(error),
+            Error::Io(error) => Some(error),
+            #[cfg(feature = "yaml")]
+            Error::Yaml(error) => Some(error),
+            #[cfg(feature = "toml")]
+            Error::TomlSerialize(error) => Some(error),
+            #[cfg(feature = "toml")]
+            Error::TomlDeserialize(error) => Some(error),
+            #[cfg(feature = "storage")]
+            Error::Sqlite(error) => Some(error),
+            #[cfg(feature = "json")]
+            Error::Json(error) => Some(error)
+        }
+    }
+}
+
+/// Errors raised while transcoding with serde; see `transcode`.
+impl serde::ser::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Error::Invalid(message.to_string())
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Error::Invalid(message.to_string())
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::Invalid(message.to_string())
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Invalid(message)
+    }
+}
+
+impl From<TryFromSliceError> for Error {
+    fn from(error: TryFromSliceError) -> Self {
+        Error::Invalid(error.to_string())
+    }
+}
+
+impl From<base64::DecodeError> for Error {
+    fn from(error: base64::DecodeError) -> Self {
+        Error::Invalid(error.to_string())
+    }
+}
+
+impl From<Desync> for Error {
+    fn from(error: Desync) -> Self {
+        Error::Desync(error)
+    }
+}
+
+impl From<ConversionError> for Error {
+    fn from(error: ConversionError) -> Self {
+        Error::Conversion(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+#[cfg(feature = "yaml")]
+impl From<serde_yaml::Error> for Error {
+    fn from(error: serde_yaml::Error) -> Self {
+        Error::Yaml(error)
+    }
+}
+
+#[cfg(feature = "toml")]
+impl From<toml::ser::Error> for Error {
+    fn from(error: toml::ser::Error) -> Self {
+        Error::TomlSerialize(error)
+    }
+}
+
+#[cfg(feature = "toml")]
+impl From<toml::de::Error> for Error {
+    fn from(error: toml::de::Error) -> Self {
+        Error::TomlDeserialize(error)
+    }
+}
+
+#[cfg(feature = "storage")]
+impl From<rusqlite::Error> for Error {
+    fn from(error: rusqlite::Error) -> Self {
+        Error::Sqlite(error)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Json(error)
+    }
+}
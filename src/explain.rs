@@ -0,0 +1,310 @@
+use std::fmt;
+use serde::{Serialize, Serializer};
+use crate::classify::looks_like_protobuf;
+use crate::decoder::field_number;
+use crate::names::format_path;
+use crate::{Decoder, FieldPath, Result, SerializedMessage, VarInt, WireType};
+
+/// A way of reading the payload of a length-delimited field.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Candidate {
+    String,
+    Message,
+    /// Varints packed back to back, as a packed repeated integer field.
+    PackedVarInts,
+    /// Packed 4-byte values, such as `repeated float`.
+    PackedFixed32,
+    /// Packed 8-byte values, such as `repeated double`.
+    PackedFixed64
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Candidate::String => "string",
+            Candidate::Message => "message",
+            Candidate::PackedVarInts => "packed varints",
+            Candidate::PackedFixed32 => "packed fixed32",
+            Candidate::PackedFixed64 => "packed fixed64"
+        })
+    }
+}
+
+/// An attempt to read a payload as one candidate.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Attempt {
+    pub candidate: Candidate,
+    /// Whether the payload is valid as the candidate.
+    pub succeeded: bool,
+    /// How plausible a successful reading is, from `0.0` to `1.0`, if the candidate is scored.
+    ///
+    /// Messages are scored by `looks_like_protobuf`, and packed varints
+    /// by the share of them which are minimally encoded, as encoders write them.
+    pub score: Option<f32>,
+    /// Why the attempt succeeded or failed.
+    pub reason: String
+}
+
+/// How a single field was dissected.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FieldExplanation {
+    pub path: FieldPath,
+    /// The offset of the field header in the outermost message.
+    pub offset: usize,
+    #[serde(serialize_with = "wire_type_name")]
+    pub wire_type: WireType,
+    /// The length of the value, excluding the header and any length prefix.
+    pub len: usize,
+    /// The readings tried for a length-delimited payload; empty for other wire types.
+    pub attempts: Vec<Attempt>,
+    /// The kinds of value the field was decoded as; see `Value::kind`.
+    ///
+    /// A payload which is both a string and a message is decoded as both.
+    pub chosen: Vec<&'static str>,
+    /// Why the field was decoded as it was.
+    pub reason: String
+}
+
+/// An explanation of how every field of a message was dissected.
+///
+/// Renders as text with `Display`, or as JSON with `serde`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DissectionReport {
+    /// The decoded message.
+    pub message: SerializedMessage,
+    /// Every field, nested fields following their parent, in wire order.
+    pub fields: Vec<FieldExplanation>
+}
+
+impl fmt::Display for DissectionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for field in &self.fields {
+            let indent = "  ".repeat(field.path.len() - 1);
+            writeln!(
+                f, "{indent}{} @ {} ({}, {} bytes): {}",
+                format_path(&field.path), field.offset, field.wire_type.name(), field.len, field.chosen.join(" and ")
+            )?;
+
+            for attempt in &field.attempts {
+                let sign = if attempt.succeeded { '+' } else { '-' };
+                match attempt.score {
+                    Some(score) => writeln!(f, "{indent}  {sign} {} ({score:.2}): {}", attempt.candidate, attempt.reason)?,
+                    None => writeln!(f, "{indent}  {sign} {}: {}", attempt.candidate, attempt.reason)?
+                }
+            }
+            writeln!(f, "{indent}  = {}", field.reason)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Decoder {
+    /// Decodes a message, explaining which readings of each field were
+    /// attempted, which succeeded, and why the decoded one was chosen.
+    ///
+    /// Useful when the heuristics guess wrong. Interpreters, transforms,
+    /// and wire type handlers are not explained, and fields they affect
+    /// may be explained as the decoder would without them.
+    pub fn explain(&self, bytes: &[u8]) -> Result<DissectionReport> {
+        let message = self.decode(bytes)?;
+
+        let mut fields = vec![];
+        self.explain_message(bytes, 0, 0, &mut vec![], &mut fields)?;
+
+        Ok(DissectionReport { message, fields })
+    }
+
+    /// Explains the fields of a message, failing where decoding it would.
+    fn explain_message(
+        &self, bytes: &[u8], offset: usize, depth: usize,
+        path: &mut FieldPath, fields: &mut Vec<FieldExplanation>
+    ) -> Result<()> {
+        let config = self.config();
+        let mut index = 0usize;
+
+        while index < bytes.len() {
+            let start = index;
+            let Some((tag, tag_len)) = VarInt::read_at(bytes, index) else {
+                return Err("Invalid message; malformed field header.".into());
+            };
+            let field = field_number(tag, config)?;
+            let Ok(wire_type) = WireType::try_from((tag & 0b111) as u8) else {
+                return Err("Invalid wire type specified".into());
+            };
+            index += tag_len;
+
+            path.push(field);
+            let mut nested = vec![];
+            let (len, attempts, chosen, reason) = match wire_type {
+                WireType::VarInt => {
+                    let Some((_, len)) = VarInt::try_decode_at(bytes, index) else {
+                        return Err("Invalid message; malformed varint field.".into());
+                    };
+                    (len, vec![], vec!["varint"], "Varints are always decoded as integers.".to_string())
+                }
+                WireType::Fixed64 | WireType::Fixed32 => {
+                    let (len, kind) = if wire_type == WireType::Fixed64 { (8, "double") } else { (4, "float") };
+                    if bytes.len() - index < len {
+                        return Err(format!("Invalid message; not enough bytes for a {} field.", wire_type.name()).into());
+                    }
+                    (len, vec![], vec![kind], format!("Fixed-width values are always decoded as {kind}s."))
+                }
+                WireType::StartGroup => return Err("Start group wire type is not supported.".into()),
+                WireType::EndGroup => return Err("End group wire type is not supported.".into()),
+                WireType::LengthDelimited => {
+                    let Some((len, prefix)) = VarInt::read_at(bytes, index) else {
+                        return Err("Invalid message; malformed length of a length-delimited field.".into());
+                    };
+                    index += prefix;
+
+                    let Some(len) = usize::try_from(len).ok().filter(|len| *len <= config.max_field_bytes) else {
+                        return Err(format!(
+                            "Invalid message; field length exceeds the limit of {} bytes.",
+                            config.max_field_bytes
+                        ).into());
+                    };
+                    let Some(payload) = bytes.get(index..index.saturating_add(len)) else {
+                        return Err("Invalid message; not enough bytes for a length-delimited field.".into());
+                    };
+
+                    let (attempts, chosen, reason) = self.explain_payload(payload, offset + index, depth, path, &mut nested);
+                    (len, attempts, chosen, reason)
+                }
+            };
+            index += len;
+
+            fields.push(FieldExplanation { path: path.clone(), offset: offset + start, wire_type, len, attempts, chosen, reason });
+            fields.append(&mut nested);
+            path.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Attempts every reading of a length-delimited payload, and explains the chosen one.
+    ///
+    /// Fields of a payload decoded as a message are added to `nested`.
+    fn explain_payload(
+        &self, payload: &[u8], offset: usize, depth: usize,
+        path: &mut FieldPath, nested: &mut Vec<FieldExplanation>
+    ) -> (Vec<Attempt>, Vec<&'static str>, String) {
+        let config = self.config();
+        if payload.is_empty() {
+            let kind = config.empty.value().kind();
+            return (vec![], vec![kind], format!("An empty payload is valid as any type; it is decoded as {kind}, per `DecodeConfig::empty`."));
+        }
+
+        let string = match (std::str::from_utf8(payload), config.strings.accept(payload)) {
+            (Err(error), _) => attempt(Candidate::String, false, None, format!("Invalid UTF-8 at byte {}.", error.valid_up_to())),
+            (Ok(_), None) => attempt(Candidate::String, false, None, "Valid UTF-8, but rejected by `DecodeConfig::strings`.".to_string()),
+            (Ok(_), Some(_)) => attempt(Candidate::String, true, None, "Valid UTF-8.".to_string())
+        };
+
+        let message = if depth >= config.max_depth {
+            attempt(Candidate::Message, false, None, format!("Nested deeper than the maximum depth of {}.", config.max_depth))
+        } else {
+            let parent = path.len();
+            match self.explain_message(payload, offset, depth + 1, path, nested) {
+                Ok(()) => {
+                    let fields = nested.iter().filter(|field| field.path.len() == parent + 1).count();
+                    let score = looks_like_protobuf(payload).score();
+                    attempt(Candidate::Message, true, Some(score), format!("Decoded {fields} fields."))
+                }
+                Err(error) => {
+                    // The walk stopped partway through, leaving fields of the payload on the path.
+                    path.truncate(parent);
+                    nested.clear();
+                    attempt(Candidate::Message, false, None, error.to_string())
+                }
+            }
+        };
+
+        let (string_ok, message_ok) = (string.succeeded, message.succeeded);
+        let attempts = vec![string, message, packed_varints(payload), packed_fixed(payload, 4), packed_fixed(payload, 8)];
+
+        let (chosen, reason) = match (string_ok, message_ok) {
+            (true, true) => (vec!["string", "message"], "The payload is both a string and a message, so it is decoded as both."),
+            (true, false) => (vec!["string"], "The payload is a string, but not a message."),
+            (false, true) => (vec!["message"], "The payload is a message, but not a string."),
+            (false, false) => (
+                vec!["bytes"],
+                "The payload is neither a string nor a message, so it is kept as bytes; packed readings are never chosen without a schema."
+            )
+        };
+
+        (attempts, chosen, reason.to_string())
+    }
+}
+
+/// Creates an attempt.
+fn attempt(candidate: Candidate, succeeded: bool, score: Option<f32>, reason: String) -> Attempt {
+    Attempt { candidate, succeeded, score, reason }
+}
+
+/// Attempts to read a payload as packed varints.
+fn packed_varints(payload: &[u8]) -> Attempt {
+    let (mut index, mut count, mut minimal) = (0usize, 0usize, 0usize);
+    while index < payload.len() {
+        let Some((value, len)) = VarInt::read_at(payload, index) else {
+            return attempt(Candidate::PackedVarInts, false, None, format!("Malformed varint at byte {index}."));
+        };
+        if len == VarInt::minimal_length(value) {
+            minimal += 1;
+        }
+        (index, count) = (index + len, count + 1);
+    }
+
+    let score = minimal as f32 / count as f32;
+    attempt(Candidate::PackedVarInts, true, Some(score), format!("{count} varints, {minimal} minimally encoded."))
+}
+
+/// Attempts to read a payload as packed values of the given width.
+fn packed_fixed(payload: &[u8], width: usize) -> Attempt {
+    let candidate = if width == 4 { Candidate::PackedFixed32 } else { Candidate::PackedFixed64 };
+    if payload.len().is_multiple_of(width) {
+        attempt(candidate, true, None, format!("{} values.", payload.len() / width))
+    } else {
+        attempt(candidate, false, None, format!("The length is not a multiple of {width}."))
+    }
+}
+
+/// Serializes a wire type as its name.
+fn wire_type_name<S: Serializer>(wire_type: &WireType, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(wire_type.name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explain() {
+        let bytes = [
+            0x0a, 0x03, b'h', b'e', b'y',
+            0x12, 0x02, 0x08, 0x07,
+            0x1a, 0x02, 0xff, 0x01,
+            0x25, 0x00, 0x00, 0xc0, 0x3f
+        ];
+
+        let report = Decoder::default().explain(&bytes).unwrap();
+        let paths: Vec<_> = report.fields.iter().map(|field| field.path.clone()).collect();
+        assert_eq!(paths, [vec![1], vec![2], vec![2, 1], vec![3], vec![4]]);
+
+        let chosen: Vec<_> = report.fields.iter().map(|field| field.chosen.join("+")).collect();
+        assert_eq!(chosen, ["string", "string+message", "varint", "bytes", "float"]);
+        assert_eq!((report.fields[2].offset, report.fields[3].offset), (7, 9));
+
+        // `[0xff, 0x01]` is not UTF-8 nor a message, but is a single minimal varint.
+        let attempts = &report.fields[3].attempts;
+        assert_eq!(attempts[0].reason, "Invalid UTF-8 at byte 0.");
+        assert!(!attempts[1].succeeded);
+        assert_eq!((attempts[2].succeeded, attempts[2].score), (true, Some(1.0)));
+        assert!(report.to_string().contains("3 @ 9 (length-delimited, 2 bytes): bytes"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["fields"][1]["wire_type"], "length-delimited");
+        assert_eq!(json["fields"][1]["attempts"][1]["candidate"], "message");
+    }
+}
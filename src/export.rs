@@ -0,0 +1,168 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use crate::schema::{FieldType, Schema};
+
+impl Schema {
+    /// Exports the schema as a proto3 `.proto` file, ready to compile into generated code.
+    ///
+    /// Fields are named from their schema names, as set by `with_names`, or
+    /// `field_N` otherwise. Names are made into valid, unique identifiers, and
+    /// nested messages are declared inside their parent, named after their field
+    /// in PascalCase. Varints are exported as `int64`, and fixed-width fields as
+    /// `float`/`double`, as the decoder reads them.
+    ///
+    /// `package`: The package of the file, omitted if empty.
+    /// `message_name`: The name of the root message.
+    pub fn export_proto(&self, package: &str, message_name: &str) -> String {
+        let mut output = String::from("syntax = \"proto3\";\n\n");
+
+        let package: Vec<String> = package.split('.')
+            .filter(|part| !part.is_empty())
+            .map(|part| identifier(part).unwrap_or_else(|| "_".to_string()))
+            .collect();
+        if !package.is_empty() {
+            writeln!(output, "package {};\n", package.join(".")).unwrap();
+        }
+
+        let name = identifier(message_name).unwrap_or_else(|| "Message".to_string());
+        write_message(&mut output, self, &name, 0);
+        output
+    }
+}
+
+/// Writes a message definition, followed by its nested messages, to the output.
+fn write_message(output: &mut String, schema: &Schema, name: &str, depth: usize) {
+    let indent = "  ".repeat(depth);
+    writeln!(output, "{indent}message {name} {{").unwrap();
+
+    // Fields and nested messages share a scope, so their names must not collide.
+    let mut taken = BTreeSet::new();
+    let names: Vec<String> = schema.iter()
+        .map(|(field, field_schema)| {
+            let name = field_schema.name.as_deref().and_then(identifier);
+            unique(&mut taken, name.unwrap_or_else(|| format!("field_{field}")), *field)
+        })
+        .collect();
+    let nested: Vec<(String, &Schema)> = schema.iter()
+        .filter_map(|(field, field_schema)| match &field_schema.field_type {
+            FieldType::Message(nested) => {
+                let name = field_schema.name.as_deref().and_then(type_name);
+                Some((unique(&mut taken, name.unwrap_or_else(|| format!("Field{field}")), *field), nested))
+            }
+            _ => None
+        })
+        .collect();
+
+    let mut nested_names = nested.iter().map(|(name, _)| name);
+    for ((field, field_schema), name) in schema.iter().zip(&names) {
+        let label = if field_schema.repeated { "repeated " } else { "" };
+        let type_name = match &field_schema.field_type {
+            FieldType::VarInt => "int64",
+            FieldType::Fixed32 => "float",
+            FieldType::Fixed64 => "double",
+            FieldType::String => "string",
+            FieldType::Bytes => "bytes",
+            FieldType::Message(_) => nested_names.next().unwrap()
+        };
+
+        writeln!(output, "{indent}  {label}{type_name} {name} = {field};").unwrap();
+    }
+
+    for (name, nested) in &nested {
+        output.push('\n');
+        write_message(output, nested, name, depth + 1);
+    }
+
+    writeln!(output, "{indent}}}").unwrap();
+}
+
+/// Makes a name into an identifier by replacing invalid characters with underscores.
+///
+/// Returns `None` if the name has no letters or digits.
+fn identifier(name: &str) -> Option<String> {
+    if !name.chars().any(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+
+    let name: String = name.trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    Some(if name.starts_with(|c: char| c.is_ascii_digit()) { format!("_{name}") } else { name })
+}
+
+/// Makes a field name into a PascalCase message name, such as `UserInfo` for `user_info`.
+fn type_name(name: &str) -> Option<String> {
+    let name: String = name.split(|c: char| !c.is_ascii_alphanumeric())
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            chars.next().map(|first| first.to_ascii_uppercase()).into_iter().chain(chars)
+        })
+        .collect();
+
+    match name.chars().next() {
+        Some(first) if first.is_ascii_alphabetic() => Some(name),
+        _ => None
+    }
+}
+
+/// Returns the name, or the name suffixed with the field number if it is taken, and takes it.
+///
+/// Names are compared ignoring case and underscores, as protoc does for
+/// proto3 fields, whose JSON names would otherwise collide.
+fn unique(taken: &mut BTreeSet<String>, mut name: String, field: u32) -> String {
+    let key = |name: &str| name.replace('_', "").to_ascii_lowercase();
+    while taken.contains(&key(&name)) {
+        name = format!("{name}_{field}");
+    }
+
+    taken.insert(key(&name));
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::names::FieldNameMap;
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn export_proto() {
+        let mut inner = vec![];
+        inner.write_u32(1, 150);
+        inner.write_u32(2, 7);
+
+        let mut bytes = vec![];
+        bytes.write_u32(1, 5);
+        bytes.write_str(2, "Hello, World!");
+        bytes.write_bytes(3, &inner);
+        bytes.write_bytes(3, &inner);
+        bytes.write_f64(4, 1.5);
+
+        let mut names = FieldNameMap::new();
+        names.insert(&[1], "id");
+        names.insert(&[2], "display name");
+        names.insert(&[3], "line_item");
+        names.insert(&[3, 1], "id");
+        names.insert(&[3, 2], "id");
+        names.insert(&[4], "LineItem");
+
+        let schema = Schema::infer(&decode(&bytes).unwrap()).with_names(&names);
+        assert_eq!(schema.export_proto("acme.orders.v1", "Order"), "syntax = \"proto3\";
+
+package acme.orders.v1;
+
+message Order {
+  int64 id = 1;
+  string display_name = 2;
+  repeated LineItem_3 line_item = 3;
+  double LineItem_4 = 4;
+
+  message LineItem_3 {
+    int64 id = 1;
+    int64 id_2 = 2;
+  }
+}
+");
+    }
+}
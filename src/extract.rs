@@ -0,0 +1,232 @@
+use crate::{ConversionError, Result, SerializedMessage, Value, VarInt};
+
+/// A type which can be extracted from a decoded message.
+///
+/// Derive this with the `derive` feature, annotating each field with its number:
+///
+/// ```ignore
+/// #[derive(FromSerializedMessage)]
+/// struct Login {
+///     #[field(1)]
+///     user: String,
+///     #[field(2)]
+///     token: Vec<u8>
+/// }
+/// ```
+pub trait FromSerializedMessage: Sized {
+    /// Extracts the type from the fields of a message.
+    fn from_message(message: &SerializedMessage) -> Result<Self>;
+}
+
+/// A type which can be extracted from a single decoded value.
+///
+/// Varints convert to integers which can hold their value and to booleans,
+/// strings and bytes to `String` and `Vec<u8>`, and messages to derived types.
+/// Empty payloads convert to any of these length-delimited types.
+pub trait FromValue: Sized {
+    /// Extracts the type from a value.
+    fn from_value(value: &Value) -> Result<Self>;
+}
+
+/// A type which can be extracted from a field of a message, which may be missing.
+///
+/// Any `FromValue` type is a required field, `Option` an optional field,
+/// and `Vec` a repeated field. When a field occurs more than once, a
+/// required or optional field takes the last value which converts.
+pub trait FromField: Sized {
+    /// Extracts the type from the value of a field, if the field is present.
+    fn from_field(field: u32, value: Option<&Value>) -> Result<Self>;
+}
+
+impl<T: FromValue> FromField for T {
+    fn from_field(field: u32, value: Option<&Value>) -> Result<Self> {
+        Option::<T>::from_field(field, value)?
+            .ok_or_else(|| format!("Invalid message; field {field} is missing.").into())
+    }
+}
+
+impl<T: FromValue> FromField for Option<T> {
+    fn from_field(_: u32, value: Option<&Value>) -> Result<Self> {
+        match value {
+            None => Ok(None),
+            // Ambiguous payloads are both a string and a message,
+            // so the last value of the requested type is used.
+            Some(Value::Repeated(values)) => {
+                let mut error = None;
+                for value in values.iter().rev() {
+                    match T::from_value(value) {
+                        Ok(value) => return Ok(Some(value)),
+                        Err(e) => error = error.or(Some(e))
+                    }
+                }
+                error.map_or(Ok(None), Err)
+            }
+            Some(value) => T::from_value(value).map(Some)
+        }
+    }
+}
+
+impl<T: FromValue> FromField for Vec<T> {
+    fn from_field(_: u32, value: Option<&Value>) -> Result<Self> {
+        let values = match value {
+            None => return Ok(vec![]),
+            Some(Value::Repeated(values)) => values.as_slice(),
+            Some(value) => std::slice::from_ref(value)
+        };
+
+        // Values which do not convert, such as the other
+        // interpretation of an ambiguous payload, are skipped.
+        let mut converted = vec![];
+        let mut error = None;
+        for value in values {
+            match T::from_value(value) {
+                Ok(value) => converted.push(value),
+                Err(e) => error = error.or(Some(e))
+            }
+        }
+
+        match error {
+            Some(error) if converted.is_empty() => Err(error),
+            _ => Ok(converted)
+        }
+    }
+}
+
+macro_rules! from_varint {
+    ($($t:ty => $convert:expr),*) => {
+        $(
+            impl FromValue for $t {
+                fn from_value(value: &Value) -> Result<Self> {
+                    match value {
+                        Value::VarInt(varint) => $convert(varint)
+                            .ok_or_else(|| ConversionError::new(stringify!($t), value).into()),
+                        value => Err(ConversionError::new(stringify!($t), value).into())
+                    }
+                }
+            }
+        )*
+    };
+}
+
+from_varint!(
+    i32 => |varint: &VarInt| Some(varint.as_i32()),
+    i64 => |varint: &VarInt| Some(varint.as_i64()),
+    u32 => |varint: &VarInt| varint.as_u32(),
+    u64 => |varint: &VarInt| varint.as_u64(),
+    i8 => |varint: &VarInt| i8::try_from(varint.as_i64()).ok(),
+    i16 => |varint: &VarInt| i16::try_from(varint.as_i64()).ok(),
+    u16 => |varint: &VarInt| u16::try_from(varint.as_i64()).ok(),
+    bool => |varint: &VarInt| match varint.as_u64() {
+        Some(0) => Some(false),
+        Some(1) => Some(true),
+        _ => None
+    }
+);
+
+impl FromValue for f32 {
+    fn from_value(value: &Value) -> Result<Self> {
+        Ok(value.as_float().ok_or_else(|| ConversionError::new("f32", value))?)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self> {
+        Ok(value.as_double().ok_or_else(|| ConversionError::new("f64", value))?)
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Empty => Ok(String::new()),
+            value => Ok(value.as_string().ok_or_else(|| ConversionError::new("String", value))?)
+        }
+    }
+}
+
+/// Bytes fields which happen to be valid UTF-8 are decoded as strings.
+impl FromValue for Vec<u8> {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Bytes(bytes) => Ok(bytes.clone()),
+            Value::String(string) => Ok(string.as_bytes().to_vec()),
+            Value::Empty => Ok(vec![]),
+            value => Err(ConversionError::new("Vec<u8>", value).into())
+        }
+    }
+}
+
+impl FromValue for SerializedMessage {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Empty => Ok(SerializedMessage::new()),
+            value => Ok(value.as_message().ok_or_else(|| ConversionError::new("SerializedMessage", value))?)
+        }
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self> {
+        Ok(value.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, FieldAccess, ProtobufBytes};
+
+    #[test]
+    fn extract() {
+        let inner = [0x08, 0x01];
+
+        let mut bytes = vec![];
+        bytes.write_str(1, "alice");
+        bytes.write_bytes(2, &[0xde, 0xad]);
+        bytes.write_bytes(3, &inner);
+        bytes.write_u32(4, 7);
+        bytes.write_u32(4, 8);
+        let message = decode(&bytes).unwrap();
+
+        // The payload of field 3 is both a string and a message.
+        let nested = SerializedMessage::from_field(3, message.get_ref(3)).unwrap();
+        assert_eq!(nested, decode(&inner).unwrap());
+        assert_eq!(Vec::<u32>::from_field(4, message.get_ref(4)).unwrap(), [7, 8]);
+        assert_eq!(Option::<u32>::from_field(5, message.get_ref(5)).unwrap(), None);
+        assert!(u32::from_field(5, message.get_ref(5)).is_err());
+        assert!(u32::from_field(1, message.get_ref(1)).is_err());
+
+        #[cfg(feature = "derive")]
+        {
+            use crate::FromSerializedMessage;
+
+            #[derive(Debug, PartialEq, FromSerializedMessage)]
+            struct Flags {
+                #[field(1)]
+                enabled: bool
+            }
+
+            #[derive(Debug, PartialEq, FromSerializedMessage)]
+            struct Login {
+                #[field(1)]
+                user: String,
+                #[field(2)]
+                token: Vec<u8>,
+                #[field(3)]
+                flags: Flags,
+                #[field(4)]
+                attempts: Vec<u64>,
+                #[field(5)]
+                session: Option<u32>
+            }
+
+            assert_eq!(Login::from_message(&message).unwrap(), Login {
+                user: "alice".to_string(),
+                token: vec![0xde, 0xad],
+                flags: Flags { enabled: true },
+                attempts: vec![7, 8],
+                session: None
+            });
+        }
+    }
+}
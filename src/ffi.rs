@@ -0,0 +1,120 @@
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::{ptr, slice};
+use crate::dissect::dissect_to_json;
+use crate::{decode, Result};
+
+/// Returned when a function succeeds.
+pub const PROTOSHARK_OK: c_int = 0;
+
+/// Returned when a function fails.
+pub const PROTOSHARK_ERROR: c_int = -1;
+
+/// Decodes a protobuf message into JSON.
+///
+/// On success, `out` holds the JSON; on failure, the error message.
+/// Either must be released with `protoshark_free_string`.
+/// The matching C declarations are in `include/protoshark.h`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, or be null if `len` is 0.
+/// `out` must be a valid pointer to write a string pointer to.
+#[no_mangle]
+pub unsafe extern "C" fn protoshark_decode_json(data: *const u8, len: usize, out: *mut *mut c_char) -> c_int {
+    run(data, len, out, |bytes| Ok(serde_json::to_string(&decode(bytes)?)?))
+}
+
+/// Decodes a protobuf message into indented text.
+///
+/// # Safety
+///
+/// See `protoshark_decode_json`.
+#[no_mangle]
+pub unsafe extern "C" fn protoshark_decode_text(data: *const u8, len: usize, out: *mut *mut c_char) -> c_int {
+    run(data, len, out, |bytes| Ok(format!("{:#}", decode(bytes)?)))
+}
+
+/// Dissects a protobuf message into JSON with the versioned schema of `dissect_to_json`.
+///
+/// `options` is a JSON object of `DissectOptions`, or null for the defaults.
+///
+/// # Safety
+///
+/// See `protoshark_decode_json`. `options` must be null or a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn protoshark_dissect_json(
+    data: *const u8,
+    len: usize,
+    options: *const c_char,
+    out: *mut *mut c_char
+) -> c_int {
+    let options = if options.is_null() { Ok("") } else { CStr::from_ptr(options).to_str() };
+    run(data, len, out, |bytes| Ok(dissect_to_json(bytes, options.map_err(|_| "Invalid options; not UTF-8.")?)?))
+}
+
+/// Frees a string returned by this library. Null is ignored.
+///
+/// # Safety
+///
+/// `string` must have been returned by this library, and not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn protoshark_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Returns the version of the library, as a static string.
+#[no_mangle]
+pub extern "C" fn protoshark_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// Runs a function on the input bytes, writing its output or error to `out`.
+///
+/// Panics are caught, since unwinding into C is undefined behavior.
+unsafe fn run<F>(data: *const u8, len: usize, out: *mut *mut c_char, function: F) -> c_int
+where
+    F: FnOnce(&[u8]) -> Result<String>
+{
+    if out.is_null() || (data.is_null() && len > 0) {
+        return PROTOSHARK_ERROR;
+    }
+
+    let bytes = if len == 0 { &[] } else { slice::from_raw_parts(data, len) };
+    let (status, output) = match catch_unwind(AssertUnwindSafe(|| function(bytes))) {
+        Ok(Ok(output)) => (PROTOSHARK_OK, output),
+        Ok(Err(error)) => (PROTOSHARK_ERROR, error.to_string()),
+        Err(_) => (PROTOSHARK_ERROR, "Internal error; the decoder panicked.".to_string())
+    };
+
+    // Strings cannot contain nul bytes in C.
+    *out = CString::new(output.replace('\0', "\\0"))
+        .map_or(ptr::null_mut(), CString::into_raw);
+
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProtobufBytes;
+
+    #[test]
+    fn decode_json() {
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+
+        unsafe {
+            let mut out = ptr::null_mut();
+            assert_eq!(protoshark_decode_json(bytes.as_ptr(), bytes.len(), &mut out), PROTOSHARK_OK);
+            assert_eq!(CStr::from_ptr(out).to_str().unwrap(), r#"{"1":150}"#);
+            protoshark_free_string(out);
+
+            assert_eq!(protoshark_decode_json([0x0a, 0x05].as_ptr(), 2, &mut out), PROTOSHARK_ERROR);
+            assert!(!CStr::from_ptr(out).to_bytes().is_empty());
+            protoshark_free_string(out);
+        }
+    }
+}
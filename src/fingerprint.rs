@@ -0,0 +1,120 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use crate::{SerializedMessage, Value};
+
+/// The FNV-1a offset basis, so fingerprints are stable across runs and builds.
+const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// The FNV-1a prime.
+const PRIME: u64 = 0x0100_0000_01b3;
+
+/// Hashes the shape of a message, ignoring the values of its fields.
+///
+/// The hash covers field numbers, wire types and the shape of nested messages,
+/// so messages of the same type usually share a fingerprint. Strings and bytes
+/// are not told apart, and a field seen once has the same shape as when repeated,
+/// since both depend on the values. Fingerprints are stable, so they can be stored.
+pub fn fingerprint(message: &SerializedMessage) -> u64 {
+    let mut hash = OFFSET;
+    for (field, value) in message.iter() {
+        hash = mix(hash, *field as u64);
+        for shape in shapes(value) {
+            hash = mix(hash, shape);
+        }
+    }
+
+    hash
+}
+
+/// Returns the distinct shapes of a value, or of each value of a repeated field.
+fn shapes(value: &Value) -> BTreeSet<u64> {
+    match value {
+        Value::Repeated(values) => values.iter().flat_map(shapes).collect(),
+        // Tag each shape with its wire type.
+        Value::VarInt(_) => BTreeSet::from([0]),
+        Value::Double(_) => BTreeSet::from([1]),
+        Value::Float(_) => BTreeSet::from([5]),
+        Value::Message(message) => BTreeSet::from([mix(2, fingerprint(message))]),
+        Value::String(_) | Value::Bytes(_) | Value::Summary { .. } | Value::Map(_) | Value::Empty => BTreeSet::from([2])
+    }
+}
+
+/// Mixes a word into a hash, one byte at a time.
+fn mix(hash: u64, word: u64) -> u64 {
+    word.to_le_bytes().iter()
+        .fold(hash, |hash, byte| (hash ^ *byte as u64).wrapping_mul(PRIME))
+}
+
+/// A group of messages which share a fingerprint.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cluster {
+    pub fingerprint: u64,
+    /// The indices of the messages in the cluster, in order.
+    pub members: Vec<usize>
+}
+
+impl Cluster {
+    /// Returns the number of messages in the cluster.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Returns true if the cluster has no messages.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+}
+
+/// Groups messages by fingerprint, as probable instances of the same type.
+///
+/// Clusters are sorted from largest to smallest,
+/// and clusters of the same size by their first message.
+pub fn cluster<'a, I: IntoIterator<Item = &'a SerializedMessage>>(messages: I) -> Vec<Cluster> {
+    let mut clusters: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for (index, message) in messages.into_iter().enumerate() {
+        clusters.entry(fingerprint(message)).or_default().push(index);
+    }
+
+    let mut clusters: Vec<Cluster> = clusters.into_iter()
+        .map(|(fingerprint, members)| Cluster { fingerprint, members })
+        .collect();
+    clusters.sort_by_key(|cluster| (Reverse(cluster.len()), cluster.members[0]));
+
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn clusters() {
+        let login = |user: &str, attempts: u32| {
+            let mut inner = vec![];
+            for attempt in 0..attempts {
+                inner.write_u32(1, attempt + 300);
+            }
+
+            let mut bytes = vec![];
+            bytes.write_str(1, user);
+            bytes.write_bytes(2, &inner);
+            decode(&bytes).unwrap()
+        };
+        let ping = |time: f64| {
+            let mut bytes = vec![];
+            bytes.write_f64(1, time);
+            decode(&bytes).unwrap()
+        };
+
+        let messages = [login("alice", 1), ping(1.0), login("bob", 3), ping(2.5), login("carol", 2)];
+        assert_eq!(fingerprint(&messages[0]), fingerprint(&messages[2]));
+        assert_ne!(fingerprint(&messages[0]), fingerprint(&messages[1]));
+
+        let clusters = cluster(&messages);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].members, [0, 2, 4]);
+        assert_eq!(clusters[1].members, [1, 3]);
+        assert_eq!(clusters[1].fingerprint, fingerprint(&ping(0.0)));
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use crate::names::{format_path, parse_path};
+use crate::{FieldPath, Result, SerializedMessage, Value};
+
+/// Flattens nested messages into values keyed by dotted paths, like `"2.3.1"`.
+///
+/// Only fields holding a single, non-empty message are flattened; repeated
+/// fields, maps, and empty messages are kept whole, so `unflatten` restores
+/// the original message.
+pub fn flatten(message: &SerializedMessage) -> BTreeMap<String, Value> {
+    let mut flattened = BTreeMap::new();
+    flatten_into(message, &mut vec![], &mut flattened);
+
+    flattened
+}
+
+/// Rebuilds nested messages from values keyed by dotted paths.
+///
+/// Fails if a key is not a dotted path, or if a path is both
+/// a value and the parent of another path.
+pub fn unflatten(flattened: &BTreeMap<String, Value>) -> Result<SerializedMessage> {
+    let entries = flattened.iter()
+        .map(|(path, value)| Ok((parse_path(path)?, value)))
+        .collect::<Result<Vec<_>>>()?;
+
+    unflatten_from(entries.iter().map(|(path, value)| (path.as_slice(), *value)).collect(), &mut vec![])
+}
+
+/// Flattens the fields of a message, prefixed by its path.
+fn flatten_into(message: &SerializedMessage, path: &mut FieldPath, flattened: &mut BTreeMap<String, Value>) {
+    for (field, value) in message {
+        path.push(*field);
+        match value {
+            Value::Message(nested) if nested.iter().len() > 0 => flatten_into(nested, path, flattened),
+            value => {
+                flattened.insert(format_path(path), value.clone());
+            }
+        }
+        path.pop();
+    }
+}
+
+/// Builds a message from values keyed by paths relative to it.
+fn unflatten_from(entries: Vec<(&[u32], &Value)>, path: &mut FieldPath) -> Result<SerializedMessage> {
+    let mut fields: BTreeMap<u32, Vec<(&[u32], &Value)>> = BTreeMap::new();
+    for (path, value) in entries {
+        // Paths have at least one field, since parsing an empty path fails.
+        let (field, rest) = path.split_first().unwrap();
+        fields.entry(*field).or_default().push((rest, value));
+    }
+
+    let mut message = SerializedMessage::new();
+    for (field, entries) in fields {
+        path.push(field);
+        let value = match entries.as_slice() {
+            [([], value)] => (*value).clone(),
+            entries if entries.iter().all(|(rest, _)| !rest.is_empty()) => {
+                Value::Message(unflatten_from(entries.to_vec(), path)?)
+            }
+            _ => return Err(format!("Invalid field path '{}'; it has both a value and nested fields.", format_path(path)).into())
+        };
+        path.pop();
+        message.set_field(field, value);
+    }
+
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn flatten() {
+        let mut inner = vec![];
+        inner.write_u32(1, 7);
+
+        let mut middle = vec![];
+        middle.write_bytes(3, &inner);
+        middle.write_str(4, "hey");
+
+        let mut bytes = vec![];
+        bytes.write_bytes(2, &middle);
+        bytes.write_u32(5, 1);
+        bytes.write_u32(5, 2);
+        let message = decode(&bytes).unwrap();
+
+        let flattened = super::flatten(&message);
+        assert_eq!(flattened.keys().collect::<Vec<_>>(), ["2.3.1", "2.4", "5"]);
+        assert_eq!(flattened["2.3.1"], Value::VarInt(7i64.into()));
+        assert_eq!(unflatten(&flattened).unwrap(), message);
+
+        let conflicting = BTreeMap::from([
+            ("2".to_string(), Value::VarInt(1i64.into())),
+            ("2.1".to_string(), Value::VarInt(1i64.into()))
+        ]);
+        assert!(unflatten(&conflicting).is_err());
+    }
+}
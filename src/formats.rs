@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize};
+use crate::{encoding, MapKey, Result, SerializedMessage, Value, VarInt};
+
+/// YAML and TOML documents are tables of fields keyed by field number, whose
+/// values are tagged with their `type`, as in the output of `dissect_to_json`.
+///
+/// Varints and the bits of fixed64 values are written as decimal strings and
+/// bytes as Base64, so every value survives a round trip exactly. Repeated
+/// fields are lists of values. The signed reading of a varint and the value
+/// of a fixed-width field are only written for readability; parsing reads
+/// the unsigned varint and the bits.
+impl SerializedMessage {
+    /// Renders the message as YAML.
+    #[cfg(feature = "yaml")]
+    pub fn to_yaml(&self) -> Result<String> {
+        Ok(serde_yaml::to_string(&fields(self))?)
+    }
+
+    /// Parses a message from YAML, such as one rendered by `to_yaml`.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        message(serde_yaml::from_str(text)?)
+    }
+
+    /// Renders the message as TOML.
+    #[cfg(feature = "toml")]
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(&fields(self))?)
+    }
+
+    /// Parses a message from TOML, such as one rendered by `to_toml`.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(text: &str) -> Result<Self> {
+        message(toml::from_str(text)?)
+    }
+}
+
+type Fields = BTreeMap<FieldKey, Node>;
+
+/// The value of a field, or every value of a repeated field.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Node {
+    Repeated(Vec<Node>),
+    Value(Tagged)
+}
+
+/// A single value, tagged with its `type`.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Tagged {
+    /// A varint, as unsigned and as two's complement signed decimals.
+    Varint {
+        value: String,
+        #[serde(skip_deserializing)]
+        signed: String
+    },
+    /// A fixed32 value, as a float and as its bits.
+    Fixed32 {
+        #[serde(skip_deserializing)]
+        value: f32,
+        bits: u32
+    },
+    /// A fixed64 value, as a double and as its bits in decimal.
+    Fixed64 {
+        #[serde(skip_deserializing)]
+        value: f64,
+        bits: String
+    },
+    String { value: String },
+    /// Bytes in standard, padded Base64.
+    Bytes { value: String },
+    Message { fields: Fields },
+    Map { entries: Vec<Entry> },
+    Summary { field_count: usize, type_histogram: BTreeMap<String, usize> },
+    Empty
+}
+
+/// An entry of a map field.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    key: MapKey,
+    value: Node
+}
+
+/// Tags the fields of a message.
+fn fields(message: &SerializedMessage) -> Fields {
+    message.into_iter().map(|(field, value)| (FieldKey(*field), node(value))).collect()
+}
+
+/// Tags a value.
+fn node(value: &Value) -> Node {
+    Node::Value(match value {
+        Value::VarInt(varint) => Tagged::Varint {
+            value: (varint.as_i64() as u64).to_string(),
+            signed: varint.as_i64().to_string()
+        },
+        Value::Float(value) => Tagged::Fixed32 { value: *value, bits: value.to_bits() },
+        Value::Double(value) => Tagged::Fixed64 { value: *value, bits: value.to_bits().to_string() },
+        Value::String(value) => Tagged::String { value: value.clone() },
+        Value::Bytes(value) => Tagged::Bytes { value: encoding::base64_encode(value) },
+        Value::Message(message) => Tagged::Message { fields: fields(message) },
+        Value::Repeated(values) => return Node::Repeated(values.iter().map(node).collect()),
+        Value::Summary { field_count, type_histogram } => Tagged::Summary {
+            field_count: *field_count,
+            type_histogram: type_histogram.clone()
+        },
+        Value::Map(entries) => Tagged::Map {
+            entries: entries.iter().map(|(key, value)| Entry { key: key.clone(), value: node(value) }).collect()
+        },
+        Value::Empty => Tagged::Empty
+    })
+}
+
+/// Reads a message from its tagged fields.
+fn message(fields: Fields) -> Result<SerializedMessage> {
+    let mut message = SerializedMessage::new();
+    for (FieldKey(field), node) in fields {
+        message.set_field(field, value(node)?);
+    }
+
+    Ok(message)
+}
+
+/// Reads a value from its tagged form.
+fn value(node: Node) -> Result<Value> {
+    let tagged = match node {
+        Node::Repeated(nodes) => return Ok(Value::Repeated(nodes.into_iter().map(value).collect::<Result<_>>()?)),
+        Node::Value(tagged) => tagged
+    };
+
+    Ok(match tagged {
+        Tagged::Varint { value, .. } => {
+            let Ok(value) = value.parse::<u64>().or_else(|_| value.parse::<i64>().map(|value| value as u64)) else {
+                return Err(format!("Invalid message; '{value}' is not a varint.").into());
+            };
+            Value::VarInt(VarInt::decode(&VarInt::encode_minimal(value)))
+        }
+        Tagged::Fixed32 { bits, .. } => Value::Float(f32::from_bits(bits)),
+        Tagged::Fixed64 { bits, .. } => {
+            let Ok(bits) = bits.parse() else {
+                return Err(format!("Invalid message; '{bits}' are not the bits of a fixed64 value.").into());
+            };
+            Value::Double(f64::from_bits(bits))
+        }
+        Tagged::String { value } => Value::String(value),
+        Tagged::Bytes { value } => Value::Bytes(encoding::base64_decode(&value)?),
+        Tagged::Message { fields } => Value::Message(message(fields)?),
+        Tagged::Map { entries } => Value::Map(
+            entries.into_iter().map(|entry| Ok((entry.key, value(entry.value)?))).collect::<Result<_>>()?
+        ),
+        Tagged::Summary { field_count, type_histogram } => Value::Summary { field_count, type_histogram },
+        Tagged::Empty => Value::Empty
+    })
+}
+
+/// A field number, written as an integer or a string.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+struct FieldKey(u32);
+
+impl<'de> Deserialize<'de> for FieldKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(FieldKeyVisitor)
+    }
+}
+
+struct FieldKeyVisitor;
+
+impl Visitor<'_> for FieldKeyVisitor {
+    type Value = FieldKey;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a field number")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<FieldKey, E> {
+        u32::try_from(value).map(FieldKey).map_err(|_| E::custom(format!("invalid field number {value}")))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<FieldKey, E> {
+        u32::try_from(value).map(FieldKey).map_err(|_| E::custom(format!("invalid field number {value}")))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<FieldKey, E> {
+        value.parse().map(FieldKey).map_err(|_| E::custom(format!("invalid field number '{value}'")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn round_trip() {
+        let mut inner = vec![];
+        inner.write_i64(1, -5);
+
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+        bytes.write_str(2, "Hello, World!");
+        bytes.write_f64(3, 1.5);
+        bytes.write_bytes(4, &inner);
+        bytes.write_bytes(4, &inner);
+
+        let message = decode(&bytes).unwrap();
+
+        #[cfg(feature = "yaml")]
+        {
+            let yaml = message.to_yaml().unwrap();
+            assert!(yaml.starts_with("1:\n  type: varint\n  value: '150'\n  signed: '150'\n2:\n  type: string\n"));
+            assert_eq!(SerializedMessage::from_yaml(&yaml).unwrap(), message);
+            assert!(SerializedMessage::from_yaml("name: 1").is_err());
+            assert!(SerializedMessage::from_yaml("1: {type: varint, value: one}").is_err());
+        }
+
+        #[cfg(feature = "toml")]
+        {
+            let toml = message.to_toml().unwrap();
+            assert_eq!(SerializedMessage::from_toml(&toml).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn round_trip_exactly() {
+        let mut entry = SerializedMessage::new();
+        entry.set_field(1, Value::VarInt(VarInt::decode(&VarInt::encode_minimal(1 << 40))));
+
+        let mut message = SerializedMessage::new();
+        message.set_field(1, Value::VarInt(VarInt::decode(&VarInt::encode_minimal(u64::MAX - 5))));
+        message.set_field(2, Value::VarInt(VarInt::decode(&VarInt::encode_minimal(1 << 40))));
+        message.set_field(3, Value::Bytes(vec![0xff, 0x00, 0x61]));
+        message.set_field(4, Value::Float(1.1));
+        message.set_field(5, Value::Double(f64::from_bits(0x7ff8_0000_0000_0001)));
+        message.set_field(6, Value::Repeated(vec![Value::String("a".into()), Value::Message(entry.clone())]));
+        message.set_field(7, Value::Map(BTreeMap::from([(MapKey::Integer(-1), Value::Message(entry))])));
+        message.set_field(8, Value::Empty);
+
+        #[cfg(feature = "yaml")]
+        assert_eq!(SerializedMessage::from_yaml(&message.to_yaml().unwrap()).unwrap(), message);
+
+        #[cfg(feature = "toml")]
+        assert_eq!(SerializedMessage::from_toml(&message.to_toml().unwrap()).unwrap(), message);
+    }
+}
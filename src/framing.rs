@@ -0,0 +1,286 @@
+use crate::{decode, Result, SerializedMessage};
+
+/// The length of a KCP segment header.
+pub const KCP_HEADER_LEN: usize = 24;
+
+/// The KCP command of segments carrying data.
+pub const KCP_PUSH: u8 = 81;
+
+/// Describes the envelope wrapping each packet of a game protocol.
+///
+/// Packets are laid out as the head magic, the command id, the length of
+/// the packet head, the length of the body, the packet head, the body and
+/// the tail magic. Any of these may be omitted by leaving them empty or
+/// setting their size to zero, except for the body length.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Framing {
+    /// The bytes each packet starts with.
+    pub head_magic: Vec<u8>,
+    /// The size of the command id, in bytes.
+    pub cmd_id_size: usize,
+    /// The size of the packet head length, in bytes.
+    pub head_len_size: usize,
+    /// The size of the body length, in bytes.
+    pub body_len_size: usize,
+    /// Whether integers are big-endian, rather than little-endian.
+    pub big_endian: bool,
+    /// The bytes each packet ends with.
+    pub tail_magic: Vec<u8>
+}
+
+impl Framing {
+    /// A 2-byte command id followed by a 2-byte body length, big-endian.
+    pub fn cmd_length() -> Self {
+        Self {
+            head_magic: vec![],
+            cmd_id_size: 2,
+            head_len_size: 0,
+            body_len_size: 2,
+            big_endian: true,
+            tail_magic: vec![]
+        }
+    }
+
+    /// A magic-delimited envelope with a protobuf packet head, big-endian.
+    ///
+    /// Packets start with `0x4567`, followed by a 2-byte command id, a 2-byte
+    /// head length and a 4-byte body length, and end with `0x89AB`.
+    pub fn magic_envelope() -> Self {
+        Self {
+            head_magic: vec![0x45, 0x67],
+            cmd_id_size: 2,
+            head_len_size: 2,
+            body_len_size: 4,
+            big_endian: true,
+            tail_magic: vec![0x89, 0xab]
+        }
+    }
+
+    /// Reads a single packet from the start of the buffer.
+    ///
+    /// Returns the packet and the number of bytes it occupies.
+    pub fn read<'a>(&self, bytes: &'a [u8]) -> Result<(Packet<'a>, usize)> {
+        let mut index = 0usize;
+
+        if !bytes.starts_with(&self.head_magic) {
+            return Err("Invalid packet; missing the head magic.".into());
+        }
+        index += self.head_magic.len();
+
+        let cmd_id = match self.cmd_id_size {
+            0 => None,
+            size => Some(self.read_int(bytes, &mut index, size)? as u32)
+        };
+        let head_len = match self.head_len_size {
+            0 => 0,
+            size => self.read_int(bytes, &mut index, size)? as usize
+        };
+        let body_len = self.read_int(bytes, &mut index, self.body_len_size)? as usize;
+
+        let head = take(bytes, &mut index, head_len)?;
+        let body = take(bytes, &mut index, body_len)?;
+
+        if !bytes[index..].starts_with(&self.tail_magic) {
+            return Err("Invalid packet; missing the tail magic.".into());
+        }
+        index += self.tail_magic.len();
+
+        Ok((Packet { cmd_id, head, body }, index))
+    }
+
+    /// Splits a stream or datagram into its packets.
+    pub fn split<'a>(&self, bytes: &'a [u8]) -> Result<Vec<Packet<'a>>> {
+        let mut packets = vec![];
+        let mut index = 0usize;
+
+        while index < bytes.len() {
+            let (packet, len) = self.read(&bytes[index..])?;
+            index += len;
+
+            packets.push(packet);
+        }
+
+        Ok(packets)
+    }
+
+    /// Splits a stream or datagram into packets and decodes each of their bodies.
+    pub fn decode(&self, bytes: &[u8]) -> Result<Vec<SerializedMessage>> {
+        self.split(bytes)?
+            .iter()
+            .map(Packet::decode)
+            .collect()
+    }
+
+    /// Encodes a packet in this envelope.
+    pub fn encode(&self, packet: &Packet) -> Result<Vec<u8>> {
+        let mut bytes = self.head_magic.clone();
+        if self.cmd_id_size > 0 {
+            self.write_int(&mut bytes, packet.cmd_id.unwrap_or(0) as u64, self.cmd_id_size)?;
+        } else if packet.cmd_id.is_some() {
+            return Err("Invalid packet; the framing has no command id.".into());
+        }
+
+        if self.head_len_size > 0 {
+            self.write_int(&mut bytes, packet.head.len() as u64, self.head_len_size)?;
+        } else if !packet.head.is_empty() {
+            return Err("Invalid packet; the framing has no packet head.".into());
+        }
+
+        self.write_int(&mut bytes, packet.body.len() as u64, self.body_len_size)?;
+        bytes.extend(packet.head);
+        bytes.extend(packet.body);
+        bytes.extend(&self.tail_magic);
+        Ok(bytes)
+    }
+
+    fn read_int(&self, bytes: &[u8], index: &mut usize, size: usize) -> Result<u64> {
+        if !matches!(size, 1 | 2 | 4 | 8) {
+            return Err(format!("Invalid framing; unsupported integer size {size}.").into());
+        }
+
+        let mut buffer = [0u8; 8];
+        let int = take(bytes, index, size)?;
+        Ok(if self.big_endian {
+            buffer[8 - size..].copy_from_slice(int);
+            u64::from_be_bytes(buffer)
+        } else {
+            buffer[..size].copy_from_slice(int);
+            u64::from_le_bytes(buffer)
+        })
+    }
+
+    fn write_int(&self, bytes: &mut Vec<u8>, value: u64, size: usize) -> Result<()> {
+        if !matches!(size, 1 | 2 | 4 | 8) {
+            return Err(format!("Invalid framing; unsupported integer size {size}.").into());
+        }
+        if size < 8 && value >> (size * 8) != 0 {
+            return Err(format!("Invalid packet; {value} does not fit in {size} bytes.").into());
+        }
+
+        if self.big_endian {
+            bytes.extend(&value.to_be_bytes()[8 - size..]);
+        } else {
+            bytes.extend(&value.to_le_bytes()[..size]);
+        }
+        Ok(())
+    }
+}
+
+/// A single packet stripped of its envelope.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet<'a> {
+    /// The command id, if the framing has one.
+    pub cmd_id: Option<u32>,
+    /// The packet head, which is usually a protobuf message itself.
+    pub head: &'a [u8],
+    /// The body of the packet.
+    pub body: &'a [u8]
+}
+
+impl Packet<'_> {
+    /// Decodes the body of the packet as a protobuf message.
+    pub fn decode(&self) -> Result<SerializedMessage> {
+        decode(self.body)
+    }
+
+    /// Decodes the packet head as a protobuf message.
+    pub fn decode_head(&self) -> Result<SerializedMessage> {
+        decode(self.head)
+    }
+}
+
+/// A single segment of a KCP datagram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KcpSegment<'a> {
+    /// The conversation id.
+    pub conv: u32,
+    /// The command; see `KCP_PUSH`.
+    pub cmd: u8,
+    /// The number of fragments following this one.
+    pub frg: u8,
+    /// The sequence number.
+    pub sn: u32,
+    /// The data carried by the segment.
+    pub data: &'a [u8]
+}
+
+/// Splits a KCP datagram into its segments.
+///
+/// Segment headers are little-endian, as in the reference implementation.
+pub fn split_kcp(bytes: &[u8]) -> Result<Vec<KcpSegment<'_>>> {
+    let mut segments = vec![];
+    let mut index = 0usize;
+
+    while index < bytes.len() {
+        let header = take(bytes, &mut index, KCP_HEADER_LEN)
+            .map_err(|_| "Invalid KCP segment; not enough bytes for the header.")?;
+
+        let conv = u32::from_le_bytes(header[0..4].try_into()?);
+        let (cmd, frg) = (header[4], header[5]);
+        let sn = u32::from_le_bytes(header[12..16].try_into()?);
+        let len = u32::from_le_bytes(header[20..24].try_into()?) as usize;
+
+        let data = take(bytes, &mut index, len)
+            .map_err(|_| "Invalid KCP segment; not enough bytes for the data.")?;
+        segments.push(KcpSegment { conv, cmd, frg, sn, data });
+    }
+
+    Ok(segments)
+}
+
+/// Reassembles the data pushed in a KCP datagram, in order of arrival.
+pub fn kcp_data(bytes: &[u8]) -> Result<Vec<u8>> {
+    Ok(split_kcp(bytes)?
+        .iter()
+        .filter(|segment| segment.cmd == KCP_PUSH)
+        .flat_map(|segment| segment.data.iter().copied())
+        .collect())
+}
+
+fn take<'a>(bytes: &'a [u8], index: &mut usize, len: usize) -> Result<&'a [u8]> {
+    let Some(slice) = index.checked_add(len).and_then(|end| bytes.get(*index..end)) else {
+        return Err("Invalid packet; not enough bytes.".into());
+    };
+
+    *index += len;
+    Ok(slice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProtobufBytes;
+
+    #[test]
+    fn split_and_decode() {
+        let mut head = vec![];
+        head.write_u32(1, 42);
+        let mut body = vec![];
+        body.write_str(1, "Hello, World!");
+
+        let framing = Framing::magic_envelope();
+        let packet = Packet { cmd_id: Some(1001), head: &head, body: &body };
+        let mut bytes = framing.encode(&packet).unwrap();
+        assert_eq!(bytes[..4], [0x45, 0x67, 0x03, 0xe9]);
+        bytes.extend(framing.encode(&Packet { cmd_id: Some(7), head: &[], body: &[] }).unwrap());
+
+        let packets = framing.split(&bytes).unwrap();
+        assert_eq!(packets, [packet, Packet { cmd_id: Some(7), head: &[], body: &[] }]);
+        assert_eq!(packets[0].decode().unwrap().get(1).unwrap().as_string().unwrap(), "Hello, World!");
+        assert!(framing.split(&bytes[..bytes.len() - 1]).is_err());
+
+        // The same packets carried by a KCP datagram.
+        let mut datagram = vec![];
+        for (sn, chunk) in bytes.chunks(20).enumerate() {
+            datagram.extend(1u32.to_le_bytes());
+            datagram.extend([KCP_PUSH, 0, 0, 1]);
+            datagram.extend([0; 4]);
+            datagram.extend((sn as u32).to_le_bytes());
+            datagram.extend([0; 4]);
+            datagram.extend((chunk.len() as u32).to_le_bytes());
+            datagram.extend(chunk);
+        }
+        assert_eq!(split_kcp(&datagram).unwrap()[1].sn, 1);
+        assert_eq!(framing.decode(&kcp_data(&datagram).unwrap()).unwrap().len(), 2);
+    }
+}
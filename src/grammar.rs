@@ -0,0 +1,117 @@
+use std::fmt::Write;
+use crate::schema::{FieldType, Schema};
+use crate::MAX_FIELD_NUMBER;
+
+/// Exports a schema as a `.proto` definition for structured fuzzers.
+///
+/// The output is a proto2 file which can be compiled and handed to
+/// libprotobuf-mutator (or any other descriptor-driven mutator).
+/// Fixed-width fields are exported as `fixed32`/`fixed64` rather than
+/// floating point so mutations cover every bit pattern. Fields whose numbers
+/// cannot be declared, such as those reserved for the protobuf implementation,
+/// are left out so the file compiles.
+///
+/// `schema`: The schema of the root message.
+/// `name`: The name of the root message.
+pub fn to_lpm_proto(schema: &Schema, name: &str) -> String {
+    let mut output = String::from("syntax = \"proto2\";\n\n");
+    write_message(&mut output, schema, name, 0);
+    output
+}
+
+/// Writes a message definition (and its nested messages) to the output.
+fn write_message(output: &mut String, schema: &Schema, name: &str, depth: usize) {
+    let indent = "  ".repeat(depth);
+    writeln!(output, "{indent}message {name} {{").unwrap();
+
+    for (field, field_schema) in schema.iter().filter(|(field, _)| is_declarable(**field)) {
+        let label = if field_schema.repeated { "repeated" } else { "optional" };
+        let type_name = match &field_schema.field_type {
+            FieldType::VarInt => "uint64".to_string(),
+            FieldType::Fixed32 => "fixed32".to_string(),
+            FieldType::Fixed64 => "fixed64".to_string(),
+            FieldType::String => "string".to_string(),
+            FieldType::Bytes => "bytes".to_string(),
+            FieldType::Message(_) => nested_name(*field)
+        };
+
+        let name = field_schema.name.clone().unwrap_or_else(|| format!("field_{field}"));
+        writeln!(output, "{indent}  {label} {type_name} {name} = {field};").unwrap();
+    }
+
+    // Nested message types are declared inside their parent.
+    for (field, field_schema) in schema.iter().filter(|(field, _)| is_declarable(**field)) {
+        if let FieldType::Message(nested) = &field_schema.field_type {
+            output.push('\n');
+            write_message(output, nested, &nested_name(*field), depth + 1);
+        }
+    }
+
+    writeln!(output, "{indent}}}").unwrap();
+}
+
+/// Returns whether a field number may be declared in a `.proto` file.
+fn is_declarable(field: u32) -> bool {
+    (1..=MAX_FIELD_NUMBER).contains(&field) && !(19_000..=19_999).contains(&field)
+}
+
+/// Returns the type name used for a nested message at the given field.
+fn nested_name(field: u32) -> String {
+    format!("Field{field}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::FieldSchema;
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn export_nested() {
+        let mut inner = vec![];
+        inner.write_u32(1, 150);
+
+        let mut bytes = vec![];
+        bytes.write_u32(1, 5);
+        bytes.write_str(2, "Hello, World!");
+        bytes.write_bytes(3, &inner);
+        bytes.write_bytes(3, &inner);
+
+        let message = decode(&bytes).unwrap();
+        let proto = to_lpm_proto(&Schema::infer(&message), "Root");
+
+        assert_eq!(proto, "syntax = \"proto2\";
+
+message Root {
+  optional uint64 field_1 = 1;
+  optional string field_2 = 2;
+  repeated Field3 field_3 = 3;
+
+  message Field3 {
+    optional uint64 field_1 = 1;
+  }
+}
+");
+    }
+
+    #[test]
+    fn skip_undeclarable_fields() {
+        let mut schema = Schema::new();
+        for field in [1, 19_000, 19_999, MAX_FIELD_NUMBER + 1] {
+            schema.insert(field, FieldSchema::new(FieldType::VarInt, false));
+        }
+        schema.insert(20_000, FieldSchema::new(FieldType::Message(schema.clone()), false));
+
+        assert_eq!(to_lpm_proto(&schema, "Root"), "syntax = \"proto2\";
+
+message Root {
+  optional uint64 field_1 = 1;
+  optional Field20000 field_20000 = 20000;
+
+  message Field20000 {
+    optional uint64 field_1 = 1;
+  }
+}
+");
+    }
+}
@@ -0,0 +1,160 @@
+use std::borrow::Cow;
+use crate::{decode, Result, SerializedMessage};
+#[cfg(feature = "gzip")]
+use crate::payload::Compression;
+#[cfg(feature = "compression")]
+use crate::payload::Decompressed;
+
+/// The length of a gRPC frame header: a compression flag and a big-endian length.
+pub const HEADER_LEN: usize = 5;
+
+/// A single length-prefixed gRPC message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame<'a> {
+    /// Whether the payload is compressed.
+    pub compressed: bool,
+    /// The (possibly compressed) payload of the frame.
+    pub data: &'a [u8]
+}
+
+impl<'a> Frame<'a> {
+    /// Reads a single frame from the start of the buffer.
+    ///
+    /// Returns the frame and the number of bytes it occupies.
+    pub fn read(bytes: &'a [u8]) -> Result<(Self, usize)> {
+        if bytes.len() < HEADER_LEN {
+            return Err("Invalid gRPC frame; not enough bytes for the header.".into());
+        }
+
+        let compressed = match bytes[0] {
+            0 => false,
+            1 => true,
+            flag => return Err(format!("Invalid gRPC compression flag {flag}.").into())
+        };
+
+        let length = u32::from_be_bytes(bytes[1..HEADER_LEN].try_into()?) as usize;
+        let Some((data, end)) = HEADER_LEN.checked_add(length).and_then(|end| Some((bytes.get(HEADER_LEN..end)?, end))) else {
+            return Err("Invalid gRPC frame; not enough bytes for the payload.".into());
+        };
+
+        Ok((Self { compressed, data }, end))
+    }
+
+    /// Returns the uncompressed payload of the frame.
+    ///
+    /// Compressed frames are assumed to use gzip, the default gRPC encoding,
+    /// unless they start with the magic number of zlib, or of zstd with the
+    /// `compression` feature. Payloads are decompressed up to
+    /// `MAX_DECOMPRESSED_BYTES`, since frames may come straight from the network.
+    pub fn payload(&self) -> Result<Cow<'a, [u8]>> {
+        if !self.compressed {
+            return Ok(Cow::Borrowed(self.data));
+        }
+
+        #[cfg(feature = "gzip")]
+        {
+            let compression = Compression::detect(self.data).unwrap_or(Compression::Gzip);
+            Ok(Cow::Owned(compression.decompress(self.data)?))
+        }
+
+        #[cfg(not(feature = "gzip"))]
+        Err("Compressed gRPC frames require the `gzip` feature.".into())
+    }
+
+    /// Decompresses and decodes a compressed frame, along with its sizes.
+    ///
+    /// Returns `None` if the frame is not compressed.
+    #[cfg(feature = "compression")]
+    pub fn decompress(&self) -> Result<Option<Decompressed>> {
+        if !self.compressed {
+            return Ok(None);
+        }
+
+        let compression = Compression::detect(self.data).unwrap_or(Compression::Gzip);
+        Decompressed::dissect(compression, self.data).map(Some)
+    }
+
+    /// Decodes the payload of the frame as a protobuf message.
+    pub fn decode(&self) -> Result<SerializedMessage> {
+        decode(&self.payload()?)
+    }
+
+    /// Encodes the frame, including its header.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.data.len());
+        bytes.push(self.compressed as u8);
+        bytes.extend((self.data.len() as u32).to_be_bytes());
+        bytes.extend(self.data);
+        bytes
+    }
+}
+
+/// Splits a gRPC stream or HTTP/2 body into its frames.
+pub fn split_frames(bytes: &[u8]) -> Result<Vec<Frame<'_>>> {
+    let mut frames = vec![];
+    let mut index = 0usize;
+
+    while index < bytes.len() {
+        let (frame, len) = Frame::read(&bytes[index..])?;
+        index += len;
+
+        frames.push(frame);
+    }
+
+    Ok(frames)
+}
+
+/// Splits a gRPC stream or HTTP/2 body into frames and decodes each of them.
+pub fn decode_frames(bytes: &[u8]) -> Result<Vec<SerializedMessage>> {
+    split_frames(bytes)?
+        .iter()
+        .map(Frame::decode)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProtobufBytes;
+
+    #[test]
+    fn split_and_decode() {
+        let mut message = vec![];
+        message.write_str(1, "Hello, World!");
+
+        let mut body = Frame { compressed: false, data: &message }.to_bytes();
+        body.extend(Frame { compressed: false, data: &[] }.to_bytes());
+
+        let messages = decode_frames(&body).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].get(1).unwrap().as_string().unwrap(), "Hello, World!");
+        assert!(messages[1].get(1).is_none());
+
+        assert!(split_frames(&body[..body.len() - 1]).is_err());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn decompress() {
+        use std::io::Write;
+
+        let mut message = vec![];
+        message.write_str(1, "Hello, World!");
+
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&message).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let frame = Frame { compressed: true, data: &compressed };
+        let decompressed = frame.decompress().unwrap().unwrap();
+        assert_eq!(decompressed.compression, Compression::Gzip);
+        assert_eq!((decompressed.compressed_len, decompressed.len), (compressed.len(), message.len()));
+        assert_eq!(decompressed.message, decode(&message).unwrap());
+        assert_eq!(Frame { compressed: false, data: &message }.decompress().unwrap(), None);
+
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+        encoder.write_all(&vec![0; crate::payload::MAX_DECOMPRESSED_BYTES + 1]).unwrap();
+        let bomb = encoder.finish().unwrap();
+        assert!(Frame { compressed: true, data: &bomb }.payload().is_err());
+    }
+}
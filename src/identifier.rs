@@ -0,0 +1,99 @@
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// A bytes field recognized as an identifier or address by its length and contents.
+///
+/// Recognition is heuristic: 4 bytes are an IPv4 address only if they are not
+/// printable text and look like a host address, outside of `0.0.0.0/8`,
+/// multicast and reserved ranges, and not ending in 0 or 255. 16 bytes are
+/// a UUID only with valid version and variant bits, and an IPv6 address only
+/// within the commonly assigned ranges.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Identifier {
+    /// A UUID, in RFC 4122 byte order. GUIDs with mixed-endian fields are
+    /// formatted in this order too, so their first three groups appear swapped.
+    Uuid([u8; 16]),
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr)
+}
+
+impl Identifier {
+    /// Recognizes bytes as an identifier or address.
+    ///
+    /// Pass the raw payload of string readings too, since addresses such as
+    /// `127.0.0.1` are valid UTF-8.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if let Ok(octets) = <[u8; 4]>::try_from(bytes) {
+            let text = octets.iter().all(|byte| byte.is_ascii_graphic() || *byte == b' ');
+            let host = (1..224).contains(&octets[0]) && !matches!(octets[3], 0 | 255);
+            return (host && !text).then_some(Identifier::Ipv4(Ipv4Addr::from(octets)));
+        }
+
+        let octets = <[u8; 16]>::try_from(bytes).ok()?;
+        let version = octets[6] >> 4;
+        let variant = octets[8] >> 6;
+        if (1..=8).contains(&version) && variant == 0b10 {
+            return Some(Identifier::Uuid(octets));
+        }
+
+        let address = Ipv6Addr::from(octets);
+        let segment = address.segments()[0];
+        let assigned = segment & 0xe000 == 0x2000 // Global unicast.
+            || segment & 0xfe00 == 0xfc00 // Unique local.
+            || segment & 0xffc0 == 0xfe80 // Link local.
+            || address.is_loopback()
+            || address.to_ipv4_mapped().is_some();
+        assigned.then_some(Identifier::Ipv6(address))
+    }
+
+    /// Returns the name of the kind of identifier, as used in JSON output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Identifier::Uuid(_) => "uuid",
+            Identifier::Ipv4(_) => "ipv4",
+            Identifier::Ipv6(_) => "ipv6"
+        }
+    }
+}
+
+/// UUIDs are formatted as hyphenated lowercase hex, and addresses in their usual notation.
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Identifier::Uuid(octets) => {
+                for (i, byte) in octets.iter().enumerate() {
+                    if matches!(i, 4 | 6 | 8 | 10) {
+                        f.write_str("-")?;
+                    }
+                    write!(f, "{byte:02x}")?;
+                }
+                Ok(())
+            }
+            Identifier::Ipv4(address) => write!(f, "{address}"),
+            Identifier::Ipv6(address) => write!(f, "{address}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers() {
+        let uuid = [0x12, 0x3e, 0x45, 0x67, 0xe8, 0x9b, 0x42, 0xd3, 0xa4, 0x56, 0x42, 0x66, 0x14, 0x17, 0x40, 0x00];
+        assert_eq!(Identifier::detect(&uuid).unwrap().to_string(), "123e4567-e89b-42d3-a456-426614174000");
+
+        let ipv6 = "2001:db8::1".parse::<Ipv6Addr>().unwrap();
+        assert_eq!(Identifier::detect(&ipv6.octets()), Some(Identifier::Ipv6(ipv6)));
+        assert_eq!(Identifier::detect(&[192, 168, 0, 1]).unwrap().to_string(), "192.168.0.1");
+        assert_eq!(Identifier::detect(&[127, 0, 0, 1]).unwrap().to_string(), "127.0.0.1");
+        assert_eq!(Identifier::detect(&[0, 0, 0, 1]), None);
+        assert_eq!(Identifier::detect(&[1, 0, 0, 0]), None);
+        assert_eq!(Identifier::detect(&[239, 1, 2, 3]), None);
+        assert_eq!(Identifier::detect(b"test"), None);
+
+        assert_eq!(Identifier::detect(&[0xff; 16]), None);
+        assert_eq!(Identifier::detect(&[1, 2, 3]), None);
+    }
+}
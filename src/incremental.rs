@@ -0,0 +1,164 @@
+use crate::{Decoder, Result, Value, VarInt};
+
+/// A push-style decoder for messages which arrive in fragments,
+/// such as a message split across TCP segments.
+///
+/// Chunks are fed as they arrive, and every field is returned as soon as
+/// all of its bytes are available. Fields are decoded with the wrapped
+/// `Decoder`, so its limits and options apply to each of them.
+///
+/// Only the standard wire types can be decoded incrementally,
+/// since the length of a field of any other wire type is unknown.
+#[derive(Clone, Debug, Default)]
+pub struct IncrementalDecoder {
+    decoder: Decoder,
+    /// The bytes received but not yet decoded.
+    buffer: Vec<u8>,
+    /// The header of a field whose value is incomplete, if any.
+    pending: Option<Pending>
+}
+
+/// A field whose header has been read, waiting for the rest of its bytes.
+#[derive(Copy, Clone, Debug)]
+struct Pending {
+    /// The length of the field, including its header.
+    len: usize
+}
+
+impl IncrementalDecoder {
+    /// Creates a new incremental decoder using the given decoder.
+    pub fn new(decoder: Decoder) -> Self {
+        Self { decoder, buffer: vec![], pending: None }
+    }
+
+    /// Adds a chunk of the message, returning the fields it completes.
+    ///
+    /// Fails if the message is malformed; the buffered bytes
+    /// are discarded, so decoding cannot resume afterward.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<(u32, Value)>> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut fields = vec![];
+        let mut offset = 0usize;
+
+        loop {
+            let len = match self.pending {
+                Some(pending) => pending.len,
+                None => match field_len(&self.buffer[offset..], self.decoder.config().max_field_bytes) {
+                    Ok(Some(len)) => len,
+                    Ok(None) => break,
+                    Err(error) => return Err(self.fail(error))
+                }
+            };
+
+            let Some(field) = self.buffer.get(offset..offset + len) else {
+                // Keep the header, so it is not read again for every chunk.
+                self.pending = Some(Pending { len });
+                break;
+            };
+
+            let message = match self.decoder.decode(field) {
+                Ok(message) => message,
+                Err(error) => return Err(self.fail(error))
+            };
+            fields.extend(message);
+
+            self.pending = None;
+            offset += len;
+        }
+
+        self.buffer.drain(..offset);
+        Ok(fields)
+    }
+
+    /// Returns the number of bytes buffered for the next, incomplete field.
+    pub fn pending_bytes(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Ends the message, failing if a field is incomplete.
+    pub fn finish(self) -> Result<()> {
+        match self.buffer.len() {
+            0 => Ok(()),
+            len => Err(format!("Invalid message; stream ended with {len} bytes of an incomplete field.").into())
+        }
+    }
+
+    /// Discards the buffered bytes after an error.
+    fn fail(&mut self, error: crate::Error) -> crate::Error {
+        self.buffer.clear();
+        self.pending = None;
+        error
+    }
+}
+
+/// Returns the length of the field at the start of the bytes,
+/// or `None` if its header is incomplete.
+///
+/// Fields longer than the limit are rejected before they are buffered.
+pub(crate) fn field_len(bytes: &[u8], max_field_bytes: usize) -> Result<Option<usize>> {
+    let Some((tag, header_len)) = read_varint(bytes, 0)? else {
+        return Ok(None);
+    };
+
+    let value_len = match tag & 0b111 {
+        0 => match read_varint(bytes, header_len)? {
+            Some((_, len)) => len,
+            // The varint continues past the buffered bytes.
+            None => return Ok(None)
+        },
+        1 => 8,
+        2 => match read_varint(bytes, header_len)? {
+            Some((len, varint_len)) => usize::try_from(len).ok()
+                .filter(|len| *len <= max_field_bytes)
+                .and_then(|len| len.checked_add(varint_len))
+                .ok_or(format!("Invalid message; field exceeds the limit of {max_field_bytes} bytes."))?,
+            None => return Ok(None)
+        },
+        5 => 4,
+        wire_type => return Err(format!("Invalid message; wire type {wire_type} cannot be decoded incrementally.").into())
+    };
+
+    Ok(header_len.checked_add(value_len))
+}
+
+/// Reads a varint, returning `None` if it is incomplete.
+fn read_varint(bytes: &[u8], index: usize) -> Result<Option<(u64, usize)>> {
+    match VarInt::read_at(bytes, index) {
+        Some(varint) => Ok(Some(varint)),
+        // Ten bytes without an end are malformed, rather than incomplete.
+        None if bytes.len().saturating_sub(index) >= 10 => Err("Invalid message; malformed varint.".into()),
+        None => Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, ProtobufBytes, SerializedMessage};
+
+    #[test]
+    fn fragments() {
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+        bytes.write_str(2, &"a".repeat(300));
+        bytes.write_f64(3, 1.5);
+
+        let mut decoder = IncrementalDecoder::default();
+        let mut message = SerializedMessage::new();
+        for chunk in bytes.chunks(7) {
+            for (field, value) in decoder.feed(chunk).unwrap() {
+                message.insert(field, value);
+            }
+        }
+
+        assert_eq!(decoder.pending_bytes(), 0);
+        assert_eq!(message, decode(&bytes).unwrap());
+        decoder.finish().unwrap();
+
+        let mut truncated = IncrementalDecoder::default();
+        assert_eq!(truncated.feed(&bytes[..12]).unwrap().len(), 1);
+        assert_eq!(truncated.pending_bytes(), 2);
+        assert!(truncated.finish().is_err());
+    }
+}
@@ -1,37 +1,246 @@
-pub(crate) mod utils;
+mod error;
+mod display;
 pub mod bytes;
+pub mod encoding;
+pub mod view;
 pub mod varint;
-
-use std::{collections::BTreeMap, error::Error};
+pub mod access;
+pub mod extract;
+pub mod compose;
+pub mod transcode;
+pub mod unknown;
+pub mod classify;
+pub mod decoder;
+pub mod incremental;
+pub mod raw;
+pub mod lossy;
+pub mod roundtrip;
+pub mod canonical;
+pub mod profiler;
+pub mod schema;
+pub mod template;
+pub mod pool;
+pub mod size;
+pub mod explain;
+pub mod fingerprint;
+pub mod wellknown;
+pub mod identifier;
+pub mod search;
+pub mod redact;
+pub mod mutate;
+pub mod flatten;
+pub mod grammar;
+pub mod export;
+pub mod grpc;
+pub mod framing;
+pub mod reassembly;
+pub mod tail;
+pub mod transform;
+pub mod pacing;
+pub mod negotiation;
+pub mod packing;
+pub mod maps;
+pub mod analysis;
+pub mod names;
+pub mod table;
+pub mod payload;
+pub mod protoscope;
+
+#[cfg(feature = "simulator")]
+pub mod simulator;
+
+#[cfg(any(feature = "yaml", feature = "toml"))]
+mod formats;
+
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+mod bridge;
+
+#[cfg(feature = "storage")]
+pub mod storage;
+
+#[cfg(feature = "pcap")]
+pub mod pcap;
+
+#[cfg(feature = "prost")]
+pub mod prost;
+
+#[cfg(feature = "prost")]
+pub mod validate;
+
+#[cfg(feature = "project")]
+pub mod project;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "python")]
+mod python;
+
+#[cfg(feature = "arena")]
+pub mod arena;
+
+#[cfg(feature = "async")]
+pub mod stream;
+
+#[cfg(feature = "proxy")]
+pub mod proxy;
+
+#[cfg(feature = "crypto")]
+pub mod crypto;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(all(feature = "prost", feature = "json"))]
+pub mod protojson;
+
+#[cfg(feature = "conformance")]
+pub mod conformance;
+
+#[cfg(feature = "capture")]
+pub mod capture;
+
+#[cfg(feature = "json")]
+pub mod dissect;
+
+use std::{collections::BTreeMap, fmt};
+use std::hash::{Hash, Hasher};
+use std::mem;
 use std::collections::btree_map;
 use paste::paste;
 use serde::{Deserialize, Serialize};
 
+// Re-export all `error` items.
+pub use crate::error::*;
+
 // Re-export all `bytes` items.
 pub use crate::bytes::*;
 
+// Re-export all `view` items.
+pub use crate::view::*;
+
 // Re-export all `varint` items.
 pub use crate::varint::*;
 
-type DecodeError = Box<dyn Error>;
+// Re-export all `access` items.
+pub use crate::access::*;
+
+// Re-export all `extract` items.
+pub use crate::extract::*;
+
+// Re-export all `compose` items.
+pub use crate::compose::*;
+
+// Re-export the derive macros, which share the names of their traits.
+#[cfg(feature = "derive")]
+pub use protoshark_derive::{FromSerializedMessage, ToSerializedMessage};
+
+// Derived code refers to the crate by name, including within the crate.
+extern crate self as protoshark;
+
+// Re-export all `unknown` items.
+pub use crate::unknown::*;
+
+// Re-export all `maps` items.
+pub use crate::maps::*;
+
+// Re-export all `decoder` items.
+pub use crate::decoder::*;
+
+// Re-export all `incremental` items.
+pub use crate::incremental::*;
+
+// Re-export all `raw` items.
+pub use crate::raw::*;
+
+// Re-export all `lossy` items.
+pub use crate::lossy::*;
+
+// Re-export all `roundtrip` items.
+pub use crate::roundtrip::*;
+
+// Re-export all `profiler` items.
+pub use crate::profiler::*;
+
+// Re-export all `classify` items.
+pub use crate::classify::*;
+
+/// The field numbers leading to a (possibly nested) field.
+pub type FieldPath = Vec<u32>;
 // pub type SerializedMessage = BTreeMap<u32, Value>;
 
 /// A serialized message.
-#[derive(Clone, Serialize, Deserialize)]
+///
+/// Fields are kept in field number order. Messages created with `new_ordered`,
+/// or decoded with `DecodeConfig::preserve_order`, also record the order fields
+/// were inserted in, and are encoded in that order; the order is otherwise
+/// ignored, so it does not affect equality or hashing.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct SerializedMessage {
-    backing: BTreeMap<u32, Value>
+    backing: BTreeMap<u32, Value>,
+    /// The field number of every inserted value, in order, if recorded.
+    #[serde(skip)]
+    order: Option<Vec<u32>>
 }
 
 impl SerializedMessage {
     /// Creates a new serialized message instance.
     pub fn new() -> Self {
-        Self { backing: BTreeMap::new() }
+        Self { backing: BTreeMap::new(), order: None }
+    }
+
+    /// Creates a new message which records the order its fields are inserted in.
+    pub fn new_ordered() -> Self {
+        Self { backing: BTreeMap::new(), order: Some(vec![]) }
+    }
+
+    /// Returns the field number of every inserted value in order, if recorded.
+    ///
+    /// Fields set with `set_field` rather than inserted are not recorded.
+    pub fn wire_order(&self) -> Option<&[u32]> {
+        self.order.as_deref()
+    }
+
+    /// Returns every value with its field number, one per occurrence,
+    /// in the recorded order of the message.
+    ///
+    /// Values without a recorded position follow in field number order,
+    /// as do all values of a message which does not record its order.
+    pub fn fields_in_order(&self) -> Vec<(u32, &Value)> {
+        let mut fields = vec![];
+        let mut written: BTreeMap<u32, usize> = BTreeMap::new();
+        for field in self.order.iter().flatten() {
+            let Some(value) = self.backing.get(field) else {
+                continue;
+            };
+
+            let index = written.entry(*field).or_default();
+            if let Some(value) = occurrences(value).get(*index) {
+                fields.push((*field, value));
+                *index += 1;
+            }
+        }
+
+        for (field, value) in &self.backing {
+            let skipped = written.get(field).copied().unwrap_or_default();
+            fields.extend(occurrences(value).iter().skip(skipped).map(|value| (*field, value)));
+        }
+
+        fields
     }
 
     /// Inserts a value into the map.
     ///
     /// If a duplicate value exists, the value is replaced with an array.
     pub fn insert(&mut self, field: u32, value: Value) {
+        if let Some(order) = &mut self.order {
+            order.push(field);
+        }
+
         // Check if the value exists.
         if self.backing.contains_key(&field) {
             // Get the existing value.
@@ -56,24 +265,114 @@ impl SerializedMessage {
         }
     }
 
+    /// Sets the value of a field, replacing any existing values.
+    ///
+    /// Repeated values are flattened; an empty repeated value removes the field,
+    /// and a repeated value with a single element is stored as that element.
+    pub fn set_field(&mut self, field: u32, value: Value) {
+        match normalize(value) {
+            Some(value) => self.backing.insert(field, value),
+            None => self.backing.remove(&field)
+        };
+    }
+
+    /// Removes a field, returning its value if it existed.
+    pub fn remove_field(&mut self, field: u32) -> Option<Value> {
+        self.backing.remove(&field)
+    }
+
+    /// Moves the values of a field to a new field number.
+    ///
+    /// If the new field already has values, the moved values are appended to them.
+    /// Returns false if the old field did not exist.
+    pub fn renumber_field(&mut self, old: u32, new: u32) -> bool {
+        let Some(value) = self.backing.remove(&old) else {
+            return false;
+        };
+
+        match value {
+            Value::Repeated(values) => {
+                for value in values {
+                    self.insert(new, value);
+                }
+            }
+            value => self.insert(new, value)
+        }
+
+        true
+    }
+
+    /// Removes every field.
+    pub fn clear(&mut self) {
+        self.backing.clear();
+        if let Some(order) = &mut self.order {
+            order.clear();
+        }
+    }
+
     /// Gets the value at the given field.
     pub fn get(&self, field: u32) -> Option<Value> {
         self.backing.get(&field).cloned()
     }
 
+    /// Gets the value at the given path of nested fields.
+    ///
+    /// Every field but the last must be a nested message.
+    pub fn get_path(&self, path: &[u32]) -> Option<Value> {
+        let (last, parents) = path.split_last()?;
+
+        let mut message = self;
+        for field in parents {
+            match message.backing.get(field) {
+                Some(Value::Message(nested)) => message = nested,
+                _ => return None
+            }
+        }
+
+        message.get(*last)
+    }
+
+    /// Encodes the message into protobuf wire format.
+    ///
+    /// Fields are written in ascending field number order,
+    /// or in the recorded order; see `fields_in_order`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        if self.order.is_some() {
+            for (field, value) in self.fields_in_order() {
+                bytes.write_value(field, value);
+            }
+        } else {
+            for (field, value) in self {
+                bytes.write_value(*field, value);
+            }
+        }
+
+        bytes
+    }
+
     /// Returns the backing iterator.
-    pub fn iter(&self) -> btree_map::Iter<u32, Value> {
+    pub fn iter(&self) -> btree_map::Iter<'_, u32, Value> {
         self.backing.iter()
     }
 
     /// Returns a mutable backing iterator.
-    pub fn iter_mut(&mut self) -> btree_map::IterMut<u32, Value> {
+    pub fn iter_mut(&mut self) -> btree_map::IterMut<'_, u32, Value> {
         self.backing.iter_mut()
     }
+}
 
-    /// Returns the backing map as an iterator.
-    pub fn into_iter(self) -> btree_map::IntoIter<u32, Value> {
-        self.backing.into_iter()
+impl PartialEq for SerializedMessage {
+    fn eq(&self, other: &Self) -> bool {
+        self.backing == other.backing
+    }
+}
+
+impl Eq for SerializedMessage {}
+
+impl Hash for SerializedMessage {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.backing.hash(state);
     }
 }
 
@@ -104,94 +403,124 @@ impl IntoIterator for SerializedMessage {
     }
 }
 
+/// Flattens nested repeated values, and unwraps repeated values with fewer than two elements.
+fn normalize(value: Value) -> Option<Value> {
+    let Value::Repeated(values) = value else {
+        return Some(value);
+    };
+
+    let mut flattened = vec![];
+    for value in values {
+        match normalize(value) {
+            Some(Value::Repeated(values)) => flattened.extend(values),
+            Some(value) => flattened.push(value),
+            None => {}
+        }
+    }
+
+    match flattened.len() {
+        0 => None,
+        1 => flattened.pop(),
+        _ => Some(Value::Repeated(flattened))
+    }
+}
+
+/// Returns the values of each occurrence of a field.
+fn occurrences(value: &Value) -> &[Value] {
+    match value {
+        Value::Repeated(values) => values,
+        value => std::slice::from_ref(value)
+    }
+}
+
 /// Decodes a protobuf-encoded message.
 ///
 /// `bytes`: A slice of bytes representing the protobuf-encoded message.
 ///
 /// Returns a HashMap of field numbers to values.
-pub fn decode(bytes: &[u8]) -> Result<SerializedMessage, DecodeError> {
-    let bytes_len = bytes.len();
-
-    let mut message = SerializedMessage::new();
-    let mut index = 0usize;
-
-    while index < bytes.len() {
-        let varint = VarInt::raw_at(bytes, index);
-        let Ok(header) = Header::decode(&varint) else {
-            return Err("Invalid wire type specified".into());
-        };
-
-        index += varint.len();
-
-        match header.wire_type {
-            WireType::VarInt => {
-                let (varint, len) = VarInt::decode_at(bytes, index);
-                index += len;
+/// Uses the default limits; see `Decoder` to configure them.
+///
+/// Never panics; malformed input of any kind is reported as an `Err`.
+pub fn decode(bytes: &[u8]) -> Result<SerializedMessage> {
+    Decoder::default().decode(bytes)
+}
 
-                message.insert(header.field_number, Value::VarInt(varint));
-            }
-            WireType::Fixed64 => {
-                if bytes_len < index || bytes_len < index + 8 {
-                    return Err("Invalid message; not enough bytes for a fixed64 field.".into());
-                }
+/// Decodes a protobuf-encoded message from Base64 text, ignoring whitespace.
+///
+/// Uses the default limits; see `Decoder::decode_base64`.
+pub fn decode_base64(text: &str) -> Result<SerializedMessage> {
+    Decoder::default().decode_base64(text)
+}
 
-                let bytes: [u8; 8] = bytes[index..index + 8].try_into()?;
-                index += 8;
+/// Decodes a protobuf-encoded message from hex text, ignoring whitespace.
+///
+/// Uses the default limits; see `Decoder::decode_hex`.
+pub fn decode_hex(text: &str) -> Result<SerializedMessage> {
+    Decoder::default().decode_hex(text)
+}
 
-                let value = f64::from_le_bytes(bytes);
-                message.insert(header.field_number, Value::Double(value));
-            }
-            WireType::LengthDelimited => {
-                let (data_len, varint_len) = VarInt::decode_at(bytes, index);
-                index += varint_len;
+/// Decodes a protobuf-encoded message, locating every field in the buffer.
+///
+/// Uses the default limits; see `Decoder::decode_with_spans`.
+pub fn decode_with_spans(bytes: &[u8]) -> Result<Vec<Spanned<Value>>> {
+    Decoder::default().decode_with_spans(bytes)
+}
 
-                if bytes_len < index || bytes_len < index + data_len.as_i32() as usize {
-                    return Err("Invalid message; not enough bytes for a length-delimited field.".into());
-                }
+/// Decodes as much of a protobuf-encoded message as possible, reporting what was skipped.
+///
+/// Uses the default limits; see `Decoder::decode_lossy`.
+pub fn decode_lossy(bytes: &[u8]) -> (SerializedMessage, Vec<DecodeError>) {
+    Decoder::default().decode_lossy(bytes)
+}
 
-                let bytes = &bytes[index..index + data_len.as_i32() as usize];
-                index += data_len.as_i32() as usize;
+/// Decodes a message, reporting progress and stopping once the token is cancelled.
+///
+/// Uses the default limits; see `Decoder::decode_with_progress`.
+pub fn decode_with_progress<F: FnMut(Progress)>(bytes: &[u8], token: &CancellationToken, callback: F) -> Result<SerializedMessage> {
+    Decoder::default().decode_with_progress(bytes, token, callback)
+}
 
-                let data = decode(bytes);
-                let string = std::str::from_utf8(bytes);
+/// Decodes several messages concatenated back to back without framing.
+///
+/// Uses the default limits; see `Decoder::decode_concatenated`.
+pub fn decode_concatenated(bytes: &[u8]) -> Result<Vec<(usize, SerializedMessage)>> {
+    Decoder::default().decode_concatenated(bytes)
+}
 
-                if data.is_err() && string.is_err() {
-                    message.insert(header.field_number, Value::Bytes(bytes.to_vec()));
-                } else {
-                    if let Ok(string) = string {
-                        message.insert(header.field_number, Value::String(string.to_string()));
-                    }
-                    if let Ok(data) = data {
-                        message.insert(header.field_number, Value::Message(data));
-                    }
-                }
-            }
-            WireType::StartGroup => {
-                return Err("Start group wire type is not supported.".into());
-            }
-            WireType::EndGroup => {
-                return Err("End group wire type is not supported.".into());
-            }
-            WireType::Fixed32 => {
-                if bytes_len < index || bytes_len < index + 4 {
-                    return Err("Invalid message; not enough bytes for a fixed32 field.".into());
-                }
+/// Decodes a message, encodes it again, and explains how the bytes differ.
+///
+/// Uses the default limits; see `Decoder::verify_roundtrip`.
+pub fn verify_roundtrip(original: &[u8]) -> Result<RoundTripReport> {
+    Decoder::default().verify_roundtrip(original)
+}
 
-                let bytes: [u8; 4] = bytes[index..index + 4].try_into()?;
-                index += 4;
+/// Decodes a message, explaining how each field was dissected.
+///
+/// Uses the default limits; see `Decoder::explain`.
+pub fn explain(bytes: &[u8]) -> Result<explain::DissectionReport> {
+    Decoder::default().explain(bytes)
+}
 
-                let value = f32::from_le_bytes(bytes);
-                message.insert(header.field_number, Value::Float(value));
-            }
-        }
-    }
+/// Re-encodes a message canonically, for hashing and deduplication.
+///
+/// Uses the default limits; see `Decoder::canonicalize`.
+pub fn canonicalize(bytes: &[u8]) -> Result<Vec<u8>> {
+    Decoder::default().canonicalize(bytes)
+}
 
-    Ok(message)
+/// Decodes a batch of protobuf-encoded messages concurrently.
+///
+/// Uses the default limits; see `Decoder::decode_many`.
+#[cfg(feature = "parallel")]
+pub fn decode_many(messages: &[&[u8]]) -> Vec<Result<SerializedMessage>> {
+    Decoder::default().decode_many(messages)
 }
 
-struct Header {
-    field_number: u32,
-    wire_type: WireType
+/// The header of a field: its field number and wire type.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Header {
+    pub field_number: u32,
+    pub wire_type: WireType
 }
 
 impl Header {
@@ -200,16 +529,29 @@ impl Header {
         Self { field_number, wire_type }
     }
 
-    /// Decodes a protobuf header.
-    /// bytes: A slice of bytes representing the header.
-    pub fn decode(bytes: &[u8]) -> Result<Self, ()> {
-        let varint = VarInt::decode(bytes);
-        let int = varint.as_u32().ok_or(())?;
+    /// Decodes the header at a specific index.
+    ///
+    /// Returns the header and the number of bytes read. Fails if the header
+    /// is malformed, the field number is out of range, or the wire type is unknown.
+    pub fn decode_at(bytes: &[u8], index: usize) -> Result<(Self, usize)> {
+        let Some((tag, len)) = VarInt::read_at(bytes, index) else {
+            return Err("Invalid message; malformed field header.".into());
+        };
 
-        Ok(Self {
-            field_number: int >> 3,
-            wire_type: WireType::try_from(0b0000_0111 & int as u8)?
-        })
+        let field_number = decoder::field_number(tag, &DecodeConfig::default())?;
+        let Ok(wire_type) = WireType::try_from((tag & 0b111) as u8) else {
+            return Err(format!("Invalid message; wire type {} is not supported.", tag & 0b111).into());
+        };
+
+        Ok((Self { field_number, wire_type }, len))
+    }
+
+    /// Returns the number of bytes `encode` writes, which is always five.
+    ///
+    /// Like varints, headers are encoded padded to five bytes, whatever their
+    /// field number, rather than in the `VarInt::minimal_length` of their tag.
+    pub fn padded_len(&self) -> usize {
+        5
     }
 
     /// Converts the header into a slice of bytes.
@@ -222,15 +564,17 @@ impl Header {
     /// Encodes the header into a slice of bytes.
     pub fn encode(&self, bytes: &mut Vec<u8>) {
         let wire_type: u32 = self.wire_type.into();
-        let integer = (self.field_number << 3) | wire_type;
+        let tag = (self.field_number << 3) | wire_type;
 
-        bytes.append(&mut VarInt::encode(integer as i32));
+        // Encoded as unsigned, since tags of large field numbers would be sign-extended.
+        bytes.extend(VarInt::encode_padded(tag as u64, 5).unwrap_or_default());
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+/// The encoding of a field's value on the wire.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
-enum WireType {
+pub enum WireType {
     VarInt,
     Fixed64,
     LengthDelimited,
@@ -239,6 +583,25 @@ enum WireType {
     Fixed32
 }
 
+impl WireType {
+    /// Returns the name of the wire type.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WireType::VarInt => "varint",
+            WireType::Fixed64 => "fixed64",
+            WireType::LengthDelimited => "length-delimited",
+            WireType::StartGroup => "start group",
+            WireType::EndGroup => "end group",
+            WireType::Fixed32 => "fixed32"
+        }
+    }
+
+    /// Returns true if the wire type is deprecated, as groups are.
+    pub fn is_deprecated(&self) -> bool {
+        matches!(self, WireType::StartGroup | WireType::EndGroup)
+    }
+}
+
 impl TryFrom<u8> for WireType {
     type Error = ();
 
@@ -255,9 +618,9 @@ impl TryFrom<u8> for WireType {
     }
 }
 
-impl Into<u32> for WireType {
-    fn into(self) -> u32 {
-        match self {
+impl From<WireType> for u32 {
+    fn from(value: WireType) -> Self {
+        match value {
             WireType::VarInt => 0,
             WireType::Fixed64 => 1,
             WireType::LengthDelimited => 2,
@@ -329,6 +692,30 @@ impl Number {
     }
 }
 
+/// An error returned when a value is not of the requested type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConversionError {
+    /// The requested type.
+    pub expected: &'static str,
+    /// The kind of value which was found instead.
+    pub found: &'static str
+}
+
+impl ConversionError {
+    /// Creates a new conversion error for the given value.
+    pub fn new(expected: &'static str, value: &Value) -> Self {
+        Self { expected, found: value.kind() }
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cannot convert a {} value into {}.", self.found, self.expected)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
 macro_rules! value_conversion {
     ($($t:ty => $v:ident; $name:ident),*) => {
         $(
@@ -338,11 +725,13 @@ macro_rules! value_conversion {
                 }
             }
 
-            impl Into<$t> for Value {
-                fn into(self) -> $t {
-                    match self {
-                        Value::$v(value) => value,
-                        _ => panic!("Invalid conversion.")
+            impl TryFrom<Value> for $t {
+                type Error = ConversionError;
+
+                fn try_from(value: Value) -> Result<Self, Self::Error> {
+                    match value {
+                        Value::$v(value) => Ok(value),
+                        value => Err(ConversionError::new(stringify!($t), &value))
                     }
                 }
             }
@@ -371,7 +760,74 @@ pub enum Value {
     #[serde(with = "base64")]
     Bytes(Vec<u8>),
     Message(SerializedMessage),
-    Repeated(Vec<Value>)
+    Repeated(Vec<Value>),
+    /// A message with too many fields to decode, summarized instead.
+    ///
+    /// See `DecodeConfig::max_fields`.
+    Summary {
+        field_count: usize,
+        /// The number of fields of each wire type, keyed by wire type name.
+        type_histogram: BTreeMap<String, usize>
+    },
+    /// A map field, folded from its repeated key/value entries.
+    ///
+    /// See `fold_maps`.
+    Map(BTreeMap<MapKey, Value>),
+    /// An empty length-delimited field, which may be an empty string,
+    /// empty bytes, or an empty message.
+    ///
+    /// See `DecodeConfig::empty`.
+    Empty
+}
+
+/// Values are compared structurally.
+///
+/// Floating point values are compared by their bits, so that equality is
+/// reflexive and consistent with hashing: `NaN` equals an identical `NaN`,
+/// and `0.0` does not equal `-0.0`.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::VarInt(a), Value::VarInt(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            (Value::Double(a), Value::Double(b)) => a.to_bits() == b.to_bits(),
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::Message(a), Value::Message(b)) => a == b,
+            (Value::Repeated(a), Value::Repeated(b)) => a == b,
+            (
+                Value::Summary { field_count: a, type_histogram: a_histogram },
+                Value::Summary { field_count: b, type_histogram: b_histogram }
+            ) => a == b && a_histogram == b_histogram,
+            (Value::Map(a), Value::Map(b)) => a == b,
+            (Value::Empty, Value::Empty) => true,
+            _ => false
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+
+        match self {
+            Value::VarInt(value) => value.hash(state),
+            Value::Float(value) => value.to_bits().hash(state),
+            Value::Double(value) => value.to_bits().hash(state),
+            Value::String(value) => value.hash(state),
+            Value::Bytes(value) => value.hash(state),
+            Value::Message(value) => value.hash(state),
+            Value::Repeated(values) => values.hash(state),
+            Value::Summary { field_count, type_histogram } => {
+                field_count.hash(state);
+                type_histogram.hash(state);
+            }
+            Value::Map(entries) => entries.hash(state),
+            Value::Empty => {}
+        }
+    }
 }
 
 value_conversion!(
@@ -381,7 +837,8 @@ value_conversion!(
     String => String; string,
     Vec<u8> => Bytes; bytes,
     SerializedMessage => Message; message,
-    Vec<Value> => Repeated; repeated
+    Vec<Value> => Repeated; repeated,
+    BTreeMap<MapKey, Value> => Map; map
 );
 
 // Special conversions.
@@ -392,20 +849,31 @@ impl From<bool> for Value {
     }
 }
 
-impl Into<bool> for Value {
-    fn into(self) -> bool {
-        match self {
-            Value::VarInt(value) => match value.as_i32() {
-                0 => false,
-                1 => true,
-                _ => panic!("Invalid conversion.")
-            },
-            _ => panic!("Invalid conversion.")
-        }
+impl TryFrom<Value> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        value.as_bool().ok_or_else(|| ConversionError::new("bool", &value))
     }
 }
 
 impl Value {
+    /// Returns the name of the kind of value.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::VarInt(_) => "varint",
+            Value::Float(_) => "float",
+            Value::Double(_) => "double",
+            Value::String(_) => "string",
+            Value::Bytes(_) => "bytes",
+            Value::Message(_) => "message",
+            Value::Repeated(_) => "repeated",
+            Value::Summary { .. } => "summary",
+            Value::Map(_) => "map",
+            Value::Empty => "empty"
+        }
+    }
+
     pub fn as_bool(&self) -> Option<bool> {
         match self {
             Value::VarInt(value) => match value.as_i32() {
@@ -444,20 +912,57 @@ impl Value {
             _ => None
         }
     }
+
+    /// Reinterprets the bits of a fixed32 field, decoded as a float, as an unsigned integer.
+    pub fn as_fixed_u32(&self) -> Option<u32> {
+        match self {
+            Value::Float(value) => Some(value.to_bits()),
+            _ => None
+        }
+    }
+
+    /// Reinterprets the bits of a fixed32 field, decoded as a float, as a signed integer.
+    pub fn as_fixed_i32(&self) -> Option<i32> {
+        self.as_fixed_u32().map(|bits| bits as i32)
+    }
+
+    /// Reinterprets the bits of a fixed64 field, decoded as a double, as an unsigned integer.
+    pub fn as_fixed_u64(&self) -> Option<u64> {
+        match self {
+            Value::Double(value) => Some(value.to_bits()),
+            _ => None
+        }
+    }
+
+    /// Reinterprets the bits of a fixed64 field, decoded as a double, as a signed integer.
+    pub fn as_fixed_i64(&self) -> Option<i64> {
+        self.as_fixed_u64().map(|bits| bits as i64)
+    }
+
+    /// Returns the raw bits of a fixed32 or fixed64 field, exactly as encoded.
+    ///
+    /// Useful for fields which are actually bitfields rather than floats.
+    pub fn raw_bits(&self) -> Option<u64> {
+        match self {
+            Value::Float(value) => Some(value.to_bits() as u64),
+            Value::Double(value) => Some(value.to_bits()),
+            _ => None
+        }
+    }
 }
 
 mod base64 {
-    use crate::utils;
-    use serde::{Serialize, Deserialize, Deserializer, Serializer};
+    use crate::encoding;
+    use serde::{de, Serialize, Deserialize, Deserializer, Serializer};
 
-    pub fn serialize<S: Serializer>(v: &Vec<u8>, s: S) -> Result<S::Ok, S::Error> {
-        let base64 = utils::base64_encode(v);
+    pub fn serialize<S: Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        let base64 = encoding::base64_encode(v);
         String::serialize(&base64, s)
     }
 
     pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
         let base64 = String::deserialize(d)?;
-        Ok(utils::base64_decode(base64))
+        encoding::base64_decode(&base64).map_err(de::Error::custom)
     }
 }
 
@@ -467,12 +972,125 @@ mod tests {
 
     #[test]
     fn decode_all() {
-        let message = utils::base64_decode(
+        let message = encoding::base64_decode(
             "CMr7/f///////wEQgbCkvIv9////ARiaiigg/8/bw/QCLcP1SEAxswxxHH+ELkE4AUINSGVsbG8sIFdvcmxkIUogy7Z2rm0bzr4uZoGQPV2M+i52+c6kZtCFIKs/il2DQXdQAlovIgh5ZWFoeWVhaHog+RnnJSsU6kdRW/n67wdtWq59l0BbgApj5M6jlnpwZKDIOAA="
-        );
+        ).unwrap();
         let decoded = decode(&message).expect("Failed to decode the message.");
 
         let json = serde_json::to_string(&decoded).unwrap();
         assert_eq!(json, r#"{"1":-33334,"2":[-1215752191,-99999999999],"3":656666,"4":1215752191,"5":3.14,"6":999999.55555,"7":1,"8":"Hello, World!","9":"y7Z2rm0bzr4uZoGQPV2M+i52+c6kZtCFIKs/il2DQXc=","10":2,"11":{"4":"yeahyeah","15":"+RnnJSsU6kdRW/n67wdtWq59l0BbgApj5M6jlnpwZKA=","905":0}}"#);
     }
+
+    #[test]
+    fn deserialize_base64() {
+        let bytes = base64::deserialize(&mut serde_json::Deserializer::from_str(r#""+/8B""#)).unwrap();
+        assert_eq!(bytes, [0xfb, 0xff, 0x01]);
+        assert!(base64::deserialize(&mut serde_json::Deserializer::from_str(r#""not base64!""#)).is_err());
+    }
+
+    #[test]
+    fn try_from_value() {
+        assert_eq!(f32::try_from(Value::Float(1.5)), Ok(1.5));
+        assert_eq!(bool::try_from(Value::from(true)), Ok(true));
+
+        let error = String::try_from(Value::Float(1.5)).unwrap_err();
+        assert_eq!(error.to_string(), "Cannot convert a float value into String.");
+    }
+
+    #[test]
+    fn fixed_bits() {
+        let mut bytes = vec![];
+        bytes.write_f32(1, f32::from_bits(0xdead_beef));
+        bytes.write_f64(2, f64::from_bits(-2i64 as u64));
+
+        let message = decode(&bytes).unwrap();
+        let (fixed32, fixed64) = (message.get(1).unwrap(), message.get(2).unwrap());
+        assert_eq!(fixed32.as_fixed_u32(), Some(0xdead_beef));
+        assert_eq!(fixed32.as_fixed_i32(), Some(0xdead_beef_u32 as i32));
+        assert_eq!(fixed32.raw_bits(), Some(0xdead_beef));
+        assert_eq!(fixed32.as_fixed_u64(), None);
+        assert_eq!(fixed64.as_fixed_i64(), Some(-2));
+        assert_eq!(fixed64.raw_bits(), Some(-2i64 as u64));
+        assert_eq!(Value::VarInt(1.into()).raw_bits(), None);
+    }
+
+    #[test]
+    fn hash_messages() {
+        let mut a = SerializedMessage::new();
+        a.insert(1, Value::Double(f64::NAN));
+        a.insert(2, Value::VarInt(150.into()));
+
+        let mut b = a.clone();
+        assert_eq!(a, b);
+
+        b.insert(3, Value::Float(0.0));
+        let set: std::collections::HashSet<_> = [a.clone(), b, a].into_iter().collect();
+        assert_eq!(set.len(), 2);
+
+        assert_ne!(Value::Float(0.0), Value::Float(-0.0));
+        assert_eq!(Value::VarInt(VarInt::decode(&[0x96, 0x81, 0x80, 0x00])), Value::VarInt(150.into()));
+    }
+
+    #[test]
+    fn wire_order() {
+        let mut inner = vec![];
+        inner.write_u32(2, 1);
+        inner.write_u32(1, 2);
+
+        let mut bytes = vec![];
+        bytes.write_u32(3, 1);
+        bytes.write_u32(1, 2);
+        bytes.write_u32(3, 3);
+        bytes.write_bytes(2, &inner);
+
+        let decoder = Decoder::new(DecodeConfig { preserve_order: true, ..Default::default() });
+        let mut message = decoder.decode(&bytes).unwrap();
+        assert_eq!(message.wire_order(), Some([3, 1, 3, 2].as_slice()));
+        assert_eq!(message.encode(), bytes);
+        assert_eq!(message, decode(&bytes).unwrap());
+        assert_ne!(decode(&bytes).unwrap().encode(), bytes);
+
+        message.set_field(4, Value::VarInt(4.into()));
+        message.insert(3, Value::VarInt(5.into()));
+        let fields: Vec<_> = message.fields_in_order().into_iter().map(|(field, _)| field).collect();
+        assert_eq!(fields, [3, 1, 3, 2, 3, 4]);
+    }
+
+    #[test]
+    fn mutate() {
+        let mut message = SerializedMessage::new();
+        message.insert(1, Value::from(true));
+        message.insert(2, Value::from(1.5f32));
+        message.insert(3, Value::from(2.5f32));
+
+        message.set_field(1, Value::Repeated(vec![Value::Repeated(vec![Value::from(false)])]));
+        assert_eq!(message.get(1), Some(Value::from(false)));
+
+        message.set_field(1, Value::Repeated(vec![]));
+        assert_eq!(message.get(1), None);
+
+        assert!(message.renumber_field(3, 2));
+        assert!(!message.renumber_field(3, 2));
+        assert_eq!(message.get(2), Some(Value::Repeated(vec![Value::from(1.5f32), Value::from(2.5f32)])));
+        assert_eq!(decode(&message.encode()).unwrap(), message);
+
+        assert!(message.remove_field(2).is_some());
+        message.insert(4, Value::from(true));
+        message.clear();
+        assert_eq!(message, SerializedMessage::new());
+    }
+
+    #[test]
+    fn header() {
+        let header = Header::new(MAX_FIELD_NUMBER, WireType::LengthDelimited);
+        let bytes = header.to_bytes();
+        assert_eq!(bytes.len(), header.padded_len());
+        assert_eq!(Header::new(1, WireType::VarInt).to_bytes().len(), 5);
+        assert_eq!(Header::decode_at(&bytes, 0).unwrap(), (header, 5));
+
+        assert_eq!(Header::decode_at(&[0x00, 0x1b], 1).unwrap(), (Header::new(3, WireType::StartGroup), 1));
+        assert!(WireType::StartGroup.is_deprecated());
+        assert!(Header::decode_at(&[0x0e], 0).is_err());
+        assert!(Header::decode_at(&[0x02], 0).is_err());
+    }
 }
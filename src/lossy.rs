@@ -0,0 +1,112 @@
+use std::{error, fmt};
+use crate::incremental::field_len;
+use crate::{Decoder, Error, SerializedMessage, Value};
+
+/// An error found while decoding a message in best effort mode.
+#[derive(Debug)]
+pub struct DecodeError {
+    /// The offset of the field which failed to decode.
+    pub offset: usize,
+    pub error: Error
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "At offset {}: {}", self.offset, self.error)
+    }
+}
+
+impl error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl Decoder {
+    /// Decodes as much of a protobuf-encoded message as possible.
+    ///
+    /// Fields which fail to decode are skipped and reported with their offsets.
+    /// Decoding stops at the first field whose length cannot be determined,
+    /// such as a truncated field, since the next field cannot be found.
+    /// Only the standard wire types are supported.
+    ///
+    /// Each field is decoded on its own, so `max_fields` limits only the
+    /// messages nested in them.
+    pub fn decode_lossy(&self, bytes: &[u8]) -> (SerializedMessage, Vec<DecodeError>) {
+        let mut message = if self.config().preserve_order { SerializedMessage::new_ordered() } else { SerializedMessage::new() };
+        let mut errors = vec![];
+        let mut offset = 0usize;
+
+        while offset < bytes.len() {
+            let field = match field_len(&bytes[offset..], self.config().max_field_bytes) {
+                Ok(Some(len)) => bytes.get(offset..offset + len),
+                Ok(None) => None,
+                Err(error) => {
+                    errors.push(DecodeError { offset, error });
+                    break;
+                }
+            };
+            let Some(field) = field else {
+                errors.push(DecodeError { offset, error: "Invalid message; the field is truncated.".into() });
+                break;
+            };
+
+            match self.decode(field) {
+                Ok(decoded) => {
+                    for (number, value) in decoded {
+                        // A payload read several ways is a repeated value of its readings.
+                        match value {
+                            Value::Repeated(values) => values.into_iter().for_each(|value| message.insert(number, value)),
+                            value => message.insert(number, value)
+                        }
+                    }
+                }
+                Err(error) => errors.push(DecodeError { offset, error })
+            }
+            offset += field.len();
+        }
+
+        (message, errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, decode_lossy, DecodeConfig, ProtobufBytes};
+
+    #[test]
+    fn lossy() {
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+        // Field number zero, which is skipped.
+        let skipped = bytes.len();
+        bytes.extend([0x00, 0x01]);
+        bytes.write_str(2, "Hello, World!");
+        let truncated = bytes.len();
+        bytes.write_str(3, "Goodbye");
+        bytes.truncate(bytes.len() - 2);
+
+        let (message, errors) = decode_lossy(&bytes);
+        assert_eq!(message.get(1), Some(Value::VarInt(150.into())));
+        assert_eq!(message.get(2).unwrap().as_string().unwrap(), "Hello, World!");
+        assert!(message.get(3).is_none());
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].offset, skipped);
+        assert!(matches!(errors[0].error, Error::FieldNumber(0)));
+        assert_eq!(errors[1].offset, truncated);
+
+        // Fields beyond `max_fields` are kept, and each field is read as `decode` reads it.
+        let mut bytes = vec![];
+        bytes.write_u32(1, 1);
+        bytes.write_str(2, "a");
+        bytes.write_str(2, "b");
+        bytes.write_u32(1, 2);
+        let decoder = Decoder::new(DecodeConfig { max_fields: Some(1), ..Default::default() });
+        let (message, errors) = decoder.decode_lossy(&bytes);
+        assert!(decoder.decode(&bytes).is_err());
+        assert!(errors.is_empty());
+        assert_eq!(message, decode(&bytes).unwrap());
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::schema::{FieldType, Schema};
+use crate::{FieldAccess, SerializedMessage, Value};
+
+/// The key of a map field.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MapKey {
+    Integer(i64),
+    String(String)
+}
+
+impl MapKey {
+    /// Creates the entry message holding this key and a value.
+    pub fn entry(&self, value: Value) -> SerializedMessage {
+        let mut entry = SerializedMessage::new();
+        entry.insert(1, match self {
+            MapKey::Integer(key) => Value::VarInt((*key).into()),
+            MapKey::String(key) => Value::String(key.clone())
+        });
+        entry.insert(2, value);
+
+        entry
+    }
+
+    /// Reads a key from the value of an entry's key field.
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::VarInt(key) => Some(MapKey::Integer(key.as_i64())),
+            Value::String(key) => Some(MapKey::String(key.clone())),
+            // A string key which also decodes as a message.
+            Value::Repeated(values) => match values.as_slice() {
+                [Value::String(key), Value::Message(_)] => Some(MapKey::String(key.clone())),
+                _ => None
+            },
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for MapKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapKey::Integer(key) => write!(f, "{key}"),
+            MapKey::String(key) => write!(f, "{key:?}")
+        }
+    }
+}
+
+impl From<i64> for MapKey {
+    fn from(key: i64) -> Self {
+        MapKey::Integer(key)
+    }
+}
+
+impl From<String> for MapKey {
+    fn from(key: String) -> Self {
+        MapKey::String(key)
+    }
+}
+
+impl From<&str> for MapKey {
+    fn from(key: &str) -> Self {
+        MapKey::String(key.to_string())
+    }
+}
+
+/// Folds repeated key/value entry messages into `Value::Map`, throughout the message.
+///
+/// A field is folded if every element is a message with a key in field 1,
+/// a value in field 2, and no other fields, and no key occurs twice.
+/// At least two entries are required, since a single entry
+/// cannot be told apart from an ordinary message.
+pub fn fold_maps(message: &mut SerializedMessage) {
+    fold(message, None);
+}
+
+/// Folds the fields which the schema describes as maps, throughout the message.
+///
+/// Map fields are repeated messages with only fields 1 and 2.
+/// Unlike `fold_maps`, single entries are folded, entries missing a key
+/// or value use the default of its type, and later duplicate keys win.
+pub fn fold_maps_with_schema(message: &mut SerializedMessage, schema: &Schema) {
+    fold(message, Some(schema));
+}
+
+/// Folds the maps of a message, using the schema if one is given.
+fn fold(message: &mut SerializedMessage, schema: Option<&Schema>) {
+    for (field, value) in message.iter_mut() {
+        let field_type = schema.and_then(|schema| schema.get(*field))
+            .map(|field_schema| &field_schema.field_type);
+        let nested = match field_type {
+            Some(FieldType::Message(nested)) => Some(nested),
+            _ => None
+        };
+
+        // Fold the nested messages first, so map values are folded too.
+        match value {
+            Value::Message(message) => fold(message, nested),
+            Value::Repeated(values) => {
+                for value in values {
+                    if let Value::Message(message) = value {
+                        fold(message, nested);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        let map = match (schema, nested) {
+            (None, _) => fold_heuristic(value),
+            (Some(_), Some(entry)) if is_entry_schema(entry) => fold_schema(value, entry),
+            _ => None
+        };
+
+        if let Some(map) = map {
+            *value = Value::Map(map);
+        }
+    }
+}
+
+/// Folds the entries of a field, if they look like a map.
+fn fold_heuristic(value: &Value) -> Option<BTreeMap<MapKey, Value>> {
+    let entries = entries(value)?;
+    if entries.len() < 2 {
+        return None;
+    }
+
+    let mut map = BTreeMap::new();
+    for entry in entries {
+        if entry.iter().any(|(field, _)| *field != 1 && *field != 2) {
+            return None;
+        }
+
+        let key = MapKey::from_value(entry.get_ref(1)?)?;
+        let value = entry.get_ref(2)?.clone();
+        if map.insert(key, value).is_some() {
+            return None;
+        }
+    }
+
+    Some(map)
+}
+
+/// Folds the entries of a field which the schema describes as a map.
+fn fold_schema(value: &Value, schema: &Schema) -> Option<BTreeMap<MapKey, Value>> {
+    let default = |field: u32| schema.get(field).map(|field| default_value(&field.field_type));
+
+    let mut map = BTreeMap::new();
+    for entry in entries(value)? {
+        let key = entry.get_ref(1).cloned().or_else(|| default(1))?;
+        let value = entry.get_ref(2).cloned().or_else(|| default(2))?;
+        map.insert(MapKey::from_value(&key)?, value);
+    }
+
+    Some(map)
+}
+
+/// Returns the entry messages of a field, or `None` if it has other values.
+fn entries(value: &Value) -> Option<Vec<&SerializedMessage>> {
+    match value {
+        Value::Message(message) => Some(vec![message]),
+        Value::Repeated(values) => {
+            let mut entries = vec![];
+            let mut values = values.iter().peekable();
+
+            while let Some(value) = values.next() {
+                match value {
+                    Value::Message(message) => entries.push(message),
+                    // Entries which are valid UTF-8 are also decoded as strings.
+                    Value::String(_) if matches!(values.peek(), Some(Value::Message(_))) => {}
+                    _ => return None
+                }
+            }
+
+            Some(entries)
+        }
+        _ => None
+    }
+}
+
+/// Returns true if the schema only has a key and a value field.
+fn is_entry_schema(schema: &Schema) -> bool {
+    schema.get(1).is_some() && schema.iter().all(|(field, _)| *field == 1 || *field == 2)
+}
+
+/// Returns the value omitted from the wire for a field of the given type.
+fn default_value(field_type: &FieldType) -> Value {
+    match field_type {
+        FieldType::VarInt => Value::VarInt(0.into()),
+        FieldType::Fixed32 => Value::Float(0.0),
+        FieldType::Fixed64 => Value::Double(0.0),
+        FieldType::String => Value::String(String::new()),
+        FieldType::Bytes => Value::Bytes(vec![]),
+        FieldType::Message(_) => Value::Message(SerializedMessage::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn fold() {
+        let mut bytes = vec![];
+        for (key, value) in [("alice", 1), ("bob", 2)] {
+            let mut entry = vec![];
+            entry.write_str(1, key);
+            entry.write_u32(2, value);
+            bytes.write_bytes(1, &entry);
+        }
+        // An ordinary repeated message, with a duplicate "key".
+        for _ in 0..2 {
+            let mut entry = vec![];
+            entry.write_u32(1, 7);
+            entry.write_u32(2, 8);
+            bytes.write_bytes(2, &entry);
+        }
+
+        let mut message = decode(&bytes).unwrap();
+        fold_maps(&mut message);
+
+        let Some(Value::Map(map)) = message.get(1) else {
+            panic!("Expected a map.");
+        };
+        assert_eq!(map[&"bob".into()], Value::VarInt(2.into()));
+        assert!(matches!(message.get(2), Some(Value::Repeated(_))));
+
+        let mut encoded = decode(&message.encode()).unwrap();
+        fold_maps(&mut encoded);
+        assert_eq!(encoded.get(1), message.get(1));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{FieldPath, SerializedMessage, Value, VarInt, WireType};
+
+/// The bits flipped in varints: the lowest bit, the continuation boundary,
+/// and the sign bits of 32-bit and 64-bit integers.
+const FLIPPED_BITS: [u32; 4] = [0, 7, 31, 63];
+
+/// The number of bytes length-delimited values are extended by.
+const EXTENSIONS: [usize; 2] = [1, 1024];
+
+/// The byte length-delimited values are extended with.
+const FILLER: u8 = b'A';
+
+/// A change made to one occurrence of a field.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mutation {
+    /// A bit of a varint was flipped.
+    BitFlip { bit: u32 },
+    /// A length-delimited value was extended by some bytes.
+    Extend { by: usize },
+    /// The value was reinterpreted with another wire type.
+    Confuse { wire_type: WireType },
+    /// The occurrence was deleted.
+    Delete,
+    /// The occurrence was written twice.
+    Duplicate
+}
+
+/// A variation of a message, re-encoded for replaying.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mutant {
+    /// The path of the mutated field.
+    pub path: FieldPath,
+    /// The change made to the field.
+    pub mutation: Mutation,
+    /// The encoded variation.
+    pub bytes: Vec<u8>
+}
+
+/// Produces every systematic variation of a decoded message.
+///
+/// Each variation changes a single occurrence of a single field,
+/// at any nesting level, so a failure can be traced to that change.
+/// Variations are listed field by field, outermost fields first.
+pub fn mutations(message: &SerializedMessage) -> Vec<Mutant> {
+    mutate_message(message)
+        .into_iter()
+        .map(|(path, mutation, message)| Mutant { path, mutation, bytes: message.encode() })
+        .collect()
+}
+
+/// Produces the variations of a message, with paths relative to it.
+fn mutate_message(message: &SerializedMessage) -> Vec<(FieldPath, Mutation, SerializedMessage)> {
+    let mut mutants = vec![];
+    for (field, value) in message {
+        let occurrences = match value {
+            Value::Repeated(values) => values.as_slice(),
+            value => std::slice::from_ref(value)
+        };
+
+        // Replaces one occurrence of the field with any number of values.
+        let replace = |index: usize, replacement: Vec<Value>| {
+            let mut values = occurrences.to_vec();
+            values.splice(index..=index, replacement);
+
+            let mut mutant = message.clone();
+            mutant.set_field(*field, Value::Repeated(values));
+            mutant
+        };
+
+        for (index, occurrence) in occurrences.iter().enumerate() {
+            mutants.push((vec![*field], Mutation::Delete, replace(index, vec![])));
+            mutants.push((vec![*field], Mutation::Duplicate, replace(index, vec![occurrence.clone(); 2])));
+
+            for (mutation, value) in mutate_value(occurrence) {
+                mutants.push((vec![*field], mutation, replace(index, vec![value])));
+            }
+
+            if let Value::Message(nested) = occurrence {
+                for (mut path, mutation, nested) in mutate_message(nested) {
+                    path.insert(0, *field);
+                    mutants.push((path, mutation, replace(index, vec![Value::Message(nested)])));
+                }
+            }
+        }
+    }
+
+    mutants
+}
+
+/// Produces the variations of a single value.
+fn mutate_value(value: &Value) -> Vec<(Mutation, Value)> {
+    let mut mutants = vec![];
+    if let Value::VarInt(varint) = value {
+        for bit in FLIPPED_BITS {
+            let flipped = VarInt::from(varint.as_i64() ^ (1i64 << bit));
+            mutants.push((Mutation::BitFlip { bit }, Value::VarInt(flipped)));
+        }
+    }
+
+    if let Some(payload) = payload(value) {
+        for by in EXTENSIONS {
+            let mut extended = payload.clone();
+            extended.resize(payload.len() + by, FILLER);
+            mutants.push((Mutation::Extend { by }, Value::Bytes(extended)));
+        }
+    }
+
+    // The bits of a scalar, or the length of a payload, reinterpreted with each other wire type.
+    let bits = match value {
+        Value::VarInt(varint) => varint.as_i64() as u64,
+        Value::Float(float) => float.to_bits() as u64,
+        Value::Double(double) => double.to_bits(),
+        value => match payload(value) {
+            Some(payload) => payload.len() as u64,
+            None => return mutants
+        }
+    };
+
+    for wire_type in [WireType::VarInt, WireType::Fixed64, WireType::LengthDelimited, WireType::Fixed32] {
+        let confused = match wire_type {
+            WireType::VarInt if !matches!(value, Value::VarInt(_)) => Value::VarInt(VarInt::from(bits as i64)),
+            WireType::Fixed64 if !matches!(value, Value::Double(_)) => Value::Double(f64::from_bits(bits)),
+            WireType::Fixed32 if !matches!(value, Value::Float(_)) => Value::Float(f32::from_bits(bits as u32)),
+            WireType::LengthDelimited if payload(value).is_none() => Value::Bytes(match value {
+                Value::VarInt(varint) => varint.to_bytes(),
+                Value::Float(float) => float.to_le_bytes().to_vec(),
+                _ => bits.to_le_bytes().to_vec()
+            }),
+            _ => continue
+        };
+        mutants.push((Mutation::Confuse { wire_type }, confused));
+    }
+
+    mutants
+}
+
+/// Returns the payload of a length-delimited value.
+fn payload(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::String(string) => Some(string.as_bytes().to_vec()),
+        Value::Bytes(bytes) => Some(bytes.clone()),
+        Value::Empty => Some(vec![]),
+        Value::Message(message) => Some(message.encode()),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn mutate() {
+        let mut nested = vec![];
+        nested.write_str(1, "hey");
+
+        let mut bytes = vec![];
+        bytes.write_i32(1, 150);
+        bytes.write_bytes(2, &nested);
+        let message = decode(&bytes).unwrap();
+
+        let mutants = mutations(&message);
+        let find = |path: &[u32], mutation: Mutation| {
+            let mutant = mutants.iter().find(|mutant| mutant.path == path && mutant.mutation == mutation).unwrap();
+            decode(&mutant.bytes).unwrap()
+        };
+
+        assert_eq!(find(&[1], Mutation::BitFlip { bit: 0 }).get(1), Some(Value::VarInt(151i64.into())));
+        assert_eq!(find(&[1], Mutation::Delete).get(1), None);
+        assert_eq!(find(&[2, 1], Mutation::Duplicate).get_path(&[2, 1]), Some(Value::Repeated(vec![
+            Value::String("hey".to_string()),
+            Value::String("hey".to_string())
+        ])));
+        assert_eq!(find(&[2, 1], Mutation::Extend { by: 1 }).get_path(&[2, 1]), Some(Value::String("heyA".to_string())));
+        assert_eq!(
+            find(&[2, 1], Mutation::Confuse { wire_type: WireType::VarInt }).get_path(&[2, 1]),
+            Some(Value::VarInt(3i64.into()))
+        );
+        assert!(mutants.iter().all(|mutant| mutant.bytes != bytes));
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use crate::identifier::Identifier;
+use crate::{display, Error, FieldPath, Result, SerializedMessage, Value};
+
+/// Human names for fields, keyed by path, for rendering without a schema.
+///
+/// Paths are written as dot-separated field numbers (e.g. `1.4`), both in
+/// JSON objects and in CSV files of `path,name` lines.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "BTreeMap<String, String>", into = "BTreeMap<String, String>")]
+pub struct FieldNameMap {
+    names: BTreeMap<FieldPath, String>
+}
+
+impl FieldNameMap {
+    /// Creates a new, empty name map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a name map from CSV text.
+    ///
+    /// Each line is a path and a name; blank lines and `#` comments are ignored.
+    pub fn from_csv(text: &str) -> Result<Self> {
+        let mut names = Self::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((path, name)) = line.split_once(',') else {
+                return Err(format!("Invalid field name map; line {} has no name.", number + 1).into());
+            };
+            names.names.insert(parse_path(path.trim())?, name.trim().to_string());
+        }
+
+        Ok(names)
+    }
+
+    /// Names the field at the given path.
+    pub fn insert<S: Into<String>>(&mut self, path: &[u32], name: S) {
+        self.names.insert(path.to_vec(), name.into());
+    }
+
+    /// Returns the name of the field at the given path.
+    pub fn get(&self, path: &[u32]) -> Option<&str> {
+        self.names.get(path).map(String::as_str)
+    }
+
+    /// Returns the number of named fields.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns whether no fields are named.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Labels the fields of a message with their names, for display or serialization.
+    pub fn named<'a>(&'a self, message: &'a SerializedMessage) -> Named<'a> {
+        Named::new(message).with_names(self)
+    }
+}
+
+impl TryFrom<BTreeMap<String, String>> for FieldNameMap {
+    type Error = Error;
+
+    fn try_from(names: BTreeMap<String, String>) -> Result<Self> {
+        let names = names.into_iter()
+            .map(|(path, name)| Ok((parse_path(&path)?, name)))
+            .collect::<Result<_>>()?;
+
+        Ok(Self { names })
+    }
+}
+
+impl From<FieldNameMap> for BTreeMap<String, String> {
+    fn from(names: FieldNameMap) -> Self {
+        names.names.into_iter()
+            .map(|(path, name)| (format_path(&path), name))
+            .collect()
+    }
+}
+
+/// Symbolic names for the values of enum fields, keyed by path.
+///
+/// In JSON, each path maps to an object of values and their names
+/// (e.g. `{"7": {"0": "IDLE", "1": "RUNNING"}}`); in CSV files, each
+/// line is a `path,value,name` triple.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "BTreeMap<String, BTreeMap<i64, String>>", into = "BTreeMap<String, BTreeMap<i64, String>>")]
+pub struct EnumNameMap {
+    enums: BTreeMap<FieldPath, BTreeMap<i64, String>>
+}
+
+impl EnumNameMap {
+    /// Creates a new, empty enum map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses an enum map from CSV text.
+    ///
+    /// Each line is a path, a value and a name; blank lines and `#` comments are ignored.
+    pub fn from_csv(text: &str) -> Result<Self> {
+        let mut enums = Self::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut columns = line.splitn(3, ',').map(str::trim);
+            let (Some(path), Some(value), Some(name)) = (columns.next(), columns.next(), columns.next()) else {
+                return Err(format!("Invalid enum map; line {} is not a path, value and name.", number + 1).into());
+            };
+            let Ok(value) = value.parse() else {
+                return Err(format!("Invalid enum map; line {} has an invalid value.", number + 1).into());
+            };
+            enums.insert(&parse_path(path)?, value, name);
+        }
+
+        Ok(enums)
+    }
+
+    /// Names a value of the enum field at the given path.
+    pub fn insert<S: Into<String>>(&mut self, path: &[u32], value: i64, name: S) {
+        self.enums.entry(path.to_vec()).or_default().insert(value, name.into());
+    }
+
+    /// Returns the name of a value of the enum field at the given path.
+    pub fn get(&self, path: &[u32], value: i64) -> Option<&str> {
+        self.enums.get(path)?.get(&value).map(String::as_str)
+    }
+
+    /// Returns the number of enum fields.
+    pub fn len(&self) -> usize {
+        self.enums.len()
+    }
+
+    /// Returns whether no enum fields are named.
+    pub fn is_empty(&self) -> bool {
+        self.enums.is_empty()
+    }
+}
+
+impl TryFrom<BTreeMap<String, BTreeMap<i64, String>>> for EnumNameMap {
+    type Error = Error;
+
+    fn try_from(enums: BTreeMap<String, BTreeMap<i64, String>>) -> Result<Self> {
+        let enums = enums.into_iter()
+            .map(|(path, values)| Ok((parse_path(&path)?, values)))
+            .collect::<Result<_>>()?;
+
+        Ok(Self { enums })
+    }
+}
+
+impl From<EnumNameMap> for BTreeMap<String, BTreeMap<i64, String>> {
+    fn from(enums: EnumNameMap) -> Self {
+        enums.enums.into_iter()
+            .map(|(path, values)| (format_path(&path), values))
+            .collect()
+    }
+}
+
+/// The names used to label fields and enum values when rendering.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct Labels<'a> {
+    pub(crate) fields: Option<&'a FieldNameMap>,
+    pub(crate) enums: Option<&'a EnumNameMap>,
+    /// Whether values which look like Unix timestamps are commented with their date.
+    pub(crate) timestamps: bool,
+    /// Whether bytes which look like UUIDs or IP addresses are annotated with them.
+    pub(crate) identifiers: bool
+}
+
+/// A message whose fields and enum values are labelled with their names, where known.
+///
+/// Displays like `SerializedMessage`, and serializes with field names as
+/// keys; named enum values keep their number, as `RUNNING (1)` in text and
+/// as `{"name": "RUNNING", "value": 1}` in JSON.
+#[derive(Copy, Clone, Debug)]
+pub struct Named<'a> {
+    message: &'a SerializedMessage,
+    labels: Labels<'a>
+}
+
+impl<'a> Named<'a> {
+    /// Wraps a message, without any names yet.
+    pub fn new(message: &'a SerializedMessage) -> Self {
+        Self { message, labels: Labels::default() }
+    }
+
+    /// Labels fields with their names in the given map.
+    pub fn with_names(mut self, names: &'a FieldNameMap) -> Self {
+        self.labels.fields = Some(names);
+        self
+    }
+
+    /// Labels enum values with their names in the given map.
+    pub fn with_enums(mut self, enums: &'a EnumNameMap) -> Self {
+        self.labels.enums = Some(enums);
+        self
+    }
+
+    /// Comments varint and fixed64 values which look like Unix timestamps
+    /// with their ISO-8601 date, as `1700000000 /* 2023-11-14T22:13:20Z */`.
+    ///
+    /// Only the text rendering is affected; see `EpochUnit::detect`.
+    pub fn with_timestamps(mut self) -> Self {
+        self.labels.timestamps = true;
+        self
+    }
+
+    /// Annotates bytes which look like UUIDs or IP addresses with their
+    /// usual notation, keeping the raw bytes, as `/* ipv4 203.0.113.7 */` in
+    /// text and as `{"value": "ywBxBw==", "ipv4": "203.0.113.7"}` in JSON.
+    ///
+    /// See `Identifier::detect`.
+    pub fn with_identifiers(mut self) -> Self {
+        self.labels.identifiers = true;
+        self
+    }
+}
+
+impl fmt::Display for Named<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let indent = f.alternate().then_some(0);
+        display::write_message(f, self.message, indent, self.labels, &mut vec![])
+    }
+}
+
+impl Serialize for Named<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        NamedMessage { message: self.message, labels: self.labels, path: vec![] }.serialize(serializer)
+    }
+}
+
+struct NamedMessage<'a> {
+    message: &'a SerializedMessage,
+    labels: Labels<'a>,
+    path: FieldPath
+}
+
+impl Serialize for NamedMessage<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.message.iter().map(|(field, value)| {
+            let mut path = self.path.clone();
+            path.push(*field);
+
+            let key = match self.labels.fields.and_then(|names| names.get(&path)) {
+                Some(name) => name.to_string(),
+                None => field.to_string()
+            };
+            (key, NamedValue { value, labels: self.labels, path })
+        }))
+    }
+}
+
+struct NamedValue<'a> {
+    value: &'a Value,
+    labels: Labels<'a>,
+    path: FieldPath
+}
+
+impl NamedValue<'_> {
+    fn child<'a>(&'a self, value: &'a Value) -> NamedValue<'a> {
+        NamedValue { value, labels: self.labels, path: self.path.clone() }
+    }
+}
+
+impl Serialize for NamedValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.value {
+            Value::VarInt(varint) => match self.labels.enums.and_then(|enums| enums.get(&self.path, varint.as_i64())) {
+                Some(name) => {
+                    let mut state = serializer.serialize_struct("EnumValue", 2)?;
+                    state.serialize_field("name", name)?;
+                    state.serialize_field("value", varint)?;
+                    state.end()
+                }
+                None => varint.serialize(serializer)
+            },
+            Value::Message(message) => {
+                NamedMessage { message, labels: self.labels, path: self.path.clone() }.serialize(serializer)
+            }
+            // Addresses such as 127.0.0.1 are valid UTF-8, so string readings are checked too.
+            Value::Bytes(_) | Value::String(_) => {
+                match raw(self.value).and_then(Identifier::detect).filter(|_| self.labels.identifiers) {
+                    Some(identifier) => {
+                        let mut state = serializer.serialize_struct("Identifier", 2)?;
+                        state.serialize_field("value", self.value)?;
+                        state.serialize_field(identifier.kind(), &identifier.to_string())?;
+                        state.end()
+                    }
+                    None => self.value.serialize(serializer)
+                }
+            }
+            Value::Repeated(values) => serializer.collect_seq(values.iter().map(|value| self.child(value))),
+            Value::Map(entries) => serializer.collect_map(entries.iter().map(|(key, value)| (key, self.child(value)))),
+            value => value.serialize(serializer)
+        }
+    }
+}
+
+/// Returns the payload of a bytes or string value.
+fn raw(value: &Value) -> Option<&[u8]> {
+    match value {
+        Value::Bytes(bytes) => Some(bytes),
+        Value::String(string) => Some(string.as_bytes()),
+        _ => None
+    }
+}
+
+/// Parses a dot-separated path of field numbers.
+pub(crate) fn parse_path(path: &str) -> Result<FieldPath> {
+    path.split('.')
+        .map(|field| field.parse().map_err(|_| format!("Invalid field path '{path}'.").into()))
+        .collect()
+}
+
+/// Formats a path as dot-separated field numbers.
+pub(crate) fn format_path(path: &[u32]) -> String {
+    path.iter().map(u32::to_string).collect::<Vec<_>>().join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn named() {
+        let mut player = vec![];
+        player.write_u32(4, 1234);
+        player.write_str(5, "Traveler");
+
+        let mut bytes = vec![];
+        bytes.write_bytes(1, &player);
+        bytes.write_u32(2, 7);
+
+        let names = FieldNameMap::from_csv("# path,name\n1,player\n1.4,player_id\n\n2 , level\n").unwrap();
+        assert_eq!(names.len(), 3);
+        assert!(FieldNameMap::from_csv("1.x,name").is_err());
+
+        let message = decode(&bytes).unwrap();
+        assert_eq!(names.named(&message).to_string(), r#"{player: {player_id: 1234, 5: "Traveler"}, level: 7}"#);
+        assert_eq!(
+            serde_json::to_string(&names.named(&message)).unwrap(),
+            r#"{"player":{"player_id":1234,"5":"Traveler"},"level":7}"#
+        );
+
+        let mut enums = EnumNameMap::from_csv("2,7,RUNNING\n2,-1,UNKNOWN").unwrap();
+        assert_eq!(enums.get(&[2], -1), Some("UNKNOWN"));
+        enums.insert(&[1, 4], 0, "NONE");
+        let named = Named::new(&message).with_names(&names).with_enums(&enums);
+        assert_eq!(named.to_string(), r#"{player: {player_id: 1234, 5: "Traveler"}, level: RUNNING (7)}"#);
+        assert_eq!(
+            serde_json::to_string(&named).unwrap(),
+            r#"{"player":{"player_id":1234,"5":"Traveler"},"level":{"name":"RUNNING","value":7}}"#
+        );
+        let json = serde_json::to_string(&enums).unwrap();
+        assert_eq!(serde_json::from_str::<EnumNameMap>(&json).unwrap(), enums);
+
+        let mut times = vec![];
+        times.write_u64(1, 1_700_000_000);
+        times.write_f64(2, f64::from_bits(1_700_000_000_500));
+        times.write_u32(3, 7);
+        let times = decode(&times).unwrap();
+        assert_eq!(
+            Named::new(&times).with_timestamps().to_string(),
+            "{1: 1700000000 /* 2023-11-14T22:13:20Z */, 2: 1700000000500 /* 2023-11-14T22:13:20.500Z */, 3: 7}"
+        );
+        assert!(!times.to_string().contains("/*"));
+
+        let mut address = vec![];
+        address.write_bytes(1, &[203, 0, 113, 7]);
+        let address = decode(&address).unwrap();
+        let named = Named::new(&address).with_identifiers();
+        assert_eq!(named.to_string(), "{1: <4 bytes: cb 00 71 07> /* ipv4 203.0.113.7 */}");
+        assert_eq!(serde_json::to_string(&named).unwrap(), r#"{"1":{"value":"ywBxBw==","ipv4":"203.0.113.7"}}"#);
+
+        let mut loopback = vec![];
+        loopback.write_bytes(1, &[127, 0, 0, 1]);
+        let loopback = decode(&loopback).unwrap();
+        assert!(matches!(loopback.get(1), Some(Value::String(_))));
+        let named = Named::new(&loopback).with_identifiers();
+        assert!(serde_json::to_string(&named).unwrap().ends_with(r#","ipv4":"127.0.0.1"}}"#));
+        assert!(named.to_string().ends_with("/* ipv4 127.0.0.1 */}"));
+
+        let json = serde_json::to_string(&names).unwrap();
+        assert_eq!(json, r#"{"1":"player","1.4":"player_id","2":"level"}"#);
+        assert_eq!(serde_json::from_str::<FieldNameMap>(&json).unwrap(), names);
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::schema::Schema;
+use crate::{FieldPath, SerializedMessage, Value};
+
+/// The schemas and remapping tables used by one protocol version.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// The schemas of the version, keyed by message name.
+    pub schemas: BTreeMap<String, Schema>,
+    /// Maps the message (command) ids of the version onto canonical ids.
+    pub remap: BTreeMap<u32, u32>
+}
+
+impl Profile {
+    /// Maps a message id of this version onto its canonical id.
+    ///
+    /// Ids without a mapping are returned unchanged.
+    pub fn remap(&self, id: u32) -> u32 {
+        self.remap.get(&id).copied().unwrap_or(id)
+    }
+}
+
+/// Detects the protocol version from handshake messages.
+#[derive(Clone, Debug)]
+pub struct VersionDetector {
+    paths: Vec<FieldPath>,
+    profiles: BTreeMap<String, Profile>,
+    max_messages: usize
+}
+
+impl Default for VersionDetector {
+    fn default() -> Self {
+        Self { paths: vec![], profiles: BTreeMap::new(), max_messages: 8 }
+    }
+}
+
+impl VersionDetector {
+    /// Creates a detector with no paths or profiles.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a path which may hold the version; paths are checked in the order added.
+    pub fn add_path(&mut self, path: FieldPath) {
+        self.paths.push(path);
+    }
+
+    /// Registers the profile of a version.
+    pub fn add_profile<S: Into<String>>(&mut self, version: S, profile: Profile) {
+        self.profiles.insert(version.into(), profile);
+    }
+
+    /// Sets how many messages of a session are inspected before giving up.
+    pub fn set_max_messages(&mut self, max_messages: usize) {
+        self.max_messages = max_messages;
+    }
+
+    /// Reads the version from a message.
+    ///
+    /// Strings are used as-is; integers are formatted in decimal.
+    pub fn detect(&self, message: &SerializedMessage) -> Option<String> {
+        self.paths.iter()
+            .filter_map(|path| message.get_path(path))
+            .find_map(|value| match value {
+                Value::String(version) => Some(version),
+                Value::VarInt(version) => Some(version.as_i64().to_string()),
+                _ => None
+            })
+    }
+
+    /// Starts negotiating the version of a new session.
+    pub fn session(&self) -> Negotiation<'_> {
+        Negotiation { detector: self, observed: 0, version: None }
+    }
+}
+
+/// The version negotiation state of a single session.
+pub struct Negotiation<'a> {
+    detector: &'a VersionDetector,
+    observed: usize,
+    version: Option<String>
+}
+
+impl<'a> Negotiation<'a> {
+    /// Inspects a message of the session, returning the selected profile.
+    ///
+    /// Once a version with a registered profile is detected,
+    /// it is used for the rest of the session.
+    pub fn observe(&mut self, message: &SerializedMessage) -> Option<&'a Profile> {
+        if self.version.is_none() && self.observed < self.detector.max_messages {
+            self.observed += 1;
+            self.version = self.detector.detect(message)
+                .filter(|version| self.detector.profiles.contains_key(version));
+        }
+
+        self.profile()
+    }
+
+    /// Returns the detected version, if any.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Returns the profile of the detected version, if any.
+    pub fn profile(&self) -> Option<&'a Profile> {
+        self.version.as_ref().and_then(|version| self.detector.profiles.get(version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate() {
+        let mut profile = Profile::default();
+        profile.remap.insert(101, 1);
+
+        let mut detector = VersionDetector::new();
+        detector.add_path(vec![2, 1]);
+        detector.add_profile("4.0.0", profile);
+
+        let mut handshake = SerializedMessage::new();
+        handshake.insert(1, "4.0.0".to_string().into());
+        let mut client = SerializedMessage::new();
+        client.insert(2, handshake.into());
+
+        let mut session = detector.session();
+        assert!(session.observe(&SerializedMessage::new()).is_none());
+        assert_eq!(session.observe(&client).unwrap().remap(101), 1);
+        assert!(session.observe(&SerializedMessage::new()).is_some());
+        assert_eq!(session.version(), Some("4.0.0"));
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The longest a message is delayed by its pacing, so that tiny rates and
+/// speeds wait a day rather than overflowing.
+const MAX_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How fast messages are sent when replaying or forwarding traffic.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Pacing {
+    /// Messages are sent as fast as possible.
+    Unlimited,
+    /// Messages are sent at a constant rate, in messages per second.
+    Rate(f64),
+    /// Messages are sent with their original spacing, scaled by a speed multiplier.
+    ///
+    /// A speed of `2.0` replays twice as fast as the capture.
+    Original { speed: f64 }
+}
+
+/// A limit on how many messages may be sent within a time window.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BurstLimit {
+    pub messages: usize,
+    pub window: Duration
+}
+
+/// Schedules messages according to a pacing policy.
+///
+/// Used by `PcapReader::replay` and the proxy's `serve_paced`.
+///
+/// ```no_run
+/// # use protoshark::pacing::{Pacer, Pacing};
+/// let mut pacer = Pacer::new(Pacing::Rate(10.0));
+/// for _ in 0..100 {
+///     pacer.wait(None);
+///     // Send the message.
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Pacer {
+    pacing: Pacing,
+    burst: Option<BurstLimit>,
+    /// The time the last message was scheduled for.
+    last: Option<Instant>,
+    /// The send time and capture timestamp of the first message.
+    anchor: Option<(Instant, Duration)>,
+    /// The send times of messages within the burst window.
+    recent: VecDeque<Instant>
+}
+
+impl Pacer {
+    /// Creates a new pacer with no burst limit.
+    pub fn new(pacing: Pacing) -> Self {
+        Self { pacing, burst: None, last: None, anchor: None, recent: VecDeque::new() }
+    }
+
+    /// Sets the burst limit, applied in addition to the pacing policy.
+    pub fn set_burst_limit(&mut self, burst: Option<BurstLimit>) {
+        self.burst = burst;
+        self.recent.clear();
+    }
+
+    /// Waits until the next message may be sent.
+    ///
+    /// `timestamp`: The capture timestamp of the message, used by `Pacing::Original`.
+    pub fn wait(&mut self, timestamp: Option<Duration>) {
+        let delay = self.delay_at(Instant::now(), timestamp);
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+
+    /// Schedules the next message, returning how long to wait from `now` before sending it.
+    ///
+    /// The delay from pacing alone is at most a day, however small the rate or speed.
+    ///
+    /// `timestamp`: The capture timestamp of the message, used by `Pacing::Original`.
+    pub fn delay_at(&mut self, now: Instant, timestamp: Option<Duration>) -> Duration {
+        let mut target = match self.pacing {
+            Pacing::Unlimited => now,
+            Pacing::Rate(rate) => match self.last {
+                Some(last) if rate > 0.0 => last + seconds(1.0 / rate),
+                _ => now
+            },
+            Pacing::Original { speed } => match (self.anchor, timestamp) {
+                (Some((start, first)), Some(timestamp)) if speed > 0.0 => {
+                    start + seconds(timestamp.saturating_sub(first).as_secs_f64() / speed)
+                }
+                (None, Some(timestamp)) => {
+                    self.anchor = Some((now, timestamp));
+                    now
+                }
+                _ => now
+            }
+        }.max(now);
+
+        if let Some(burst) = self.burst {
+            // Forget sends which have left the window.
+            while self.recent.front().is_some_and(|sent| *sent + burst.window <= target) {
+                self.recent.pop_front();
+            }
+
+            if burst.messages > 0 && self.recent.len() >= burst.messages {
+                let oldest = self.recent[self.recent.len() - burst.messages];
+                target = target.max(oldest + burst.window);
+            }
+
+            self.recent.push_back(target);
+        }
+
+        self.last = Some(target);
+        target - now
+    }
+}
+
+/// Converts seconds into a delay, clamped to `MAX_DELAY`.
+fn seconds(seconds: f64) -> Duration {
+    Duration::try_from_secs_f64(seconds).map_or(MAX_DELAY, |delay| delay.min(MAX_DELAY))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn original_pacing_with_burst() {
+        let mut pacer = Pacer::new(Pacing::Original { speed: 2.0 });
+        pacer.set_burst_limit(Some(BurstLimit { messages: 2, window: Duration::from_secs(1) }));
+
+        let now = Instant::now();
+        let delays: Vec<_> = [0, 1000, 1000, 1000]
+            .into_iter()
+            .map(|millis| pacer.delay_at(now, Some(Duration::from_millis(millis))))
+            .collect();
+
+        assert_eq!(delays, vec![
+            Duration::ZERO,
+            Duration::from_millis(500),
+            Duration::from_millis(1000),
+            Duration::from_millis(1500)
+        ]);
+    }
+
+    #[test]
+    fn extreme_pacing() {
+        let now = Instant::now();
+        let mut pacer = Pacer::new(Pacing::Rate(f64::MIN_POSITIVE));
+        pacer.delay_at(now, None);
+        assert_eq!(pacer.delay_at(now, None), MAX_DELAY);
+
+        let mut pacer = Pacer::new(Pacing::Original { speed: f64::MIN_POSITIVE });
+        pacer.delay_at(now, Some(Duration::ZERO));
+        assert_eq!(pacer.delay_at(now, Some(Duration::from_secs(1))), MAX_DELAY);
+    }
+}
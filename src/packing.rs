@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::collections::btree_map;
+use crate::{FieldPath, SerializedMessage, Value};
+
+/// The most distinct values a field may have to be considered an enum.
+const MAX_ENUM_VALUES: usize = 16;
+
+/// A Rust integer type.
+///
+/// There is no `u8`, since a `Vec<u8>` field holds bytes rather than repeated varints.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IntegerType {
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64
+}
+
+impl IntegerType {
+    /// Returns the smallest integer type which can hold every value in the range,
+    /// preferring unsigned types above `i8`.
+    pub fn fitting(min: i64, max: i64) -> Self {
+        if min >= i8::MIN as i64 && max <= i8::MAX as i64 {
+            IntegerType::I8
+        } else if min >= 0 {
+            if max <= u16::MAX as i64 {
+                IntegerType::U16
+            } else if max <= u32::MAX as i64 {
+                IntegerType::U32
+            } else {
+                IntegerType::U64
+            }
+        } else if min >= i16::MIN as i64 && max <= i16::MAX as i64 {
+            IntegerType::I16
+        } else if min >= i32::MIN as i64 && max <= i32::MAX as i64 {
+            IntegerType::I32
+        } else {
+            IntegerType::I64
+        }
+    }
+
+    /// Returns the name of the type in Rust source.
+    pub fn rust_name(&self) -> &'static str {
+        match self {
+            IntegerType::U16 => "u16",
+            IntegerType::U32 => "u32",
+            IntegerType::U64 => "u64",
+            IntegerType::I8 => "i8",
+            IntegerType::I16 => "i16",
+            IntegerType::I32 => "i32",
+            IntegerType::I64 => "i64"
+        }
+    }
+}
+
+/// How a varint field can be stored compactly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackingHint {
+    /// The smallest integer type which fits every observed value.
+    pub integer: IntegerType,
+    /// The smallest observed value.
+    pub min: i64,
+    /// The largest observed value.
+    pub max: i64,
+    /// The number of values observed.
+    pub samples: usize,
+    /// The distinct observed values, if the field looks like an enum.
+    ///
+    /// Such fields can be compacted into an enum.
+    pub enum_values: Option<BTreeSet<i64>>
+}
+
+/// Packing hints for the varint fields of a message type, keyed by path.
+///
+/// Derived from corpus statistics, for generating memory-efficient typed views.
+/// Every `IntegerType` implements `FromValue` and `ToValue`, so types deriving
+/// `FromSerializedMessage` and `ToSerializedMessage` can declare each field
+/// as its hinted type; values which do not fit fail to extract.
+#[derive(Clone, Debug, Default)]
+pub struct PackingHints {
+    hints: BTreeMap<FieldPath, PackingHint>
+}
+
+/// The values observed for a single field.
+struct Observed {
+    min: i64,
+    max: i64,
+    samples: usize,
+    /// The distinct values, until there are too many to be an enum.
+    distinct: Option<BTreeSet<i64>>
+}
+
+impl PackingHints {
+    /// Derives packing hints from a corpus of samples of the same message type.
+    pub fn from_corpus<'a, I: IntoIterator<Item = &'a SerializedMessage>>(messages: I) -> Self {
+        let mut observed = BTreeMap::new();
+        for message in messages {
+            observe(message, &mut vec![], &mut observed);
+        }
+
+        let hints = observed.into_iter()
+            .map(|(path, observed)| {
+                // A field is only an enum if values repeat.
+                let enum_values = observed.distinct
+                    .filter(|distinct| distinct.len() < observed.samples);
+
+                (path, PackingHint {
+                    integer: IntegerType::fitting(observed.min, observed.max),
+                    min: observed.min,
+                    max: observed.max,
+                    samples: observed.samples,
+                    enum_values
+                })
+            })
+            .collect();
+
+        Self { hints }
+    }
+
+    /// Gets the hint for the field at the given path.
+    pub fn get(&self, path: &[u32]) -> Option<&PackingHint> {
+        self.hints.get(path)
+    }
+
+    /// Returns an iterator over the hints, ordered by path.
+    pub fn iter(&self) -> btree_map::Iter<'_, FieldPath, PackingHint> {
+        self.hints.iter()
+    }
+}
+
+/// Records every varint in the message.
+fn observe(message: &SerializedMessage, path: &mut FieldPath, observed: &mut BTreeMap<FieldPath, Observed>) {
+    for (field, value) in message {
+        path.push(*field);
+        observe_value(value, path, observed);
+        path.pop();
+    }
+}
+
+/// Records every varint in the value.
+fn observe_value(value: &Value, path: &mut FieldPath, observed: &mut BTreeMap<FieldPath, Observed>) {
+    match value {
+        Value::VarInt(varint) => {
+            let value = varint.as_i64();
+            let field = observed.entry(path.clone()).or_insert_with(|| Observed {
+                min: value,
+                max: value,
+                samples: 0,
+                distinct: Some(BTreeSet::new())
+            });
+
+            field.min = field.min.min(value);
+            field.max = field.max.max(value);
+            field.samples += 1;
+
+            if let Some(distinct) = &mut field.distinct {
+                distinct.insert(value);
+                if distinct.len() > MAX_ENUM_VALUES {
+                    field.distinct = None;
+                }
+            }
+        }
+        Value::Message(message) => observe(message, path, observed),
+        Value::Repeated(values) => {
+            for value in values {
+                observe_value(value, path, observed);
+            }
+        }
+        Value::Map(entries) => {
+            for value in entries.values() {
+                observe_value(value, path, observed);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corpus_hints() {
+        let corpus: Vec<_> = (0..100i64)
+            .map(|i| {
+                let mut message = SerializedMessage::new();
+                message.insert(1, Value::VarInt((i * 1000).into()));
+                message.insert(2, Value::VarInt((i % 3).into()));
+                message
+            })
+            .collect();
+
+        let hints = PackingHints::from_corpus(&corpus);
+
+        let counter = hints.get(&[1]).unwrap();
+        assert_eq!(counter.integer, IntegerType::U32);
+        assert!(counter.enum_values.is_none());
+
+        let state = hints.get(&[2]).unwrap();
+        assert_eq!(state.integer.rust_name(), "i8");
+        assert_eq!(state.enum_values.as_ref().unwrap().len(), 3);
+
+        #[cfg(feature = "derive")]
+        {
+            use crate::{FromSerializedMessage, ToSerializedMessage};
+
+            #[derive(Debug, PartialEq, FromSerializedMessage, ToSerializedMessage)]
+            struct Packed {
+                #[field(1)]
+                counter: u32,
+                #[field(2)]
+                state: i8
+            }
+
+            let packed = Packed::from_message(&corpus[42]).unwrap();
+            assert_eq!(packed, Packed { counter: 42_000, state: 0 });
+            assert_eq!(packed.to_message(), corpus[42]);
+
+            let mut outlier = SerializedMessage::new();
+            outlier.insert(1, Value::VarInt(0.into()));
+            outlier.insert(2, Value::VarInt(128.into()));
+            assert!(Packed::from_message(&outlier).is_err());
+        }
+    }
+}
@@ -0,0 +1,454 @@
+use std::fmt;
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD};
+use crate::{decode, looks_like_protobuf, FieldPath, SerializedMessage, Value};
+
+/// Payloads at least this long may be reported as encrypted.
+const MIN_ENCRYPTED_LEN: usize = 32;
+
+/// Strings shorter than this are not checked for embedded payloads.
+const MIN_EMBEDDED_LEN: usize = 8;
+
+/// The most bytes a payload is decompressed to by `Compression::decompress`,
+/// so that a small payload cannot expand to exhaust memory.
+pub const MAX_DECOMPRESSED_BYTES: usize = 64 * 1024 * 1024;
+
+/// A compression format, recognized by its magic number.
+///
+/// Brotli streams have no magic number, so they cannot be
+/// told apart from encrypted data and are not recognized.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Compression {
+    Gzip,
+    Zlib,
+    Zstd
+}
+
+impl Compression {
+    /// Recognizes the compression format of a payload by its magic number.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0x1f, 0x8b, ..] => Some(Compression::Gzip),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Compression::Zstd),
+            // A deflate header with a valid check value.
+            [cmf, flg, ..] if cmf & 0x0f == 8 && cmf >> 4 <= 7 && u16::from_be_bytes([*cmf, *flg]) % 31 == 0 => {
+                Some(Compression::Zlib)
+            }
+            _ => None
+        }
+    }
+
+    /// Decompresses a payload in this format, up to `MAX_DECOMPRESSED_BYTES`.
+    #[cfg(feature = "gzip")]
+    pub fn decompress(&self, bytes: &[u8]) -> crate::Result<Vec<u8>> {
+        self.decompress_limited(bytes, MAX_DECOMPRESSED_BYTES)
+    }
+
+    /// Decompresses a payload in this format, failing if it expands beyond `limit` bytes.
+    #[cfg(feature = "gzip")]
+    pub fn decompress_limited(&self, bytes: &[u8], limit: usize) -> crate::Result<Vec<u8>> {
+        match self {
+            Compression::Gzip => read_limited(flate2::read::GzDecoder::new(bytes), limit),
+            Compression::Zlib => read_limited(flate2::read::ZlibDecoder::new(bytes), limit),
+            #[cfg(feature = "compression")]
+            Compression::Zstd => {
+                let decoder = ruzstd::decoding::StreamingDecoder::new(bytes)
+                    .map_err(|error| format!("Invalid zstd payload; {error}."))?;
+                read_limited(decoder, limit)
+            }
+            #[cfg(not(feature = "compression"))]
+            Compression::Zstd => Err("Zstandard payloads require the `compression` feature.".into())
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compression::Gzip => "gzip",
+            Compression::Zlib => "zlib",
+            Compression::Zstd => "zstd"
+        })
+    }
+}
+
+/// What a bytes payload likely contains.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PayloadReport {
+    /// The Shannon entropy of the payload, in bits per byte, from `0.0` to `8.0`.
+    pub entropy: f64,
+    /// The compression format, if the payload starts with a known magic number.
+    pub compression: Option<Compression>,
+    /// Whether the payload is indistinguishable from random data,
+    /// which usually means it is encrypted.
+    pub likely_encrypted: bool
+}
+
+impl PayloadReport {
+    /// Inspects a payload.
+    pub fn of(bytes: &[u8]) -> Self {
+        let entropy = entropy(bytes);
+        let compression = Compression::detect(bytes);
+
+        // Short payloads cannot reach 8 bits per byte, so compare against their maximum.
+        let max = (bytes.len().min(256) as f64).log2();
+        let likely_encrypted = compression.is_none()
+            && bytes.len() >= MIN_ENCRYPTED_LEN
+            && entropy >= max * 0.95;
+
+        Self { entropy, compression, likely_encrypted }
+    }
+}
+
+/// Reads a decompressing reader to the end, failing once it yields more than `limit` bytes.
+#[cfg(feature = "gzip")]
+fn read_limited<R: std::io::Read>(reader: R, limit: usize) -> crate::Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut payload = vec![];
+    reader.take(limit as u64 + 1).read_to_end(&mut payload)?;
+    if payload.len() > limit {
+        return Err(format!("Invalid payload; decompressed size exceeds the limit of {limit} bytes.").into());
+    }
+
+    Ok(payload)
+}
+
+/// Returns the Shannon entropy of the bytes, in bits per byte.
+pub fn entropy(bytes: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for byte in bytes {
+        counts[*byte as usize] += 1;
+    }
+
+    let len = bytes.len() as f64;
+    counts.iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Inspects every bytes field of a message, including nested ones.
+pub fn inspect(message: &SerializedMessage) -> Vec<(FieldPath, PayloadReport)> {
+    let mut reports = vec![];
+    for_each_bytes(message, &mut vec![], &mut |path, bytes| {
+        reports.push((path.clone(), PayloadReport::of(bytes)));
+    });
+
+    reports
+}
+
+/// Decompresses every compressed bytes field and dissects the inner payload.
+///
+/// Payloads which fail to decompress or decode are skipped.
+#[cfg(feature = "gzip")]
+pub fn dissect_compressed(message: &SerializedMessage) -> Vec<(FieldPath, Compression, SerializedMessage)> {
+    let mut found = vec![];
+    for_each_bytes(message, &mut vec![], &mut |path, bytes| {
+        let Some(compression) = Compression::detect(bytes) else {
+            return;
+        };
+
+        let inner = compression.decompress(bytes).and_then(|inner| crate::decode(&inner));
+        if let Ok(inner) = inner {
+            found.push((path.clone(), compression, inner));
+        }
+    });
+
+    found
+}
+
+/// A compressed payload, with the message dissected from it.
+#[cfg(feature = "compression")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decompressed {
+    pub compression: Compression,
+    /// The length of the compressed payload.
+    pub compressed_len: usize,
+    /// The length of the decompressed payload.
+    pub len: usize,
+    /// The message decoded from the decompressed payload.
+    pub message: SerializedMessage
+}
+
+#[cfg(feature = "compression")]
+impl Decompressed {
+    /// Decompresses a payload in the given format and decodes the message in it.
+    pub fn dissect(compression: Compression, bytes: &[u8]) -> crate::Result<Self> {
+        let payload = compression.decompress(bytes)?;
+        let message = decode(&payload)?;
+
+        Ok(Self { compression, compressed_len: bytes.len(), len: payload.len(), message })
+    }
+
+    /// Recognizes, decompresses, and decodes a compressed payload.
+    ///
+    /// Returns `None` unless the payload has a known magic number,
+    /// decompresses, and decodes as a message.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        Self::dissect(Compression::detect(bytes)?, bytes).ok()
+    }
+}
+
+/// Decompresses every compressed bytes field, including nested ones,
+/// and dissects the inner payload along with its sizes.
+///
+/// This is an opt-in pass; decoding never applies it. Payloads which
+/// fail to decompress or decode, or which expand beyond
+/// `MAX_DECOMPRESSED_BYTES`, are skipped.
+#[cfg(feature = "compression")]
+pub fn decompress_fields(message: &SerializedMessage) -> Vec<(FieldPath, Decompressed)> {
+    let mut found = vec![];
+    for_each_bytes(message, &mut vec![], &mut |path, bytes| {
+        if let Some(decompressed) = Decompressed::detect(bytes) {
+            found.push((path.clone(), decompressed));
+        }
+    });
+
+    found
+}
+
+/// The text encoding of a payload embedded in a string field.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextEncoding {
+    Hex,
+    /// Standard or URL-safe Base64, with or without padding.
+    Base64
+}
+
+/// A protobuf message embedded in a string field as hex or Base64.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Embedded {
+    pub encoding: TextEncoding,
+    /// The decoded bytes of the string.
+    pub bytes: Vec<u8>,
+    /// The message decoded from the bytes.
+    pub message: SerializedMessage
+}
+
+impl Embedded {
+    /// Decodes a string as a hex or Base64 encoded protobuf message.
+    ///
+    /// Returns `None` unless the string is entirely hex or Base64,
+    /// and the decoded bytes likely are a protobuf message.
+    pub fn detect(text: &str) -> Option<Self> {
+        if text.len() < MIN_EMBEDDED_LEN {
+            return None;
+        }
+
+        let (encoding, bytes) = match decode_hex(text) {
+            Some(bytes) => (TextEncoding::Hex, bytes),
+            None => (TextEncoding::Base64, decode_base64(text)?)
+        };
+
+        if !looks_like_protobuf(&bytes).is_likely() {
+            return None;
+        }
+
+        let message = decode(&bytes).ok().filter(|message| message.iter().next().is_some())?;
+        Some(Self { encoding, bytes, message })
+    }
+}
+
+/// Finds every string field, including nested ones, which embeds
+/// a hex or Base64 encoded protobuf message.
+///
+/// This is an opt-in pass; decoding never applies it.
+/// Embedded messages are not searched; call this again on them.
+pub fn find_embedded(message: &SerializedMessage) -> Vec<(FieldPath, Embedded)> {
+    let mut found = vec![];
+    find_embedded_in(message, &mut vec![], &mut found);
+
+    found
+}
+
+/// Finds the embedded messages in the strings of a message.
+fn find_embedded_in(message: &SerializedMessage, path: &mut FieldPath, found: &mut Vec<(FieldPath, Embedded)>) {
+    for (field, value) in message {
+        path.push(*field);
+        find_embedded_value(value, path, found);
+        path.pop();
+    }
+}
+
+/// Finds the embedded messages in a value.
+fn find_embedded_value(value: &Value, path: &mut FieldPath, found: &mut Vec<(FieldPath, Embedded)>) {
+    match value {
+        Value::String(text) => {
+            if let Some(embedded) = Embedded::detect(text) {
+                found.push((path.clone(), embedded));
+            }
+        }
+        Value::Message(message) => find_embedded_in(message, path, found),
+        Value::Repeated(values) => {
+            for value in values {
+                find_embedded_value(value, path, found);
+            }
+        }
+        Value::Map(entries) => {
+            for value in entries.values() {
+                find_embedded_value(value, path, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Decodes a string of an even number of hex digits.
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Decodes a string in any of the common Base64 alphabets.
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    [STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD].iter()
+        .find_map(|engine| engine.decode(text).ok())
+}
+
+/// Calls the function with every bytes field of a message and its path.
+fn for_each_bytes<F: FnMut(&FieldPath, &[u8])>(message: &SerializedMessage, path: &mut FieldPath, f: &mut F) {
+    for (field, value) in message {
+        path.push(*field);
+        for_each_bytes_in(value, path, f);
+        path.pop();
+    }
+}
+
+/// Calls the function with the bytes in a value.
+fn for_each_bytes_in<F: FnMut(&FieldPath, &[u8])>(value: &Value, path: &mut FieldPath, f: &mut F) {
+    match value {
+        Value::Bytes(bytes) => f(path, bytes),
+        Value::Message(message) => for_each_bytes(message, path, f),
+        Value::Repeated(values) => {
+            for value in values {
+                for_each_bytes_in(value, path, f);
+            }
+        }
+        Value::Map(entries) => {
+            for value in entries.values() {
+                for_each_bytes_in(value, path, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports() {
+        // A simple xorshift generator stands in for encrypted data.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let random: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+
+        let mut message = SerializedMessage::new();
+        message.insert(1, Value::Bytes(random));
+        message.insert(2, Value::Bytes(vec![0x1f, 0x8b, 0x08, 0x00]));
+        message.insert(3, Value::Bytes(vec![0; 64]));
+
+        let reports = inspect(&message);
+        assert!(reports[0].1.likely_encrypted && reports[0].1.entropy > 7.9);
+        assert_eq!(reports[1].1.compression, Some(Compression::Gzip));
+        assert_eq!(reports[2].1, PayloadReport { entropy: 0.0, compression: None, likely_encrypted: false });
+        assert_eq!(Compression::detect(&[0x78, 0x9c]), Some(Compression::Zlib));
+    }
+
+    #[test]
+    fn embedded() {
+        use crate::{encoding, ProtobufBytes};
+
+        let mut inner = vec![];
+        inner.write_str(1, "Hello, World!");
+        inner.write_u32(2, 150);
+        let hex: String = inner.iter().map(|byte| format!("{byte:02x}")).collect();
+
+        let mut nested = SerializedMessage::new();
+        nested.insert(1, Value::String(hex));
+
+        let mut message = SerializedMessage::new();
+        message.insert(1, Value::String(encoding::base64_encode(&inner)));
+        message.insert(2, Value::Message(nested));
+        message.insert(3, Value::String("Not a payload.".to_string()));
+
+        let found = find_embedded(&message);
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].0.clone(), found[0].1.encoding), (vec![1], TextEncoding::Base64));
+        assert_eq!((found[1].0.clone(), found[1].1.encoding), (vec![2, 1], TextEncoding::Hex));
+        assert_eq!(found[1].1.message, decode(&inner).unwrap());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn dissect() {
+        use std::io::Write;
+        use crate::ProtobufBytes;
+
+        let mut inner = vec![];
+        inner.write_str(1, "Hello, World!");
+
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&inner).unwrap();
+
+        let mut message = SerializedMessage::new();
+        message.insert(1, Value::Bytes(encoder.finish().unwrap()));
+
+        let found = dissect_compressed(&message);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].1, Compression::Zlib);
+        assert_eq!(found[0].2.get(1).unwrap().as_string().unwrap(), "Hello, World!");
+
+        // A small payload expanding beyond the limit is rejected, not inflated.
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+        encoder.write_all(&[0; 1 << 20]).unwrap();
+        let bomb = encoder.finish().unwrap();
+        assert!(Compression::Gzip.decompress_limited(&bomb, 1000).is_err());
+        assert_eq!(Compression::Gzip.decompress_limited(&bomb, 1 << 20).unwrap().len(), 1 << 20);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn decompress() {
+        use crate::ProtobufBytes;
+        use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+
+        let mut inner = vec![];
+        inner.write_str(1, &"Hello, World! ".repeat(16));
+        let compressed = compress_to_vec(inner.as_slice(), CompressionLevel::Fastest);
+
+        let mut nested = SerializedMessage::new();
+        nested.insert(2, Value::Bytes(compressed.clone()));
+
+        let mut message = SerializedMessage::new();
+        message.insert(1, Value::Message(nested));
+
+        let found = decompress_fields(&message);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, [1, 2]);
+        assert_eq!(found[0].1.compression, Compression::Zstd);
+        assert_eq!((found[0].1.compressed_len, found[0].1.len), (compressed.len(), inner.len()));
+        assert_eq!(found[0].1.message, decode(&inner).unwrap());
+
+        // Zstandard bombs are capped like the others.
+        let bomb = compress_to_vec([0u8; 1 << 20].as_slice(), CompressionLevel::Fastest);
+        assert!(Compression::Zstd.decompress_limited(&bomb, 1000).is_err());
+    }
+}
@@ -0,0 +1,825 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+use crate::pacing::Pacer;
+use crate::{decode, Result, SerializedMessage};
+
+/// The magic number of a classic pcap file with microsecond timestamps.
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+/// The magic number of a classic pcap file with nanosecond timestamps.
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+/// The block type of a pcapng section header block.
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+/// The byte-order magic of a pcapng section header block.
+const PCAPNG_BYTE_ORDER: u32 = 0x1a2b_3c4d;
+/// The longest packet record or block read, well above any real snapshot length.
+const MAX_RECORD_LEN: usize = 64 * 1024 * 1024;
+
+/// Ethernet frames.
+pub const LINKTYPE_ETHERNET: u32 = 1;
+/// Raw IPv4 or IPv6 packets.
+pub const LINKTYPE_RAW: u32 = 101;
+/// BSD loopback encapsulation.
+pub const LINKTYPE_NULL: u32 = 0;
+/// Linux "cooked" capture encapsulation.
+pub const LINKTYPE_LINUX_SLL: u32 = 113;
+/// Linux "cooked" capture encapsulation, version 2.
+pub const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+/// The transport protocol carrying a payload.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Transport {
+    Tcp,
+    Udp
+}
+
+/// Information about the packet a payload was extracted from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PacketMetadata {
+    /// The index of the packet in the capture, starting at zero.
+    pub index: usize,
+    /// The capture time, relative to the Unix epoch.
+    pub timestamp: Duration,
+    /// The transport protocol of the packet.
+    pub transport: Transport,
+    /// The sending address.
+    pub source: SocketAddr,
+    /// The receiving address.
+    pub destination: SocketAddr
+}
+
+/// The container format of the capture.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Format {
+    Pcap,
+    PcapNg
+}
+
+/// An interface described by a pcapng interface description block.
+#[derive(Copy, Clone, Debug)]
+struct Interface {
+    link_type: u32,
+    /// The number of timestamp units per second.
+    resolution: u64
+}
+
+/// A reader which extracts TCP/UDP payloads from pcap and pcapng files.
+pub struct PcapReader<R> {
+    reader: R,
+    format: Format,
+    big_endian: bool,
+    ports: Vec<u16>,
+    interfaces: Vec<Interface>,
+    index: usize
+}
+
+impl PcapReader<BufReader<File>> {
+    /// Opens a capture file.
+    ///
+    /// `ports`: Only payloads sent from or to these ports are returned.
+    /// An empty slice matches every port.
+    pub fn open<P: AsRef<Path>>(path: P, ports: &[u16]) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?), ports)
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Creates a reader over a capture, reading its file header.
+    ///
+    /// `ports`: Only payloads sent from or to these ports are returned.
+    /// An empty slice matches every port.
+    pub fn new(mut reader: R, ports: &[u16]) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+
+        let mut pcap = Self {
+            reader,
+            format: Format::Pcap,
+            big_endian: false,
+            ports: ports.to_vec(),
+            interfaces: vec![],
+            index: 0
+        };
+
+        match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (PCAP_MAGIC_MICROS | PCAP_MAGIC_NANOS, _) => pcap.read_pcap_header(magic, false)?,
+            (_, PCAP_MAGIC_MICROS | PCAP_MAGIC_NANOS) => pcap.read_pcap_header(magic, true)?,
+            (PCAPNG_SECTION_HEADER, _) => {
+                pcap.format = Format::PcapNg;
+                pcap.read_section_header()?;
+            }
+            _ => return Err("Unrecognized capture file format.".into())
+        }
+
+        Ok(pcap)
+    }
+
+    /// Returns an iterator over the payloads which decode as protobuf messages.
+    ///
+    /// Payloads which fail to decode are skipped.
+    pub fn messages(self) -> Messages<R> {
+        Messages { reader: self }
+    }
+
+    /// Reads the next payload matching the port filter.
+    pub fn next_payload(&mut self) -> Result<Option<(PacketMetadata, Vec<u8>)>> {
+        loop {
+            let packet = match self.format {
+                Format::Pcap => self.next_pcap_packet()?,
+                Format::PcapNg => self.next_pcapng_packet()?
+            };
+            let Some((link_type, timestamp, data)) = packet else {
+                return Ok(None);
+            };
+
+            let index = self.index;
+            self.index += 1;
+
+            let Some((transport, source, destination, payload)) = parse_packet(link_type, &data) else {
+                continue;
+            };
+
+            if payload.is_empty() || !self.matches(source.port(), destination.port()) {
+                continue;
+            }
+
+            let metadata = PacketMetadata { index, timestamp, transport, source, destination };
+            return Ok(Some((metadata, payload.to_vec())));
+        }
+    }
+
+    /// Sends the payloads matching the port filter which were sent to `port`
+    /// to a writer, such as a connection to the server, in capture order.
+    ///
+    /// Each payload waits for the pacer first, given its capture timestamp,
+    /// so `Pacing::Original` reproduces the spacing of the capture.
+    /// Returns the number of payloads sent.
+    pub fn replay<W: Write>(&mut self, mut writer: W, port: u16, pacer: &mut Pacer) -> Result<usize> {
+        let mut count = 0;
+        while let Some((metadata, payload)) = self.next_payload()? {
+            if metadata.destination.port() != port {
+                continue;
+            }
+
+            pacer.wait(Some(metadata.timestamp));
+            writer.write_all(&payload)?;
+            count += 1;
+        }
+
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Returns true if either port passes the port filter.
+    fn matches(&self, source: u16, destination: u16) -> bool {
+        self.ports.is_empty() || self.ports.contains(&source) || self.ports.contains(&destination)
+    }
+
+    /// Reads the remainder of a classic pcap file header.
+    fn read_pcap_header(&mut self, magic: [u8; 4], big_endian: bool) -> Result<()> {
+        self.big_endian = big_endian;
+
+        let mut header = [0u8; 20];
+        self.reader.read_exact(&mut header)?;
+
+        let resolution = if self.u32(magic) == PCAP_MAGIC_NANOS { 1_000_000_000 } else { 1_000_000 };
+        let link_type = self.u32(header[16..20].try_into()?) & 0x0fff_ffff;
+        self.interfaces = vec![Interface { link_type, resolution }];
+
+        Ok(())
+    }
+
+    /// Reads a packet record from a classic pcap file.
+    fn next_pcap_packet(&mut self) -> Result<Option<(u32, Duration, Vec<u8>)>> {
+        let mut header = [0u8; 16];
+        if !read_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+
+        let seconds = self.u32(header[0..4].try_into()?) as u64;
+        let fraction = self.u32(header[4..8].try_into()?) as u64;
+        let length = self.u32(header[8..12].try_into()?) as usize;
+
+        let data = read_record(&mut self.reader, length)?;
+
+        let interface = self.interfaces[0];
+        let timestamp = Duration::from_secs(seconds)
+            + Duration::from_nanos(fraction * 1_000_000_000 / interface.resolution);
+
+        Ok(Some((interface.link_type, timestamp, data)))
+    }
+
+    /// Reads the body of a pcapng section header block.
+    ///
+    /// The block type has already been consumed.
+    fn read_section_header(&mut self) -> Result<()> {
+        let mut header = [0u8; 8];
+        self.reader.read_exact(&mut header)?;
+
+        let byte_order: [u8; 4] = header[4..8].try_into()?;
+        self.big_endian = match u32::from_le_bytes(byte_order) {
+            PCAPNG_BYTE_ORDER => false,
+            _ if u32::from_be_bytes(byte_order) == PCAPNG_BYTE_ORDER => true,
+            _ => return Err("Invalid pcapng byte-order magic.".into())
+        };
+
+        // Interfaces are scoped to their section.
+        self.interfaces.clear();
+
+        let length = self.u32(header[0..4].try_into()?) as usize;
+        if length < 12 {
+            return Err("Invalid pcapng section header length.".into());
+        }
+
+        read_record(&mut self.reader, length - 12)?;
+
+        Ok(())
+    }
+
+    /// Reads blocks from a pcapng file until a packet is found.
+    fn next_pcapng_packet(&mut self) -> Result<Option<(u32, Duration, Vec<u8>)>> {
+        loop {
+            let mut block_type = [0u8; 4];
+            if !read_or_eof(&mut self.reader, &mut block_type)? {
+                return Ok(None);
+            }
+
+            if u32::from_le_bytes(block_type) == PCAPNG_SECTION_HEADER {
+                self.read_section_header()?;
+                continue;
+            }
+
+            let mut length = [0u8; 4];
+            self.reader.read_exact(&mut length)?;
+            let length = self.u32(length) as usize;
+            if length < 12 {
+                return Err("Invalid pcapng block length.".into());
+            }
+
+            // The body excludes the type and both copies of the length.
+            let body = read_record(&mut self.reader, length - 12)?;
+            self.reader.read_exact(&mut [0u8; 4])?;
+
+            match self.u32(block_type) {
+                // Interface description block.
+                1 => {
+                    if body.len() < 8 {
+                        return Err("Invalid pcapng interface description block.".into());
+                    }
+
+                    let link_type = self.u16(body[0..2].try_into()?) as u32;
+                    let resolution = self.interface_resolution(&body[8..]);
+                    self.interfaces.push(Interface { link_type, resolution });
+                }
+                // Enhanced packet block.
+                6 => {
+                    if body.len() < 20 {
+                        return Err("Invalid pcapng enhanced packet block.".into());
+                    }
+
+                    let interface_id = self.u32(body[0..4].try_into()?) as usize;
+                    let Some(interface) = self.interfaces.get(interface_id).copied() else {
+                        return Err(format!("Unknown pcapng interface {interface_id}.").into());
+                    };
+
+                    let high = self.u32(body[4..8].try_into()?) as u64;
+                    let low = self.u32(body[8..12].try_into()?) as u64;
+                    let captured = self.u32(body[12..16].try_into()?) as usize;
+                    let Some(data) = body.get(20..20 + captured) else {
+                        return Err("Invalid pcapng enhanced packet length.".into());
+                    };
+
+                    let timestamp = to_duration((high << 32) | low, interface.resolution);
+                    return Ok(Some((interface.link_type, timestamp, data.to_vec())));
+                }
+                // Simple packet block.
+                3 => {
+                    let Some(interface) = self.interfaces.first().copied() else {
+                        return Err("Simple packet block without an interface.".into());
+                    };
+                    if body.len() < 4 {
+                        return Err("Invalid pcapng simple packet block.".into());
+                    }
+
+                    let original = self.u32(body[0..4].try_into()?) as usize;
+                    let data = &body[4..body.len().min(4 + original)];
+
+                    return Ok(Some((interface.link_type, Duration::ZERO, data.to_vec())));
+                }
+                // Every other block type is irrelevant.
+                _ => {}
+            }
+        }
+    }
+
+    /// Reads the timestamp resolution from interface description block options.
+    fn interface_resolution(&self, mut options: &[u8]) -> u64 {
+        while options.len() >= 4 {
+            let code = self.u16([options[0], options[1]]);
+            let length = self.u16([options[2], options[3]]) as usize;
+            let padded = (length + 3) & !3;
+
+            // The `if_tsresol` option.
+            if code == 9 && length == 1 && options.len() > 4 {
+                let value = options[4];
+                return if value & 0x80 == 0 {
+                    10u64.checked_pow(value as u32).unwrap_or(1_000_000)
+                } else {
+                    2u64.checked_pow((value & 0x7f) as u32).unwrap_or(1_000_000)
+                };
+            }
+
+            // The `opt_endofopt` option.
+            if code == 0 {
+                break;
+            }
+
+            options = options.get(4 + padded..).unwrap_or_default();
+        }
+
+        1_000_000
+    }
+
+    /// Reads a `u16` in the byte order of the capture.
+    fn u16(&self, bytes: [u8; 2]) -> u16 {
+        if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) }
+    }
+
+    /// Reads a `u32` in the byte order of the capture.
+    fn u32(&self, bytes: [u8; 4]) -> u32 {
+        if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = Result<(PacketMetadata, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_payload().transpose()
+    }
+}
+
+/// An iterator over the payloads of a capture which decode as protobuf messages.
+pub struct Messages<R> {
+    reader: PcapReader<R>
+}
+
+impl<R: Read> Iterator for Messages<R> {
+    type Item = Result<(PacketMetadata, SerializedMessage)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (metadata, payload) = match self.reader.next_payload() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => return None,
+                Err(error) => return Some(Err(error))
+            };
+
+            if let Ok(message) = decode(&payload) {
+                return Some(Ok((metadata, message)));
+            }
+        }
+    }
+}
+
+/// The largest payload written into a single synthesized packet.
+const MAX_SEGMENT_LEN: usize = 65_000;
+
+/// A writer which synthesizes a pcap file from payloads.
+///
+/// Payloads are wrapped in Ethernet, IPv4/IPv6, and TCP/UDP headers
+/// (with valid checksums) so the capture can be opened in Wireshark.
+pub struct PcapWriter<W: Write> {
+    writer: W,
+    /// The next sequence number of each TCP flow.
+    sequences: HashMap<(SocketAddr, SocketAddr), u32>
+}
+
+impl PcapWriter<BufWriter<File>> {
+    /// Creates a capture file, overwriting any existing file.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Creates a writer, writing the pcap file header.
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(&PCAP_MAGIC_MICROS.to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?; // Major version.
+        writer.write_all(&4u16.to_le_bytes())?; // Minor version.
+        writer.write_all(&[0u8; 8])?; // Time zone and accuracy.
+        writer.write_all(&(u16::MAX as u32).to_le_bytes())?; // Snapshot length.
+        writer.write_all(&LINKTYPE_ETHERNET.to_le_bytes())?;
+
+        Ok(Self { writer, sequences: HashMap::new() })
+    }
+
+    /// Writes an encoded message as a packet.
+    pub fn write_message(&mut self, metadata: &PacketMetadata, message: &SerializedMessage) -> Result<()> {
+        self.write_payload(metadata, &message.encode())
+    }
+
+    /// Writes a payload as one or more packets.
+    ///
+    /// The index of the metadata is ignored; packets are written in call order.
+    /// TCP payloads are split into several segments if needed.
+    pub fn write_payload(&mut self, metadata: &PacketMetadata, payload: &[u8]) -> Result<()> {
+        match metadata.transport {
+            Transport::Udp => {
+                if payload.len() > MAX_SEGMENT_LEN {
+                    return Err("Payload is too large for a single UDP datagram.".into());
+                }
+
+                let mut segment = Vec::with_capacity(8 + payload.len());
+                segment.extend(metadata.source.port().to_be_bytes());
+                segment.extend(metadata.destination.port().to_be_bytes());
+                segment.extend((8 + payload.len() as u16).to_be_bytes());
+                segment.extend([0, 0]);
+                segment.extend(payload);
+
+                self.write_segment(metadata, 17, segment, 6)
+            }
+            Transport::Tcp => {
+                let flow = (metadata.source, metadata.destination);
+                let acknowledgement = self.sequences
+                    .get(&(metadata.destination, metadata.source))
+                    .copied()
+                    .unwrap_or_default();
+
+                for chunk in payload.chunks(MAX_SEGMENT_LEN) {
+                    let sequence = self.sequences.entry(flow).or_default();
+
+                    let mut segment = Vec::with_capacity(20 + chunk.len());
+                    segment.extend(metadata.source.port().to_be_bytes());
+                    segment.extend(metadata.destination.port().to_be_bytes());
+                    segment.extend(sequence.to_be_bytes());
+                    segment.extend(acknowledgement.to_be_bytes());
+                    segment.extend([0x50, 0x18]); // Header length; PSH and ACK flags.
+                    segment.extend(u16::MAX.to_be_bytes()); // Window size.
+                    segment.extend([0, 0, 0, 0]); // Checksum and urgent pointer.
+                    segment.extend(chunk);
+
+                    *sequence = sequence.wrapping_add(chunk.len() as u32);
+                    self.write_segment(metadata, 6, segment, 16)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Wraps a transport segment in IP and Ethernet headers and writes it.
+    ///
+    /// `checksum_offset`: The offset of the checksum in the transport header.
+    fn write_segment(
+        &mut self, metadata: &PacketMetadata, protocol: u8, mut segment: Vec<u8>, checksum_offset: usize
+    ) -> Result<()> {
+        let mut frame = vec![0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 1];
+
+        match (metadata.source.ip(), metadata.destination.ip()) {
+            (IpAddr::V4(source), IpAddr::V4(destination)) => {
+                let mut pseudo = vec![];
+                pseudo.extend(source.octets());
+                pseudo.extend(destination.octets());
+                pseudo.extend([0, protocol]);
+                pseudo.extend((segment.len() as u16).to_be_bytes());
+                set_checksum(&mut segment, checksum_offset, &pseudo, protocol);
+
+                let mut header = vec![0x45, 0];
+                header.extend((20 + segment.len() as u16).to_be_bytes());
+                header.extend([0, 0, 0x40, 0, 64, protocol, 0, 0]); // Don't fragment.
+                header.extend(source.octets());
+                header.extend(destination.octets());
+                let checksum = checksum(&[&header]);
+                header[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+                frame.extend(0x0800u16.to_be_bytes());
+                frame.extend(header);
+            }
+            (IpAddr::V6(source), IpAddr::V6(destination)) => {
+                let mut pseudo = vec![];
+                pseudo.extend(source.octets());
+                pseudo.extend(destination.octets());
+                pseudo.extend((segment.len() as u32).to_be_bytes());
+                pseudo.extend([0, 0, 0, protocol]);
+                set_checksum(&mut segment, checksum_offset, &pseudo, protocol);
+
+                frame.extend(0x86ddu16.to_be_bytes());
+                frame.extend([0x60, 0, 0, 0]);
+                frame.extend((segment.len() as u16).to_be_bytes());
+                frame.extend([protocol, 64]);
+                frame.extend(source.octets());
+                frame.extend(destination.octets());
+            }
+            _ => return Err("Source and destination addresses must use the same IP version.".into())
+        }
+
+        frame.extend(segment);
+
+        let timestamp = metadata.timestamp;
+        self.writer.write_all(&(timestamp.as_secs() as u32).to_le_bytes())?;
+        self.writer.write_all(&timestamp.subsec_micros().to_le_bytes())?;
+        self.writer.write_all(&(frame.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&(frame.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&frame)?;
+
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Computes the transport checksum of a segment and stores it.
+fn set_checksum(segment: &mut [u8], offset: usize, pseudo: &[u8], protocol: u8) {
+    let mut checksum = checksum(&[pseudo, segment]);
+
+    // A zero UDP checksum means "no checksum".
+    if protocol == 17 && checksum == 0 {
+        checksum = 0xffff;
+    }
+
+    segment[offset..offset + 2].copy_from_slice(&checksum.to_be_bytes());
+}
+
+/// Computes the Internet checksum over several buffers.
+fn checksum(buffers: &[&[u8]]) -> u16 {
+    let mut sum = 0u32;
+    for (index, byte) in buffers.iter().flat_map(|buffer| buffer.iter()).enumerate() {
+        sum += if index % 2 == 0 { (*byte as u32) << 8 } else { *byte as u32 };
+    }
+
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+/// Fills the buffer, returning false on a clean end of file.
+fn read_or_eof<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0usize;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(len) => read += len,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error)
+        }
+    }
+
+    Ok(true)
+}
+
+/// Reads a record of the given length, which is checked before anything is allocated.
+///
+/// Lengths come from the file, so the buffer only grows as bytes actually arrive.
+fn read_record<R: Read>(reader: &mut R, length: usize) -> Result<Vec<u8>> {
+    if length > MAX_RECORD_LEN {
+        return Err(format!("Invalid capture; record of {length} bytes exceeds the limit of {MAX_RECORD_LEN} bytes.").into());
+    }
+
+    let mut data = Vec::with_capacity(length.min(64 * 1024));
+    reader.take(length as u64).read_to_end(&mut data)?;
+    if data.len() < length {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+
+    Ok(data)
+}
+
+/// Converts a timestamp in the given units per second into a duration.
+fn to_duration(timestamp: u64, resolution: u64) -> Duration {
+    let seconds = timestamp / resolution;
+    let fraction = timestamp % resolution;
+    Duration::from_secs(seconds) + Duration::from_nanos((fraction as u128 * 1_000_000_000 / resolution as u128) as u64)
+}
+
+/// Extracts the transport payload of a captured packet.
+fn parse_packet(link_type: u32, data: &[u8]) -> Option<(Transport, SocketAddr, SocketAddr, &[u8])> {
+    let ip = match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut ether_type = u16::from_be_bytes(data.get(12..14)?.try_into().ok()?);
+            let mut offset = 14;
+
+            // Skip any 802.1Q VLAN tags.
+            while ether_type == 0x8100 || ether_type == 0x88a8 {
+                ether_type = u16::from_be_bytes(data.get(offset + 2..offset + 4)?.try_into().ok()?);
+                offset += 4;
+            }
+
+            if ether_type != 0x0800 && ether_type != 0x86dd {
+                return None;
+            }
+
+            data.get(offset..)?
+        }
+        LINKTYPE_LINUX_SLL => data.get(16..)?,
+        LINKTYPE_LINUX_SLL2 => data.get(20..)?,
+        LINKTYPE_NULL => data.get(4..)?,
+        LINKTYPE_RAW | 12 | 14 => data,
+        _ => return None
+    };
+
+    match ip.first()? >> 4 {
+        4 => {
+            let header_len = ((ip[0] & 0x0f) as usize) * 4;
+            let total_len = u16::from_be_bytes(ip.get(2..4)?.try_into().ok()?) as usize;
+
+            // Only the first fragment carries the transport header.
+            let fragment_offset = u16::from_be_bytes(ip.get(6..8)?.try_into().ok()?) & 0x1fff;
+            if fragment_offset != 0 {
+                return None;
+            }
+
+            let source = IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(ip.get(12..16)?).ok()?));
+            let destination = IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(ip.get(16..20)?).ok()?));
+
+            // Trim any link-layer padding. Segmentation offload writes a total length
+            // of zero, in which case the captured length is used.
+            let ip = if total_len == 0 { ip } else { ip.get(..total_len.min(ip.len()))? };
+            parse_transport(*ip.get(9)?, source, destination, ip.get(header_len..)?)
+        }
+        6 => {
+            let payload_len = u16::from_be_bytes(ip.get(4..6)?.try_into().ok()?) as usize;
+            let source = IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(ip.get(8..24)?).ok()?));
+            let destination = IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(ip.get(24..40)?).ok()?));
+
+            let mut next_header = ip[6];
+            let mut payload = ip.get(40..(40 + payload_len).min(ip.len()))?;
+
+            // Skip hop-by-hop, routing, and destination option headers.
+            while matches!(next_header, 0 | 43 | 60) {
+                let length = (*payload.get(1)? as usize + 1) * 8;
+                next_header = payload[0];
+                payload = payload.get(length..)?;
+            }
+
+            parse_transport(next_header, source, destination, payload)
+        }
+        _ => None
+    }
+}
+
+/// Extracts the payload of a TCP or UDP segment.
+fn parse_transport(
+    protocol: u8, source: IpAddr, destination: IpAddr, segment: &[u8]
+) -> Option<(Transport, SocketAddr, SocketAddr, &[u8])> {
+    let source_port = u16::from_be_bytes(segment.get(0..2)?.try_into().ok()?);
+    let destination_port = u16::from_be_bytes(segment.get(2..4)?.try_into().ok()?);
+
+    let (transport, payload) = match protocol {
+        6 => {
+            let offset = ((*segment.get(12)? >> 4) as usize) * 4;
+            (Transport::Tcp, segment.get(offset..)?)
+        }
+        17 => {
+            let length = u16::from_be_bytes(segment.get(4..6)?.try_into().ok()?) as usize;
+            (Transport::Udp, segment.get(8..length.clamp(8, segment.len()))?)
+        }
+        _ => return None
+    };
+
+    Some((
+        transport,
+        SocketAddr::new(source, source_port),
+        SocketAddr::new(destination, destination_port),
+        payload
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use crate::pacing::Pacing;
+    use crate::ProtobufBytes;
+
+    #[test]
+    fn read_udp_payloads() {
+        let mut message = vec![];
+        message.write_str(1, "Hello, World!");
+
+        // A raw IPv4 packet carrying a UDP datagram from port 22101.
+        let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, 17, 0, 0, 127, 0, 0, 1, 127, 0, 0, 2];
+        packet.extend(22101u16.to_be_bytes());
+        packet.extend(50000u16.to_be_bytes());
+        packet.extend((8 + message.len() as u16).to_be_bytes());
+        packet.extend([0, 0]);
+        packet.extend(&message);
+        let total_len = packet.len() as u16;
+        packet[2..4].copy_from_slice(&total_len.to_be_bytes());
+
+        let mut capture = vec![];
+        capture.extend(PCAP_MAGIC_MICROS.to_le_bytes());
+        capture.extend([2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0]);
+        capture.extend(LINKTYPE_RAW.to_le_bytes());
+        for port in [1u16, 22101] {
+            packet[20..22].copy_from_slice(&port.to_be_bytes());
+
+            capture.extend(1_700_000_000u32.to_le_bytes());
+            capture.extend(500_000u32.to_le_bytes());
+            capture.extend((packet.len() as u32).to_le_bytes());
+            capture.extend((packet.len() as u32).to_le_bytes());
+            capture.extend(&packet);
+        }
+
+        let reader = PcapReader::new(capture.as_slice(), &[22101]).unwrap();
+        let messages = reader.messages().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(messages.len(), 1);
+
+        let (metadata, message) = &messages[0];
+        assert_eq!(metadata.index, 1);
+        assert_eq!(metadata.transport, Transport::Udp);
+        assert_eq!(metadata.source, "127.0.0.1:22101".parse().unwrap());
+        assert_eq!(metadata.timestamp, Duration::from_millis(1_700_000_000_500));
+        assert_eq!(message.get(1).unwrap().as_string().unwrap(), "Hello, World!");
+
+        // Offloaded packets have a total length of zero, and short ones must not panic.
+        packet[2..4].copy_from_slice(&[0, 0]);
+        assert_eq!(parse_packet(LINKTYPE_RAW, &packet).unwrap().3, &packet[28..]);
+        assert!(parse_packet(LINKTYPE_RAW, &[0x45, 0, 0, 4, 0, 0, 0, 0]).is_none());
+
+        // Record lengths are not trusted before the bytes arrive.
+        let mut truncated = capture[..24].to_vec();
+        truncated.extend([0; 8]);
+        truncated.extend([0xff, 0xff, 0xff, 0x03, 0, 0, 0, 0]);
+        let mut reader = PcapReader::new(truncated.as_slice(), &[]).unwrap();
+        assert!(reader.next_payload().is_err());
+    }
+
+    #[test]
+    fn write_and_read() {
+        let mut message = SerializedMessage::new();
+        message.insert(1, "Hello, World!".to_string().into());
+
+        let mut metadata = PacketMetadata {
+            index: 0,
+            timestamp: Duration::from_secs(1_700_000_000),
+            transport: Transport::Tcp,
+            source: "[::1]:22101".parse().unwrap(),
+            destination: "[::1]:50000".parse().unwrap()
+        };
+
+        let mut writer = PcapWriter::new(vec![]).unwrap();
+        writer.write_message(&metadata, &message).unwrap();
+
+        metadata.transport = Transport::Udp;
+        metadata.source = "10.0.0.1:22101".parse().unwrap();
+        metadata.destination = "10.0.0.2:50000".parse().unwrap();
+        writer.write_message(&metadata, &message).unwrap();
+
+        let capture = writer.into_inner();
+        let reader = PcapReader::new(capture.as_slice(), &[]).unwrap();
+        let messages = reader.messages().collect::<Result<Vec<_>, _>>().unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].0.transport, Transport::Tcp);
+        assert_eq!(messages[1].0, PacketMetadata { index: 1, ..metadata });
+        assert_eq!(messages[1].1.get(1).unwrap().as_string().unwrap(), "Hello, World!");
+    }
+
+    #[test]
+    fn replay() {
+        let mut metadata = PacketMetadata {
+            index: 0,
+            timestamp: Duration::from_secs(1_700_000_000),
+            transport: Transport::Udp,
+            source: "10.0.0.1:22101".parse().unwrap(),
+            destination: "10.0.0.2:50000".parse().unwrap()
+        };
+
+        let mut writer = PcapWriter::new(vec![]).unwrap();
+        for payload in [b"one", b"two"] {
+            writer.write_payload(&metadata, payload).unwrap();
+            metadata.timestamp += Duration::from_millis(60);
+        }
+        // A response, which is not replayed.
+        metadata.source = "10.0.0.2:50000".parse().unwrap();
+        metadata.destination = "10.0.0.1:22101".parse().unwrap();
+        writer.write_payload(&metadata, b"ack").unwrap();
+
+        let capture = writer.into_inner();
+        let mut reader = PcapReader::new(capture.as_slice(), &[]).unwrap();
+        let mut pacer = Pacer::new(Pacing::Original { speed: 2.0 });
+        let mut sent = vec![];
+        let start = Instant::now();
+        assert_eq!(reader.replay(&mut sent, 50000, &mut pacer).unwrap(), 2);
+
+        // The payloads were 60 ms apart, replayed at twice the speed.
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(sent, b"onetwo");
+    }
+}
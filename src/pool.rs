@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+use crate::template::{RangeKind, Template};
+use crate::schema::Schema;
+use crate::{FieldAccess, FieldPath, Result, SerializedMessage, Value};
+
+/// Message schemas indexed by fully qualified name, such as `example.Player`.
+///
+/// Decoding through the pool follows the named schema, and dissects the
+/// payload of every `google.protobuf.Any` whose type URL names a schema in
+/// the pool, at any nesting level. Schemas are inferred or learned from
+/// exemplars rather than declared; `validate::Validator` indexes the types
+/// of compiled descriptor sets instead.
+#[derive(Clone, Debug, Default)]
+pub struct SchemaPool {
+    templates: BTreeMap<String, Template>
+}
+
+impl SchemaPool {
+    /// Creates a new, empty pool.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the schema of a message type, replacing any existing schema.
+    pub fn insert<S: Into<String>>(&mut self, name: S, schema: Schema) {
+        self.templates.insert(name.into(), Template::new(schema));
+    }
+
+    /// Adds a message type as a template, which may declare extension and reserved ranges.
+    pub fn insert_template<S: Into<String>>(&mut self, name: S, template: Template) {
+        self.templates.insert(name.into(), template);
+    }
+
+    /// Returns the schema of a message type.
+    pub fn get(&self, name: &str) -> Option<&Schema> {
+        self.templates.get(name).map(Template::schema)
+    }
+
+    /// Returns the number of message types.
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    /// Returns whether the pool has no message types.
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    /// Decodes a message of the named type, resolving any `Any` fields in it.
+    ///
+    /// Also returns the path of every field in an extension or reserved range
+    /// of its template, including those in the payloads of `Any` fields.
+    /// Fails if the type is not in the pool, or the message does not match its schema.
+    pub fn decode(&self, name: &str, bytes: &[u8]) -> Result<(SerializedMessage, Vec<(FieldPath, RangeKind)>)> {
+        let Some(template) = self.templates.get(name) else {
+            return Err(format!("Invalid message; type '{name}' is not in the pool.").into());
+        };
+
+        let (message, mut flagged) = template.apply(bytes)?;
+        let message = self.resolve_in(&message, &mut vec![], &mut flagged);
+        Ok((message, flagged))
+    }
+
+    /// Dissects the payload of every `Any` in a decoded message whose type is in the pool.
+    ///
+    /// An `Any` is a message with a type URL in field 1 and a payload in field 2.
+    /// Payloads which were decoded as messages are re-encoded first, which is
+    /// only faithful if they round-trip; decoding through the pool avoids this.
+    /// Payloads which do not match the schema of their type are left as they are.
+    pub fn resolve(&self, message: &SerializedMessage) -> SerializedMessage {
+        self.resolve_in(message, &mut vec![], &mut vec![])
+    }
+
+    /// Resolves the `Any` messages in a message at the given path,
+    /// recording the flagged fields of their payloads.
+    fn resolve_in(&self, message: &SerializedMessage, path: &mut FieldPath, flagged: &mut Vec<(FieldPath, RangeKind)>) -> SerializedMessage {
+        if let Some(resolved) = self.resolve_any(message, path, flagged) {
+            return resolved;
+        }
+
+        let mut resolved = message.clone();
+        for (field, value) in resolved.iter_mut() {
+            path.push(*field);
+            self.resolve_value(value, path, flagged);
+            path.pop();
+        }
+
+        resolved
+    }
+
+    /// Resolves the `Any` messages in a value.
+    fn resolve_value(&self, value: &mut Value, path: &mut FieldPath, flagged: &mut Vec<(FieldPath, RangeKind)>) {
+        match value {
+            Value::Message(message) => *message = self.resolve_in(message, path, flagged),
+            Value::Repeated(values) => values.iter_mut().for_each(|value| self.resolve_value(value, path, flagged)),
+            Value::Map(entries) => entries.values_mut().for_each(|value| self.resolve_value(value, path, flagged)),
+            _ => {}
+        }
+    }
+
+    /// Dissects the payload of a message, if it is an `Any` of a type in the pool.
+    fn resolve_any(
+        &self,
+        message: &SerializedMessage,
+        path: &mut FieldPath,
+        flagged: &mut Vec<(FieldPath, RangeKind)>
+    ) -> Option<SerializedMessage> {
+        if message.iter().any(|(field, _)| *field > 2) {
+            return None;
+        }
+
+        let (_, name) = message.get_str(1)?.rsplit_once('/')?;
+        let template = self.templates.get(name)?;
+        let payload = match message.get_ref(2) {
+            None | Some(Value::Empty) => vec![],
+            Some(Value::Bytes(bytes)) => bytes.clone(),
+            Some(Value::Message(packed)) => packed.encode(),
+            // A payload which is also valid UTF-8, possibly decoded as a message too.
+            Some(_) => message.get_str(2)?.as_bytes().to_vec()
+        };
+
+        let (packed, packed_flagged) = template.apply(&payload).ok()?;
+        path.push(2);
+        flagged.extend(packed_flagged.into_iter().map(|(field, kind)| ([path.as_slice(), &field].concat(), kind)));
+        let packed = self.resolve_in(&packed, path, flagged);
+        path.pop();
+
+        let mut resolved = message.clone();
+        resolved.set_field(2, Value::Message(packed));
+
+        Some(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{FieldSchema, FieldType};
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn resolve_any() {
+        let mut player = Schema::new();
+        player.insert(1, FieldSchema::new(FieldType::VarInt, false));
+        player.insert(2, FieldSchema::new(FieldType::String, false));
+
+        let mut any = Schema::new();
+        any.insert(1, FieldSchema::new(FieldType::String, false));
+        any.insert(2, FieldSchema::new(FieldType::Bytes, false));
+
+        let mut envelope = Schema::new();
+        envelope.insert(1, FieldSchema::new(FieldType::Message(any), true));
+
+        let mut pool = SchemaPool::new();
+        pool.insert_template("example.Player", Template::new(player).with_extensions(&[], 100..=199));
+        pool.insert("example.Envelope", envelope);
+
+        let mut packed = vec![];
+        packed.write_u32(1, 7);
+        packed.write_str(2, "hey");
+        packed.write_u32(150, 1);
+
+        let mut known = vec![];
+        known.write_str(1, "type.googleapis.com/example.Player");
+        known.write_bytes(2, &packed);
+
+        let mut unknown = vec![];
+        unknown.write_str(1, "type.googleapis.com/example.Unknown");
+        unknown.write_bytes(2, &[0xff]);
+
+        let mut bytes = vec![];
+        bytes.write_bytes(1, &known);
+        bytes.write_bytes(1, &unknown);
+
+        let (message, flagged) = pool.decode("example.Envelope", &bytes).unwrap();
+        assert_eq!(flagged, [(vec![1, 2, 150], RangeKind::Extension)]);
+        let Some(Value::Repeated(anys)) = message.get(1) else {
+            panic!("Expected both Any messages.");
+        };
+
+        let mut player = SerializedMessage::new();
+        player.insert(1, Value::VarInt(7i64.into()));
+        player.insert(2, Value::String("hey".to_string()));
+        player.insert(150, Value::VarInt(1i64.into()));
+        assert_eq!(anys[0].as_message().unwrap().get(2), Some(Value::Message(player.clone())));
+        assert_eq!(anys[1].as_message().unwrap().get(2), Some(Value::Bytes(vec![0xff])));
+
+        // Messages decoded without a schema are resolved too.
+        let resolved = pool.resolve(&decode(&known).unwrap());
+        assert_eq!(resolved.get(2), Some(Value::Message(player)));
+        assert!(pool.decode("example.Unknown", &bytes).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt;
+use std::time::Duration;
+
+/// The names of the wire types, indexed by their value.
+const WIRE_TYPES: [&str; 6] = ["varint", "fixed64", "length-delimited", "start group", "end group", "fixed32"];
+
+/// The number of occurrences and total time of a decoding activity.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timing {
+    pub count: usize,
+    pub time: Duration
+}
+
+impl Timing {
+    /// Records a single occurrence.
+    fn record(&mut self, time: Duration) {
+        self.count += 1;
+        self.time += time;
+    }
+}
+
+/// Collects decoding timings across many calls.
+///
+/// Pass it to `Decoder::decode_profiled` for every message in a batch,
+/// then print it to see where decoding time goes.
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    wire_types: [Timing; 6],
+    depths: Vec<Timing>,
+    heuristics: Timing,
+    total: Timing,
+    bytes: usize
+}
+
+impl Profiler {
+    /// Creates a new, empty profiler.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the time spent parsing fields of the given wire type.
+    pub fn wire_type(&self, wire_type: u8) -> Timing {
+        self.wire_types.get(wire_type as usize).copied().unwrap_or_default()
+    }
+
+    /// Returns the time spent parsing fields of messages at the given depth.
+    pub fn depth(&self, depth: usize) -> Timing {
+        self.depths.get(depth).copied().unwrap_or_default()
+    }
+
+    /// Returns the time spent deciding how to interpret length-delimited fields.
+    pub fn heuristics(&self) -> Timing {
+        self.heuristics
+    }
+
+    /// Returns the total time spent decoding, with one occurrence per message.
+    pub fn total(&self) -> Timing {
+        self.total
+    }
+
+    /// Returns the number of bytes decoded.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Clears every timing.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Records the time spent parsing a single field.
+    pub(crate) fn record_field(&mut self, wire_type: Option<u8>, depth: usize, time: Duration) {
+        if let Some(timing) = wire_type.and_then(|wire_type| self.wire_types.get_mut(wire_type as usize)) {
+            timing.record(time);
+        }
+
+        if self.depths.len() <= depth {
+            self.depths.resize(depth + 1, Timing::default());
+        }
+        self.depths[depth].record(time);
+    }
+
+    /// Records the time spent interpreting a length-delimited field.
+    pub(crate) fn record_heuristic(&mut self, time: Duration) {
+        self.heuristics.record(time);
+    }
+
+    /// Records the time spent decoding a whole message.
+    pub(crate) fn record_message(&mut self, bytes: usize, time: Duration) {
+        self.total.record(time);
+        self.bytes += bytes;
+    }
+}
+
+impl fmt::Display for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total.time.as_secs_f64();
+        let share = |time: Duration| if total > 0.0 { time.as_secs_f64() / total * 100.0 } else { 0.0 };
+
+        writeln!(f, "decoded {} messages ({} bytes) in {:?}", self.total.count, self.bytes, self.total.time)?;
+
+        writeln!(f, "by wire type:")?;
+        for (name, timing) in WIRE_TYPES.iter().zip(&self.wire_types) {
+            if timing.count > 0 {
+                writeln!(f, "  {name:<18} {:>10} fields {:>12?} {:>5.1}%", timing.count, timing.time, share(timing.time))?;
+            }
+        }
+
+        writeln!(f, "by depth:")?;
+        for (depth, timing) in self.depths.iter().enumerate() {
+            writeln!(f, "  {depth:<18} {:>10} fields {:>12?} {:>5.1}%", timing.count, timing.time, share(timing.time))?;
+        }
+
+        write!(f, "heuristics:          {:>10} fields {:>12?} {:>5.1}%",
+            self.heuristics.count, self.heuristics.time, share(self.heuristics.time))
+    }
+}
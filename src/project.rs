@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::negotiation::Profile;
+use crate::schema::Schema;
+use crate::Result;
+
+/// The name of the manifest file in a project directory.
+pub const MANIFEST_FILE: &str = "protoshark.json";
+
+/// The current version of the manifest format.
+pub const MANIFEST_FORMAT: u32 = 1;
+
+/// The manifest of a project, listing its contents.
+///
+/// Paths are relative to the project directory, so projects can be moved
+/// and checked into version control.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The version of the manifest format.
+    pub format: u32,
+    pub name: String,
+    /// The capture files of the project.
+    #[serde(default)]
+    pub captures: Vec<PathBuf>,
+    /// The schema files of the project, keyed by message name.
+    #[serde(default)]
+    pub schemas: BTreeMap<String, PathBuf>,
+    /// The protocol version profile files of the project, keyed by version.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, PathBuf>,
+    /// Notes on messages and fields, keyed by what they describe (e.g. `Login.2`).
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>
+}
+
+/// A reversing workspace: captures, schemas, profiles, and notes in one directory.
+///
+/// The directory holds a `protoshark.json` manifest, with schemas
+/// and profiles stored as JSON files in `schemas/` and `profiles/`.
+#[derive(Clone, Debug)]
+pub struct Project {
+    root: PathBuf,
+    pub name: String,
+    /// The capture files, relative to the project directory.
+    pub captures: Vec<PathBuf>,
+    /// The schemas, keyed by message name.
+    pub schemas: BTreeMap<String, Schema>,
+    /// The protocol version profiles, keyed by version.
+    pub profiles: BTreeMap<String, Profile>,
+    /// Notes on messages and fields.
+    pub annotations: BTreeMap<String, String>
+}
+
+impl Project {
+    /// Creates a new, empty project in the given directory.
+    ///
+    /// Nothing is written until the project is saved.
+    pub fn new<P: Into<PathBuf>, S: Into<String>>(root: P, name: S) -> Self {
+        Self {
+            root: root.into(),
+            name: name.into(),
+            captures: vec![],
+            schemas: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            annotations: BTreeMap::new()
+        }
+    }
+
+    /// Loads the project in the given directory.
+    pub fn open<P: Into<PathBuf>>(root: P) -> Result<Self> {
+        let root = root.into();
+        let manifest: Manifest = serde_json::from_slice(&fs::read(root.join(MANIFEST_FILE))?)?;
+        if manifest.format > MANIFEST_FORMAT {
+            return Err(format!("Project format {} is newer than the supported format {MANIFEST_FORMAT}.", manifest.format).into());
+        }
+
+        let mut project = Project::new(root, manifest.name);
+        project.captures = manifest.captures;
+        project.annotations = manifest.annotations;
+
+        for (name, path) in manifest.schemas {
+            let schema = serde_json::from_slice(&fs::read(project.root.join(path))?)?;
+            project.schemas.insert(name, schema);
+        }
+        for (version, path) in manifest.profiles {
+            let profile = serde_json::from_slice(&fs::read(project.root.join(path))?)?;
+            project.profiles.insert(version, profile);
+        }
+
+        Ok(project)
+    }
+
+    /// Writes the manifest, schemas, and profiles to the project directory.
+    pub fn save(&self) -> Result<()> {
+        let manifest = self.manifest();
+
+        for (name, path) in &manifest.schemas {
+            write_json(&self.root.join(path), &self.schemas[name])?;
+        }
+        for (version, path) in &manifest.profiles {
+            write_json(&self.root.join(path), &self.profiles[version])?;
+        }
+
+        write_json(&self.root.join(MANIFEST_FILE), &manifest)
+    }
+
+    /// Returns the manifest describing the project.
+    pub fn manifest(&self) -> Manifest {
+        Manifest {
+            format: MANIFEST_FORMAT,
+            name: self.name.clone(),
+            captures: self.captures.clone(),
+            schemas: self.schemas.keys()
+                .map(|name| (name.clone(), Path::new("schemas").join(file_name(name))))
+                .collect(),
+            profiles: self.profiles.keys()
+                .map(|version| (version.clone(), Path::new("profiles").join(file_name(version))))
+                .collect(),
+            annotations: self.annotations.clone()
+        }
+    }
+
+    /// Returns the project directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the full path of a file in the project, such as a capture.
+    pub fn path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.root.join(path)
+    }
+}
+
+/// Returns the file name used to store a named item.
+fn file_name(name: &str) -> String {
+    let name: String = name.chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .collect();
+
+    format!("{name}.json")
+}
+
+/// Writes a value as pretty-printed JSON, creating parent directories.
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut json = serde_json::to_vec_pretty(value)?;
+    json.push(b'\n');
+    Ok(fs::write(path, json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{FieldSchema, FieldType};
+
+    #[test]
+    fn save_and_open() {
+        let root = std::env::temp_dir().join(format!("protoshark-project-{}", std::process::id()));
+
+        let mut schema = Schema::new();
+        schema.insert(1, FieldSchema::new(FieldType::String, false));
+
+        let mut profile = Profile::default();
+        profile.remap.insert(101, 1);
+
+        let mut project = Project::new(&root, "Example");
+        project.captures.push(PathBuf::from("captures/login.pcap"));
+        project.schemas.insert("example.Login".to_string(), schema.clone());
+        project.profiles.insert("4.0.0".to_string(), profile);
+        project.annotations.insert("example.Login.1".to_string(), "The username.".to_string());
+        project.save().unwrap();
+
+        let opened = Project::open(&root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(opened.manifest(), project.manifest());
+        assert_eq!(opened.schemas["example.Login"], schema);
+        assert_eq!(opened.profiles["4.0.0"].remap(101), 1);
+        assert_eq!(opened.path(&opened.captures[0]), root.join("captures/login.pcap"));
+    }
+}
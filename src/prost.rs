@@ -0,0 +1,150 @@
+use prost_types::value::Kind;
+use prost_types::{Any, ListValue, Struct};
+use crate::{decode, encoding, Number, Result, SerializedMessage, Value, VarInt};
+
+impl SerializedMessage {
+    /// Wraps the encoded message in an `Any` with the given type URL.
+    pub fn to_any<S: Into<String>>(&self, type_url: S) -> Any {
+        Any { type_url: type_url.into(), value: self.encode() }
+    }
+
+    /// Decodes the message packed in an `Any`.
+    ///
+    /// Returns the fully qualified type name from the type URL along with the message,
+    /// so the name can be used to look up a schema for the message.
+    pub fn from_any(any: &Any) -> Result<(String, SerializedMessage)> {
+        let name = any.type_url.rsplit('/').next().unwrap_or_default();
+        Ok((name.to_string(), decode(&any.value)?))
+    }
+}
+
+/// Messages become structs keyed by field number.
+///
+/// Bytes are encoded as Base64 strings, matching the serde representation.
+impl From<&SerializedMessage> for Struct {
+    fn from(message: &SerializedMessage) -> Self {
+        let fields = message.iter()
+            .map(|(field, value)| (field.to_string(), to_struct_value(value)))
+            .collect();
+
+        Struct { fields }
+    }
+}
+
+/// Structs keyed by field number become messages.
+///
+/// Integral numbers become varints, and `null` fields are skipped.
+impl TryFrom<&Struct> for SerializedMessage {
+    type Error = crate::Error;
+
+    fn try_from(value: &Struct) -> Result<Self> {
+        let mut message = SerializedMessage::new();
+        for (key, value) in &value.fields {
+            let Ok(field) = key.parse::<u32>() else {
+                return Err(format!("Struct key {key:?} is not a field number.").into());
+            };
+
+            match from_struct_value(value)? {
+                Some(Value::Repeated(values)) => values.into_iter().for_each(|value| message.insert(field, value)),
+                Some(value) => message.insert(field, value),
+                None => {}
+            }
+        }
+
+        Ok(message)
+    }
+}
+
+/// Converts a value into a struct value.
+fn to_struct_value(value: &Value) -> prost_types::Value {
+    let kind = match value {
+        Value::VarInt(varint) => Kind::NumberValue(match Number::closest(varint.clone()) {
+            Number::Integer(value) => value as f64,
+            Number::Long(value) => value as f64,
+            Number::UnsignedInteger(value) => value as f64,
+            Number::UnsignedLong(value) => value as f64
+        }),
+        Value::Float(value) => Kind::NumberValue(*value as f64),
+        Value::Double(value) => Kind::NumberValue(*value),
+        Value::String(value) => Kind::StringValue(value.clone()),
+        Value::Bytes(value) => Kind::StringValue(encoding::base64_encode(value)),
+        Value::Empty => Kind::StringValue(String::new()),
+        Value::Message(message) => Kind::StructValue(message.into()),
+        Value::Repeated(values) => Kind::ListValue(ListValue {
+            values: values.iter().map(to_struct_value).collect()
+        }),
+        Value::Map(entries) => Kind::StructValue(Struct {
+            fields: entries.iter()
+                .map(|(key, value)| (key.to_string(), to_struct_value(value)))
+                .collect()
+        }),
+        Value::Summary { field_count, type_histogram } => {
+            let histogram = type_histogram.iter()
+                .map(|(wire_type, count)| (wire_type.clone(), number(*count as f64)))
+                .collect();
+
+            Kind::StructValue(Struct {
+                fields: [
+                    ("field_count".to_string(), number(*field_count as f64)),
+                    ("type_histogram".to_string(), prost_types::Value { kind: Some(Kind::StructValue(Struct { fields: histogram })) })
+                ].into()
+            })
+        }
+    };
+
+    prost_types::Value { kind: Some(kind) }
+}
+
+/// Creates a number struct value.
+fn number(value: f64) -> prost_types::Value {
+    prost_types::Value { kind: Some(Kind::NumberValue(value)) }
+}
+
+/// Converts a struct value into a value, or `None` if it is null.
+fn from_struct_value(value: &prost_types::Value) -> Result<Option<Value>> {
+    let value = match &value.kind {
+        None | Some(Kind::NullValue(_)) => return Ok(None),
+        Some(Kind::NumberValue(value)) if value.fract() == 0.0 && value.abs() < i64::MAX as f64 => {
+            Value::VarInt(VarInt::from(*value as i64))
+        }
+        Some(Kind::NumberValue(value)) => Value::Double(*value),
+        Some(Kind::StringValue(value)) => Value::String(value.clone()),
+        Some(Kind::BoolValue(value)) => Value::from(*value),
+        Some(Kind::StructValue(value)) => Value::Message(value.try_into()?),
+        Some(Kind::ListValue(list)) => {
+            let mut values = vec![];
+            for value in &list.values {
+                values.extend(from_struct_value(value)?);
+            }
+            Value::Repeated(values)
+        }
+    };
+
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FieldAccess;
+
+    #[test]
+    fn well_known_types() {
+        let mut inner = SerializedMessage::new();
+        inner.insert(1, Value::from("Hello, World!".to_string()));
+
+        let mut message = SerializedMessage::new();
+        message.insert(1, Value::VarInt(150.into()));
+        message.insert(2, Value::Double(1.5));
+        message.insert(3, Value::Message(inner));
+
+        let structure = Struct::from(&message);
+        assert_eq!(structure.fields["1"].kind, Some(Kind::NumberValue(150.0)));
+        assert_eq!(SerializedMessage::try_from(&structure).unwrap(), message);
+
+        let any = message.to_any("type.googleapis.com/example.Message");
+        let (name, decoded) = SerializedMessage::from_any(&any).unwrap();
+        assert_eq!(name, "example.Message");
+        assert_eq!(decoded.get_u32(1), Some(150));
+    }
+}
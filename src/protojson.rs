@@ -0,0 +1,651 @@
+use std::collections::BTreeMap;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+use serde_json::{Map, Number, Value as Json};
+use crate::decoder::field_number;
+use crate::wellknown::WellKnown;
+use crate::{encoding, DecodeConfig, Error, Result, VarInt, WireType};
+
+/// The deepest nesting of messages which is rendered, as in the reference parsers.
+const MAX_DEPTH: usize = 100;
+
+/// Renders encoded messages in the proto3 JSON mapping, using the message
+/// and enum types of a descriptor set.
+///
+/// Type names are resolved by their fully qualified name, or failing that,
+/// by their longest suffix naming a known type.
+#[derive(Clone, Debug, Default)]
+pub struct JsonMapping<'a> {
+    messages: BTreeMap<String, &'a DescriptorProto>,
+    enums: BTreeMap<String, &'a EnumDescriptorProto>
+}
+
+/// A field as read from the wire, borrowing its payload.
+#[derive(Clone, Debug)]
+enum Field<'a> {
+    VarInt(u64),
+    Fixed32(&'a [u8]),
+    Fixed64(&'a [u8]),
+    LengthDelimited(&'a [u8])
+}
+
+impl<'a> JsonMapping<'a> {
+    /// Indexes the types of every file in the set.
+    pub fn new(files: &'a FileDescriptorSet) -> Self {
+        let mut mapping = Self::default();
+        for file in &files.file {
+            for descriptor in &file.message_type {
+                mapping.index(file.package(), descriptor);
+            }
+            for descriptor in &file.enum_type {
+                mapping.enums.insert(qualify(file.package(), descriptor.name()), descriptor);
+            }
+        }
+
+        mapping
+    }
+
+    /// Renders an encoded message of the named type, such as `example.Player`,
+    /// in the canonical proto3 JSON mapping.
+    ///
+    /// Fields are named by their `json_name`, or in lowerCamelCase, and written
+    /// in declaration order. 64-bit integers are strings, bytes are Base64,
+    /// enums are the names of their values, and non-finite floats are `"NaN"`
+    /// or `"Infinity"`. The well-known types take their special forms, such as
+    /// RFC 3339 timestamps, even when the set does not declare them.
+    ///
+    /// Occurrences of a singular message are merged, and of any other singular
+    /// field the last one wins, as parsers do. Unknown fields are dropped.
+    pub fn to_json(&self, bytes: &[u8], name: &str) -> Result<Json> {
+        self.message_json(bytes, name, 0)
+    }
+
+    /// Indexes a message type and the types nested in it.
+    fn index(&mut self, scope: &str, descriptor: &'a DescriptorProto) {
+        let name = qualify(scope, descriptor.name());
+        for nested in &descriptor.nested_type {
+            self.index(&name, nested);
+        }
+        for nested in &descriptor.enum_type {
+            self.enums.insert(qualify(&name, nested.name()), nested);
+        }
+
+        self.messages.insert(name, descriptor);
+    }
+
+    /// Returns the message type with the given name.
+    fn message(&self, name: &str) -> Option<&'a DescriptorProto> {
+        suffixes(name).find_map(|name| self.messages.get(name).copied())
+    }
+
+    /// Returns the enum type with the given name.
+    fn enumeration(&self, name: &str) -> Option<&'a EnumDescriptorProto> {
+        suffixes(name).find_map(|name| self.enums.get(name).copied())
+    }
+
+    /// Renders a message of the named type at the given depth.
+    fn message_json(&self, bytes: &[u8], name: &str, depth: usize) -> Result<Json> {
+        if let Some(json) = self.well_known_json(bytes, name.trim_start_matches('.'), depth)? {
+            return Ok(json);
+        }
+        let Some(descriptor) = self.message(name) else {
+            return Err(format!("Invalid message; type '{name}' is not in the descriptor set.").into());
+        };
+
+        let fields = read_fields(bytes)?;
+        let mut object = Map::new();
+        for field in &descriptor.field {
+            if let Some(occurrences) = fields.get(&(field.number() as u32)) {
+                object.insert(json_name(field), self.field_json(field, occurrences, depth)?);
+            }
+        }
+
+        Ok(Json::Object(object))
+    }
+
+    /// Renders every occurrence of a field as its single value, array, or map.
+    fn field_json(&self, field: &FieldDescriptorProto, occurrences: &[Field], depth: usize) -> Result<Json> {
+        if field.label() != Label::Repeated {
+            if field.r#type() == Type::Message {
+                // Occurrences of a message merge, as their concatenation does.
+                let mut bytes = vec![];
+                for occurrence in occurrences {
+                    bytes.extend_from_slice(payload(field, occurrence)?);
+                }
+                return self.message_json(&bytes, field.type_name(), depth + 1);
+            }
+
+            // Fields are only grouped when they occur.
+            return self.value_json(field, occurrences.last().unwrap(), depth);
+        }
+
+        if let Some((key_field, value_field)) = self.map_entry(field) {
+            let mut object = Map::new();
+            for occurrence in occurrences {
+                let entry = read_fields(payload(field, occurrence)?)?;
+                let key = match entry.get(&1).and_then(|keys| keys.last()) {
+                    Some(key) => self.value_json(key_field, key, depth + 1)?,
+                    None => self.default_json(key_field)
+                };
+                let value = match entry.get(&2) {
+                    Some(values) => self.field_json(value_field, values, depth + 1)?,
+                    None => self.default_json(value_field)
+                };
+
+                let key = match key {
+                    Json::String(key) => key,
+                    key => key.to_string()
+                };
+                object.insert(key, value);
+            }
+
+            return Ok(Json::Object(object));
+        }
+
+        let mut values = vec![];
+        for occurrence in occurrences {
+            match occurrence {
+                // Repeated scalars may be packed into a single length-delimited payload.
+                Field::LengthDelimited(packed) if is_packable(field.r#type()) => {
+                    for value in unpack(field.r#type(), packed)? {
+                        values.push(self.value_json(field, &value, depth)?);
+                    }
+                }
+                occurrence => values.push(self.value_json(field, occurrence, depth)?)
+            }
+        }
+
+        Ok(Json::Array(values))
+    }
+
+    /// Renders a single occurrence of a field by its declared type.
+    fn value_json(&self, field: &FieldDescriptorProto, value: &Field, depth: usize) -> Result<Json> {
+        Ok(match (field.r#type(), value) {
+            (Type::Double, Field::Fixed64(bytes)) => float(f64::from_le_bytes((*bytes).try_into()?)),
+            // Written through its shortest decimal form, so that 0.1 stays 0.1.
+            (Type::Float, Field::Fixed32(bytes)) => float(f32::from_le_bytes((*bytes).try_into()?).to_string().parse().unwrap()),
+            (Type::Fixed32, Field::Fixed32(bytes)) => u32::from_le_bytes((*bytes).try_into()?).into(),
+            (Type::Sfixed32, Field::Fixed32(bytes)) => i32::from_le_bytes((*bytes).try_into()?).into(),
+            (Type::Fixed64, Field::Fixed64(bytes)) => u64::from_le_bytes((*bytes).try_into()?).to_string().into(),
+            (Type::Sfixed64, Field::Fixed64(bytes)) => i64::from_le_bytes((*bytes).try_into()?).to_string().into(),
+            (Type::Int32, Field::VarInt(value)) => (*value as i32).into(),
+            (Type::Uint32, Field::VarInt(value)) => (*value as u32).into(),
+            (Type::Sint32, Field::VarInt(value)) => ((*value as u32 >> 1) as i32 ^ -((*value & 1) as i32)).into(),
+            (Type::Int64, Field::VarInt(value)) => (*value as i64).to_string().into(),
+            (Type::Uint64, Field::VarInt(value)) => value.to_string().into(),
+            (Type::Sint64, Field::VarInt(value)) => ((*value >> 1) as i64 ^ -((*value & 1) as i64)).to_string().into(),
+            (Type::Bool, Field::VarInt(value)) => (*value != 0).into(),
+            (Type::Enum, Field::VarInt(value)) => self.enum_json(field.type_name(), *value as i32),
+            (Type::String, Field::LengthDelimited(bytes)) => string(bytes)?,
+            (Type::Bytes, Field::LengthDelimited(bytes)) => encoding::base64_encode(bytes).into(),
+            (Type::Message, Field::LengthDelimited(bytes)) => self.message_json(bytes, field.type_name(), depth + 1)?,
+            _ => return Err(wrong_wire_type(field))
+        })
+    }
+
+    /// Renders the value a field takes when it is not set.
+    fn default_json(&self, field: &FieldDescriptorProto) -> Json {
+        match field.r#type() {
+            Type::Int64 | Type::Uint64 | Type::Sint64 | Type::Fixed64 | Type::Sfixed64 => "0".into(),
+            Type::Bool => false.into(),
+            Type::String | Type::Bytes => "".into(),
+            Type::Enum => self.enum_json(field.type_name(), 0),
+            Type::Message => self.message_json(&[], field.type_name(), 0).unwrap_or(Json::Null),
+            _ => 0.into()
+        }
+    }
+
+    /// Renders an enum value by its name, or by its number if it has none.
+    fn enum_json(&self, name: &str, number: i32) -> Json {
+        if name.trim_start_matches('.') == "google.protobuf.NullValue" {
+            return Json::Null;
+        }
+
+        self.enumeration(name)
+            .and_then(|enumeration| enumeration.value.iter().find(|value| value.number() == number))
+            .map_or_else(|| number.into(), |value| value.name().into())
+    }
+
+    /// Returns the key and value fields of a map field's entry type, if it is a map.
+    fn map_entry(&self, field: &FieldDescriptorProto) -> Option<(&'a FieldDescriptorProto, &'a FieldDescriptorProto)> {
+        if field.r#type() != Type::Message {
+            return None;
+        }
+
+        let entry = self.message(field.type_name())?;
+        if !entry.options.as_ref().is_some_and(|options| options.map_entry()) {
+            return None;
+        }
+
+        let field = |number| entry.field.iter().find(|field| field.number() == number);
+        Some((field(1)?, field(2)?))
+    }
+
+    /// Renders a message of a well-known type in its special form, if it is one.
+    fn well_known_json(&self, bytes: &[u8], name: &str, depth: usize) -> Result<Option<Json>> {
+        // Checked here rather than by callers, since `Any` payloads recurse into this directly.
+        if depth > MAX_DEPTH {
+            return Err(format!("Invalid message; messages are nested deeper than {MAX_DEPTH} levels.").into());
+        }
+
+        let json = match name {
+            "google.protobuf.Timestamp" | "google.protobuf.Duration" => {
+                let fields = read_fields(bytes)?;
+                let number = |number| match fields.get(&number).and_then(|values| values.last()) {
+                    Some(Field::VarInt(value)) => Ok(*value as i64),
+                    None => Ok(0),
+                    Some(_) => Err(Error::from(format!("Invalid message; malformed {name}.")))
+                };
+                let (seconds, nanos) = (number(1)?, number(2)? as i32);
+
+                let time = match name {
+                    "google.protobuf.Timestamp" => WellKnown::Timestamp { seconds, nanos },
+                    _ => WellKnown::Duration { seconds, nanos }
+                };
+                time.to_string().into()
+            }
+            "google.protobuf.DoubleValue" => self.wrapper_json(bytes, Type::Double, depth)?,
+            "google.protobuf.FloatValue" => self.wrapper_json(bytes, Type::Float, depth)?,
+            "google.protobuf.Int64Value" => self.wrapper_json(bytes, Type::Int64, depth)?,
+            "google.protobuf.UInt64Value" => self.wrapper_json(bytes, Type::Uint64, depth)?,
+            "google.protobuf.Int32Value" => self.wrapper_json(bytes, Type::Int32, depth)?,
+            "google.protobuf.UInt32Value" => self.wrapper_json(bytes, Type::Uint32, depth)?,
+            "google.protobuf.BoolValue" => self.wrapper_json(bytes, Type::Bool, depth)?,
+            "google.protobuf.StringValue" => self.wrapper_json(bytes, Type::String, depth)?,
+            "google.protobuf.BytesValue" => self.wrapper_json(bytes, Type::Bytes, depth)?,
+            "google.protobuf.FieldMask" => {
+                let mut paths = vec![];
+                for path in read_fields(bytes)?.get(&1).into_iter().flatten() {
+                    let Field::LengthDelimited(path) = path else {
+                        return Err("Invalid message; malformed google.protobuf.FieldMask.".into());
+                    };
+                    paths.push(camel_case(std::str::from_utf8(path).map_err(|_| "Invalid message; a string is not valid UTF-8.")?));
+                }
+                paths.join(",").into()
+            }
+            "google.protobuf.Struct" => {
+                let mut object = Map::new();
+                for entry in read_fields(bytes)?.get(&1).into_iter().flatten() {
+                    let Field::LengthDelimited(entry) = entry else {
+                        return Err("Invalid message; malformed google.protobuf.Struct.".into());
+                    };
+
+                    let (mut key, mut value) = (String::new(), Json::Null);
+                    for (number, field) in wire_fields(entry)? {
+                        match (number, field) {
+                            (1, Field::LengthDelimited(bytes)) => key = String::from_utf8(bytes.to_vec())
+                                .map_err(|_| "Invalid message; a string is not valid UTF-8.")?,
+                            (2, Field::LengthDelimited(bytes)) => value = self.message_json(bytes, "google.protobuf.Value", depth + 1)?,
+                            _ => {}
+                        }
+                    }
+                    object.insert(key, value);
+                }
+                Json::Object(object)
+            }
+            "google.protobuf.ListValue" => {
+                let mut values = vec![];
+                for value in read_fields(bytes)?.get(&1).into_iter().flatten() {
+                    let Field::LengthDelimited(value) = value else {
+                        return Err("Invalid message; malformed google.protobuf.ListValue.".into());
+                    };
+                    values.push(self.message_json(value, "google.protobuf.Value", depth + 1)?);
+                }
+                Json::Array(values)
+            }
+            "google.protobuf.Value" => {
+                // The kinds are a oneof, of which the last one set wins.
+                let mut json = Json::Null;
+                for (number, field) in wire_fields(bytes)? {
+                    json = match (number, field) {
+                        (1, Field::VarInt(_)) => Json::Null,
+                        (2, Field::Fixed64(bytes)) => float(f64::from_le_bytes(bytes.try_into()?)),
+                        (3, Field::LengthDelimited(bytes)) => string(bytes)?,
+                        (4, Field::VarInt(value)) => (value != 0).into(),
+                        (5, Field::LengthDelimited(bytes)) => self.message_json(bytes, "google.protobuf.Struct", depth + 1)?,
+                        (6, Field::LengthDelimited(bytes)) => self.message_json(bytes, "google.protobuf.ListValue", depth + 1)?,
+                        _ => return Err("Invalid message; malformed google.protobuf.Value.".into())
+                    };
+                }
+                json
+            }
+            "google.protobuf.Any" => {
+                let (mut type_url, mut value) = (String::new(), &[][..]);
+                for (number, field) in wire_fields(bytes)? {
+                    match (number, field) {
+                        (1, Field::LengthDelimited(bytes)) => type_url = String::from_utf8(bytes.to_vec())
+                            .map_err(|_| "Invalid message; a string is not valid UTF-8.")?,
+                        (2, Field::LengthDelimited(bytes)) => value = bytes,
+                        _ => {}
+                    }
+                }
+                if type_url.is_empty() {
+                    return Ok(Some(Json::Object(Map::new())));
+                }
+
+                // The type is named by the last segment of its URL.
+                let name = type_url.rsplit('/').next().unwrap_or_default();
+                let mut object = Map::new();
+                object.insert("@type".to_string(), type_url.clone().into());
+                match self.well_known_json(value, name, depth + 1)? {
+                    Some(json) => {
+                        object.insert("value".to_string(), json);
+                    }
+                    None => {
+                        let Json::Object(fields) = self.message_json(value, name, depth + 1)? else {
+                            unreachable!("Messages which are not well-known are objects.");
+                        };
+                        object.extend(fields);
+                    }
+                }
+                Json::Object(object)
+            }
+            "google.protobuf.Empty" => Json::Object(Map::new()),
+            _ => return Ok(None)
+        };
+
+        Ok(Some(json))
+    }
+
+    /// Renders a wrapper message as the value of its single field.
+    fn wrapper_json(&self, bytes: &[u8], field_type: Type, depth: usize) -> Result<Json> {
+        let field = FieldDescriptorProto {
+            name: Some("value".to_string()),
+            number: Some(1),
+            r#type: Some(field_type as i32),
+            ..Default::default()
+        };
+
+        match read_fields(bytes)?.get(&1) {
+            Some(occurrences) => self.field_json(&field, occurrences, depth),
+            None => Ok(self.default_json(&field))
+        }
+    }
+}
+
+/// Reads the fields of a message in wire order, failing if it is malformed.
+fn wire_fields(bytes: &[u8]) -> Result<Vec<(u32, Field<'_>)>> {
+    let config = DecodeConfig::default();
+    let mut fields = vec![];
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let Some((tag, tag_len)) = VarInt::read_at(bytes, index) else {
+            return Err("Invalid message; malformed field header.".into());
+        };
+        let number = field_number(tag, &config)?;
+        index += tag_len;
+
+        let (field, len) = match WireType::try_from((tag & 0b111) as u8) {
+            Ok(WireType::VarInt) => {
+                let Some((value, len)) = VarInt::read_at(bytes, index) else {
+                    return Err("Invalid message; malformed varint field.".into());
+                };
+                (Field::VarInt(value), len)
+            }
+            Ok(WireType::Fixed32) => (Field::Fixed32(slice(bytes, index, 4)?), 4),
+            Ok(WireType::Fixed64) => (Field::Fixed64(slice(bytes, index, 8)?), 8),
+            Ok(WireType::LengthDelimited) => {
+                let Some((len, prefix)) = VarInt::read_at(bytes, index) else {
+                    return Err("Invalid message; malformed length of a length-delimited field.".into());
+                };
+                index += prefix;
+
+                let len = usize::try_from(len).unwrap_or(usize::MAX);
+                (Field::LengthDelimited(slice(bytes, index, len)?), len)
+            }
+            Ok(WireType::StartGroup | WireType::EndGroup) => return Err("Invalid message; groups are not supported.".into()),
+            Err(_) => return Err("Invalid wire type specified".into())
+        };
+
+        fields.push((number, field));
+        index += len;
+    }
+
+    Ok(fields)
+}
+
+/// Returns the bytes of a field's value, failing if the message ends first.
+fn slice(bytes: &[u8], index: usize, len: usize) -> Result<&[u8]> {
+    index.checked_add(len)
+        .and_then(|end| bytes.get(index..end))
+        .ok_or_else(|| "Invalid message; not enough bytes for a field.".into())
+}
+
+/// Reads the fields of a message, grouped by number, each in wire order.
+fn read_fields(bytes: &[u8]) -> Result<BTreeMap<u32, Vec<Field<'_>>>> {
+    let mut fields: BTreeMap<u32, Vec<Field>> = BTreeMap::new();
+    for (number, field) in wire_fields(bytes)? {
+        fields.entry(number).or_default().push(field);
+    }
+
+    Ok(fields)
+}
+
+/// Returns the fully qualified name of a type in a package or message.
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() { name.to_string() } else { format!("{scope}.{name}") }
+}
+
+/// Returns a type name without its leading dot, followed by each shorter suffix of it.
+fn suffixes(name: &str) -> impl Iterator<Item = &str> {
+    let name = name.trim_start_matches('.');
+    std::iter::once(name).chain(name.match_indices('.').map(move |(index, _)| &name[index + 1..]))
+}
+
+/// Returns the payload of a length-delimited occurrence of a field.
+fn payload<'b>(field: &FieldDescriptorProto, occurrence: &Field<'b>) -> Result<&'b [u8]> {
+    match occurrence {
+        Field::LengthDelimited(payload) => Ok(payload),
+        _ => Err(wrong_wire_type(field))
+    }
+}
+
+/// Returns whether repeated fields of a type may be packed.
+fn is_packable(field_type: Type) -> bool {
+    !matches!(field_type, Type::String | Type::Bytes | Type::Message | Type::Group)
+}
+
+/// Splits a packed payload into its values.
+fn unpack(field_type: Type, payload: &[u8]) -> Result<Vec<Field<'_>>> {
+    let width = match field_type {
+        Type::Double | Type::Fixed64 | Type::Sfixed64 => 8,
+        Type::Float | Type::Fixed32 | Type::Sfixed32 => 4,
+        _ => 0
+    };
+    if width != 0 {
+        if !payload.len().is_multiple_of(width) {
+            return Err("Invalid message; packed payload is not a whole number of values.".into());
+        }
+        return Ok(payload.chunks(width).map(|value| if width == 4 { Field::Fixed32(value) } else { Field::Fixed64(value) }).collect());
+    }
+
+    let mut values = vec![];
+    let mut index = 0;
+    while index < payload.len() {
+        let Some((value, len)) = VarInt::read_at(payload, index) else {
+            return Err("Invalid message; malformed varint in a packed payload.".into());
+        };
+        values.push(Field::VarInt(value));
+        index += len;
+    }
+
+    Ok(values)
+}
+
+/// Returns the error of a field encoded with a wire type its type cannot have.
+fn wrong_wire_type(field: &FieldDescriptorProto) -> Error {
+    format!("Invalid message; field '{}' has the wrong wire type for its type.", field.name()).into()
+}
+
+/// Renders a string, failing if it is not valid UTF-8.
+fn string(bytes: &[u8]) -> Result<Json> {
+    match std::str::from_utf8(bytes) {
+        Ok(string) => Ok(string.into()),
+        Err(_) => Err("Invalid message; a string is not valid UTF-8.".into())
+    }
+}
+
+/// Renders a float as a number, or as the string naming it if it is not finite.
+fn float(value: f64) -> Json {
+    match Number::from_f64(value) {
+        Some(number) => Json::Number(number),
+        None if value.is_nan() => "NaN".into(),
+        None if value > 0.0 => "Infinity".into(),
+        None => "-Infinity".into()
+    }
+}
+
+/// Returns the JSON name of a field, derived as protoc does unless set explicitly.
+fn json_name(field: &FieldDescriptorProto) -> String {
+    match &field.json_name {
+        Some(name) => name.clone(),
+        None => camel_case(field.name())
+    }
+}
+
+/// Converts a snake_case name to lowerCamelCase, such as `userName` for `user_name`.
+fn camel_case(name: &str) -> String {
+    let mut output = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        match c {
+            '_' => upper = true,
+            c if upper => {
+                output.push(c.to_ascii_uppercase());
+                upper = false;
+            }
+            c => output.push(c)
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::{DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FileDescriptorProto, FileDescriptorSet, MessageOptions};
+    use serde_json::json;
+    use crate::ProtobufBytes;
+
+    fn field(name: &str, number: i32, label: Label, field_type: Type, type_name: &str) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(label as i32),
+            r#type: Some(field_type as i32),
+            type_name: (!type_name.is_empty()).then(|| type_name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn to_json() {
+        let status = EnumDescriptorProto {
+            name: Some("Status".to_string()),
+            value: [(0, "PENDING"), (1, "SHIPPED")].map(|(number, name)| EnumValueDescriptorProto {
+                name: Some(name.to_string()),
+                number: Some(number),
+                ..Default::default()
+            }).into(),
+            ..Default::default()
+        };
+        let stock = DescriptorProto {
+            name: Some("StockEntry".to_string()),
+            field: vec![
+                field("key", 1, Label::Optional, Type::String, ""),
+                field("value", 2, Label::Optional, Type::Int32, "")
+            ],
+            options: Some(MessageOptions { map_entry: Some(true), ..Default::default() }),
+            ..Default::default()
+        };
+        let order = DescriptorProto {
+            name: Some("Order".to_string()),
+            field: vec![
+                field("id", 1, Label::Optional, Type::Uint64, ""),
+                FieldDescriptorProto { json_name: Some("customer".to_string()), ..field("customer_name", 2, Label::Optional, Type::String, "") },
+                field("token", 3, Label::Optional, Type::Bytes, ""),
+                field("quantities", 4, Label::Repeated, Type::Sint32, ""),
+                field("status", 5, Label::Optional, Type::Enum, ".shop.Order.Status"),
+                field("stock", 6, Label::Repeated, Type::Message, ".shop.Order.StockEntry"),
+                field("created_at", 7, Label::Optional, Type::Message, ".google.protobuf.Timestamp"),
+                field("price", 8, Label::Optional, Type::Float, ""),
+                field("discount", 9, Label::Optional, Type::Double, ""),
+                field("parent", 10, Label::Optional, Type::Message, ".shop.Order"),
+                field("note", 11, Label::Optional, Type::Message, ".google.protobuf.StringValue")
+            ],
+            nested_type: vec![stock],
+            enum_type: vec![status],
+            ..Default::default()
+        };
+        let files = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                package: Some("shop".to_string()),
+                message_type: vec![order],
+                ..Default::default()
+            }]
+        };
+
+        let mut entry = vec![];
+        entry.write_str(1, "apples");
+        entry.write_u32(2, 3);
+        let mut timestamp = vec![];
+        timestamp.write_u64(1, 1_700_000_000);
+        timestamp.write_u32(2, 500_000_000);
+        let mut parent = vec![];
+        parent.write_u64(1, 7);
+        let mut other_parent = vec![];
+        other_parent.write_str(2, "Bob");
+
+        let mut bytes = vec![];
+        bytes.write_u64(1, u64::MAX);
+        bytes.write_str(2, "Alice");
+        bytes.write_bytes(3, &[0xff, 0x00]);
+        bytes.write_bytes(4, &[0x02, 0x03]);
+        bytes.write_u32(4, 4);
+        bytes.write_u32(5, 1);
+        bytes.write_bytes(6, &entry);
+        bytes.write_bytes(7, &timestamp);
+        bytes.write_f32(8, 0.1);
+        bytes.write_f64(9, f64::NAN);
+        bytes.write_bytes(10, &parent);
+        bytes.write_bytes(10, &other_parent);
+        bytes.write_bytes(11, &[]);
+
+        let mapping = JsonMapping::new(&files);
+        assert_eq!(mapping.to_json(&bytes, "shop.Order").unwrap(), json!({
+            "id": "18446744073709551615",
+            "customer": "Alice",
+            "token": "/wA=",
+            "quantities": [1, -2, 2],
+            "status": "SHIPPED",
+            "stock": { "apples": 3 },
+            "createdAt": "2023-11-14T22:13:20.500Z",
+            "price": 0.1,
+            "discount": "NaN",
+            "parent": { "id": "7", "customer": "Bob" },
+            "note": ""
+        }));
+
+        let mut wrong = vec![];
+        wrong.write_u32(2, 1);
+        assert!(mapping.to_json(&wrong, "shop.Order").is_err());
+        assert!(mapping.to_json(&bytes, "shop.Unknown").is_err());
+    }
+
+    #[test]
+    fn nested_any() {
+        let nest = |depth| (0..depth).fold(vec![], |value, _| {
+            let mut any = vec![];
+            any.write_str(1, "type.googleapis.com/google.protobuf.Any");
+            any.write_bytes(2, &value);
+            any
+        });
+
+        let files = FileDescriptorSet::default();
+        let mapping = JsonMapping::new(&files);
+        assert!(mapping.to_json(&nest(MAX_DEPTH), "google.protobuf.Any").is_ok());
+        assert!(mapping.to_json(&nest(MAX_DEPTH + 2), "google.protobuf.Any").is_err());
+    }
+}
@@ -0,0 +1,361 @@
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::CharIndices;
+use crate::{decode, Error, Result, SerializedMessage, Value, VarInt, MAX_FIELD_NUMBER};
+
+impl SerializedMessage {
+    /// Renders the message as protoscope text, for reading and hand-editing
+    /// with the `protoscope` tool.
+    ///
+    /// Fields are written one per line, with nested messages indented in
+    /// braces. A payload decoded both as a string and as a message is
+    /// written once, as the message.
+    ///
+    /// Fails if the message holds a `Value::Summary`, whose fields were not
+    /// decoded and so cannot be written.
+    pub fn to_protoscope(&self) -> Result<String> {
+        let mut text = String::new();
+        write_message(&mut text, self, 0)?;
+
+        Ok(text)
+    }
+
+    /// Assembles protoscope text and decodes the resulting bytes.
+    ///
+    /// Supports fields with inferred or explicit wire types (`1:`, `1:LEN`),
+    /// integers with `i32`, `i64`, and `z` suffixes, floats, booleans,
+    /// quoted strings, backquoted hex, braces, and `#` comments.
+    pub fn from_protoscope(text: &str) -> Result<Self> {
+        decode(&assemble(text)?)
+    }
+}
+
+/// Writes the fields of a message, one per line.
+fn write_message(text: &mut String, message: &SerializedMessage, indent: usize) -> Result<()> {
+    for (field, value) in message {
+        write_value(text, *field, value, indent)?;
+    }
+
+    Ok(())
+}
+
+/// Writes a field, once for each of its values.
+fn write_value(text: &mut String, field: u32, value: &Value, indent: usize) -> Result<()> {
+    let pad = "  ".repeat(indent);
+    match value {
+        Value::VarInt(varint) => writeln!(text, "{pad}{field}: {}", varint.as_i64()).unwrap(),
+        Value::Float(value) => writeln!(text, "{pad}{field}: {}", float(*value as f64, format!("{value:?}"), "i32")).unwrap(),
+        Value::Double(value) => writeln!(text, "{pad}{field}: {}", float(*value, format!("{value:?}"), "")).unwrap(),
+        Value::String(value) => writeln!(text, "{pad}{field}: {{{}}}", quote(value)).unwrap(),
+        Value::Bytes(bytes) => {
+            let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+            writeln!(text, "{pad}{field}: {{`{hex}`}}").unwrap();
+        }
+        Value::Empty => writeln!(text, "{pad}{field}: {{}}").unwrap(),
+        Value::Message(message) if message.iter().next().is_none() => writeln!(text, "{pad}{field}: {{}}").unwrap(),
+        Value::Message(message) => {
+            writeln!(text, "{pad}{field}: {{").unwrap();
+            write_message(text, message, indent + 1)?;
+            writeln!(text, "{pad}}}").unwrap();
+        }
+        Value::Repeated(values) => match values.as_slice() {
+            // A single payload which is both a string and a message.
+            [Value::String(_), message @ Value::Message(_)] => write_value(text, field, message, indent)?,
+            values => {
+                for value in values {
+                    write_value(text, field, value, indent)?;
+                }
+            }
+        },
+        Value::Summary { field_count, .. } => {
+            return Err(format!(
+                "Invalid message; field {field} is a summary of {field_count} fields, which cannot be written as protoscope."
+            ).into());
+        }
+        Value::Map(entries) => {
+            for (key, value) in entries {
+                write_value(text, field, &Value::Message(key.entry(value.clone())), indent)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Formats a float so that protoscope reads it back as one, with the given suffix.
+///
+/// The literal is the shortest formatting of the float at its own width.
+fn float(value: f64, mut literal: String, suffix: &str) -> String {
+    let width = if suffix.is_empty() { "64" } else { "32" };
+    if value.is_nan() {
+        return format!("nan{width}");
+    }
+    if value.is_infinite() {
+        let sign = if value < 0.0 { "-" } else { "" };
+        return format!("{sign}inf{width}");
+    }
+
+    if !literal.contains('.') {
+        let exponent = literal.find('e').unwrap_or(literal.len());
+        literal.insert_str(exponent, ".0");
+    }
+
+    literal + suffix
+}
+
+/// Quotes a string, escaping quotes, backslashes, and control characters.
+fn quote(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() && c.is_ascii() => write!(quoted, "\\x{:02x}", c as u32).unwrap(),
+            c => quoted.push(c)
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+/// A token of protoscope text.
+#[derive(Debug, PartialEq)]
+enum Token {
+    /// A field number, with its wire type if given explicitly.
+    Tag(u64, Option<u64>),
+    VarInt(u64),
+    Fixed32([u8; 4]),
+    Fixed64([u8; 8]),
+    Bytes(Vec<u8>),
+    Open,
+    Close
+}
+
+/// Assembles protoscope text into the bytes it describes.
+///
+/// Tags, varints, and length prefixes are written with the fewest bytes.
+/// Braces may nest to any depth, as they are assembled without recursion.
+pub fn assemble(text: &str) -> Result<Vec<u8>> {
+    let mut tokens = tokenize(text)?.into_iter().peekable();
+    // The payloads of the open braces, innermost last, above the top level.
+    let mut stack = vec![vec![]];
+
+    while let Some(token) = tokens.next() {
+        let bytes = stack.last_mut().unwrap();
+        match token {
+            Token::Tag(field, wire_type) => {
+                if field == 0 || field > MAX_FIELD_NUMBER as u64 {
+                    return Err(Error::FieldNumber(field));
+                }
+                let wire_type = match (wire_type, tokens.peek()) {
+                    (Some(wire_type), _) => wire_type,
+                    (None, Some(Token::VarInt(_))) => 0,
+                    (None, Some(Token::Fixed64(_))) => 1,
+                    (None, Some(Token::Open | Token::Bytes(_))) => 2,
+                    (None, Some(Token::Fixed32(_))) => 5,
+                    (None, _) => return Err(format!("Invalid protoscope; field {field} has no value.").into())
+                };
+                bytes.extend(VarInt::encode_minimal(field << 3 | wire_type));
+            }
+            Token::VarInt(value) => bytes.extend(VarInt::encode_minimal(value)),
+            Token::Fixed32(value) => bytes.extend(value),
+            Token::Fixed64(value) => bytes.extend(value),
+            Token::Bytes(value) => bytes.extend(value),
+            Token::Open => stack.push(vec![]),
+            Token::Close => {
+                if stack.len() == 1 {
+                    return Err("Invalid protoscope; unmatched '}'.".into());
+                }
+                let nested = stack.pop().unwrap();
+                let bytes = stack.last_mut().unwrap();
+                bytes.extend(VarInt::encode_minimal(nested.len() as u64));
+                bytes.extend(nested);
+            }
+        }
+    }
+
+    match stack.len() {
+        1 => Ok(stack.pop().unwrap()),
+        _ => Err("Invalid protoscope; unmatched '{'.".into())
+    }
+}
+
+/// Splits protoscope text into tokens.
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '#' => while chars.next_if(|(_, c)| *c != '\n').is_some() {},
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '"' => tokens.push(Token::Bytes(string(&mut chars)?)),
+            '`' => {
+                let hex: String = std::iter::from_fn(|| chars.next_if(|(_, c)| *c != '`').map(|(_, c)| c))
+                    .filter(|c| !c.is_whitespace())
+                    .collect();
+                if chars.next().is_none() {
+                    return Err("Invalid protoscope; unterminated hex literal.".into());
+                }
+                tokens.push(Token::Bytes(hex_bytes(&hex)?));
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((index, c)) = chars.next_if(|(_, c)| !c.is_whitespace() && !"{}\"`#".contains(*c)) {
+                    end = index + c.len_utf8();
+                }
+                tokens.push(word(&text[start..end])?);
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Reads the rest of a quoted string, processing escapes.
+fn string(chars: &mut Peekable<CharIndices>) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    loop {
+        match chars.next().map(|(_, c)| c) {
+            None => return Err("Invalid protoscope; unterminated string.".into()),
+            Some('"') => return Ok(bytes),
+            Some('\\') => match chars.next().map(|(_, c)| c) {
+                Some('n') => bytes.push(b'\n'),
+                Some('r') => bytes.push(b'\r'),
+                Some('t') => bytes.push(b'\t'),
+                Some('x') => {
+                    let hex: String = (0..2).filter_map(|_| chars.next().map(|(_, c)| c)).collect();
+                    bytes.extend(hex_bytes(&hex)?);
+                }
+                Some(c @ ('"' | '\\' | '\'')) => bytes.push(c as u8),
+                _ => return Err("Invalid protoscope; unknown escape in string.".into())
+            },
+            Some(c) => bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes())
+        }
+    }
+}
+
+/// Parses hex digits into bytes.
+fn hex_bytes(hex: &str) -> Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid protoscope; '{hex}' is not hex.").into());
+    }
+
+    Ok((0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect())
+}
+
+/// Parses a tag, number, or keyword.
+fn word(word: &str) -> Result<Token> {
+    let invalid = || format!("Invalid protoscope; unknown token '{word}'.").into();
+
+    if let Some((field, wire_type)) = word.split_once(':') {
+        let field = field.parse().map_err(|_| invalid())?;
+        let wire_type = match wire_type {
+            "" => None,
+            "VARINT" => Some(0),
+            "I64" => Some(1),
+            "LEN" => Some(2),
+            "I32" => Some(5),
+            _ => return Err(invalid())
+        };
+        return Ok(Token::Tag(field, wire_type));
+    }
+
+    match word {
+        "true" => return Ok(Token::VarInt(1)),
+        "false" => return Ok(Token::VarInt(0)),
+        "inf32" | "-inf32" | "nan32" => return Ok(Token::Fixed32(word.replace("32", "").parse::<f32>().unwrap().to_le_bytes())),
+        "inf64" | "-inf64" | "nan64" => return Ok(Token::Fixed64(word.replace("64", "").parse::<f64>().unwrap().to_le_bytes())),
+        _ => {}
+    }
+
+    let (number, suffix) = match word.strip_suffix("i32").or_else(|| word.strip_suffix("i64")) {
+        Some(number) => (number, &word[number.len()..]),
+        None => match word.strip_suffix('z') {
+            Some(number) => (number, "z"),
+            None => (word, "")
+        }
+    };
+    let hex = number.trim_start_matches('-').starts_with("0x");
+
+    if !hex && number.contains(['.', 'e', 'E']) {
+        let value: f64 = number.parse().map_err(|_| invalid())?;
+        return match suffix {
+            "i32" => Ok(Token::Fixed32((value as f32).to_le_bytes())),
+            "" | "i64" => Ok(Token::Fixed64(value.to_le_bytes())),
+            _ => Err(invalid())
+        };
+    }
+
+    let value = integer(number).ok_or_else(invalid)?;
+    match suffix {
+        "i32" => Ok(Token::Fixed32((value as u32).to_le_bytes())),
+        "i64" => Ok(Token::Fixed64((value as u64).to_le_bytes())),
+        "z" => {
+            let value = i64::try_from(value).map_err(|_| invalid())?;
+            Ok(Token::VarInt(((value << 1) ^ (value >> 63)) as u64))
+        }
+        _ => Ok(Token::VarInt(value as u64))
+    }
+}
+
+/// Parses a decimal or hex integer, which may be negative or above `i64::MAX`.
+fn integer(number: &str) -> Option<i128> {
+    let (negative, digits) = match number.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, number)
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<i128>().ok()?
+    };
+    let value = if negative { -value } else { value };
+
+    (i64::MIN as i128..=u64::MAX as i128).contains(&value).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protoscope() {
+        let mut nested = SerializedMessage::new();
+        nested.insert(1, Value::VarInt((-2i64).into()));
+        nested.insert(2, Value::Double(2.0));
+
+        let mut message = SerializedMessage::new();
+        message.insert(1, Value::VarInt(150.into()));
+        message.insert(2, Value::String("say \"hi\"".to_string()));
+        message.insert(3, Value::Bytes(vec![0xff, 0x00]));
+        message.insert(4, Value::Message(nested));
+        message.insert(5, Value::Float(0.1));
+
+        let text = message.to_protoscope().unwrap();
+        assert_eq!(text, "1: 150\n2: {\"say \\\"hi\\\"\"}\n3: {`ff00`}\n4: {\n  1: -2\n  2: 2.0\n}\n5: 0.1i32\n");
+        assert_eq!(SerializedMessage::from_protoscope(&text).unwrap(), message);
+
+        assert_eq!(assemble("1: 150").unwrap(), [0x08, 0x96, 0x01]);
+        assert_eq!(assemble("1:I64 3i64 # fixed\n2: -1z 3: {`0102` \"a\"}").unwrap(),
+            [0x09, 3, 0, 0, 0, 0, 0, 0, 0, 0x10, 0x01, 0x1a, 0x03, 0x01, 0x02, b'a']);
+        assert!(assemble("1: {2: 3").is_err());
+        assert!(assemble("1: 2 }").is_err());
+        assert!(assemble("1: bogus").is_err());
+        assert!(matches!(assemble("0: 1"), Err(Error::FieldNumber(0))));
+        assert!(matches!(assemble("536870912: 1"), Err(Error::FieldNumber(536870912))));
+        assert!(matches!(assemble("4294967296: 1"), Err(Error::FieldNumber(4294967296))));
+
+        let deep = "1: {".repeat(100_000) + &"}".repeat(100_000);
+        assert!(assemble(&deep).is_ok());
+
+        let mut summarized = SerializedMessage::new();
+        summarized.insert(1, Value::Summary { field_count: 3, type_histogram: Default::default() });
+        assert!(summarized.to_protoscope().is_err());
+    }
+}
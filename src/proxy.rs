@@ -0,0 +1,383 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use crate::grpc::{Frame, HEADER_LEN};
+use crate::pacing::Pacer;
+use crate::{Result, SerializedMessage};
+
+/// The connection preface sent by HTTP/2 clients.
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// The length of an HTTP/2 frame header.
+const FRAME_HEADER_LEN: usize = 9;
+
+/// The longest HTTP/2 frame which is buffered for dissection; longer frames are relayed undissected.
+const MAX_FRAME_LEN: usize = 1024 * 1024;
+
+/// The longest gRPC message which is buffered for dissection, gRPC's default limit.
+pub const MAX_MESSAGE_LEN: usize = 4 * 1024 * 1024;
+
+/// The direction a message travelled through the proxy.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient
+}
+
+/// A callback receiving every message dissected by the proxy,
+/// with its direction and HTTP/2 stream id.
+pub type Callback = dyn Fn(Direction, u32, Result<SerializedMessage>) + Send + Sync;
+
+/// Accepts connections forever, relaying each to the upstream address
+/// on its own threads and dissecting the gRPC messages in between.
+///
+/// Only cleartext HTTP/2 (h2c) is dissected; other traffic is relayed untouched.
+/// A connection which fails to be accepted is skipped.
+pub fn serve<A, F>(listener: TcpListener, upstream: A, callback: F) -> io::Result<()>
+where
+    A: ToSocketAddrs + Clone + Send + 'static,
+    F: Fn(Direction, u32, Result<SerializedMessage>) + Send + Sync + 'static
+{
+    serve_paced(listener, upstream, None, callback)
+}
+
+/// Like `serve`, but forwards the messages of each client at the pace of its own
+/// copy of `pacer`, so the upstream server is not sent traffic at full speed.
+pub fn serve_paced<A, F>(listener: TcpListener, upstream: A, pacer: Option<Pacer>, callback: F) -> io::Result<()>
+where
+    A: ToSocketAddrs + Clone + Send + 'static,
+    F: Fn(Direction, u32, Result<SerializedMessage>) + Send + Sync + 'static
+{
+    let callback: Arc<Callback> = Arc::new(callback);
+    for client in listener.incoming() {
+        // Such as when out of file descriptors, which only loses this connection.
+        let Ok(client) = client else {
+            continue;
+        };
+        let (upstream, pacer, callback) = (upstream.clone(), pacer.clone(), callback.clone());
+        thread::spawn(move || relay(client, upstream, pacer, callback));
+    }
+
+    Ok(())
+}
+
+/// Relays a single connection to the upstream address until either side closes it.
+///
+/// `pacer`: Paces the messages sent by the client, if given. Live traffic has
+/// no capture timestamps, so `Pacing::Original` does not delay it.
+pub fn relay<A: ToSocketAddrs>(
+    client: TcpStream,
+    upstream: A,
+    pacer: Option<Pacer>,
+    callback: Arc<Callback>
+) -> io::Result<()> {
+    let server = TcpStream::connect(upstream)?;
+    let (client_read, server_write) = (client.try_clone()?, server.try_clone()?);
+    let (client_close, server_close) = (client.try_clone()?, server.try_clone()?);
+
+    let forward = {
+        let callback = callback.clone();
+        thread::spawn(move || pipe(client_read, server_write, Direction::ClientToServer, pacer, &*callback))
+    };
+    let backward = pipe(server, client, Direction::ServerToClient, None, &*callback);
+    if backward.is_err() {
+        // Unblock the other direction, which would otherwise wait on the client forever.
+        let _ = client_close.shutdown(Shutdown::Both);
+        let _ = server_close.shutdown(Shutdown::Both);
+    }
+
+    forward.join().map_err(|_| io::Error::other("Relay thread panicked."))??;
+    backward
+}
+
+/// Copies bytes from one socket to another, dissecting them on the way.
+///
+/// With a pacer, bytes completing messages are held until the pacer lets the
+/// last of them through, so messages read together are sent together.
+fn pipe(
+    mut from: TcpStream,
+    mut to: TcpStream,
+    direction: Direction,
+    mut pacer: Option<Pacer>,
+    callback: &Callback
+) -> io::Result<()> {
+    let mut dissector = Http2Dissector::new(direction);
+    let mut buffer = [0u8; 16 * 1024];
+
+    loop {
+        let len = from.read(&mut buffer)?;
+        if len == 0 {
+            // Let the other side see the end of the stream.
+            let _ = to.shutdown(Shutdown::Write);
+            return Ok(());
+        }
+
+        let messages = dissector.feed(&buffer[..len]);
+        if let Some(pacer) = &mut pacer {
+            for _ in &messages {
+                pacer.wait(None);
+            }
+        }
+
+        to.write_all(&buffer[..len])?;
+        for (stream_id, message) in messages {
+            callback(direction, stream_id, message);
+        }
+    }
+}
+
+/// Extracts gRPC messages from one direction of an HTTP/2 connection.
+///
+/// Frames longer than 1 MiB are skipped without being buffered, and a stream
+/// with a message longer than `MAX_MESSAGE_LEN` is reset: its messages are
+/// skipped until it ends. Both are reported as errors on their stream.
+#[derive(Clone, Debug)]
+pub struct Http2Dissector {
+    /// The bytes of the incomplete HTTP/2 frame.
+    buffer: Vec<u8>,
+    /// The number of bytes of a skipped frame still to be discarded.
+    skip: usize,
+    /// The number of preface bytes still expected from the client.
+    preface: usize,
+    /// Whether the traffic turned out not to be HTTP/2.
+    disabled: bool,
+    /// The gRPC bytes of each stream which do not yet form a message.
+    streams: HashMap<u32, Vec<u8>>,
+    /// The streams whose messages are skipped until they end.
+    reset: HashSet<u32>
+}
+
+impl Http2Dissector {
+    /// Creates a dissector for traffic in the given direction.
+    pub fn new(direction: Direction) -> Self {
+        Self {
+            buffer: vec![],
+            skip: 0,
+            preface: if direction == Direction::ClientToServer { PREFACE.len() } else { 0 },
+            disabled: false,
+            streams: HashMap::new(),
+            reset: HashSet::new()
+        }
+    }
+
+    /// Adds bytes of the connection, returning the messages they complete
+    /// along with their stream ids.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<(u32, Result<SerializedMessage>)> {
+        if self.disabled {
+            return vec![];
+        }
+        self.buffer.extend_from_slice(bytes);
+
+        if self.preface > 0 {
+            let start = PREFACE.len() - self.preface;
+            let len = self.preface.min(self.buffer.len());
+            if self.buffer[..len] != PREFACE[start..start + len] {
+                self.disable();
+                return vec![];
+            }
+
+            self.buffer.drain(..len);
+            self.preface -= len;
+        }
+
+        let mut messages = vec![];
+        loop {
+            let skipped = self.skip.min(self.buffer.len());
+            self.buffer.drain(..skipped);
+            self.skip -= skipped;
+            if self.buffer.len() < FRAME_HEADER_LEN {
+                break;
+            }
+
+            let length = u32::from_be_bytes([0, self.buffer[0], self.buffer[1], self.buffer[2]]) as usize;
+            let (frame_type, flags) = (self.buffer[3], self.buffer[4]);
+            let stream_id = u32::from_be_bytes([self.buffer[5], self.buffer[6], self.buffer[7], self.buffer[8]]) & 0x7fff_ffff;
+            if length > MAX_FRAME_LEN {
+                self.skip = FRAME_HEADER_LEN + length;
+                let error = format!("Invalid HTTP/2 frame; length exceeds the limit of {MAX_FRAME_LEN} bytes.");
+                messages.push((stream_id, Err(error.into())));
+                if frame_type == 0 {
+                    self.reset_stream(stream_id, flags);
+                }
+                continue;
+            }
+            if self.buffer.len() < FRAME_HEADER_LEN + length {
+                break;
+            }
+
+            let frame: Vec<u8> = self.buffer.drain(..FRAME_HEADER_LEN + length).collect();
+
+            // Only DATA frames carry messages.
+            if frame_type == 0 {
+                match data_payload(&frame[FRAME_HEADER_LEN..], flags) {
+                    Some(payload) => self.push_data(stream_id, payload, &mut messages),
+                    None => messages.push((stream_id, Err("Invalid HTTP/2 frame; padding exceeds the payload.".into())))
+                }
+
+                // END_STREAM; any remaining bytes are an incomplete message.
+                if flags & 0x1 != 0 {
+                    self.streams.remove(&stream_id);
+                    self.reset.remove(&stream_id);
+                }
+            }
+        }
+
+        messages
+    }
+
+    /// Appends the data of a stream, decoding every complete gRPC message.
+    fn push_data(&mut self, stream_id: u32, payload: &[u8], messages: &mut Vec<(u32, Result<SerializedMessage>)>) {
+        if self.reset.contains(&stream_id) {
+            return;
+        }
+        let buffer = self.streams.entry(stream_id).or_default();
+        buffer.extend_from_slice(payload);
+
+        while buffer.len() >= HEADER_LEN {
+            let length = u32::from_be_bytes([buffer[1], buffer[2], buffer[3], buffer[4]]) as usize;
+            if length > MAX_MESSAGE_LEN {
+                let error = format!("Invalid gRPC frame; message exceeds the limit of {MAX_MESSAGE_LEN} bytes.");
+                messages.push((stream_id, Err(error.into())));
+                self.streams.remove(&stream_id);
+                self.reset.insert(stream_id);
+                return;
+            }
+            if buffer.len() < HEADER_LEN + length {
+                break;
+            }
+
+            let message = Frame::read(buffer).and_then(|(frame, _)| frame.decode());
+            buffer.drain(..HEADER_LEN + length);
+            messages.push((stream_id, message));
+        }
+    }
+
+    /// Skips the rest of a stream whose data was lost, unless the frame ends it.
+    fn reset_stream(&mut self, stream_id: u32, flags: u8) {
+        self.streams.remove(&stream_id);
+        if flags & 0x1 == 0 {
+            self.reset.insert(stream_id);
+        }
+    }
+
+    /// Stops dissecting, since the traffic is not HTTP/2.
+    fn disable(&mut self) {
+        self.disabled = true;
+        self.buffer = vec![];
+        self.streams.clear();
+        self.reset.clear();
+    }
+}
+
+/// Returns the payload of a DATA frame without its padding.
+fn data_payload(payload: &[u8], flags: u8) -> Option<&[u8]> {
+    // PADDED; the first byte is the padding length.
+    if flags & 0x8 == 0 {
+        return Some(payload);
+    }
+
+    let (&padding, payload) = payload.split_first()?;
+    payload.get(..payload.len().checked_sub(padding as usize)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+    use crate::pacing::Pacing;
+    use crate::ProtobufBytes;
+
+    /// Builds an HTTP/2 DATA frame.
+    fn data_frame(stream_id: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        frame.extend([0, flags]);
+        frame.extend(stream_id.to_be_bytes());
+        frame.extend(payload);
+        frame
+    }
+
+    #[test]
+    fn dissect() {
+        let mut message = vec![];
+        message.write_str(1, "Hello, World!");
+        let grpc = Frame { compressed: false, data: &message }.to_bytes();
+
+        // A message split across two frames, followed by a padded frame on another stream.
+        let mut bytes = PREFACE.to_vec();
+        bytes.extend(data_frame(1, 0, &grpc[..4]));
+        bytes.extend(data_frame(1, 0x1, &grpc[4..]));
+        bytes.extend(data_frame(3, 0x8, &[[2].as_slice(), &grpc, &[0, 0]].concat()));
+
+        let mut dissector = Http2Dissector::new(Direction::ClientToServer);
+        let mut messages = vec![];
+        for chunk in bytes.chunks(5) {
+            messages.extend(dissector.feed(chunk));
+        }
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].0, 1);
+        assert_eq!(messages[1].0, 3);
+        assert_eq!(messages[1].1.as_ref().unwrap().get(1).unwrap().as_string().unwrap(), "Hello, World!");
+
+        let mut plain = Http2Dissector::new(Direction::ClientToServer);
+        assert!(plain.feed(b"GET / HTTP/1.1\r\n\r\n").is_empty());
+
+        // An oversized frame is skipped, and a stream with an oversized message is reset until it ends.
+        let mut oversized = data_frame(5, 0, &[]);
+        oversized[..3].copy_from_slice(&[0x20, 0, 0]);
+        let huge = [&[0][..], &(MAX_MESSAGE_LEN as u32 + 1).to_be_bytes()].concat();
+        let mut dissector = Http2Dissector::new(Direction::ServerToClient);
+        let mut messages = dissector.feed(&oversized);
+        messages.extend(dissector.feed(&vec![0; 0x200000]));
+        messages.extend(dissector.feed(&data_frame(1, 0, &huge)));
+        messages.extend(dissector.feed(&data_frame(1, 0x1, &grpc)));
+        messages.extend(dissector.feed(&data_frame(1, 0, &grpc)));
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].1.is_err() && messages[1].1.is_err());
+        assert!(messages[2].1.is_ok());
+        assert!(dissector.buffer.is_empty());
+    }
+
+    #[test]
+    fn pace_forwarded_messages() {
+        let mut message = vec![];
+        message.write_str(1, "Hello, World!");
+        let grpc = Frame { compressed: false, data: &message }.to_bytes();
+
+        let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream_address = upstream.local_addr().unwrap();
+        let received = thread::spawn(move || {
+            let (mut socket, _) = upstream.accept().unwrap();
+            let mut bytes = vec![];
+            socket.read_to_end(&mut bytes).unwrap();
+            bytes
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, streams) = mpsc::channel();
+        let pacer = Pacer::new(Pacing::Rate(20.0));
+        thread::spawn(move || {
+            serve_paced(listener, upstream_address, Some(pacer), move |_, stream_id, message| {
+                sender.send((stream_id, message.is_ok())).unwrap();
+            })
+        });
+
+        let mut bytes = PREFACE.to_vec();
+        for stream_id in [1, 3, 5] {
+            bytes.extend(data_frame(stream_id, 0x1, &grpc));
+        }
+        let start = Instant::now();
+        let mut client = TcpStream::connect(address).unwrap();
+        client.write_all(&bytes).unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+
+        // Three messages at 20 per second take at least 100 ms to forward.
+        assert_eq!(received.join().unwrap(), bytes);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(streams.iter().take(3).collect::<Vec<_>>(), [(1, true), (3, true), (5, true)]);
+    }
+}
@@ -0,0 +1,87 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+use crate::{MapKey, SerializedMessage, Value};
+
+/// The `protoshark` Python module.
+#[pymodule]
+fn protoshark(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(decode, module)?)
+}
+
+/// Decodes a protobuf message into a dict keyed by field number.
+///
+/// Repeated fields become lists, and nested messages become dicts.
+#[pyfunction]
+fn decode<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyDict>> {
+    let message = crate::decode(data).map_err(|error| PyValueError::new_err(error.to_string()))?;
+    message_to_dict(py, &message)
+}
+
+/// Converts a message into a dict keyed by field number.
+fn message_to_dict<'py>(py: Python<'py>, message: &SerializedMessage) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    for (field, value) in message {
+        dict.set_item(field, to_python(py, value)?)?;
+    }
+
+    Ok(dict)
+}
+
+/// Converts a value into the closest Python type.
+fn to_python<'py>(py: Python<'py>, value: &Value) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match value {
+        Value::VarInt(value) => value.as_i64().into_pyobject(py)?.into_any(),
+        Value::Float(value) => value.into_pyobject(py)?.into_any(),
+        Value::Double(value) => value.into_pyobject(py)?.into_any(),
+        Value::String(value) => value.into_pyobject(py)?.into_any(),
+        Value::Bytes(value) => PyBytes::new(py, value).into_any(),
+        Value::Message(message) => message_to_dict(py, message)?.into_any(),
+        Value::Empty => py.None().into_bound(py),
+        Value::Repeated(values) => {
+            let values = values.iter()
+                .map(|value| to_python(py, value))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new(py, values)?.into_any()
+        }
+        Value::Summary { field_count, type_histogram } => {
+            let dict = PyDict::new(py);
+            dict.set_item("field_count", field_count)?;
+            dict.set_item("type_histogram", type_histogram.clone())?;
+            dict.into_any()
+        }
+        Value::Map(entries) => {
+            let dict = PyDict::new(py);
+            for (key, value) in entries {
+                match key {
+                    MapKey::Integer(key) => dict.set_item(key, to_python(py, value)?)?,
+                    MapKey::String(key) => dict.set_item(key, to_python(py, value)?)?
+                }
+            }
+            dict.into_any()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProtobufBytes;
+
+    #[test]
+    fn decode_dict() {
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+        bytes.write_bytes(2, &[0xff]);
+        bytes.write_u32(3, 1);
+        bytes.write_u32(3, 2);
+
+        Python::initialize();
+        Python::attach(|py| {
+            let dict = decode(py, &bytes).unwrap();
+            assert_eq!(dict.get_item(1).unwrap().unwrap().extract::<i64>().unwrap(), 150);
+            assert_eq!(dict.get_item(2).unwrap().unwrap().extract::<Vec<u8>>().unwrap(), vec![0xff]);
+            assert_eq!(dict.get_item(3).unwrap().unwrap().extract::<Vec<i64>>().unwrap(), vec![1, 2]);
+        });
+    }
+}
@@ -0,0 +1,209 @@
+use std::ops::Range;
+use crate::incremental::field_len;
+use crate::{Decoder, Result, SerializedMessage, Value, VarInt};
+
+/// A field decoded alongside the exact bytes it was decoded from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedField {
+    pub field: u32,
+    pub wire_type: u8,
+    /// The decoded interpretation of the field, which may be lossy.
+    pub value: Value,
+    /// The bytes of the whole field, including its header.
+    raw: Vec<u8>,
+    /// The index of the payload of a length-delimited field in `raw`,
+    /// or of the value of any other field.
+    payload: usize
+}
+
+impl DecodedField {
+    /// Returns the bytes of the whole field, including its header.
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// Returns the bytes of the value, without the header,
+    /// or the length prefix of a length-delimited field.
+    ///
+    /// Decode this with `Decoder::decode_raw` to retain the bytes of nested fields.
+    pub fn payload(&self) -> &[u8] {
+        &self.raw[self.payload..]
+    }
+}
+
+/// A message whose fields retain the bytes they were decoded from,
+/// so it re-encodes to exactly the original bytes.
+///
+/// Unlike `SerializedMessage`, fields are kept in wire order with
+/// one entry per occurrence, so interleaved fields are preserved too.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RawMessage {
+    pub fields: Vec<DecodedField>
+}
+
+impl RawMessage {
+    /// Returns the first occurrence of a field.
+    pub fn get(&self, field: u32) -> Option<&DecodedField> {
+        self.fields.iter().find(|decoded| decoded.field == field)
+    }
+
+    /// Encodes the message from the retained bytes of its fields.
+    pub fn encode(&self) -> Vec<u8> {
+        self.fields.iter()
+            .flat_map(|decoded| decoded.raw.iter().copied())
+            .collect()
+    }
+
+    /// Converts the message into its decoded interpretation.
+    pub fn to_message(&self) -> SerializedMessage {
+        let mut message = SerializedMessage::new();
+        for decoded in &self.fields {
+            message.insert(decoded.field, decoded.value.clone());
+        }
+
+        message
+    }
+}
+
+/// A decoded field with the location of its bytes in the original buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Spanned<T> {
+    pub field: u32,
+    pub value: T,
+    /// The offsets of the field header.
+    pub header: Range<usize>,
+    /// The offsets of the value, without the length prefix of a length-delimited field.
+    pub payload: Range<usize>,
+    /// The nested fields, in wire order, if the value is a message.
+    pub children: Vec<Spanned<T>>
+}
+
+impl<T> Spanned<T> {
+    /// Returns the offsets of the whole field, including its header.
+    pub fn span(&self) -> Range<usize> {
+        self.header.start..self.payload.end
+    }
+}
+
+impl Decoder {
+    /// Decodes a protobuf-encoded message, retaining the bytes of every field.
+    ///
+    /// Each field is decoded on its own with this decoder. Only the standard
+    /// wire types are supported, since the length of other fields is unknown.
+    pub fn decode_raw(&self, bytes: &[u8]) -> Result<RawMessage> {
+        let mut fields = vec![];
+        let mut index = 0usize;
+
+        while index < bytes.len() {
+            let Some(len) = field_len(&bytes[index..], self.config().max_field_bytes)? else {
+                return Err("Invalid message; the last field is incomplete.".into());
+            };
+            let Some(raw) = bytes.get(index..index + len) else {
+                return Err("Invalid message; the last field is incomplete.".into());
+            };
+            index += len;
+
+            let (Some((field, value)), Some((wire_type, payload))) = (self.decode(raw)?.into_iter().next(), header(raw)) else {
+                return Err("Invalid message; malformed field.".into());
+            };
+
+            fields.push(DecodedField { field, wire_type, value, raw: raw.to_vec(), payload });
+        }
+
+        Ok(RawMessage { fields })
+    }
+
+    /// Decodes a protobuf-encoded message, locating every field in the buffer.
+    ///
+    /// Fields are returned in wire order with one entry per occurrence, and
+    /// the fields of nested messages are located recursively.
+    pub fn decode_with_spans(&self, bytes: &[u8]) -> Result<Vec<Spanned<Value>>> {
+        self.spans(bytes, 0)
+    }
+
+    /// Locates the fields of a message starting at `offset` in the original buffer.
+    fn spans(&self, bytes: &[u8], offset: usize) -> Result<Vec<Spanned<Value>>> {
+        let mut spans = vec![];
+        let mut start = offset;
+
+        for decoded in self.decode_raw(bytes)?.fields {
+            let header_len = VarInt::read_at(&decoded.raw, 0).map_or(0, |(_, len)| len);
+            let header = start..start + header_len;
+            let payload = start + decoded.payload..start + decoded.raw.len();
+            start = payload.end;
+
+            let children = if has_message(&decoded.value) {
+                self.spans(decoded.payload(), payload.start)?
+            } else {
+                vec![]
+            };
+            spans.push(Spanned { field: decoded.field, value: decoded.value, header, payload, children });
+        }
+
+        Ok(spans)
+    }
+}
+
+/// Returns whether a value was decoded as a message, possibly alongside a string.
+fn has_message(value: &Value) -> bool {
+    match value {
+        Value::Message(_) => true,
+        Value::Repeated(values) => values.iter().any(|value| matches!(value, Value::Message(_))),
+        _ => false
+    }
+}
+
+/// Returns the wire type and the index of the payload of a single encoded field.
+fn header(raw: &[u8]) -> Option<(u8, usize)> {
+    let (tag, header_len) = VarInt::read_at(raw, 0)?;
+    let wire_type = (tag & 0b111) as u8;
+    match wire_type {
+        2 => Some((wire_type, header_len + VarInt::read_at(raw, header_len)?.1)),
+        _ => Some((wire_type, header_len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProtobufBytes;
+
+    #[test]
+    fn byte_exact() {
+        let mut bytes = vec![];
+        // A minimal varint, which the encoder would pad.
+        bytes.extend([0x08, 0x96, 0x01]);
+        bytes.write_str(2, "Hello, World!");
+        // Interleaved with the repeated field 1.
+        bytes.extend([0x08, 0x80, 0x80, 0x00]);
+
+        let message = Decoder::default().decode_raw(&bytes).unwrap();
+        assert_eq!(message.encode(), bytes);
+        assert_ne!(message.to_message().encode(), bytes);
+
+        assert_eq!(message.fields.len(), 3);
+        assert_eq!(message.get(2).unwrap().payload(), b"Hello, World!");
+        assert_eq!(message.fields[2].value, Value::VarInt(0.into()));
+        assert_eq!(message.to_message(), crate::decode(&bytes).unwrap());
+    }
+
+    #[test]
+    fn spans() {
+        let mut inner = vec![];
+        inner.write_u32(1, 150);
+
+        let mut bytes = vec![0x08, 0x96, 0x01];
+        bytes.write_bytes(2, &inner);
+
+        let spans = crate::decode_with_spans(&bytes).unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!((spans[0].header.clone(), spans[0].payload.clone()), (0..1, 1..3));
+        assert_eq!(spans[1].span(), 3..bytes.len());
+        assert_eq!(&bytes[spans[1].payload.clone()], inner);
+
+        let child = &spans[1].children[0];
+        assert_eq!(child.value, Value::VarInt(150.into()));
+        assert_eq!(&bytes[child.span()], inner);
+        assert_eq!(child.payload.end, bytes.len());
+    }
+}
@@ -0,0 +1,216 @@
+use std::mem;
+use crate::grpc::{Frame, HEADER_LEN};
+use crate::{framing, Decoder, Result, SerializedMessage, VarInt};
+
+/// The most bytes of a varint length prefix.
+const MAX_PREFIX_LEN: usize = 10;
+
+/// How messages are delimited in a stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// The whole stream is a single message, as is each datagram.
+    Raw,
+    /// Each message is prefixed with its length as a varint,
+    /// as written by `writeDelimitedTo` in the official libraries.
+    LengthDelimited,
+    /// Each message is a gRPC frame; see `grpc::Frame`.
+    Grpc,
+    /// Each message is the body of a packet in the envelope.
+    Envelope(framing::Framing)
+}
+
+/// Splits a stream into messages as its bytes arrive.
+///
+/// No more than the decoder's `max_field_bytes` are buffered for a message,
+/// so a corrupt length cannot exhaust memory. A malformed or oversized message
+/// cannot be skipped, so the rest of the stream is ignored after one.
+/// Envelope framing cannot tell a malformed packet from an incomplete one,
+/// so its errors are only reported once the limit is exceeded, or by `finish`.
+#[derive(Clone, Debug)]
+pub struct Reassembler {
+    framing: Framing,
+    decoder: Decoder,
+    /// The bytes which do not yet form a message.
+    buffer: Vec<u8>,
+    /// The offset of the start of `buffer` in the stream.
+    pub(crate) offset: u64,
+    /// Whether the stream failed or ended, after which nothing more is returned.
+    done: bool
+}
+
+impl Reassembler {
+    /// Creates a reassembler using the default decoder.
+    pub fn new(framing: Framing) -> Self {
+        Self::with_decoder(framing, Decoder::default())
+    }
+
+    /// Creates a reassembler using the given decoder, whose `max_field_bytes`
+    /// also limits the length of messages.
+    pub fn with_decoder(framing: Framing, decoder: Decoder) -> Self {
+        Self { framing, decoder, buffer: vec![], offset: 0, done: false }
+    }
+
+    /// Returns whether the stream failed or ended, after which no more messages are returned.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Adds bytes of the stream without reading any messages from them.
+    pub fn push(&mut self, bytes: &[u8]) {
+        if !self.done {
+            self.buffer.extend_from_slice(bytes);
+        }
+    }
+
+    /// Adds bytes of the stream, returning the messages they complete,
+    /// followed by the error of the stream if it turns out to be malformed.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Result<SerializedMessage>> {
+        self.push(bytes);
+
+        let mut messages = vec![];
+        loop {
+            match self.next_message() {
+                Ok(Some((_, message))) => messages.push(message),
+                Ok(None) => break,
+                Err(error) => {
+                    messages.push(Err(error));
+                    break;
+                }
+            }
+        }
+
+        messages
+    }
+
+    /// Removes the first message from the buffer, if all of its bytes have arrived,
+    /// returning it with the offset of its framing in the stream.
+    ///
+    /// Messages which fail to decode are returned as errors without failing
+    /// the stream. A malformed stream fails once, returning its error.
+    pub fn next_message(&mut self) -> Result<Option<(u64, Result<SerializedMessage>)>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        match self.split() {
+            Ok(Some((message, len))) => {
+                let offset = self.offset;
+                self.buffer.drain(..len);
+                self.offset += len as u64;
+                Ok(Some((offset, message)))
+            }
+            Ok(None) => Ok(None),
+            Err(error) => {
+                self.done = true;
+                self.buffer = vec![];
+                Err(error)
+            }
+        }
+    }
+
+    /// Ends the stream, returning the last message of raw framing,
+    /// or an error if the stream ended within a message.
+    pub fn finish(&mut self) -> Option<Result<SerializedMessage>> {
+        if mem::replace(&mut self.done, true) {
+            return None;
+        }
+
+        let buffer = mem::take(&mut self.buffer);
+        match &self.framing {
+            Framing::Raw => Some(self.decoder.decode(&buffer)),
+            _ if buffer.is_empty() => None,
+            Framing::Envelope(framing) => framing.read(&buffer).err().map(Err),
+            _ => Some(Err("Invalid stream; the stream ended within a message.".into()))
+        }
+    }
+
+    /// Returns the first message of the buffer and its length, if all of its bytes have arrived.
+    fn split(&self) -> Result<Option<(Result<SerializedMessage>, usize)>> {
+        let buffer = self.buffer.as_slice();
+        let max = self.decoder.config().max_field_bytes;
+
+        match &self.framing {
+            Framing::Raw if buffer.len() > max => Err(too_long(max)),
+            Framing::Raw => Ok(None),
+            Framing::LengthDelimited => {
+                let Some((len, prefix)) = VarInt::read_at(buffer, 0) else {
+                    if buffer.len() >= MAX_PREFIX_LEN {
+                        return Err("Invalid stream; malformed message length.".into());
+                    }
+                    return Ok(None);
+                };
+
+                let Some(len) = usize::try_from(len).ok().filter(|len| *len <= max) else {
+                    return Err(too_long(max));
+                };
+                Ok(buffer.get(prefix..prefix + len).map(|message| (self.decoder.decode(message), prefix + len)))
+            }
+            Framing::Grpc => {
+                if buffer.len() < HEADER_LEN {
+                    return Ok(None);
+                }
+
+                let len = u32::from_be_bytes(buffer[1..HEADER_LEN].try_into()?) as usize;
+                if len > max {
+                    return Err(too_long(max));
+                }
+                if buffer.len() < HEADER_LEN + len {
+                    return Ok(None);
+                }
+
+                let (frame, _) = Frame::read(buffer)?;
+                Ok(Some((frame.payload().and_then(|payload| self.decoder.decode(&payload)), HEADER_LEN + len)))
+            }
+            Framing::Envelope(framing) => match framing.read(buffer) {
+                Ok((packet, len)) => Ok(Some((self.decoder.decode(packet.body), len))),
+                Err(_) if buffer.len() > max => Err(too_long(max)),
+                Err(_) => Ok(None)
+            }
+        }
+    }
+}
+
+/// Returns the error of a message longer than the limit.
+fn too_long(max: usize) -> crate::Error {
+    format!("Invalid stream; message exceeds the limit of {max} bytes.").into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DecodeConfig, ProtobufBytes};
+
+    #[test]
+    fn reassemble() {
+        let mut message = vec![];
+        message.write_str(1, "hey");
+        let mut delimited = vec![message.len() as u8];
+        delimited.extend(&message);
+
+        // Messages split across reads are reassembled, and keep their offsets.
+        let mut reassembler = Reassembler::new(Framing::LengthDelimited);
+        assert!(reassembler.feed(&delimited[..2]).is_empty());
+        reassembler.push(&[&delimited[2..], &delimited[..]].concat());
+        assert_eq!(reassembler.next_message().unwrap().unwrap().0, 0);
+        assert_eq!(reassembler.next_message().unwrap().unwrap().0, delimited.len() as u64);
+        assert!(reassembler.next_message().unwrap().is_none());
+        assert!(reassembler.finish().is_none());
+
+        // Every framing stops buffering at the limit.
+        let decoder = Decoder::new(DecodeConfig { max_field_bytes: 16, ..Default::default() });
+        let framings = [Framing::Raw, Framing::LengthDelimited, Framing::Grpc, Framing::Envelope(framing::Framing::cmd_length())];
+        for framing in framings {
+            let mut reassembler = Reassembler::with_decoder(framing, decoder.clone());
+            let messages = reassembler.feed(&[0x7f; 32]);
+            assert!(matches!(messages.as_slice(), [Err(_)]));
+            assert!(reassembler.is_done());
+            assert!(reassembler.feed(&delimited).is_empty());
+            assert!(reassembler.finish().is_none());
+        }
+
+        let mut raw = Reassembler::new(Framing::Raw);
+        assert!(raw.feed(&message).is_empty());
+        assert_eq!(raw.finish().unwrap().unwrap().get(1).unwrap().as_string().unwrap(), "hey");
+        assert!(raw.finish().is_none());
+    }
+}
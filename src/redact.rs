@@ -0,0 +1,137 @@
+use crate::{FieldPath, SerializedMessage, Value};
+
+/// The byte which redacted strings and bytes are filled with.
+pub const PLACEHOLDER: u8 = b'*';
+
+/// A predicate over the path and value of a field.
+type Predicate = Box<dyn Fn(&[u32], &Value) -> bool>;
+
+/// A string or bytes value which was redacted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redaction {
+    /// The path of the redacted field.
+    pub path: FieldPath,
+    /// The length of the redacted value, in bytes.
+    pub len: usize
+}
+
+/// Scrubs secrets from messages before they are shared.
+///
+/// Strings and bytes are replaced with placeholders of the same length,
+/// so the sanitized message re-encodes to the same size as the original.
+/// A payload decoded both as a string and as a message is replaced entirely.
+#[derive(Default)]
+pub struct Redactor {
+    paths: Vec<FieldPath>,
+    predicates: Vec<Predicate>
+}
+
+impl Redactor {
+    /// Creates a new redactor, which redacts nothing yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Redacts every string and bytes value at the path, or nested within it.
+    pub fn with_path(mut self, path: &[u32]) -> Self {
+        self.paths.push(path.to_vec());
+        self
+    }
+
+    /// Redacts every string and bytes value for which the predicate,
+    /// given the path and the value, returns true.
+    pub fn with_predicate<F: Fn(&[u32], &Value) -> bool + 'static>(mut self, predicate: F) -> Self {
+        self.predicates.push(Box::new(predicate));
+        self
+    }
+
+    /// Returns the sanitized message, and the values which were redacted.
+    pub fn redact(&self, message: &SerializedMessage) -> (SerializedMessage, Vec<Redaction>) {
+        let mut message = message.clone();
+        let mut redactions = vec![];
+        self.redact_message(&mut message, &mut vec![], &mut redactions);
+
+        (message, redactions)
+    }
+
+    /// Redacts the fields of a message in place.
+    fn redact_message(&self, message: &mut SerializedMessage, path: &mut FieldPath, redactions: &mut Vec<Redaction>) {
+        for (field, value) in message.iter_mut() {
+            path.push(*field);
+            self.redact_value(value, path, redactions);
+            path.pop();
+        }
+    }
+
+    /// Redacts a value in place, and any messages nested in it.
+    fn redact_value(&self, value: &mut Value, path: &mut FieldPath, redactions: &mut Vec<Redaction>) {
+        let matched = self.paths.iter().any(|prefix| path.starts_with(prefix))
+            || self.predicates.iter().any(|predicate| predicate(path, value));
+
+        match value {
+            // A single payload which is both a string and a message.
+            Value::Repeated(values) if matches!(values.as_slice(), [Value::String(_), Value::Message(_)]) => {
+                let mut string = values[0].clone();
+                self.redact_value(&mut string, path, redactions);
+                if string != values[0] {
+                    *value = string;
+                } else if let Value::Message(message) = &mut values[1] {
+                    self.redact_message(message, path, redactions);
+                }
+            }
+            Value::Repeated(values) => {
+                for value in values {
+                    self.redact_value(value, path, redactions);
+                }
+            }
+            Value::Map(entries) => {
+                for value in entries.values_mut() {
+                    self.redact_value(value, path, redactions);
+                }
+            }
+            Value::Message(message) => self.redact_message(message, path, redactions),
+            Value::String(string) if matched => {
+                redactions.push(Redaction { path: path.clone(), len: string.len() });
+                *string = char::from(PLACEHOLDER).to_string().repeat(string.len());
+            }
+            Value::Bytes(bytes) if matched => {
+                redactions.push(Redaction { path: path.clone(), len: bytes.len() });
+                bytes.fill(PLACEHOLDER);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn redact() {
+        let mut credentials = vec![];
+        credentials.write_str(1, "alice");
+        credentials.write_bytes(2, &[0xff, 0x01, 0x02]);
+
+        let mut bytes = vec![];
+        bytes.write_bytes(1, &credentials);
+        bytes.write_str(2, "session=secret");
+        bytes.write_str(3, "hello");
+        let message = decode(&bytes).unwrap();
+
+        let redactor = Redactor::new()
+            .with_path(&[1])
+            .with_predicate(|_, value| matches!(value, Value::String(string) if string.contains("secret")));
+        let (sanitized, redactions) = redactor.redact(&message);
+
+        assert_eq!(redactions, [
+            Redaction { path: vec![1, 1], len: 5 },
+            Redaction { path: vec![1, 2], len: 3 },
+            Redaction { path: vec![2], len: 14 }
+        ]);
+        assert_eq!(sanitized.get_path(&[1, 1]), Some(Value::String("*****".to_string())));
+        assert_eq!(sanitized.get(3), Some(Value::String("hello".to_string())));
+        assert_eq!(sanitized.encode().len(), message.encode().len());
+    }
+}
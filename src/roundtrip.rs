@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use crate::names::format_path;
+use crate::{Decoder, FieldPath, Result, VarInt};
+
+/// How a re-encoded message differs from the bytes it was decoded from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Difference {
+    /// The fields of the message were written in a different order.
+    Reordered { path: FieldPath },
+    /// Occurrences of a field separated by other fields were written together.
+    Merged { path: FieldPath },
+    /// The field was written a different number of times,
+    /// such as a payload decoded both as a string and as a message.
+    Count { path: FieldPath, original: usize, encoded: usize },
+    /// The header of the field was written with a different number of bytes.
+    Header { path: FieldPath, original: usize, encoded: usize },
+    /// A varint was written with a different number of bytes.
+    VarInt { path: FieldPath, original: usize, encoded: usize },
+    /// The length prefix of the field was written with a different number of bytes.
+    LengthPrefix { path: FieldPath, original: usize, encoded: usize },
+    /// The value of the field changed.
+    Value { path: FieldPath }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Reordered { path } if path.is_empty() => {
+                f.write_str("Fields were reordered; the encoder writes them in ascending order.")
+            }
+            Difference::Reordered { path } => {
+                write!(f, "Fields of {} were reordered; the encoder writes them in ascending order.", format_path(path))
+            }
+            Difference::Merged { path } => {
+                write!(f, "Field {} was interleaved with other fields; its occurrences were merged.", format_path(path))
+            }
+            Difference::Count { path, original, encoded } => {
+                write!(f, "Field {} occurred {original} times, but was written {encoded} times.", format_path(path))
+            }
+            Difference::Header { path, original, encoded } => {
+                write!(f, "The header of field {} took {original} bytes, but was written with {encoded}.", format_path(path))
+            }
+            Difference::VarInt { path, original, encoded } => {
+                write!(f, "The varint of field {} took {original} bytes, but was written with {encoded}.", format_path(path))
+            }
+            Difference::LengthPrefix { path, original, encoded } => {
+                write!(f, "The length of field {} took {original} bytes, but was written with {encoded}.", format_path(path))
+            }
+            Difference::Value { path } => write!(f, "The value of field {} changed.", format_path(path))
+        }
+    }
+}
+
+/// The result of decoding a message and encoding it again.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoundTripReport {
+    /// The re-encoded bytes.
+    pub encoded: Vec<u8>,
+    /// The offset of the first byte which differs, if any.
+    pub first_difference: Option<usize>,
+    /// Explanations of how the bytes differ, outermost fields first.
+    pub differences: Vec<Difference>
+}
+
+impl RoundTripReport {
+    /// Returns true if the message re-encodes to exactly the original bytes.
+    pub fn is_exact(&self) -> bool {
+        self.first_difference.is_none()
+    }
+
+    /// Returns true if the message re-encodes to the same fields and values,
+    /// ignoring differences in field order and varint padding.
+    ///
+    /// Receivers decoding with a schema see the same message, since merged
+    /// occurrences of a field keep their order.
+    pub fn is_equivalent(&self) -> bool {
+        self.differences.iter().all(|difference| matches!(difference,
+            Difference::Reordered { .. } | Difference::Merged { .. } | Difference::Header { .. }
+                | Difference::VarInt { .. } | Difference::LengthPrefix { .. }
+        ))
+    }
+}
+
+impl Decoder {
+    /// Decodes a message, encodes it again, and explains how the bytes differ.
+    ///
+    /// Use this before replaying re-encoded messages, to check the
+    /// receiver will see the same fields. Nested messages are compared
+    /// field by field; other differing payloads are reported as changed values.
+    pub fn verify_roundtrip(&self, original: &[u8]) -> Result<RoundTripReport> {
+        let encoded = self.decode(original)?.encode();
+
+        let first_difference = original.iter().zip(&encoded)
+            .position(|(a, b)| a != b)
+            .or_else(|| (original.len() != encoded.len()).then(|| original.len().min(encoded.len())));
+
+        let mut differences = vec![];
+        if first_difference.is_some() {
+            compare(original, &encoded, &mut vec![], &mut differences);
+        }
+
+        Ok(RoundTripReport { encoded, first_difference, differences })
+    }
+}
+
+/// A field as laid out on the wire.
+struct WireField<'a> {
+    field: u32,
+    wire_type: u64,
+    /// The number of bytes of the header.
+    header: usize,
+    /// The number of bytes of the length prefix, if length-delimited.
+    prefix: usize,
+    /// The bytes of the value, without the length prefix.
+    value: &'a [u8]
+}
+
+/// Splits a message into its fields, if it is well-formed.
+fn split(bytes: &[u8]) -> Option<Vec<WireField<'_>>> {
+    let mut fields = vec![];
+    let mut index = 0usize;
+
+    while index < bytes.len() {
+        let (tag, header) = VarInt::read_at(bytes, index)?;
+        index += header;
+
+        let (prefix, len) = match tag & 0b111 {
+            0 => (0, VarInt::read_at(bytes, index)?.1),
+            1 => (0, 8),
+            2 => {
+                let (len, prefix) = VarInt::read_at(bytes, index)?;
+                (prefix, usize::try_from(len).ok()?)
+            }
+            5 => (0, 4),
+            _ => return None
+        };
+        let start = index + prefix;
+        let value = bytes.get(start..start.checked_add(len)?)?;
+        index = start + len;
+
+        fields.push(WireField { field: u32::try_from(tag >> 3).ok()?, wire_type: tag & 0b111, header, prefix, value });
+    }
+
+    Some(fields)
+}
+
+/// Compares the fields of an original and a re-encoded message.
+fn compare(original: &[u8], encoded: &[u8], path: &mut FieldPath, differences: &mut Vec<Difference>) {
+    let (Some(original), Some(encoded)) = (split(original), split(encoded)) else {
+        push(differences, Difference::Value { path: path.clone() });
+        return;
+    };
+
+    // Fields in order of first occurrence, noting any seen again after other fields.
+    let order = |fields: &[WireField]| {
+        let mut order: Vec<u32> = vec![];
+        let mut interleaved = vec![];
+        for (index, field) in fields.iter().enumerate() {
+            if !order.contains(&field.field) {
+                order.push(field.field);
+            } else if fields[index - 1].field != field.field && !interleaved.contains(&field.field) {
+                interleaved.push(field.field);
+            }
+        }
+        (order, interleaved)
+    };
+    let (original_order, interleaved) = order(&original);
+    let (encoded_order, _) = order(&encoded);
+
+    if original_order != encoded_order {
+        push(differences, Difference::Reordered { path: path.clone() });
+    }
+    for field in interleaved {
+        path.push(field);
+        push(differences, Difference::Merged { path: path.clone() });
+        path.pop();
+    }
+
+    let mut original = group(original);
+    let mut encoded = group(encoded);
+
+    let fields: BTreeSet<u32> = original.keys().chain(encoded.keys()).copied().collect();
+    for field in fields {
+        let a = original.remove(&field).unwrap_or_default();
+        let b = encoded.remove(&field).unwrap_or_default();
+        path.push(field);
+
+        if a.len() != b.len() {
+            push(differences, Difference::Count { path: path.clone(), original: a.len(), encoded: b.len() });
+        }
+
+        for (a, b) in a.iter().zip(&b) {
+            if a.header != b.header {
+                push(differences, Difference::Header { path: path.clone(), original: a.header, encoded: b.header });
+            }
+
+            match (a.wire_type, b.wire_type) {
+                (0, 0) if a.value.len() != b.value.len() => {
+                    push(differences, Difference::VarInt { path: path.clone(), original: a.value.len(), encoded: b.value.len() });
+                    if VarInt::read_at(a.value, 0).map(|(value, _)| value) != VarInt::read_at(b.value, 0).map(|(value, _)| value) {
+                        push(differences, Difference::Value { path: path.clone() });
+                    }
+                }
+                (2, 2) => {
+                    if a.prefix != b.prefix {
+                        push(differences, Difference::LengthPrefix { path: path.clone(), original: a.prefix, encoded: b.prefix });
+                    }
+                    if a.value != b.value {
+                        compare(a.value, b.value, path, differences);
+                    }
+                }
+                _ if a.wire_type != b.wire_type || a.value != b.value => push(differences, Difference::Value { path: path.clone() }),
+                _ => {}
+            }
+        }
+
+        path.pop();
+    }
+}
+
+/// Groups fields by field number, keeping the order of their occurrences.
+fn group(fields: Vec<WireField<'_>>) -> BTreeMap<u32, Vec<WireField<'_>>> {
+    let mut groups: BTreeMap<u32, Vec<WireField>> = BTreeMap::new();
+    for field in fields {
+        groups.entry(field.field).or_default().push(field);
+    }
+
+    groups
+}
+
+/// Records a difference, unless it was already recorded for another occurrence.
+fn push(differences: &mut Vec<Difference>, difference: Difference) {
+    if !differences.contains(&difference) {
+        differences.push(difference);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, verify_roundtrip};
+
+    #[test]
+    fn roundtrip() {
+        let encoded = decode(&[0x08, 0x96, 0x01]).unwrap().encode();
+        let report = verify_roundtrip(&encoded).unwrap();
+        assert!(report.is_exact());
+        assert!(report.differences.is_empty());
+
+        // Minimal headers, with field 2 written on both sides of field 1.
+        let original = [0x10, 0x01, 0x08, 0x02, 0x10, 0x03, 0x1a, 0x03, 0x08, 0x96, 0x01];
+        let report = verify_roundtrip(&original).unwrap();
+        assert!(!report.is_exact());
+        assert!(report.is_equivalent());
+        assert_eq!(report.first_difference, Some(0));
+        assert_eq!(report.differences, [
+            Difference::Reordered { path: vec![] },
+            Difference::Merged { path: vec![2] },
+            Difference::Header { path: vec![1], original: 1, encoded: 5 },
+            Difference::Header { path: vec![2], original: 1, encoded: 5 },
+            Difference::Header { path: vec![3], original: 1, encoded: 5 },
+            Difference::LengthPrefix { path: vec![3], original: 1, encoded: 5 },
+            Difference::Header { path: vec![3, 1], original: 1, encoded: 5 }
+        ]);
+        assert_eq!(report.differences[1].to_string(), "Field 2 was interleaved with other fields; its occurrences were merged.");
+
+        // A payload which is both a string and a message is written as both.
+        let report = verify_roundtrip(&[0x0a, 0x02, 0x08, 0x04]).unwrap();
+        assert!(!report.is_equivalent());
+        assert!(report.differences.contains(&Difference::Count { path: vec![1], original: 1, encoded: 2 }));
+    }
+}
@@ -0,0 +1,520 @@
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use serde::{Deserialize, Serialize};
+use crate::names::FieldNameMap;
+use crate::{FieldPath, SerializedMessage, Value};
+
+/// The type of a field, as observed on the wire.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FieldType {
+    VarInt,
+    Fixed32,
+    Fixed64,
+    String,
+    Bytes,
+    Message(Schema)
+}
+
+impl FieldType {
+    /// Determines the field type of a value.
+    ///
+    /// Repeated values take the combined type of their elements.
+    pub fn of(value: &Value) -> Self {
+        match value {
+            Value::VarInt(_) => FieldType::VarInt,
+            Value::Float(_) => FieldType::Fixed32,
+            Value::Double(_) => FieldType::Fixed64,
+            Value::String(_) => FieldType::String,
+            // An empty payload is only known to be length-delimited.
+            Value::Bytes(_) | Value::Empty => FieldType::Bytes,
+            Value::Message(message) => FieldType::Message(Schema::infer(message)),
+            Value::Summary { .. } => FieldType::Message(Schema::new()),
+            Value::Map(entries) => entries.iter()
+                .map(|(key, value)| FieldType::Message(Schema::infer(&key.entry(value.clone()))))
+                .reduce(FieldType::merge)
+                .unwrap_or(FieldType::Bytes),
+            Value::Repeated(values) => values.iter()
+                .map(FieldType::of)
+                .reduce(FieldType::merge)
+                .unwrap_or(FieldType::Bytes)
+        }
+    }
+
+    /// Combines two observed types of the same field.
+    ///
+    /// Conflicting length-delimited interpretations widen to `Bytes`.
+    /// Conflicting wire types keep the existing type.
+    pub fn merge(self, other: FieldType) -> Self {
+        match (self, other) {
+            (FieldType::Message(mut a), FieldType::Message(b)) => {
+                a.merge_from(b);
+                FieldType::Message(a)
+            }
+            (a, b) if a == b => a,
+            (a, b) if a.is_length_delimited() && b.is_length_delimited() => FieldType::Bytes,
+            (a, _) => a
+        }
+    }
+
+    /// Returns true if the type is encoded with the length-delimited wire type.
+    pub fn is_length_delimited(&self) -> bool {
+        matches!(self, FieldType::String | FieldType::Bytes | FieldType::Message(_))
+    }
+}
+
+/// The schema of a single field.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FieldSchema {
+    pub field_type: FieldType,
+    pub repeated: bool,
+    /// The name given to the field, if it has been identified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// How the integers of a varint field are encoded, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integer: Option<IntegerEncoding>
+}
+
+impl FieldSchema {
+    /// Creates a new, unnamed field schema.
+    pub fn new(field_type: FieldType, repeated: bool) -> Self {
+        Self { field_type, repeated, name: None, integer: None }
+    }
+
+    /// Infers the schema of a field from its value.
+    fn observed(value: &Value) -> Self {
+        let repeated = matches!(value, Value::Repeated(_) | Value::Map(_));
+        let integer = varints(value).map(IntegerEncoding::of).reduce(IntegerEncoding::merge);
+        Self { integer, ..Self::new(FieldType::of(value), repeated) }
+    }
+}
+
+/// How the integers of a varint field are encoded.
+///
+/// Inferred fields are assumed to be two's complement, and are `Int32` until a
+/// sample does not fit. ZigZag encodings are only known when set explicitly.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegerEncoding {
+    Int32,
+    Int64,
+    Sint32,
+    Sint64
+}
+
+impl IntegerEncoding {
+    /// Returns the narrowest two's complement encoding which holds the varint.
+    pub fn of(varint: u64) -> Self {
+        IntegerEncoding::Int32.widen(varint)
+    }
+
+    /// Widens a 32-bit encoding to 64 bits if the varint does not fit in it.
+    pub fn widen(self, varint: u64) -> Self {
+        match self {
+            // Negative int32 values may be sign extended to 64 bits, or not.
+            IntegerEncoding::Int32 if varint > u32::MAX as u64 && i32::try_from(varint as i64).is_err() => IntegerEncoding::Int64,
+            IntegerEncoding::Sint32 if varint > u32::MAX as u64 => IntegerEncoding::Sint64,
+            encoding => encoding
+        }
+    }
+
+    /// Combines two observed encodings of the same field,
+    /// widening the first to 64 bits if either is.
+    pub fn merge(self, other: IntegerEncoding) -> Self {
+        match (self, other) {
+            (IntegerEncoding::Int32, IntegerEncoding::Int64 | IntegerEncoding::Sint64) => IntegerEncoding::Int64,
+            (IntegerEncoding::Sint32, IntegerEncoding::Int64 | IntegerEncoding::Sint64) => IntegerEncoding::Sint64,
+            (encoding, _) => encoding
+        }
+    }
+}
+
+/// How conflicting field definitions are resolved when merging schemas.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// Keep the definition from the first schema.
+    PreferLeft,
+    /// Keep the definition from the second schema.
+    PreferRight,
+    /// Combine the types as in `FieldType::merge`, keeping the first name.
+    Widen
+}
+
+/// A field defined differently by two merged schemas.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Conflict {
+    /// The field has different types.
+    Type { path: FieldPath, left: FieldType, right: FieldType },
+    /// The field has different names.
+    Name { path: FieldPath, left: String, right: String }
+}
+
+/// A change made to a schema while refining it with a new sample.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SchemaChange {
+    /// A field was seen for the first time.
+    Added { path: FieldPath, field_type: FieldType },
+    /// A field previously seen once was seen repeated.
+    Repeated { path: FieldPath },
+    /// A field was seen with a type conflicting with its known type.
+    Widened { path: FieldPath, from: FieldType, to: FieldType },
+    /// A varint field was seen with an integer too large for its known encoding.
+    WidenedInteger { path: FieldPath, from: IntegerEncoding, to: IntegerEncoding }
+}
+
+/// The shape of a message: the fields it contains and their types.
+///
+/// Schemas can be inferred from decoded messages or loaded with serde.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Schema {
+    fields: BTreeMap<u32, FieldSchema>
+}
+
+impl Schema {
+    /// Creates a new, empty schema.
+    pub fn new() -> Self {
+        Self { fields: BTreeMap::new() }
+    }
+
+    /// Infers a schema from a decoded message.
+    pub fn infer(message: &SerializedMessage) -> Self {
+        let mut schema = Schema::new();
+
+        for (field, value) in message {
+            schema.observe(*field, value);
+        }
+
+        schema
+    }
+
+    /// Infers a schema from several decoded samples of the same message.
+    pub fn infer_all<'a, I: IntoIterator<Item = &'a SerializedMessage>>(messages: I) -> Self {
+        let mut schema = Schema::new();
+        for message in messages {
+            schema.merge_from(Schema::infer(message));
+        }
+
+        schema
+    }
+
+    /// Records a value seen for the given field.
+    fn observe(&mut self, field: u32, value: &Value) {
+        self.merge_field(field, FieldSchema::observed(value));
+    }
+
+    /// Merges a field schema into this schema.
+    fn merge_field(&mut self, field: u32, schema: FieldSchema) {
+        match self.fields.remove(&field) {
+            Some(existing) => {
+                self.fields.insert(field, FieldSchema {
+                    field_type: existing.field_type.merge(schema.field_type),
+                    repeated: existing.repeated || schema.repeated,
+                    name: existing.name.or(schema.name),
+                    integer: merge_integers(existing.integer, schema.integer)
+                });
+            }
+            None => {
+                self.fields.insert(field, schema);
+            }
+        }
+    }
+
+    /// Merges all fields of another schema into this schema.
+    pub fn merge_from(&mut self, other: Schema) {
+        for (field, schema) in other.fields {
+            self.merge_field(field, schema);
+        }
+    }
+
+    /// Merges two schemas, such as ones inferred from different captures.
+    ///
+    /// Fields defined by only one schema are kept, and names fill in missing names.
+    /// Fields with different types or names are reported as conflicts,
+    /// and resolved using the given strategy.
+    pub fn merge(a: &Schema, b: &Schema, resolution: Resolution) -> (Schema, Vec<Conflict>) {
+        let mut merged = a.clone();
+        let mut conflicts = vec![];
+        merged.merge_resolving(b, resolution, &mut vec![], &mut conflicts);
+
+        (merged, conflicts)
+    }
+
+    /// Merges another schema into this one, resolving and recording conflicts.
+    fn merge_resolving(&mut self, other: &Schema, resolution: Resolution, path: &mut FieldPath, conflicts: &mut Vec<Conflict>) {
+        for (field, right) in &other.fields {
+            let Some(left) = self.fields.get_mut(field) else {
+                self.fields.insert(*field, right.clone());
+                continue;
+            };
+
+            path.push(*field);
+            left.repeated |= right.repeated;
+            left.integer = merge_integers(left.integer, right.integer);
+
+            match (&left.name, &right.name) {
+                (Some(a), Some(b)) if a != b => {
+                    conflicts.push(Conflict::Name { path: path.clone(), left: a.clone(), right: b.clone() });
+                    if resolution == Resolution::PreferRight {
+                        left.name = Some(b.clone());
+                    }
+                }
+                (None, Some(b)) => left.name = Some(b.clone()),
+                _ => {}
+            }
+
+            match (&mut left.field_type, &right.field_type) {
+                (FieldType::Message(a), FieldType::Message(b)) => a.merge_resolving(b, resolution, path, conflicts),
+                (a, b) if a != b => {
+                    conflicts.push(Conflict::Type { path: path.clone(), left: a.clone(), right: b.clone() });
+                    *a = match resolution {
+                        Resolution::PreferLeft => a.clone(),
+                        Resolution::PreferRight => b.clone(),
+                        Resolution::Widen => a.clone().merge(b.clone())
+                    };
+                }
+                _ => {}
+            }
+
+            path.pop();
+        }
+    }
+
+    /// Updates the schema with evidence from a new sample, returning what changed.
+    ///
+    /// New fields are added, fields seen repeated become repeated,
+    /// and conflicting types are widened as in `FieldType::merge`.
+    /// Varint fields whose integers no longer fit in 32 bits are widened
+    /// to 64 bits, keeping their ZigZag encoding if they have one.
+    /// Nested message schemas are refined recursively.
+    pub fn refine_with(&mut self, message: &SerializedMessage) -> Vec<SchemaChange> {
+        let mut changes = vec![];
+        self.refine_from(message, &mut vec![], &mut changes);
+
+        changes
+    }
+
+    /// Refines the schema with the fields of a sample, recording changes.
+    fn refine_from(&mut self, message: &SerializedMessage, path: &mut FieldPath, changes: &mut Vec<SchemaChange>) {
+        for (field, value) in message {
+            path.push(*field);
+            let schema = FieldSchema::observed(value);
+
+            match self.fields.get_mut(field) {
+                None => {
+                    changes.push(SchemaChange::Added { path: path.clone(), field_type: schema.field_type.clone() });
+                    self.fields.insert(*field, schema);
+                }
+                Some(existing) => {
+                    if schema.repeated && !existing.repeated {
+                        existing.repeated = true;
+                        changes.push(SchemaChange::Repeated { path: path.clone() });
+                    }
+
+                    match (&mut existing.field_type, schema.field_type) {
+                        (FieldType::Message(nested), FieldType::Message(_)) => {
+                            nested.refine_from_value(value, path, changes);
+                        }
+                        (field_type, other) => {
+                            let merged = field_type.clone().merge(other);
+                            if merged != *field_type {
+                                let from = std::mem::replace(field_type, merged.clone());
+                                changes.push(SchemaChange::Widened { path: path.clone(), from, to: merged });
+                            }
+                        }
+                    }
+
+                    // The raw varints are needed to tell whether a ZigZag encoding still fits.
+                    match existing.integer {
+                        Some(from) => {
+                            let to = varints(value).fold(from, IntegerEncoding::widen);
+                            if to != from {
+                                existing.integer = Some(to);
+                                changes.push(SchemaChange::WidenedInteger { path: path.clone(), from, to });
+                            }
+                        }
+                        None if existing.field_type == FieldType::VarInt => existing.integer = schema.integer,
+                        None => {}
+                    }
+                }
+            }
+
+            path.pop();
+        }
+    }
+
+    /// Refines the schema with every message in a value.
+    fn refine_from_value(&mut self, value: &Value, path: &mut FieldPath, changes: &mut Vec<SchemaChange>) {
+        match value {
+            Value::Message(message) => self.refine_from(message, path, changes),
+            Value::Repeated(values) => {
+                for value in values {
+                    self.refine_from_value(value, path, changes);
+                }
+            }
+            Value::Map(entries) => {
+                for (key, value) in entries {
+                    self.refine_from(&key.entry(value.clone()), path, changes);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Names the fields of the schema, and of its nested messages, from a name map.
+    ///
+    /// Fields missing from the map keep their existing names.
+    pub fn with_names(mut self, names: &FieldNameMap) -> Self {
+        self.apply_names(names, &mut vec![]);
+        self
+    }
+
+    /// Names the fields below the given path.
+    fn apply_names(&mut self, names: &FieldNameMap, path: &mut FieldPath) {
+        for (field, schema) in &mut self.fields {
+            path.push(*field);
+
+            if let Some(name) = names.get(path) {
+                schema.name = Some(name.to_string());
+            }
+            if let FieldType::Message(nested) = &mut schema.field_type {
+                nested.apply_names(names, path);
+            }
+
+            path.pop();
+        }
+    }
+
+    /// Inserts a field into the schema, replacing any existing definition.
+    pub fn insert(&mut self, field: u32, schema: FieldSchema) {
+        self.fields.insert(field, schema);
+    }
+
+    /// Gets the schema of the given field.
+    pub fn get(&self, field: u32) -> Option<&FieldSchema> {
+        self.fields.get(&field)
+    }
+
+    /// Returns the number of fields in the schema.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns true if the schema has no fields.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Returns an iterator over the fields of the schema.
+    pub fn iter(&self) -> btree_map::Iter<'_, u32, FieldSchema> {
+        self.fields.iter()
+    }
+}
+
+/// Combines the integer encodings of two definitions of a field.
+fn merge_integers(a: Option<IntegerEncoding>, b: Option<IntegerEncoding>) -> Option<IntegerEncoding> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.merge(b)),
+        (a, b) => a.or(b)
+    }
+}
+
+/// Returns the varints of a value, or of each element of a repeated value.
+fn varints(value: &Value) -> Box<dyn Iterator<Item = u64> + '_> {
+    match value {
+        Value::VarInt(varint) => Box::new(std::iter::once(varint.as_i64() as u64)),
+        Value::Repeated(values) => Box::new(values.iter().flat_map(varints)),
+        _ => Box::new(std::iter::empty())
+    }
+}
+
+impl<'a> IntoIterator for &'a Schema {
+    type Item = (&'a u32, &'a FieldSchema);
+    type IntoIter = btree_map::Iter<'a, u32, FieldSchema>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.fields.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refine() {
+        let mut sample = SerializedMessage::new();
+        sample.insert(1, Value::VarInt(150.into()));
+        sample.insert(2, Value::Bytes(vec![0xff]));
+
+        let mut schema = Schema::infer(&sample);
+        assert!(schema.refine_with(&sample).is_empty());
+
+        let mut inner = SerializedMessage::new();
+        inner.insert(1, Value::VarInt(1.into()));
+
+        let mut sample = SerializedMessage::new();
+        sample.insert(1, Value::VarInt(1.into()));
+        sample.insert(1, Value::VarInt(2.into()));
+        sample.insert(2, Value::String("Hello".to_string()));
+        sample.insert(3, Value::Message(inner));
+
+        let changes = schema.refine_with(&sample);
+        assert_eq!(changes, vec![
+            SchemaChange::Repeated { path: vec![1] },
+            SchemaChange::Added { path: vec![3], field_type: FieldType::Message(Schema::infer(&sample.get(3).unwrap().as_message().unwrap())) }
+        ]);
+        assert!(schema.get(1).unwrap().repeated);
+        assert_eq!(schema.get(2).unwrap().field_type, FieldType::Bytes);
+    }
+
+    #[test]
+    fn refine_integers() {
+        let mut sample = SerializedMessage::new();
+        sample.insert(1, Value::VarInt((-5i64).into()));
+        sample.insert(2, Value::VarInt(1.into()));
+
+        let mut schema = Schema::infer(&sample);
+        assert_eq!(schema.get(1).unwrap().integer, Some(IntegerEncoding::Int32));
+        schema.insert(2, FieldSchema { integer: Some(IntegerEncoding::Sint32), ..FieldSchema::new(FieldType::VarInt, false) });
+
+        // A ZigZag encoded sint32 uses every bit of a u32.
+        let mut sample = SerializedMessage::new();
+        sample.insert(1, Value::VarInt((i32::MIN as i64).into()));
+        sample.insert(2, Value::VarInt((u32::MAX as i64).into()));
+        assert!(schema.refine_with(&sample).is_empty());
+
+        let mut sample = SerializedMessage::new();
+        sample.insert(1, Value::VarInt((1i64 << 40).into()));
+        sample.insert(2, Value::VarInt((1i64 << 40).into()));
+        assert_eq!(schema.refine_with(&sample), vec![
+            SchemaChange::WidenedInteger { path: vec![1], from: IntegerEncoding::Int32, to: IntegerEncoding::Int64 },
+            SchemaChange::WidenedInteger { path: vec![2], from: IntegerEncoding::Sint32, to: IntegerEncoding::Sint64 }
+        ]);
+    }
+
+    #[test]
+    fn merge_conflicts() {
+        let mut a = Schema::new();
+        a.insert(1, FieldSchema { name: Some("id".to_string()), ..FieldSchema::new(FieldType::VarInt, false) });
+        a.insert(2, FieldSchema::new(FieldType::String, false));
+
+        let mut b = Schema::new();
+        b.insert(1, FieldSchema { name: Some("user_id".to_string()), ..FieldSchema::new(FieldType::VarInt, true) });
+        b.insert(2, FieldSchema::new(FieldType::Bytes, false));
+        b.insert(3, FieldSchema::new(FieldType::Fixed32, false));
+
+        let (merged, conflicts) = Schema::merge(&a, &b, Resolution::Widen);
+        assert_eq!(conflicts, vec![
+            Conflict::Name { path: vec![1], left: "id".to_string(), right: "user_id".to_string() },
+            Conflict::Type { path: vec![2], left: FieldType::String, right: FieldType::Bytes }
+        ]);
+        assert_eq!(merged.get(1).unwrap().name.as_deref(), Some("id"));
+        assert!(merged.get(1).unwrap().repeated);
+        assert_eq!(merged.get(2).unwrap().field_type, FieldType::Bytes);
+        assert_eq!(merged.len(), 3);
+
+        let (merged, _) = Schema::merge(&a, &b, Resolution::PreferRight);
+        assert_eq!(merged.get(1).unwrap().name.as_deref(), Some("user_id"));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{FieldPath, SerializedMessage, Value};
+
+/// Finds every value matching the predicate, at any nesting level.
+///
+/// The predicate sees each value of a repeated field separately, and
+/// nested messages both as a whole and field by field. Returns the path
+/// of each match with the value, outermost fields first.
+pub fn find<P: Fn(&Value) -> bool>(message: &SerializedMessage, predicate: P) -> Vec<(FieldPath, &Value)> {
+    let mut found = vec![];
+    find_in(message, &predicate, &mut vec![], &mut found);
+
+    found
+}
+
+/// Finds every string containing the given text.
+pub fn find_string_containing<'a>(message: &'a SerializedMessage, text: &str) -> Vec<(FieldPath, &'a Value)> {
+    find(message, |value| matches!(value, Value::String(string) if string.contains(text)))
+}
+
+/// Finds every varint or fixed64 value equal to the given number.
+///
+/// Fixed64 values are decoded as doubles, so their bits are compared.
+pub fn find_u64(message: &SerializedMessage, number: u64) -> Vec<(FieldPath, &Value)> {
+    find(message, |value| match value {
+        Value::VarInt(varint) => varint.as_i64() as u64 == number,
+        Value::Double(double) => double.to_bits() == number,
+        _ => false
+    })
+}
+
+/// Searches the fields of a message.
+fn find_in<'a, P: Fn(&Value) -> bool>(
+    message: &'a SerializedMessage, predicate: &P,
+    path: &mut FieldPath, found: &mut Vec<(FieldPath, &'a Value)>
+) {
+    for (field, value) in message {
+        path.push(*field);
+        find_value(value, predicate, path, found);
+        path.pop();
+    }
+}
+
+/// Searches a value, and any messages nested in it.
+fn find_value<'a, P: Fn(&Value) -> bool>(
+    value: &'a Value, predicate: &P,
+    path: &mut FieldPath, found: &mut Vec<(FieldPath, &'a Value)>
+) {
+    match value {
+        Value::Repeated(values) => {
+            for value in values {
+                find_value(value, predicate, path, found);
+            }
+        }
+        Value::Map(entries) => {
+            for value in entries.values() {
+                find_value(value, predicate, path, found);
+            }
+        }
+        value => {
+            if predicate(value) {
+                found.push((path.clone(), value));
+            }
+            if let Value::Message(message) = value {
+                find_in(message, predicate, path, found);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search() {
+        let mut session = SerializedMessage::new();
+        session.insert(1, Value::VarInt(12345i64.into()));
+        session.insert(2, Value::String("token=abc".to_string()));
+
+        let mut message = SerializedMessage::new();
+        message.insert(1, Value::VarInt(12345i64.into()));
+        message.insert(3, Value::Message(session.clone()));
+        message.insert(3, Value::Message(session));
+        message.insert(4, Value::Double(f64::from_bits(12345)));
+
+        let paths = |found: Vec<(FieldPath, &Value)>| found.into_iter().map(|(path, _)| path).collect::<Vec<_>>();
+        assert_eq!(paths(find_u64(&message, 12345)), [vec![1], vec![3, 1], vec![3, 1], vec![4]]);
+        assert_eq!(paths(find_string_containing(&message, "token")), [vec![3, 2], vec![3, 2]]);
+        assert_eq!(paths(find(&message, |value| matches!(value, Value::Message(_)))), [vec![3], vec![3]]);
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Deserialize;
+use serde_yaml::Value as Yaml;
+use crate::schema::{FieldType, Schema};
+use crate::{Result, SerializedMessage, Value, VarInt};
+
+/// The side of the connection which sends a message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Client,
+    Server
+}
+
+/// How messages are framed on the simulated stream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// Messages are written back to back with no framing.
+    None,
+    /// Each message is prefixed with its length as a varint.
+    VarIntPrefix,
+    /// Each message is prefixed with its length as a big-endian `u32`.
+    U32Prefix
+}
+
+impl Framing {
+    /// Frames a single encoded message.
+    pub fn frame(&self, message: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![];
+        match self {
+            Framing::None => {}
+            Framing::VarIntPrefix => bytes.extend(VarInt::from(message.len() as i64).to_bytes()),
+            Framing::U32Prefix => bytes.extend((message.len() as u32).to_be_bytes())
+        }
+
+        bytes.extend(message);
+        bytes
+    }
+}
+
+/// A single step of a scripted exchange.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Step {
+    /// The side sending the message.
+    pub from: Direction,
+    /// The name of the schema describing the message, if any.
+    #[serde(rename = "type", default)]
+    pub message_type: Option<String>,
+    /// The message, as a mapping of field numbers to values.
+    pub message: Yaml
+}
+
+/// The byte streams produced by a simulated exchange.
+#[derive(Clone, Debug, Default)]
+pub struct Exchange {
+    /// Every framed message sent by the client.
+    pub to_server: Vec<u8>,
+    /// Every framed message sent by the server.
+    pub to_client: Vec<u8>,
+    /// The individual framed messages, in script order.
+    pub frames: Vec<(Direction, Vec<u8>)>
+}
+
+/// A lightweight, in-memory protocol simulator.
+///
+/// Produces the framed byte streams of a scripted exchange,
+/// so proxy/replay code can be tested without a real server.
+pub struct Simulator {
+    schemas: BTreeMap<String, Schema>,
+    framing: Framing
+}
+
+impl Simulator {
+    /// Creates a new simulator using the given framing.
+    pub fn new(framing: Framing) -> Self {
+        Self { schemas: BTreeMap::new(), framing }
+    }
+
+    /// Registers a schema which steps can refer to by name.
+    pub fn add_schema<S: Into<String>>(&mut self, name: S, schema: Schema) {
+        self.schemas.insert(name.into(), schema);
+    }
+
+    /// Runs a YAML script; a sequence of steps.
+    ///
+    /// ```yaml
+    /// - from: client
+    ///   type: Login
+    ///   message:
+    ///     1: "player"
+    ///     2: 12345
+    /// ```
+    pub fn run(&self, script: &str) -> Result<Exchange> {
+        let steps: Vec<Step> = serde_yaml::from_str(script)?;
+        self.run_steps(&steps)
+    }
+
+    /// Runs a sequence of already-parsed steps.
+    pub fn run_steps(&self, steps: &[Step]) -> Result<Exchange> {
+        let mut exchange = Exchange::default();
+
+        for step in steps {
+            let schema = match &step.message_type {
+                Some(name) => Some(self.schemas.get(name)
+                    .ok_or(format!("Unknown message type '{name}'."))?),
+                None => None
+            };
+
+            let message = to_message(&step.message, schema)?;
+            let frame = self.framing.frame(&message.encode());
+
+            match step.from {
+                Direction::Client => exchange.to_server.extend(&frame),
+                Direction::Server => exchange.to_client.extend(&frame)
+            }
+            exchange.frames.push((step.from, frame));
+        }
+
+        Ok(exchange)
+    }
+}
+
+/// Converts a YAML mapping into a message.
+fn to_message(yaml: &Yaml, schema: Option<&Schema>) -> Result<SerializedMessage> {
+    let Yaml::Mapping(mapping) = yaml else {
+        return Err("Messages must be a mapping of field numbers to values.".into());
+    };
+
+    let mut message = SerializedMessage::new();
+    for (key, value) in mapping {
+        let Some(field) = key.as_u64().and_then(|field| u32::try_from(field).ok()) else {
+            return Err(format!("Invalid field number {key:?}.").into());
+        };
+
+        let field_type = schema
+            .and_then(|schema| schema.get(field))
+            .map(|field| &field.field_type);
+
+        message.insert(field, to_value(value, field_type)?);
+    }
+
+    Ok(message)
+}
+
+/// Converts a YAML value into a message value.
+///
+/// When a field type is known it decides the encoding,
+/// otherwise the encoding is inferred from the YAML type.
+fn to_value(yaml: &Yaml, field_type: Option<&FieldType>) -> Result<Value> {
+    if let Yaml::Sequence(values) = yaml {
+        let values = values.iter()
+            .map(|value| to_value(value, field_type))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        return Ok(Value::Repeated(values));
+    }
+
+    let value = match (field_type, yaml) {
+        (Some(FieldType::VarInt) | None, Yaml::Bool(value)) => Value::from(*value),
+        (Some(FieldType::VarInt), Yaml::Number(_)) | (None, Yaml::Number(_)) if is_integer(yaml) => {
+            Value::VarInt(to_integer(yaml).into())
+        }
+        (Some(FieldType::Fixed32), Yaml::Number(number)) => {
+            Value::Float(number.as_f64().unwrap_or_default() as f32)
+        }
+        (Some(FieldType::Fixed64) | None, Yaml::Number(number)) => {
+            Value::Double(number.as_f64().unwrap_or_default())
+        }
+        (Some(FieldType::String) | None, Yaml::String(value)) => Value::String(value.clone()),
+        (Some(FieldType::Bytes), Yaml::String(value)) => Value::Bytes(STANDARD.decode(value)?),
+        (Some(FieldType::Message(schema)), value) => Value::Message(to_message(value, Some(schema))?),
+        (None, value @ Yaml::Mapping(_)) => Value::Message(to_message(value, None)?),
+        (field_type, value) => {
+            return Err(format!("Cannot convert {value:?} into {field_type:?}.").into());
+        }
+    };
+
+    Ok(value)
+}
+
+/// Returns true if the YAML value is an integer.
+fn is_integer(yaml: &Yaml) -> bool {
+    yaml.is_i64() || yaml.is_u64()
+}
+
+/// Reads a YAML integer as an `i64`, keeping the bits of large unsigned values.
+fn to_integer(yaml: &Yaml) -> i64 {
+    yaml.as_i64().unwrap_or_else(|| yaml.as_u64().unwrap_or_default() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+    use crate::schema::FieldSchema;
+
+    #[test]
+    fn simulate_exchange() {
+        let mut login = Schema::new();
+        login.insert(1, FieldSchema::new(FieldType::String, false));
+        login.insert(2, FieldSchema::new(FieldType::Fixed64, false));
+
+        let mut simulator = Simulator::new(Framing::U32Prefix);
+        simulator.add_schema("Login", login);
+
+        let exchange = simulator.run("
+- from: client
+  type: Login
+  message:
+    1: \"Hello, World!\"
+    2: 3
+- from: server
+  message:
+    1: 0
+").unwrap();
+
+        assert_eq!(exchange.frames.len(), 2);
+
+        let response = decode(&exchange.to_client[4..]).unwrap();
+        assert_eq!(response.get(1).unwrap().as_i32(), Some(0));
+
+        let request = decode(&exchange.to_server[4..]).unwrap();
+        assert_eq!(request.get(1).unwrap().as_string().unwrap(), "Hello, World!");
+        assert_eq!(request.get(2).unwrap().as_double().unwrap(), 3.0);
+    }
+}
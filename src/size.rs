@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use crate::{FieldPath, MapKey, SerializedMessage, Value};
+
+/// The number of bytes the encoder writes for a field header, which it pads.
+const HEADER_LEN: usize = 5;
+
+/// The number of bytes the encoder writes for a length prefix, which it pads.
+const LENGTH_PREFIX_LEN: usize = 5;
+
+/// The number of bytes of an integer map key, which is converted from an `i64`.
+const INTEGER_KEY_LEN: usize = 10;
+
+impl SerializedMessage {
+    /// Returns the number of bytes `encode` writes, without encoding the message.
+    ///
+    /// Useful for preallocating buffers, such as `Vec::with_capacity`.
+    pub fn encoded_len(&self) -> usize {
+        self.iter().map(|(_, value)| value.encoded_len()).sum()
+    }
+
+    /// Returns the number of bytes each (possibly nested) field takes when encoded,
+    /// including its headers, keyed by path.
+    ///
+    /// Occurrences of a field in repeated messages are added together. The length
+    /// of a nested message includes the lengths of its fields, so the lengths of
+    /// the top-level fields add up to the length of the message.
+    pub fn field_lengths(&self) -> BTreeMap<FieldPath, usize> {
+        let mut lengths = BTreeMap::new();
+        field_lengths(self, &mut vec![], &mut lengths);
+
+        lengths
+    }
+}
+
+impl Value {
+    /// Returns the number of bytes the encoder writes for the value as a field,
+    /// including the header of each occurrence.
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            // An empty varint is written as a single zero byte.
+            Value::VarInt(varint) => HEADER_LEN + varint.length().max(1),
+            Value::Float(_) => HEADER_LEN + 4,
+            Value::Double(_) => HEADER_LEN + 8,
+            Value::String(string) => HEADER_LEN + LENGTH_PREFIX_LEN + string.len(),
+            Value::Bytes(bytes) => HEADER_LEN + LENGTH_PREFIX_LEN + bytes.len(),
+            Value::Empty => HEADER_LEN + LENGTH_PREFIX_LEN,
+            Value::Message(message) => HEADER_LEN + LENGTH_PREFIX_LEN + message.encoded_len(),
+            Value::Repeated(values) => values.iter().map(Value::encoded_len).sum(),
+            Value::Summary { .. } => 0,
+            Value::Map(entries) => entries.iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        MapKey::Integer(_) => HEADER_LEN + INTEGER_KEY_LEN,
+                        MapKey::String(key) => HEADER_LEN + LENGTH_PREFIX_LEN + key.len()
+                    };
+                    HEADER_LEN + LENGTH_PREFIX_LEN + key + value.encoded_len()
+                })
+                .sum()
+        }
+    }
+}
+
+/// Records the lengths of the fields of a message, prefixed by its path.
+fn field_lengths(message: &SerializedMessage, path: &mut FieldPath, lengths: &mut BTreeMap<FieldPath, usize>) {
+    for (field, value) in message {
+        path.push(*field);
+        *lengths.entry(path.clone()).or_default() += value.encoded_len();
+
+        let values = match value {
+            Value::Repeated(values) => values.as_slice(),
+            value => std::slice::from_ref(value)
+        };
+        for value in values {
+            if let Value::Message(nested) = value {
+                field_lengths(nested, path, lengths);
+            }
+        }
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn encoded_len() {
+        let mut inner = vec![];
+        inner.write_u32(1, 7);
+        inner.write_str(2, "hey");
+
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+        bytes.write_bytes(2, &inner);
+        bytes.write_bytes(2, &inner);
+        bytes.write_f64(3, 1.5);
+
+        let mut message = decode(&bytes).unwrap();
+        message.insert(4, Value::Map(BTreeMap::from([
+            (MapKey::Integer(1), Value::String("one".to_string())),
+            (MapKey::String("two".to_string()), Value::Empty)
+        ])));
+        assert_eq!(message.encoded_len(), message.encode().len());
+
+        let lengths = message.field_lengths();
+        assert_eq!(lengths[&vec![1]], 10);
+        assert_eq!(lengths[&vec![2]], 2 * (10 + 10 + 13));
+        assert_eq!(lengths[&vec![2, 2]], 2 * 13);
+        assert_eq!(lengths[&vec![1]] + lengths[&vec![2]] + lengths[&vec![3]] + lengths[&vec![4]], message.encoded_len());
+    }
+}
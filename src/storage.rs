@@ -0,0 +1,221 @@
+use std::path::Path;
+use rusqlite::types::Value as Sql;
+use rusqlite::{params, Connection};
+use crate::names::format_path;
+use crate::{decode, FieldPath, Result, SerializedMessage, Value};
+
+/// The tables of a capture database.
+///
+/// Fields are stored one row per occurrence, so repeated fields have a row
+/// for each value; nested messages have a row without a value, followed by
+/// rows for their own fields. Paths are dot-separated field numbers.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS messages (
+        id INTEGER PRIMARY KEY,
+        bytes BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS fields (
+        message_id INTEGER NOT NULL REFERENCES messages(id),
+        path TEXT NOT NULL,
+        type TEXT NOT NULL,
+        value
+    );
+    CREATE INDEX IF NOT EXISTS fields_path ON fields(path);
+";
+
+/// A SQLite database of decoded messages, for querying large captures.
+///
+/// Messages are stored in the `messages` table, and their fields in the
+/// `fields` table, with a `path`, a `type` (see `Value::kind`) and a `value`.
+/// Varints are stored as signed integers, and maps and summaries as text.
+#[derive(Debug)]
+pub struct Store {
+    connection: Connection
+}
+
+impl Store {
+    /// Opens or creates a database at the given path.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// Creates a database in memory.
+    pub fn in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    /// Returns the underlying connection, for running queries.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Decodes a message and stores its bytes as they were given, along with
+    /// its fields, returning the id of the message.
+    ///
+    /// Fails without storing anything if the bytes do not decode.
+    pub fn insert(&mut self, bytes: &[u8]) -> Result<i64> {
+        let transaction = self.connection.transaction()?;
+        let id = insert(&transaction, bytes)?;
+        transaction.commit()?;
+
+        Ok(id)
+    }
+
+    /// Stores many messages in a single transaction, returning their ids.
+    ///
+    /// Fails without storing anything if any of them does not decode.
+    pub fn insert_all<'a, I: IntoIterator<Item = &'a [u8]>>(&mut self, messages: I) -> Result<Vec<i64>> {
+        let transaction = self.connection.transaction()?;
+        let ids = messages.into_iter()
+            .map(|bytes| insert(&transaction, bytes))
+            .collect::<Result<_>>()?;
+        transaction.commit()?;
+
+        Ok(ids)
+    }
+
+    /// Returns the bytes of the stored message with the given id, exactly as inserted.
+    pub fn bytes(&self, id: i64) -> Result<Option<Vec<u8>>> {
+        let mut statement = self.connection.prepare_cached("SELECT bytes FROM messages WHERE id = ?1")?;
+        let mut rows = statement.query([id])?;
+
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None)
+        }
+    }
+
+    /// Returns the stored message with the given id.
+    pub fn message(&self, id: i64) -> Result<Option<SerializedMessage>> {
+        self.bytes(id)?.map(|bytes| decode(&bytes)).transpose()
+    }
+
+    /// Returns every scalar value of the field at a path, across all messages.
+    ///
+    /// Each value is paired with the id of its message, in insertion order.
+    pub fn values(&self, path: &[u32]) -> Result<Vec<(i64, Value)>> {
+        let mut statement = self.connection.prepare_cached(
+            "SELECT message_id, type, value FROM fields WHERE path = ?1 ORDER BY rowid"
+        )?;
+        let mut rows = statement.query([format_path(path)])?;
+
+        let mut values = vec![];
+        while let Some(row) = rows.next()? {
+            let value = match (row.get::<_, String>(1)?.as_str(), row.get::<_, Sql>(2)?) {
+                ("varint", Sql::Integer(value)) => Value::VarInt(value.into()),
+                ("float", Sql::Real(value)) => Value::Float(value as f32),
+                ("double", Sql::Real(value)) => Value::Double(value),
+                ("string", Sql::Text(value)) => Value::String(value),
+                ("bytes", Sql::Blob(value)) => Value::Bytes(value),
+                _ => continue
+            };
+            values.push((row.get(0)?, value));
+        }
+
+        Ok(values)
+    }
+}
+
+/// Decodes and stores a message within a transaction.
+fn insert(connection: &Connection, bytes: &[u8]) -> Result<i64> {
+    let message = decode(bytes)?;
+    connection.prepare_cached("INSERT INTO messages (bytes) VALUES (?1)")?
+        .execute([bytes])?;
+    let id = connection.last_insert_rowid();
+
+    let mut path = vec![];
+    insert_fields(connection, id, &message, &mut path)?;
+
+    Ok(id)
+}
+
+/// Stores the fields of a (possibly nested) message.
+fn insert_fields(connection: &Connection, id: i64, message: &SerializedMessage, path: &mut FieldPath) -> Result<()> {
+    for (field, value) in message.iter() {
+        path.push(*field);
+        insert_value(connection, id, value, path)?;
+        path.pop();
+    }
+
+    Ok(())
+}
+
+/// Stores a single value, and any nested fields.
+fn insert_value(connection: &Connection, id: i64, value: &Value, path: &mut FieldPath) -> Result<()> {
+    let sql = match value {
+        Value::VarInt(varint) => Sql::Integer(varint.as_i64()),
+        Value::Float(value) => Sql::Real(*value as f64),
+        Value::Double(value) => Sql::Real(*value),
+        Value::String(value) => Sql::Text(value.clone()),
+        Value::Bytes(bytes) => Sql::Blob(bytes.clone()),
+        Value::Message(_) | Value::Empty => Sql::Null,
+        // Each value of a repeated field has its own row.
+        Value::Repeated(values) => {
+            for value in values {
+                insert_value(connection, id, value, path)?;
+            }
+            return Ok(());
+        }
+        Value::Summary { .. } | Value::Map(_) => Sql::Text(value.to_string())
+    };
+
+    connection.prepare_cached("INSERT INTO fields (message_id, path, type, value) VALUES (?1, ?2, ?3, ?4)")?
+        .execute(params![id, format_path(path), value.kind(), sql])?;
+
+    if let Value::Message(message) = value {
+        insert_fields(connection, id, message, path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProtobufBytes;
+
+    #[test]
+    fn store() {
+        let mut store = Store::in_memory().unwrap();
+
+        let messages: Vec<_> = (0..3)
+            .map(|i| {
+                let mut inner = vec![];
+                inner.write_u32(5, i * 10);
+                inner.write_str(6, "Hello, World!");
+
+                let mut bytes = vec![];
+                bytes.write_u32(1, i);
+                bytes.write_bytes(2, &inner);
+                bytes
+            })
+            .collect();
+
+        let ids = store.insert_all(messages.iter().map(Vec::as_slice)).unwrap();
+        assert_eq!(store.message(ids[1]).unwrap().unwrap(), decode(&messages[1]).unwrap());
+        assert!(store.message(-1).unwrap().is_none());
+
+        // Bytes are stored as given, even where re-encoding would differ.
+        let minimal = [0x08, 0x96, 0x01];
+        let id = store.insert(&minimal).unwrap();
+        assert_eq!(store.bytes(id).unwrap().unwrap(), minimal);
+        assert!(store.insert(&[0x08]).is_err());
+
+        let values = store.values(&[2, 5]).unwrap();
+        assert_eq!(values, [
+            (ids[0], Value::VarInt(0.into())),
+            (ids[1], Value::VarInt(10.into())),
+            (ids[2], Value::VarInt(20.into()))
+        ]);
+
+        let count: i64 = store.connection()
+            .query_row("SELECT COUNT(*) FROM fields WHERE path = '2.6' AND value = 'Hello, World!'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 3);
+    }
+}
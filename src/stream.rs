@@ -0,0 +1,84 @@
+use tokio::io::{AsyncRead, AsyncReadExt};
+use crate::reassembly::Reassembler;
+use crate::{Decoder, Result, SerializedMessage};
+
+pub use crate::reassembly::Framing;
+
+/// Decodes a stream of framed messages from an asynchronous reader.
+///
+/// Messages longer than the decoder's `max_field_bytes` are rejected
+/// before they are buffered, so a corrupt length cannot exhaust memory.
+#[derive(Debug)]
+pub struct AsyncStreamDecoder<R> {
+    reader: R,
+    reassembler: Reassembler
+}
+
+impl<R: AsyncRead + Unpin> AsyncStreamDecoder<R> {
+    /// Creates a new stream decoder using the default decoder.
+    pub fn new(reader: R, framing: Framing) -> Self {
+        Self::with_decoder(reader, framing, Decoder::default())
+    }
+
+    /// Creates a new stream decoder using the given decoder.
+    pub fn with_decoder(reader: R, framing: Framing, decoder: Decoder) -> Self {
+        Self { reader, reassembler: Reassembler::with_decoder(framing, decoder) }
+    }
+
+    /// Reads and decodes the next message.
+    ///
+    /// Returns `None` once the stream ends between messages, or after a
+    /// malformed stream; a stream ending within a message is an error.
+    /// With raw framing, the whole stream is a message once it ends.
+    pub async fn next_message(&mut self) -> Option<Result<SerializedMessage>> {
+        let mut chunk = [0u8; 8192];
+        while !self.reassembler.is_done() {
+            match self.reassembler.next_message() {
+                Ok(Some((_, message))) => return Some(message),
+                Ok(None) => {}
+                Err(error) => return Some(Err(error))
+            }
+
+            match self.reader.read(&mut chunk).await {
+                Ok(0) => return self.reassembler.finish(),
+                Ok(len) => self.reassembler.push(&chunk[..len]),
+                Err(error) => return Some(Err(error.into()))
+            }
+        }
+
+        None
+    }
+
+    /// Returns the underlying reader.
+    ///
+    /// Bytes already read past the last returned message are lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grpc::Frame;
+    use crate::ProtobufBytes;
+
+    #[tokio::test]
+    async fn stream() {
+        let mut message = vec![];
+        message.write_str(1, "Hello, World!");
+
+        let mut delimited = vec![message.len() as u8];
+        delimited.extend(&message);
+        delimited.push(0);
+
+        let mut decoder = AsyncStreamDecoder::new(delimited.as_slice(), Framing::LengthDelimited);
+        assert_eq!(decoder.next_message().await.unwrap().unwrap().get(1).unwrap().as_string().unwrap(), "Hello, World!");
+        assert_eq!(decoder.next_message().await.unwrap().unwrap(), SerializedMessage::new());
+        assert!(decoder.next_message().await.is_none());
+
+        let grpc = Frame { compressed: false, data: &message }.to_bytes();
+        let mut decoder = AsyncStreamDecoder::new(&grpc[..grpc.len() - 1], Framing::Grpc);
+        assert!(decoder.next_message().await.unwrap().is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use crate::names::FieldNameMap;
+use crate::{FieldAccess, SerializedMessage, Value};
+
+/// A repeated message field projected into columns, one per nested field.
+///
+/// Rows which lack a field hold `None` in its column, so all
+/// columns are as long as the number of rows.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Table {
+    /// The values of each nested field, keyed by field number.
+    pub columns: BTreeMap<u32, Vec<Option<Value>>>,
+    /// The number of rows.
+    pub rows: usize
+}
+
+impl Table {
+    /// Builds a table with a row for each message.
+    pub fn from_rows<'a, I: IntoIterator<Item = &'a SerializedMessage>>(messages: I) -> Self {
+        let mut table = Table::default();
+
+        for message in messages {
+            for (field, value) in message.iter() {
+                table.columns.entry(*field)
+                    .or_insert_with(|| vec![None; table.rows])
+                    .push(Some(value.clone()));
+            }
+
+            table.rows += 1;
+            for column in table.columns.values_mut() {
+                column.resize(table.rows, None);
+            }
+        }
+
+        table
+    }
+
+    /// Returns the number of rows.
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns whether the table has no rows.
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    /// Returns the values of a nested field.
+    pub fn column(&self, field: u32) -> Option<&[Option<Value>]> {
+        self.columns.get(&field).map(Vec::as_slice)
+    }
+
+    /// Returns a row as a message.
+    pub fn row(&self, index: usize) -> Option<SerializedMessage> {
+        if index >= self.rows {
+            return None;
+        }
+
+        let mut message = SerializedMessage::new();
+        for (field, column) in &self.columns {
+            if let Some(value) = &column[index] {
+                message.set_field(*field, value.clone());
+            }
+        }
+
+        Some(message)
+    }
+
+    /// Formats the table as CSV, with field numbers as the header.
+    ///
+    /// See `Exporter` for TSV or named columns.
+    pub fn to_csv(&self) -> String {
+        Exporter::csv().to_string(self, &[])
+    }
+}
+
+/// Writes tables as CSV or TSV, for spreadsheets.
+///
+/// Strings are written as is, missing values as empty cells, and any
+/// other value as it is displayed; cells are quoted when needed.
+#[derive(Copy, Clone, Debug)]
+pub struct Exporter<'a> {
+    delimiter: char,
+    names: Option<&'a FieldNameMap>
+}
+
+impl<'a> Exporter<'a> {
+    /// Creates an exporter of comma-separated values.
+    pub fn csv() -> Self {
+        Self { delimiter: ',', names: None }
+    }
+
+    /// Creates an exporter of tab-separated values.
+    pub fn tsv() -> Self {
+        Self { delimiter: '\t', names: None }
+    }
+
+    /// Names columns using the given map, rather than by field number.
+    pub fn with_names(mut self, names: &'a FieldNameMap) -> Self {
+        self.names = Some(names);
+        self
+    }
+
+    /// Exports the repeated message field at the given path of a message.
+    ///
+    /// Returns `None` if the field is missing or holds no messages.
+    pub fn export(&self, message: &SerializedMessage, path: &[u32]) -> Option<String> {
+        let (field, parents) = path.split_last()?;
+        let table = match parents {
+            [] => message.as_table(*field)?,
+            parents => match message.get_path(parents)? {
+                Value::Message(parent) => parent.as_table(*field)?,
+                _ => return None
+            }
+        };
+
+        Some(self.to_string(&table, path))
+    }
+
+    /// Formats a table, given the path of its field for naming columns.
+    pub fn to_string(&self, table: &Table, path: &[u32]) -> String {
+        let mut bytes = vec![];
+        self.write(table, path, &mut bytes).expect("Writing to a vector cannot fail.");
+        String::from_utf8(bytes).expect("Cells are valid UTF-8.")
+    }
+
+    /// Writes a table, given the path of its field for naming columns.
+    pub fn write<W: Write>(&self, table: &Table, path: &[u32], mut writer: W) -> io::Result<()> {
+        let mut column_path = path.to_vec();
+        let header: Vec<String> = table.columns.keys()
+            .map(|field| {
+                column_path.push(*field);
+                let name = match self.names.and_then(|names| names.get(&column_path)) {
+                    Some(name) => self.escape(name),
+                    None => field.to_string()
+                };
+                column_path.pop();
+                name
+            })
+            .collect();
+        self.write_line(&mut writer, &header)?;
+
+        for index in 0..table.rows {
+            let cells: Vec<String> = table.columns.values()
+                .map(|column| match &column[index] {
+                    Some(Value::String(value)) => self.escape(value),
+                    Some(value) => self.escape(&value.to_string()),
+                    None => String::new()
+                })
+                .collect();
+            self.write_line(&mut writer, &cells)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_line<W: Write>(&self, writer: &mut W, cells: &[String]) -> io::Result<()> {
+        writeln!(writer, "{}", cells.join(self.delimiter.encode_utf8(&mut [0; 4])))
+    }
+
+    /// Quotes a cell if needed.
+    fn escape(&self, cell: &str) -> String {
+        if cell.contains([self.delimiter, '"', '\n', '\r']) {
+            format!("\"{}\"", cell.replace('"', "\"\""))
+        } else {
+            cell.to_string()
+        }
+    }
+}
+
+impl SerializedMessage {
+    /// Projects a repeated message field into a table, with a row for each message.
+    ///
+    /// Returns `None` if the field is missing or holds no messages.
+    pub fn as_table(&self, field: u32) -> Option<Table> {
+        let mut rows = vec![];
+        for value in self.get_repeated(field)? {
+            match value {
+                Value::Message(message) => rows.push(message),
+                // A message which is also a valid string.
+                Value::Repeated(values) => rows.extend(values.iter().filter_map(|value| match value {
+                    Value::Message(message) => Some(message),
+                    _ => None
+                })),
+                _ => {}
+            }
+        }
+
+        (!rows.is_empty()).then(|| Table::from_rows(rows))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode, ProtobufBytes};
+
+    #[test]
+    fn table() {
+        let mut first = vec![];
+        first.write_u32(1, 1);
+        first.write_str(2, "Hello, World!");
+        let mut second = vec![];
+        second.write_u32(1, 2);
+        second.write_u32(3, 7);
+
+        let mut bytes = vec![];
+        bytes.write_bytes(1, &first);
+        bytes.write_bytes(1, &second);
+        bytes.write_u32(2, 5);
+
+        let message = decode(&bytes).unwrap();
+        let table = message.as_table(1).unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.column(1).unwrap(), [Some(Value::VarInt(1.into())), Some(Value::VarInt(2.into()))]);
+        assert_eq!(table.column(3).unwrap(), [None, Some(Value::VarInt(7.into()))]);
+        assert_eq!(table.row(1).unwrap(), decode(&second).unwrap());
+        assert_eq!(table.to_csv(), "1,2,3\n1,\"Hello, World!\",\n2,,7\n");
+
+        assert!(message.as_table(2).is_none());
+        assert!(message.as_table(3).is_none());
+
+        let mut names = FieldNameMap::new();
+        names.insert(&[4, 1, 2], "name");
+        let mut outer = vec![];
+        outer.write_bytes(4, &bytes);
+        let tsv = Exporter::tsv().with_names(&names).export(&decode(&outer).unwrap(), &[4, 1]);
+        assert_eq!(tsv.unwrap(), "1\tname\t3\n1\tHello, World!\t\n2\t\t7\n");
+    }
+}
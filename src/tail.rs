@@ -0,0 +1,246 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use crate::reassembly::{Framing, Reassembler};
+use crate::{Decoder, Error, Result, SerializedMessage};
+
+/// How long `Tail` waits before reading again, once it has read everything written.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The most bytes `Tail::poll` reads in one call, so a large or fast-growing file
+/// is decoded in batches rather than buffered whole.
+pub const MAX_POLL_BYTES: usize = 1 << 20;
+
+/// A message read from a followed file.
+#[derive(Debug)]
+pub struct Record {
+    /// The offset of the length prefix in the file or stream.
+    pub offset: u64,
+    /// The decoded message, or why it failed to decode.
+    pub message: Result<SerializedMessage>
+}
+
+/// Follows a file or FIFO of length-delimited messages as it grows, like `tail -f`.
+///
+/// Each message is prefixed with its length as a varint, as written by
+/// `writeDelimitedTo` in the official libraries. A message is returned once
+/// all of its bytes have been written; a partially written one is kept until
+/// the rest arrives. Iterating waits for new messages indefinitely.
+///
+/// A message which fails to decode is returned as a record holding the error,
+/// and following continues after it. A malformed or oversized length ends
+/// the iteration with an error, since the stream cannot be resynchronized.
+///
+/// Reading a FIFO blocks until a writer writes to it or closes it,
+/// so `poll` only returns without waiting for regular files.
+#[derive(Debug)]
+pub struct Tail<R> {
+    reader: R,
+    /// The bytes read but not yet returned as a message.
+    reassembler: Reassembler,
+    poll_interval: Duration,
+    /// An error of the stream found by `poll` after other records, returned by the next call.
+    error: Option<Error>,
+    failed: bool
+}
+
+impl Tail<File> {
+    /// Follows a file from its start.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::new(File::open(path)?))
+    }
+
+    /// Follows a file from its current end, returning only messages appended later.
+    ///
+    /// Offsets are still counted from the start of the file.
+    pub fn open_at_end<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let offset = file.seek(SeekFrom::End(0))?;
+
+        let mut tail = Self::new(file);
+        tail.reassembler.offset = offset;
+        Ok(tail)
+    }
+}
+
+impl<R: Read> Tail<R> {
+    /// Follows a reader from its current position, using the default decoder.
+    ///
+    /// Offsets are counted from that position.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            reassembler: Reassembler::new(Framing::LengthDelimited),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            error: None,
+            failed: false
+        }
+    }
+
+    /// Decodes messages with the given decoder, whose `max_field_bytes` also limits their length.
+    pub fn with_decoder(mut self, decoder: Decoder) -> Self {
+        let offset = self.reassembler.offset;
+        self.reassembler = Reassembler::with_decoder(Framing::LengthDelimited, decoder);
+        self.reassembler.offset = offset;
+        self
+    }
+
+    /// Waits the given time between reads once everything written has been read.
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Reads what has been written so far, up to `MAX_POLL_BYTES`, returning
+    /// the messages completed by it without waiting. Anything left is read by
+    /// the next call.
+    ///
+    /// If the stream turns out to be malformed after some messages,
+    /// those are returned first, and the error by the next call.
+    pub fn poll(&mut self) -> Result<Vec<Record>> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        let mut chunk = [0u8; 8192];
+        let mut read = 0;
+        while read < MAX_POLL_BYTES {
+            match self.reader.read(&mut chunk)? {
+                0 => break,
+                len => {
+                    self.reassembler.push(&chunk[..len]);
+                    read += len;
+                }
+            }
+        }
+
+        let mut records = vec![];
+        loop {
+            match self.reassembler.next_message() {
+                Ok(Some((offset, message))) => records.push(Record { offset, message }),
+                Ok(None) => break,
+                Err(error) if !records.is_empty() => {
+                    self.error = Some(error);
+                    break;
+                }
+                Err(error) => return Err(error)
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Yields messages as they are written, waiting for more indefinitely.
+impl<R: Read> Iterator for Tail<R> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed {
+            if let Some(error) = self.error.take() {
+                self.failed = true;
+                return Some(Err(error));
+            }
+
+            match self.reassembler.next_message() {
+                Ok(Some((offset, message))) => return Some(Ok(Record { offset, message })),
+                Ok(None) => {}
+                Err(error) => {
+                    self.failed = true;
+                    return Some(Err(error));
+                }
+            }
+
+            let mut chunk = [0u8; 8192];
+            match self.reader.read(&mut chunk) {
+                Ok(0) => thread::sleep(self.poll_interval),
+                Ok(len) => self.reassembler.push(&chunk[..len]),
+                Err(error) => {
+                    self.failed = true;
+                    return Some(Err(error.into()));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use crate::ProtobufBytes;
+
+    #[test]
+    fn tail() {
+        let path = std::env::temp_dir().join(format!("protoshark-tail-{}", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+
+        let mut message = vec![];
+        message.write_str(1, "hey");
+        let mut delimited = vec![message.len() as u8];
+        delimited.extend(&message);
+
+        file.write_all(&delimited).unwrap();
+        let mut tail = Tail::open(&path).unwrap();
+        let mut end = Tail::open_at_end(&path).unwrap();
+
+        // A partially written message is held until the rest arrives.
+        file.write_all(&delimited[..3]).unwrap();
+        let records = tail.poll().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].message.as_ref().unwrap().get(1).unwrap().as_string().unwrap(), "hey");
+
+        let mut appended = OpenOptions::new().append(true).open(&path).unwrap();
+        appended.write_all(&delimited[3..]).unwrap();
+        let record = tail.next().unwrap().unwrap();
+        assert_eq!(record.offset, delimited.len() as u64);
+        let records = end.poll().unwrap();
+        assert_eq!((records[0].offset, records[0].message.as_ref().unwrap()), (record.offset, record.message.as_ref().unwrap()));
+
+        // A message which fails to decode does not stop the messages around it.
+        appended.write_all(&[0x02, 0x08, 0x80]).unwrap();
+        appended.write_all(&delimited).unwrap();
+        let records = end.poll().unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].message.is_err());
+        assert_eq!(records[1].offset, records[0].offset + 3);
+        assert!(records[1].message.is_ok());
+        assert!(tail.next().unwrap().unwrap().message.is_err());
+        assert!(tail.next().unwrap().unwrap().message.is_ok());
+
+        // Messages before a malformed length are returned before its error.
+        appended.write_all(&delimited).unwrap();
+        appended.write_all(&[0xff; 10]).unwrap();
+        assert_eq!(end.poll().unwrap().len(), 1);
+        assert!(end.poll().is_err());
+        assert!(tail.next().unwrap().unwrap().message.is_ok());
+        assert!(tail.next().unwrap().is_err());
+        assert!(tail.next().is_none());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn poll_in_batches() {
+        let mut message = vec![];
+        message.write_str(1, "hey");
+        let mut delimited = vec![message.len() as u8];
+        delimited.extend(&message);
+
+        let count = MAX_POLL_BYTES / delimited.len() + 100;
+        let mut tail = Tail::new(std::io::Cursor::new(delimited.repeat(count)));
+        let first = tail.poll().unwrap().len();
+        assert!(first < count);
+        assert_eq!(first + tail.poll().unwrap().len(), count);
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+use crate::classify::read_field;
+use crate::names::format_path;
+use crate::schema::{FieldType, Schema};
+use crate::{decode, FieldPath, Result, SerializedMessage, Value, VarInt};
+
+/// The shape of an exemplar message, used to decode similar messages.
+///
+/// Decoding with a template follows the exemplar's field types rather than
+/// guessing, so it is faster and gives the same interpretation every time.
+/// Messages which do not match the exemplar fail to decode.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Template {
+    schema: Schema,
+    /// The field number ranges declared by (possibly nested) messages, keyed by their path.
+    ranges: Vec<(FieldPath, RangeInclusive<u32>, RangeKind)>
+}
+
+/// The kind of a range of field numbers declared by a message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RangeKind {
+    /// Field numbers set aside for proto2 extensions.
+    Extension,
+    /// Field numbers which must not be used, usually those of deleted fields.
+    Reserved
+}
+
+/// A difference between a message and the shape of a template.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mismatch {
+    /// The exemplar has a field which the message lacks.
+    Missing { path: FieldPath },
+    /// The message has a field which the exemplar lacks.
+    Unexpected { path: FieldPath },
+    /// The field has a different type than in the exemplar.
+    Type { path: FieldPath, expected: FieldType, found: FieldType },
+    /// The field is repeated, but was seen once in the exemplar.
+    Repeated { path: FieldPath },
+    /// The message has a field in a reserved range, whose numbers must not be used.
+    Reserved { path: FieldPath }
+}
+
+impl Template {
+    /// Creates a template from a schema.
+    pub fn new(schema: Schema) -> Self {
+        Self { schema, ranges: vec![] }
+    }
+
+    /// Declares a range of extension field numbers for the message at the path.
+    ///
+    /// The path of the root message is empty.
+    pub fn with_extensions(mut self, path: &[u32], range: RangeInclusive<u32>) -> Self {
+        self.ranges.push((path.to_vec(), range, RangeKind::Extension));
+        self
+    }
+
+    /// Declares a range of reserved field numbers for the message at the path.
+    pub fn with_reserved(mut self, path: &[u32], range: RangeInclusive<u32>) -> Self {
+        self.ranges.push((path.to_vec(), range, RangeKind::Reserved));
+        self
+    }
+
+    /// Returns the kind of range the field at the path falls in, if any.
+    pub fn range_of(&self, path: &[u32]) -> Option<RangeKind> {
+        let (field, parent) = path.split_last()?;
+        self.ranges.iter()
+            .find(|(message, range, _)| message == parent && range.contains(field))
+            .map(|(_, _, kind)| *kind)
+    }
+
+    /// Creates a template from a decoded exemplar.
+    pub fn from_message(exemplar: &SerializedMessage) -> Self {
+        Self::new(Schema::infer(exemplar))
+    }
+
+    /// Decodes an exemplar and creates a template from it.
+    pub fn learn(bytes: &[u8]) -> Result<Self> {
+        Ok(Self::from_message(&decode(bytes)?))
+    }
+
+    /// Returns the schema of the template.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Decodes a message with the shape of the template.
+    ///
+    /// Fails if the message has a field the exemplar lacks, or a field
+    /// with a different wire type; missing fields are allowed.
+    /// Fields in extension or reserved ranges are decoded without a schema,
+    /// and returned with the path and range kind of each.
+    pub fn apply(&self, bytes: &[u8]) -> Result<(SerializedMessage, Vec<(FieldPath, RangeKind)>)> {
+        let mut flagged = vec![];
+        let message = self.apply_schema(&self.schema, bytes, &mut vec![], &mut flagged)?;
+
+        Ok((message, flagged))
+    }
+
+    /// Compares the shape of a decoded message with the template.
+    pub fn validate(&self, message: &SerializedMessage) -> Vec<Mismatch> {
+        let mut mismatches = vec![];
+        self.validate_schema(&self.schema, &Schema::infer(message), &mut vec![], &mut mismatches);
+
+        mismatches
+    }
+
+    /// Returns true if a decoded message has the shape of the template.
+    pub fn matches(&self, message: &SerializedMessage) -> bool {
+        self.validate(message).is_empty()
+    }
+
+    /// Decodes the fields of a (possibly nested) message following a schema.
+    fn apply_schema(
+        &self, schema: &Schema, bytes: &[u8],
+        path: &mut FieldPath, flagged: &mut Vec<(FieldPath, RangeKind)>
+    ) -> Result<SerializedMessage> {
+        let mut message = SerializedMessage::new();
+        let mut index = 0usize;
+
+        while index < bytes.len() {
+            let start = index;
+            let Some((header, len)) = VarInt::read_at(bytes, index) else {
+                return Err("Invalid message; the field header is truncated.".into());
+            };
+            index += len;
+
+            let field = u32::try_from(header >> 3).unwrap_or(u32::MAX);
+            path.push(field);
+            let Some(field_schema) = schema.get(field) else {
+                let Some(kind) = self.range_of(path) else {
+                    return Err(format!("Invalid message; field {} is not in the template.", format_path(path)).into());
+                };
+
+                // Fields in declared ranges have no schema, so are decoded on their own.
+                let Some((_, end)) = read_field(bytes, start) else {
+                    return Err(format!("Invalid message; field {} is malformed.", format_path(path)).into());
+                };
+                if let Some(value) = decode(&bytes[start..end])?.remove_field(field) {
+                    message.insert(field, value);
+                }
+
+                flagged.push((path.clone(), kind));
+                path.pop();
+                index = end;
+                continue;
+            };
+
+            let value = match (header & 0b111, &field_schema.field_type) {
+                (0, FieldType::VarInt) => {
+                    let (varint, len) = VarInt::try_decode_at(bytes, index)
+                        .ok_or("Invalid message; the varint is truncated.")?;
+                    index += len;
+                    Value::VarInt(varint)
+                }
+                (1, FieldType::Fixed64) => Value::Double(f64::from_le_bytes(fixed(bytes, &mut index)?)),
+                (5, FieldType::Fixed32) => Value::Float(f32::from_le_bytes(fixed(bytes, &mut index)?)),
+                (2, field_type) if field_type.is_length_delimited() => {
+                    let (len, prefix) = VarInt::read_at(bytes, index)
+                        .ok_or("Invalid message; the length prefix is truncated.")?;
+                    let start = index + prefix;
+                    let payload = usize::try_from(len).ok()
+                        .and_then(|len| bytes.get(start..start.checked_add(len)?))
+                        .ok_or("Invalid message; the field is truncated.")?;
+                    index = start + payload.len();
+
+                    match field_type {
+                        FieldType::String => match std::str::from_utf8(payload) {
+                            Ok(string) => Value::String(string.to_string()),
+                            Err(_) => return Err(format!("Invalid message; field {} is not a string.", format_path(path)).into())
+                        },
+                        FieldType::Message(nested) => Value::Message(self.apply_schema(nested, payload, path, flagged)?),
+                        _ => Value::Bytes(payload.to_vec())
+                    }
+                }
+                _ => return Err(format!("Invalid message; field {} does not match the template.", format_path(path)).into())
+            };
+
+            path.pop();
+            message.insert(field, value);
+        }
+
+        Ok(message)
+    }
+
+    /// Compares a schema with the schema of a message.
+    fn validate_schema(&self, expected: &Schema, found: &Schema, path: &mut FieldPath, mismatches: &mut Vec<Mismatch>) {
+        let fields: BTreeSet<u32> = expected.iter().chain(found).map(|(field, _)| *field).collect();
+
+        for field in fields {
+            path.push(field);
+
+            match (expected.get(field), found.get(field)) {
+                (Some(_), None) => mismatches.push(Mismatch::Missing { path: path.clone() }),
+                (None, Some(_)) => match self.range_of(path) {
+                    Some(RangeKind::Extension) => {}
+                    Some(RangeKind::Reserved) => mismatches.push(Mismatch::Reserved { path: path.clone() }),
+                    None => mismatches.push(Mismatch::Unexpected { path: path.clone() })
+                },
+                (Some(exemplar), Some(schema)) => {
+                    if schema.repeated && !exemplar.repeated {
+                        mismatches.push(Mismatch::Repeated { path: path.clone() });
+                    }
+
+                    match (&exemplar.field_type, &schema.field_type) {
+                        (FieldType::Message(a), FieldType::Message(b)) => self.validate_schema(a, b, path, mismatches),
+                        (a, b) if a != b => mismatches.push(Mismatch::Type { path: path.clone(), expected: a.clone(), found: b.clone() }),
+                        _ => {}
+                    }
+                }
+                (None, None) => {}
+            }
+
+            path.pop();
+        }
+    }
+}
+
+/// Reads a fixed-width value, advancing the index past it.
+fn fixed<const N: usize>(bytes: &[u8], index: &mut usize) -> Result<[u8; N]> {
+    let value = bytes.get(*index..*index + N)
+        .ok_or("Invalid message; the fixed-width value is truncated.")?;
+    *index += N;
+
+    Ok(value.try_into()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProtobufBytes;
+
+    #[test]
+    fn template() {
+        let mut inner = vec![];
+        inner.write_u32(1, 7);
+
+        let mut exemplar = vec![];
+        exemplar.write_u32(1, 150);
+        exemplar.write_bytes(2, &inner);
+        exemplar.write_f32(3, 1.5);
+        exemplar.write_str(4, "Hello, World!");
+
+        let template = Template::learn(&exemplar).unwrap();
+        assert_eq!(template.apply(&exemplar).unwrap(), (decode(&exemplar).unwrap(), vec![]));
+
+        // A string which is also a valid message is read as a string.
+        let mut bytes = vec![];
+        bytes.write_u32(1, 3);
+        bytes.write_bytes(2, &inner);
+        bytes.write_f32(3, 2.5);
+        bytes.write_str(4, "\x08\x01");
+        let (message, _) = template.apply(&bytes).unwrap();
+        assert_eq!(message.get(4), Some(Value::String("\x08\x01".to_string())));
+        assert!(template.matches(&message));
+
+        let mut bytes = vec![];
+        bytes.write_str(1, "Goodbye");
+        bytes.write_u32(5, 1);
+        assert!(template.apply(&bytes).is_err());
+
+        let message = decode(&bytes).unwrap();
+        assert_eq!(template.validate(&message), [
+            Mismatch::Type { path: vec![1], expected: FieldType::VarInt, found: FieldType::String },
+            Mismatch::Missing { path: vec![2] },
+            Mismatch::Missing { path: vec![3] },
+            Mismatch::Missing { path: vec![4] },
+            Mismatch::Unexpected { path: vec![5] }
+        ]);
+    }
+
+    #[test]
+    fn ranges() {
+        let mut inner = vec![];
+        inner.write_u32(1, 7);
+
+        let mut exemplar = vec![];
+        exemplar.write_bytes(1, &inner);
+
+        let template = Template::learn(&exemplar).unwrap()
+            .with_extensions(&[], 100..=199)
+            .with_reserved(&[1], 5..=5);
+        assert_eq!(template.range_of(&[150]), Some(RangeKind::Extension));
+        assert_eq!(template.range_of(&[1, 5]), Some(RangeKind::Reserved));
+        assert_eq!(template.range_of(&[5]), None);
+
+        inner.write_str(5, "deleted");
+        let mut bytes = vec![];
+        bytes.write_bytes(1, &inner);
+        bytes.write_u32(150, 42);
+
+        let (message, flagged) = template.apply(&bytes).unwrap();
+        assert_eq!(message, decode(&bytes).unwrap());
+        assert_eq!(flagged, [(vec![1, 5], RangeKind::Reserved), (vec![150], RangeKind::Extension)]);
+        assert_eq!(template.validate(&message), [Mismatch::Reserved { path: vec![1, 5] }]);
+
+        bytes.write_u32(200, 1);
+        assert!(template.apply(&bytes).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use std::{env, fs};
+use std::path::Path;
+use crate::{decode, SerializedMessage};
+
+/// The environment variable which, when set, rewrites golden files
+/// with the current output instead of comparing against them.
+pub const UPDATE_GOLDEN: &str = "PROTOSHARK_UPDATE_GOLDEN";
+
+/// Asserts that bytes decode to a message matching a JSON snapshot.
+///
+/// Formatting and key order of the snapshot are ignored.
+///
+/// ```ignore
+/// assert_decodes_to!([0x08, 0x96, 0x01], r#"{ "1": 150 }"#);
+/// ```
+#[macro_export]
+macro_rules! assert_decodes_to {
+    ($bytes:expr, $snapshot:expr $(,)?) => {
+        $crate::testing::assert_decodes_to(&$bytes, $snapshot)
+    };
+}
+
+/// Formats a message as a snapshot: pretty JSON, with keys sorted.
+pub fn snapshot(message: &SerializedMessage) -> String {
+    // Round-tripping through a JSON value sorts the keys.
+    let value = serde_json::to_value(message).unwrap();
+    serde_json::to_string_pretty(&value).unwrap()
+}
+
+/// Normalizes the formatting of a JSON snapshot, as produced by `snapshot`.
+///
+/// Panics if the snapshot is not valid JSON.
+#[track_caller]
+pub fn normalize(snapshot: &str) -> String {
+    let value = serde_json::from_str::<serde_json::Value>(snapshot)
+        .unwrap_or_else(|error| panic!("Invalid snapshot; {error}."));
+    serde_json::to_string_pretty(&value).unwrap()
+}
+
+/// Asserts that bytes decode to a message matching a JSON snapshot.
+///
+/// Prefer the `assert_decodes_to!` macro.
+#[track_caller]
+pub fn assert_decodes_to(bytes: &[u8], expected: &str) {
+    let message = decode(bytes).unwrap_or_else(|error| panic!("Failed to decode the message; {error}"));
+    assert_snapshot(&message, expected);
+}
+
+/// Asserts that a message matches a JSON snapshot.
+#[track_caller]
+pub fn assert_snapshot(message: &SerializedMessage, expected: &str) {
+    let (actual, expected) = (snapshot(message), normalize(expected));
+    assert!(actual == expected, "The message does not match the snapshot.\nexpected: {expected}\n  actual: {actual}");
+}
+
+/// Asserts that a message matches the snapshot in a golden file.
+///
+/// The file is created if it does not exist, and rewritten if the
+/// `PROTOSHARK_UPDATE_GOLDEN` environment variable is set.
+#[track_caller]
+pub fn assert_golden(message: &SerializedMessage, path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    if env::var_os(UPDATE_GOLDEN).is_some() || !path.exists() {
+        fs::write(path, snapshot(message) + "\n")
+            .unwrap_or_else(|error| panic!("Failed to write {}; {error}", path.display()));
+        return;
+    }
+
+    let expected = fs::read_to_string(path)
+        .unwrap_or_else(|error| panic!("Failed to read {}; {error}", path.display()));
+    assert_snapshot(message, &expected);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ProtobufBytes, Value};
+
+    #[test]
+    fn snapshots() {
+        let mut bytes = vec![];
+        bytes.write_i32(1, 150);
+        bytes.write_str(2, "hey");
+
+        assert_decodes_to!(bytes, r#"{ "2": "hey",
+            "1": 150 }"#);
+
+        let path = env::temp_dir().join(format!("protoshark-golden-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let message = decode(&bytes).unwrap();
+        assert_golden(&message, &path);
+        assert_golden(&message, &path);
+
+        let mut changed = message.clone();
+        changed.set_field(1, Value::VarInt(151i64.into()));
+        assert!(std::panic::catch_unwind(|| assert_golden(&changed, &path)).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,873 @@
+use serde::de::value::{BorrowedStrDeserializer, SeqDeserializer, U32Deserializer};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use serde::{forward_to_deserialize_any, Deserialize};
+use crate::{decode, Error, FieldAccess, IntoVarInt, MapKey, Result, SerializedMessage, Value, VarInt, MAX_FIELD_NUMBER};
+
+/// Transcodes any `Serialize` type into a message, without a schema.
+///
+/// Structs become messages, numbering their fields in declaration order
+/// from 1, unless a field is renamed to its number with `#[serde(rename = "5")]`;
+/// renaming to an invalid field number fails with `Error::FieldNumber`.
+/// Integers become varints, floats fixed-width values, strings strings,
+/// sequences repeated fields, and `None` a missing field. Unit enum variants
+/// become their index, and other variants a message holding their content
+/// in field `index + 1`. Maps with field numbers as keys become messages,
+/// and other maps map fields. `Vec<u8>` is a sequence of varints to serde;
+/// use `serde_bytes` for bytes fields.
+pub fn to_message<T: Serialize + ?Sized>(value: &T) -> Result<SerializedMessage> {
+    match value.serialize(ValueSerializer)? {
+        Some(Value::Message(message)) => Ok(message),
+        _ => Err("Invalid message; only structs and maps can be encoded as messages.".into())
+    }
+}
+
+/// Transcodes any `Serialize` type into protobuf wire format; see `to_message`.
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    Ok(to_message(value)?.encode())
+}
+
+/// Transcodes a message into any `Deserialize` type, the inverse of `to_message`.
+///
+/// Fields are matched by number as in `to_message`. Values are converted
+/// as the type requests, so a varint can be read as a `bool` or any integer,
+/// and a field which occurs more than once as a single value takes the
+/// last occurrence of the requested kind.
+pub fn from_message<'a, T: Deserialize<'a>>(message: &'a SerializedMessage) -> Result<T> {
+    T::deserialize(MessageDeserializer(message))
+}
+
+/// Decodes and transcodes a message into any `Deserialize` type; see `from_message`.
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    from_message(&decode(bytes)?)
+}
+
+/// Serializes into a value, or `None` for a missing field.
+struct ValueSerializer;
+
+/// Returns the field number of a struct field, given its position.
+///
+/// Fails with `Error::FieldNumber` if the field is renamed to a number
+/// which is zero or above `MAX_FIELD_NUMBER`.
+fn field_number(name: &str, index: u32) -> Result<u32> {
+    if name.is_empty() || !name.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Ok(index + 1);
+    }
+
+    match name.parse::<u64>() {
+        Ok(field) if (1..=MAX_FIELD_NUMBER as u64).contains(&field) => Ok(field as u32),
+        Ok(field) => Err(Error::FieldNumber(field)),
+        Err(_) => Err(Error::FieldNumber(u64::MAX))
+    }
+}
+
+/// Wraps the content of an enum variant in a message.
+fn variant(index: u32, value: Option<Value>) -> Option<Value> {
+    let mut message = SerializedMessage::new();
+    if let Some(value) = value {
+        message.set_field(index + 1, value);
+    }
+
+    Some(Value::Message(message))
+}
+
+fn varint<T: IntoVarInt>(value: T) -> Result<Option<Value>> {
+    Ok(Some(Value::VarInt(VarInt::decode(&value.into_varint()))))
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = StructSerializer;
+
+    fn serialize_bool(self, value: bool) -> Result<Self::Ok> {
+        Ok(Some(value.into()))
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<Self::Ok> {
+        varint(value as i32)
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<Self::Ok> {
+        varint(value as i32)
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<Self::Ok> {
+        varint(value)
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<Self::Ok> {
+        varint(value)
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<Self::Ok> {
+        varint(value as u32)
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<Self::Ok> {
+        varint(value as u32)
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<Self::Ok> {
+        varint(value)
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<Self::Ok> {
+        varint(value)
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<Self::Ok> {
+        Ok(Some(Value::Float(value)))
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<Self::Ok> {
+        Ok(Some(Value::Double(value)))
+    }
+
+    fn serialize_char(self, value: char) -> Result<Self::Ok> {
+        Ok(Some(Value::String(value.to_string())))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<Self::Ok> {
+        Ok(Some(Value::String(value.to_string())))
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<Self::Ok> {
+        Ok(Some(Value::Bytes(value.to_vec())))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Self::Ok> {
+        Ok(Some(Value::Message(SerializedMessage::new())))
+    }
+
+    fn serialize_unit_variant(self, _: &'static str, index: u32, _: &'static str) -> Result<Self::Ok> {
+        varint(index)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _: &'static str, value: &T) -> Result<Self::Ok> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self, _: &'static str, index: u32, _: &'static str, value: &T
+    ) -> Result<Self::Ok> {
+        Ok(variant(index, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(SeqSerializer { values: Vec::with_capacity(len.unwrap_or_default()), variant: None })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, len: usize) -> Result<Self::SerializeTupleStruct> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self, _: &'static str, index: u32, _: &'static str, len: usize
+    ) -> Result<Self::SerializeTupleVariant> {
+        Ok(SeqSerializer { values: Vec::with_capacity(len), variant: Some(index) })
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap> {
+        Ok(MapSerializer { entries: vec![], key: None })
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct> {
+        Ok(StructSerializer { message: SerializedMessage::new(), index: 0, variant: None })
+    }
+
+    fn serialize_struct_variant(
+        self, _: &'static str, index: u32, _: &'static str, _: usize
+    ) -> Result<Self::SerializeStructVariant> {
+        Ok(StructSerializer { message: SerializedMessage::new(), index: 0, variant: Some(index) })
+    }
+}
+
+/// Serializes a sequence into a repeated field.
+struct SeqSerializer {
+    values: Vec<Value>,
+    /// The index of the enum variant holding the sequence, if any.
+    variant: Option<u32>
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        match value.serialize(ValueSerializer)? {
+            Some(Value::Repeated(_)) => return Err("Invalid message; nested sequences cannot be encoded.".into()),
+            Some(value) => self.values.push(value),
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Value>> {
+        let value = Some(Value::Repeated(self.values));
+        Ok(match self.variant {
+            Some(index) => variant(index, value),
+            None => value
+        })
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+/// Serializes a map into a message or a map field.
+struct MapSerializer {
+    entries: Vec<(MapKey, Value)>,
+    key: Option<MapKey>
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.key = Some(match key.serialize(ValueSerializer)? {
+            Some(Value::VarInt(key)) => MapKey::Integer(key.as_i64()),
+            Some(Value::String(key)) => MapKey::String(key),
+            _ => return Err("Invalid message; map keys must be integers or strings.".into())
+        });
+
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self.key.take().ok_or("Invalid message; a map value has no key.")?;
+        if let Some(value) = value.serialize(ValueSerializer)? {
+            self.entries.push((key, value));
+        }
+
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        let is_field = |key: &MapKey| matches!(key, MapKey::Integer(key) if (1..=MAX_FIELD_NUMBER as i64).contains(key));
+        if !self.entries.iter().all(|(key, _)| is_field(key)) {
+            return Ok(Some(Value::Map(self.entries.into_iter().collect())));
+        }
+
+        let mut message = SerializedMessage::new();
+        for (key, value) in self.entries {
+            if let MapKey::Integer(field) = key {
+                message.set_field(field as u32, value);
+            }
+        }
+
+        Ok(Some(Value::Message(message)))
+    }
+}
+
+/// Serializes a struct into a message.
+struct StructSerializer {
+    message: SerializedMessage,
+    /// The position of the next field.
+    index: u32,
+    /// The index of the enum variant holding the struct, if any.
+    variant: Option<u32>
+}
+
+impl StructSerializer {
+    fn field<T: Serialize + ?Sized>(&mut self, name: &'static str, value: &T) -> Result<()> {
+        let field = field_number(name, self.index)?;
+        self.index += 1;
+
+        if let Some(value) = value.serialize(ValueSerializer)? {
+            self.message.set_field(field, value);
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Value>> {
+        let value = Some(Value::Message(self.message));
+        Ok(match self.variant {
+            Some(index) => variant(index, value),
+            None => value
+        })
+    }
+}
+
+impl ser::SerializeStruct for StructSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, name: &'static str, value: &T) -> Result<()> {
+        self.field(name, value)
+    }
+
+    fn skip_field(&mut self, _: &'static str) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for StructSerializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, name: &'static str, value: &T) -> Result<()> {
+        self.field(name, value)
+    }
+
+    fn skip_field(&mut self, _: &'static str) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+/// Deserializes a message as a struct or map.
+struct MessageDeserializer<'a>(&'a SerializedMessage);
+
+impl<'de> de::Deserializer<'de> for MessageDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(Fields::new(self.0))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self, _: &'static str, fields: &'static [&'static str], visitor: V
+    ) -> Result<V::Value> {
+        visitor.visit_map(StructAccess { message: self.0, fields, index: 0, value: None })
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// Deserializes a single value, as the requested type.
+struct ValueDeserializer<'a>(&'a Value);
+
+impl<'a> ValueDeserializer<'a> {
+    /// Returns the value, or the last occurrence of the requested kind if repeated.
+    fn pick(&self, kind: fn(&Value) -> bool) -> &'a Value {
+        match self.0 {
+            Value::Repeated(values) => values.iter().rev().find(|value| kind(value)).unwrap_or(self.0),
+            value => value
+        }
+    }
+
+    fn varint(&self) -> Option<&'a VarInt> {
+        match self.pick(|value| matches!(value, Value::VarInt(_))) {
+            Value::VarInt(varint) => Some(varint),
+            _ => None
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::VarInt(varint) => visitor.visit_i64(varint.as_i64()),
+            Value::Float(value) => visitor.visit_f32(*value),
+            Value::Double(value) => visitor.visit_f64(*value),
+            Value::String(value) => visitor.visit_borrowed_str(value),
+            Value::Bytes(value) => visitor.visit_borrowed_bytes(value),
+            Value::Message(message) => visitor.visit_map(Fields::new(message)),
+            Value::Repeated(values) => visitor.visit_seq(SeqDeserializer::new(values.iter().map(ValueDeserializer))),
+            Value::Summary { .. } | Value::Empty => visitor.visit_unit(),
+            Value::Map(entries) => visitor.visit_map(EntryAccess { entries: entries.iter(), value: None })
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.varint() {
+            Some(varint) => visitor.visit_bool(varint.as_i64() != 0),
+            None => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.varint() {
+            Some(varint) => visitor.visit_i64(varint.as_i64()),
+            None => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.varint() {
+            Some(varint) => visitor.visit_u64(varint.as_i64() as u64),
+            None => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        // Negative 32-bit varints are written with the padded encoder, which keeps only 32 bits.
+        match self.varint() {
+            Some(varint) => visitor.visit_i32(varint.as_i32()),
+            None => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.pick(|value| matches!(value, Value::Float(_))) {
+            Value::Float(value) => visitor.visit_f32(*value),
+            _ => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.pick(|value| matches!(value, Value::Double(_))) {
+            Value::Double(value) => visitor.visit_f64(*value),
+            _ => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.pick(|value| matches!(value, Value::String(_))) {
+            Value::String(value) => visitor.visit_borrowed_str(value),
+            Value::Empty => visitor.visit_borrowed_str(""),
+            _ => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.pick(|value| matches!(value, Value::Bytes(_) | Value::String(_))) {
+            Value::Bytes(value) => visitor.visit_borrowed_bytes(value),
+            Value::String(value) => visitor.visit_borrowed_bytes(value.as_bytes()),
+            Value::Empty => visitor.visit_borrowed_bytes(&[]),
+            _ => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        // Missing fields are not visited, so present fields are always `Some`.
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Repeated(values) => visitor.visit_seq(SeqDeserializer::new(values.iter().map(ValueDeserializer))),
+            // Bytes fields can be read as a `Vec<u8>`.
+            Value::Bytes(bytes) => visitor.visit_seq(SeqDeserializer::new(bytes.iter().copied())),
+            // Most often empty bytes, read as an empty `Vec<u8>`.
+            Value::Empty => visitor.visit_seq(SeqDeserializer::new(std::iter::empty::<u8>())),
+            value => visitor.visit_seq(SeqDeserializer::new(std::iter::once(ValueDeserializer(value))))
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _: &'static str, _: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            // Map entries which were not folded into a map field.
+            Value::Repeated(values) => {
+                let entries = values.iter()
+                    .map(|value| match value {
+                        Value::Message(entry) => match (entry.get_ref(1), entry.get_ref(2)) {
+                            (Some(key), Some(value)) => Ok((ValueDeserializer(key), ValueDeserializer(value))),
+                            _ => Err(Error::from("Invalid message; a map entry is missing its key or value."))
+                        },
+                        _ => Err(Error::from("Invalid message; a map entry is not a message."))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                visitor.visit_map(de::value::MapDeserializer::new(entries.into_iter()))
+            }
+            Value::Empty => visitor.visit_map(de::value::MapDeserializer::new(std::iter::empty::<(u32, u32)>())),
+            _ => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self, name: &'static str, fields: &'static [&'static str], visitor: V
+    ) -> Result<V::Value> {
+        match self.pick(|value| matches!(value, Value::Message(_))) {
+            Value::Message(message) => MessageDeserializer(message).deserialize_struct(name, fields, visitor),
+            Value::Empty => visitor.visit_map(de::value::MapDeserializer::new(std::iter::empty::<(u32, u32)>())),
+            _ => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self, _: &'static str, _: &'static [&'static str], visitor: V
+    ) -> Result<V::Value> {
+        match self.0 {
+            Value::VarInt(index) => visitor.visit_enum(index.as_u32().unwrap_or(u32::MAX).into_deserializer()),
+            Value::String(name) => visitor.visit_enum(BorrowedStrDeserializer::new(name)),
+            Value::Message(message) => {
+                let mut fields = message.iter();
+                match (fields.next(), fields.next()) {
+                    (Some((field, value)), None) => visitor.visit_enum(Variant { index: field - 1, value }),
+                    _ => Err("Invalid message; an enum variant must hold a single field.".into())
+                }
+            }
+            _ => self.deserialize_any(visitor)
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for ValueDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// Visits the fields of a message as a map keyed by field number.
+struct Fields<'a> {
+    fields: std::collections::btree_map::Iter<'a, u32, Value>,
+    value: Option<&'a Value>
+}
+
+impl<'a> Fields<'a> {
+    fn new(message: &'a SerializedMessage) -> Self {
+        Self { fields: message.iter(), value: None }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for Fields<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        let Some((field, value)) = self.fields.next() else {
+            return Ok(None);
+        };
+
+        self.value = Some(value);
+        seed.deserialize(FieldKey(*field)).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let value = self.value.take().ok_or("Invalid message; a field has no value.")?;
+        seed.deserialize(ValueDeserializer(value))
+    }
+}
+
+/// Visits the fields of a message named by a struct.
+struct StructAccess<'a> {
+    message: &'a SerializedMessage,
+    fields: &'static [&'static str],
+    /// The position of the next field.
+    index: usize,
+    value: Option<&'a Value>
+}
+
+impl<'de> de::MapAccess<'de> for StructAccess<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        // Missing fields are skipped, so serde fills in `None` or a default.
+        while let Some(name) = self.fields.get(self.index) {
+            let field = field_number(name, self.index as u32)?;
+            self.index += 1;
+
+            if let Some(value) = self.message.get_ref(field) {
+                self.value = Some(value);
+                return seed.deserialize(BorrowedStrDeserializer::<Error>::new(name)).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let value = self.value.take().ok_or("Invalid message; a field has no value.")?;
+        seed.deserialize(ValueDeserializer(value))
+    }
+}
+
+/// Visits the entries of a map field.
+struct EntryAccess<'a> {
+    entries: std::collections::btree_map::Iter<'a, MapKey, Value>,
+    value: Option<&'a Value>
+}
+
+impl<'de> de::MapAccess<'de> for EntryAccess<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        let Some((key, value)) = self.entries.next() else {
+            return Ok(None);
+        };
+
+        self.value = Some(value);
+        match key {
+            MapKey::Integer(key) => seed.deserialize((*key).into_deserializer()).map(Some),
+            MapKey::String(key) => seed.deserialize(BorrowedStrDeserializer::new(key)).map(Some)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let value = self.value.take().ok_or("Invalid message; a map key has no value.")?;
+        seed.deserialize(ValueDeserializer(value))
+    }
+}
+
+/// A field number used as a map key, read as a number or a string.
+struct FieldKey(u32);
+
+impl<'de> de::Deserializer<'de> for FieldKey {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u32(self.0)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.0.to_string())
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.0.to_string())
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// The variant of an enum held in a message.
+struct Variant<'a> {
+    index: u32,
+    value: &'a Value
+}
+
+impl<'de> de::EnumAccess<'de> for Variant<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let variant = seed.deserialize(U32Deserializer::<Error>::new(self.index))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Variant<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(ValueDeserializer(self.value))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_seq(ValueDeserializer(self.value), visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_struct(ValueDeserializer(self.value), "", fields, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use super::*;
+    use serde::Serialize;
+    use crate::ProtobufBytes;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Status {
+        Offline,
+        Online { since: u64 }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Profile {
+        name: String,
+        level: u32,
+        tags: Vec<String>,
+        status: Status,
+        #[serde(rename = "10")]
+        score: f64,
+        #[serde(default)]
+        email: Option<String>,
+        stats: BTreeMap<u32, i64>
+    }
+
+    #[test]
+    fn transcode() {
+        let profile = Profile {
+            name: "alice".to_string(),
+            level: 7,
+            tags: vec!["admin".to_string(), "beta".to_string()],
+            status: Status::Online { since: 1_700_000_000 },
+            score: 1.5,
+            email: None,
+            stats: BTreeMap::from([(1, 5), (2, 2)])
+        };
+
+        let mut bytes = vec![];
+        bytes.write_str(1, "alice");
+        bytes.write_u32(2, 7);
+        bytes.write_str(3, "admin");
+        bytes.write_str(3, "beta");
+        let mut online = vec![];
+        online.write_u64(1, 1_700_000_000);
+        let mut status = vec![];
+        status.write_bytes(2, &online);
+        bytes.write_bytes(4, &status);
+        let mut stats = vec![];
+        stats.write_i64(1, 5);
+        stats.write_i64(2, 2);
+        bytes.write_bytes(7, &stats);
+        bytes.write_f64(10, 1.5);
+
+        assert_eq!(to_bytes(&profile).unwrap(), bytes);
+        assert_eq!(from_bytes::<Profile>(&bytes).unwrap(), profile);
+
+        let statuses: BTreeMap<u32, Status> = from_message(&to_message(&BTreeMap::from([(3, Status::Offline)])).unwrap()).unwrap();
+        assert_eq!(statuses[&3], Status::Offline);
+        assert!(to_message(&5).is_err());
+
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Reserved {
+            #[serde(rename = "0")]
+            zero: u32
+        }
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Huge {
+            #[serde(rename = "536870912")]
+            huge: u32
+        }
+        assert!(matches!(to_message(&Reserved { zero: 1 }), Err(Error::FieldNumber(0))));
+        assert!(matches!(to_message(&Huge { huge: 1 }), Err(Error::FieldNumber(0x2000_0000))));
+        assert!(matches!(from_bytes::<Reserved>(&bytes), Err(Error::FieldNumber(0))));
+    }
+}
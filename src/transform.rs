@@ -0,0 +1,175 @@
+use std::collections::BTreeSet;
+use crate::{FieldPath, SerializedMessage, Value};
+
+/// A transformation applied to decoded messages.
+pub trait Hook {
+    /// Returns the name of the hook, used when recording provenance.
+    fn name(&self) -> &str;
+
+    /// Modifies the message in place.
+    fn apply(&self, message: &mut SerializedMessage);
+}
+
+/// A hook built from a name and a closure.
+pub struct FnHook<F> {
+    name: String,
+    function: F
+}
+
+impl<F: Fn(&mut SerializedMessage)> FnHook<F> {
+    /// Creates a new hook from a closure.
+    pub fn new<S: Into<String>>(name: S, function: F) -> Self {
+        Self { name: name.into(), function }
+    }
+}
+
+impl<F: Fn(&mut SerializedMessage)> Hook for FnHook<F> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn apply(&self, message: &mut SerializedMessage) {
+        (self.function)(message)
+    }
+}
+
+/// A single modification made by a hook.
+#[derive(Clone)]
+pub struct Change {
+    /// The name of the hook which made the change.
+    pub hook: String,
+    /// The path of the changed field.
+    pub path: FieldPath,
+    /// The value before the change; `None` if the field was added.
+    pub old: Option<Value>,
+    /// The value after the change; `None` if the field was removed.
+    pub new: Option<Value>
+}
+
+/// A decoded message, along with the changes made to it by hooks.
+#[derive(Clone, Default)]
+pub struct DecodedRecord {
+    pub message: SerializedMessage,
+    /// The changes made to the message, in the order they were made.
+    ///
+    /// Only populated when the pipeline tracks provenance.
+    pub provenance: Vec<Change>
+}
+
+impl DecodedRecord {
+    /// Creates a new record with no provenance.
+    pub fn new(message: SerializedMessage) -> Self {
+        Self { message, provenance: vec![] }
+    }
+}
+
+impl From<SerializedMessage> for DecodedRecord {
+    fn from(message: SerializedMessage) -> Self {
+        Self::new(message)
+    }
+}
+
+/// An ordered set of hooks applied to decoded records.
+#[derive(Default)]
+pub struct Pipeline {
+    hooks: Vec<Box<dyn Hook>>,
+    track_provenance: bool
+}
+
+impl Pipeline {
+    /// Creates a new, empty pipeline.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a hook to the end of the pipeline.
+    pub fn add_hook<H: Hook + 'static>(&mut self, hook: H) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Sets whether changes made by hooks are recorded on the record.
+    ///
+    /// Tracking requires a copy of the message per hook, so it is disabled by default.
+    pub fn set_track_provenance(&mut self, track: bool) {
+        self.track_provenance = track;
+    }
+
+    /// Applies every hook to the record, in order.
+    pub fn apply(&self, record: &mut DecodedRecord) {
+        for hook in &self.hooks {
+            if !self.track_provenance {
+                hook.apply(&mut record.message);
+                continue;
+            }
+
+            let before = record.message.clone();
+            hook.apply(&mut record.message);
+
+            diff(&before, &record.message, &mut vec![], hook.name(), &mut record.provenance);
+        }
+    }
+}
+
+/// Records the differences between two messages as changes.
+fn diff(
+    before: &SerializedMessage, after: &SerializedMessage,
+    path: &mut FieldPath, hook: &str, changes: &mut Vec<Change>
+) {
+    let fields: BTreeSet<u32> = before.iter()
+        .chain(after.iter())
+        .map(|(field, _)| *field)
+        .collect();
+
+    for field in fields {
+        path.push(field);
+
+        match (before.get(field), after.get(field)) {
+            (Some(Value::Message(old)), Some(Value::Message(new))) => {
+                diff(&old, &new, path, hook, changes);
+            }
+            (old, new) if old != new => {
+                changes.push(Change { hook: hook.to_string(), path: path.clone(), old, new });
+            }
+            _ => {}
+        }
+
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_provenance() {
+        let mut inner = SerializedMessage::new();
+        inner.insert(1, Value::from("secret".to_string()));
+        inner.insert(2, Value::from(1.5f32));
+
+        let mut message = SerializedMessage::new();
+        message.insert(1, Value::from(true));
+        message.insert(2, Value::Message(inner));
+
+        let mut pipeline = Pipeline::new();
+        pipeline.set_track_provenance(true);
+        pipeline.add_hook(FnHook::new("scrub", |message: &mut SerializedMessage| {
+            for (_, value) in message {
+                if let Value::Message(inner) = value {
+                    *inner = SerializedMessage::new();
+                }
+            }
+        }));
+
+        let mut record = DecodedRecord::new(message);
+        pipeline.apply(&mut record);
+
+        let paths: Vec<_> = record.provenance.iter().map(|change| change.path.clone()).collect();
+        assert_eq!(paths, vec![vec![2, 1], vec![2, 2]]);
+
+        let change = &record.provenance[0];
+        assert_eq!(change.hook, "scrub");
+        assert_eq!(change.old.as_ref().and_then(Value::as_string).unwrap(), "secret");
+        assert!(change.new.is_none());
+    }
+}
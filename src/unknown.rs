@@ -0,0 +1,120 @@
+use std::ops::Range;
+use crate::classify::read_field;
+use crate::{decode, Result, SerializedMessage};
+
+/// The fields of an encoded message which a schema does not know about.
+///
+/// Meant to be kept alongside generated structs (e.g. from prost),
+/// so fields missing from the schema can still be inspected and re-encoded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UnknownFields {
+    bytes: Vec<u8>
+}
+
+impl UnknownFields {
+    /// Creates an empty set of unknown fields.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wraps the encoded unknown fields.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self { bytes }
+    }
+
+    /// Collects the fields of an encoded message which are not in `known`.
+    ///
+    /// Fails if the message is malformed.
+    pub fn extract(bytes: &[u8], known: &[u32]) -> Result<Self> {
+        let mut unknown = vec![];
+        for (field, range) in fields(bytes)? {
+            if !known.contains(&field) {
+                unknown.extend(&bytes[range]);
+            }
+        }
+
+        Ok(Self { bytes: unknown })
+    }
+
+    /// Returns the encoded unknown fields.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns true if there are no unknown fields.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Appends the encoded fields to the unknown fields.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.bytes.extend(bytes);
+    }
+
+    /// Decodes the unknown fields.
+    pub fn decode(&self) -> Result<SerializedMessage> {
+        decode(&self.bytes)
+    }
+
+    /// Replaces the unknown fields with an encoded message.
+    pub fn set(&mut self, message: &SerializedMessage) {
+        self.bytes = message.encode();
+    }
+
+    /// Appends the unknown fields to an encoded message.
+    ///
+    /// Protobuf allows fields in any order, so the result is a valid message.
+    pub fn write_to(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(&self.bytes);
+    }
+}
+
+impl From<&SerializedMessage> for UnknownFields {
+    fn from(message: &SerializedMessage) -> Self {
+        Self { bytes: message.encode() }
+    }
+}
+
+/// Splits an encoded message into the field numbers and byte ranges of its fields.
+fn fields(bytes: &[u8]) -> Result<Vec<(u32, Range<usize>)>> {
+    let mut fields = vec![];
+    let mut index = 0usize;
+
+    while index < bytes.len() {
+        let Some((field, end)) = read_field(bytes, index) else {
+            return Err(format!("Invalid message; malformed field at offset {index}.").into());
+        };
+
+        fields.push((field as u32, index..end));
+        index = end;
+    }
+
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FieldAccess, ProtobufBytes};
+
+    #[test]
+    fn preserve_unknown() {
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+        bytes.write_str(2, "Hello, World!");
+        bytes.write_u32(7, 3);
+
+        let unknown = UnknownFields::extract(&bytes, &[1]).unwrap();
+        let message = unknown.decode().unwrap();
+        assert_eq!(message.get_ref(1), None);
+        assert_eq!(message.get_str(2), Some("Hello, World!"));
+        assert_eq!(message.get_u32(7), Some(3));
+
+        let mut known = vec![];
+        known.write_u32(1, 150);
+        unknown.write_to(&mut known);
+        assert_eq!(decode(&known).unwrap(), decode(&bytes).unwrap());
+
+        assert!(UnknownFields::extract(&bytes[..bytes.len() - 1], &[]).is_err());
+    }
+}
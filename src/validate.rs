@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+use crate::names::format_path;
+use crate::{FieldAccess, FieldPath, Result, SerializedMessage, Value, VarInt, WireType};
+
+/// A way in which a decoded message breaks the rules of its descriptor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// A proto2 `required` field is not present.
+    MissingRequired { path: FieldPath, name: String },
+    /// An enum field holds a number which is not a value of its enum.
+    ///
+    /// Unknown values are legal in proto3, whose enums are open, but are still reported.
+    EnumOutOfRange { path: FieldPath, name: String, value: i32 },
+    /// A field is encoded with a wire type its type cannot have.
+    WrongWireType { path: FieldPath, name: String, expected: WireType, found: WireType },
+    /// A field is neither declared nor within an extension range.
+    UnknownField { path: FieldPath }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::MissingRequired { path, name } => {
+                write!(f, "{}: missing required field '{name}'", format_path(path))
+            }
+            Violation::EnumOutOfRange { path, name, value } => {
+                write!(f, "{}: {value} is not a value of the enum of field '{name}'", format_path(path))
+            }
+            Violation::WrongWireType { path, name, expected, found } => {
+                write!(f, "{}: field '{name}' is {}, not {}", format_path(path), expected.name(), found.name())
+            }
+            Violation::UnknownField { path } => write!(f, "{}: unknown field", format_path(path))
+        }
+    }
+}
+
+/// Checks decoded messages against the message and enum types of a descriptor set.
+///
+/// Type names are resolved by their fully qualified name, or failing that,
+/// by their longest suffix naming a known type.
+#[derive(Clone, Debug, Default)]
+pub struct Validator<'a> {
+    messages: BTreeMap<String, &'a DescriptorProto>,
+    enums: BTreeMap<String, &'a EnumDescriptorProto>
+}
+
+impl<'a> Validator<'a> {
+    /// Indexes the types of every file in the set.
+    pub fn new(files: &'a FileDescriptorSet) -> Self {
+        let mut validator = Self::default();
+        for file in &files.file {
+            for descriptor in &file.message_type {
+                validator.index(file.package(), descriptor);
+            }
+            for descriptor in &file.enum_type {
+                validator.enums.insert(qualify(file.package(), descriptor.name()), descriptor);
+            }
+        }
+
+        validator
+    }
+
+    /// Validates a message of the named type, such as `example.Player`.
+    pub fn validate(&self, message: &SerializedMessage, name: &str) -> Result<Vec<Violation>> {
+        let Some(descriptor) = self.message(name) else {
+            return Err(format!("Invalid message; type '{name}' is not in the descriptor set.").into());
+        };
+
+        let mut violations = vec![];
+        self.validate_message(message, descriptor, &mut vec![], &mut violations);
+        Ok(violations)
+    }
+
+    /// Indexes a message type and the types nested in it.
+    fn index(&mut self, scope: &str, descriptor: &'a DescriptorProto) {
+        let name = qualify(scope, descriptor.name());
+        for nested in &descriptor.nested_type {
+            self.index(&name, nested);
+        }
+        for nested in &descriptor.enum_type {
+            self.enums.insert(qualify(&name, nested.name()), nested);
+        }
+
+        self.messages.insert(name, descriptor);
+    }
+
+    /// Returns the message type with the given name.
+    fn message(&self, name: &str) -> Option<&'a DescriptorProto> {
+        suffixes(name).find_map(|name| self.messages.get(name).copied())
+    }
+
+    /// Returns the enum type with the given name.
+    fn enumeration(&self, name: &str) -> Option<&'a EnumDescriptorProto> {
+        suffixes(name).find_map(|name| self.enums.get(name).copied())
+    }
+
+    /// Validates a message at the given path, recording its violations.
+    fn validate_message(
+        &self,
+        message: &SerializedMessage,
+        descriptor: &DescriptorProto,
+        path: &mut FieldPath,
+        violations: &mut Vec<Violation>
+    ) {
+        for (number, value) in message {
+            path.push(*number);
+
+            match descriptor.field.iter().find(|field| field.number() as u32 == *number) {
+                Some(field) => self.validate_field(value, field, path, violations),
+                None if !in_extension_range(descriptor, *number) => {
+                    violations.push(Violation::UnknownField { path: path.clone() });
+                }
+                None => {}
+            }
+
+            path.pop();
+        }
+
+        for field in &descriptor.field {
+            let number = field.number() as u32;
+            if field.label() == Label::Required && message.get_ref(number).is_none() {
+                path.push(number);
+                violations.push(Violation::MissingRequired { path: path.clone(), name: field.name().to_string() });
+                path.pop();
+            }
+        }
+    }
+
+    /// Validates every occurrence of a field, and any nested messages in it.
+    fn validate_field(&self, value: &Value, field: &FieldDescriptorProto, path: &mut FieldPath, violations: &mut Vec<Violation>) {
+        let expected = wire_type(field.r#type());
+        // Repeated scalars may be packed into a single length-delimited payload.
+        let packable = field.label() == Label::Repeated && expected != WireType::LengthDelimited;
+
+        for value in occurrences(value) {
+            let found = match value {
+                Value::VarInt(_) => WireType::VarInt,
+                Value::Float(_) => WireType::Fixed32,
+                Value::Double(_) => WireType::Fixed64,
+                _ => WireType::LengthDelimited
+            };
+            if found != expected && !(packable && found == WireType::LengthDelimited) {
+                violations.push(Violation::WrongWireType { path: path.clone(), name: field.name().to_string(), expected, found });
+                continue;
+            }
+
+            match field.r#type() {
+                Type::Enum => {
+                    let Some(enumeration) = self.enumeration(field.type_name()) else {
+                        continue;
+                    };
+
+                    for number in enum_numbers(value) {
+                        if !enumeration.value.iter().any(|value| value.number() == number) {
+                            let name = field.name().to_string();
+                            violations.push(Violation::EnumOutOfRange { path: path.clone(), name, value: number });
+                        }
+                    }
+                }
+                Type::Message => {
+                    let Some(descriptor) = self.message(field.type_name()) else {
+                        continue;
+                    };
+
+                    // A payload which is not a message, such as a lone string, cannot be checked.
+                    match value {
+                        Value::Message(message) => self.validate_message(message, descriptor, path, violations),
+                        Value::Empty => self.validate_message(&SerializedMessage::new(), descriptor, path, violations),
+                        Value::Map(entries) => {
+                            for (key, value) in entries {
+                                self.validate_message(&key.entry(value.clone()), descriptor, path, violations);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Validates a message against a descriptor, reporting missing required fields,
+/// out-of-range enum values, wrong wire types, and unknown fields.
+///
+/// Only the message and enum types declared inside the descriptor can be
+/// resolved; fields of other types are checked only for their wire type.
+/// Use a `Validator` to resolve types across a descriptor set.
+pub fn validate(message: &SerializedMessage, descriptor: &DescriptorProto) -> Vec<Violation> {
+    let mut validator = Validator::default();
+    validator.index("", descriptor);
+
+    let mut violations = vec![];
+    validator.validate_message(message, descriptor, &mut vec![], &mut violations);
+    violations
+}
+
+/// Returns the fully qualified name of a type in a package or message.
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() { name.to_string() } else { format!("{scope}.{name}") }
+}
+
+/// Returns a type name without its leading dot, followed by each shorter suffix of it.
+fn suffixes(name: &str) -> impl Iterator<Item = &str> {
+    let name = name.trim_start_matches('.');
+    std::iter::once(name).chain(name.match_indices('.').map(move |(index, _)| &name[index + 1..]))
+}
+
+/// Returns whether a field number is within an extension range of the message.
+fn in_extension_range(descriptor: &DescriptorProto, number: u32) -> bool {
+    descriptor.extension_range.iter()
+        .any(|range| (range.start() as u32..range.end() as u32).contains(&number))
+}
+
+/// Returns the wire type a field of the given type is encoded with, when not packed.
+fn wire_type(field_type: Type) -> WireType {
+    match field_type {
+        Type::Double | Type::Fixed64 | Type::Sfixed64 => WireType::Fixed64,
+        Type::Float | Type::Fixed32 | Type::Sfixed32 => WireType::Fixed32,
+        Type::String | Type::Bytes | Type::Message => WireType::LengthDelimited,
+        Type::Group => WireType::StartGroup,
+        _ => WireType::VarInt
+    }
+}
+
+/// Returns each occurrence of a field's value.
+///
+/// A payload read both as a string and as a message appears twice, which only
+/// matters to the checks of its message reading.
+fn occurrences(value: &Value) -> Vec<&Value> {
+    match value {
+        Value::Repeated(values) => values.iter().collect(),
+        value => vec![value]
+    }
+}
+
+/// Returns the enum numbers held by a varint, or by a packed payload of varints.
+///
+/// Numbers are truncated to 32 bits, as parsers do. A packed payload decoded
+/// as a message cannot be re-read faithfully, so it yields nothing.
+fn enum_numbers(value: &Value) -> Vec<i32> {
+    let packed = match value {
+        Value::VarInt(varint) => return vec![varint.as_i64() as i32],
+        Value::Bytes(bytes) => bytes.as_slice(),
+        Value::String(string) => string.as_bytes(),
+        _ => return vec![]
+    };
+
+    let mut numbers = vec![];
+    let mut index = 0;
+    while let Some((number, len)) = VarInt::read_at(packed, index) {
+        numbers.push(number as i32);
+        index += len;
+    }
+
+    numbers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::{EnumValueDescriptorProto, FileDescriptorProto};
+    use crate::{decode, ProtobufBytes};
+
+    fn field(name: &str, number: i32, label: Label, field_type: Type, type_name: &str) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(label as i32),
+            r#type: Some(field_type as i32),
+            type_name: (!type_name.is_empty()).then(|| type_name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn validate_player() {
+        let state = EnumDescriptorProto {
+            name: Some("State".to_string()),
+            value: [0, 1].map(|number| EnumValueDescriptorProto { number: Some(number), ..Default::default() }).into(),
+            ..Default::default()
+        };
+        let item = DescriptorProto {
+            name: Some("Item".to_string()),
+            field: vec![field("id", 1, Label::Required, Type::Int64, "")],
+            ..Default::default()
+        };
+        let player = DescriptorProto {
+            name: Some("Player".to_string()),
+            field: vec![
+                field("name", 1, Label::Required, Type::String, ""),
+                field("score", 2, Label::Optional, Type::Float, ""),
+                field("state", 3, Label::Optional, Type::Enum, ".game.Player.State"),
+                field("items", 4, Label::Repeated, Type::Message, ".game.Player.Item"),
+                field("levels", 5, Label::Repeated, Type::Int32, "")
+            ],
+            nested_type: vec![item],
+            enum_type: vec![state],
+            ..Default::default()
+        };
+
+        let mut bytes = vec![];
+        bytes.write_u32(2, 10);
+        bytes.write_u32(3, 7);
+        bytes.write_bytes(4, &[]);
+        bytes.extend([0x2a, 0x02, 0x01, 0x02]);
+        bytes.write_u32(9, 1);
+        let message = decode(&bytes).unwrap();
+
+        let violations = validate(&message, &player);
+        assert_eq!(violations, [
+            Violation::WrongWireType { path: vec![2], name: "score".into(), expected: WireType::Fixed32, found: WireType::VarInt },
+            Violation::EnumOutOfRange { path: vec![3], name: "state".into(), value: 7 },
+            Violation::MissingRequired { path: vec![4, 1], name: "id".into() },
+            Violation::UnknownField { path: vec![9] },
+            Violation::MissingRequired { path: vec![1], name: "name".into() }
+        ]);
+        assert_eq!(violations[0].to_string(), "2: field 'score' is fixed32, not varint");
+
+        let files = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                package: Some("game".to_string()),
+                message_type: vec![player],
+                ..Default::default()
+            }]
+        };
+        let validator = Validator::new(&files);
+        assert_eq!(validator.validate(&message, "game.Player").unwrap().len(), 5);
+        assert!(validator.validate(&message, "game.Unknown").is_err());
+    }
+}
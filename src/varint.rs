@@ -1,24 +1,39 @@
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, AddAssign, Sub, SubAssign};
 use paste::paste;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use serde::de::{SeqAccess, Visitor};
+use serde::de::{self, SeqAccess, Visitor};
 use serde::ser::SerializeSeq;
+use crate::Number;
 
+/// A variable-length integer, as decoded from the wire.
+///
+/// The value is stored decoded, alongside the number of bytes
+/// it was encoded with, so varints never allocate.
 #[derive(Clone, Debug)]
-pub struct VarInt(Vec<u8>);
+pub struct VarInt {
+    value: u64,
+    len: usize
+}
 
 impl VarInt {
     /// Decodes a variable integer into a 32-bit unsigned integer.
     /// bytes: A slice of bytes representing the variable integer.
     pub fn decode(bytes: &[u8]) -> VarInt {
-        let mut int_bytes = vec![];
-        bytes.iter().for_each(|byte| {
-            let byte = byte & 0b0111_1111;
-            int_bytes.push(byte);
-        });
-        int_bytes.reverse();
+        // The first byte holds the least significant group.
+        VarInt::from_groups(bytes.iter().rev().map(|byte| byte & 0b0111_1111))
+    }
+
+    /// Creates a varint from its 7-bit groups, most significant first.
+    ///
+    /// Bits beyond the 64 bits of the value are discarded.
+    fn from_groups<I: ExactSizeIterator<Item = u8>>(groups: I) -> VarInt {
+        let len = groups.len();
+        let value = groups.fold(0u64, |value, group| (value << 7) | group as u64);
 
-        VarInt(int_bytes)
+        VarInt { value, len }
     }
 
     /// Encodes a 32-bit integer into a variable integer.
@@ -55,13 +70,88 @@ impl VarInt {
         bytes
     }
 
+    /// Encodes a 64-bit value into its canonical, minimal-length varint.
+    pub fn encode_minimal(value: u64) -> Vec<u8> {
+        VarInt::encode_padded(value, VarInt::minimal_length(value))
+            .unwrap_or_default()
+    }
+
+    /// Encodes a 64-bit value into a varint of exactly `len` bytes,
+    /// padding it with empty continuation groups.
+    ///
+    /// Returns `None` if the value does not fit, or `len` exceeds 10 bytes.
+    pub fn encode_padded(value: u64, len: usize) -> Option<Vec<u8>> {
+        if len < VarInt::minimal_length(value) || len > 10 {
+            return None;
+        }
+
+        Some(VarInt { value, len }.to_bytes())
+    }
+
+    /// Returns the number of bytes of the minimal encoding of a value.
+    pub fn minimal_length(value: u64) -> usize {
+        (64 - value.leading_zeros() as usize).div_ceil(7).max(1)
+    }
+
+    /// Returns true if the varint was encoded with the minimal number of bytes.
+    ///
+    /// Some protocols use padded encodings, which can fingerprint an implementation.
+    pub fn is_canonical(&self) -> bool {
+        self.len == VarInt::minimal_length(self.value)
+    }
+
+    /// Returns the varint re-encoded with the minimal number of bytes.
+    pub fn canonical(&self) -> VarInt {
+        VarInt { value: self.value, len: VarInt::minimal_length(self.value) }
+    }
+
     /// Decodes a variable integer at a specific index.
     /// bytes: A slice of bytes representing the variable integer.
     /// index: The index to start reading the bytes from.
     pub fn decode_at(bytes: &[u8], index: usize) -> (VarInt, usize) {
-        let bytes = VarInt::raw_at(bytes, index);
-        let varint = VarInt::decode(&bytes);
-        (varint, bytes.len())
+        let bytes = bytes.get(index..).unwrap_or_default();
+        let len = bytes.iter()
+            .position(|byte| byte >> 7 == 0)
+            .map_or(bytes.len(), |last| last + 1);
+
+        (VarInt::decode(&bytes[..len]), len)
+    }
+
+    /// Decodes a variable integer at a specific index, if it is well-formed.
+    ///
+    /// Returns `None` if the varint is missing, truncated by the end
+    /// of the buffer, or longer than the 10 bytes of a 64-bit varint.
+    pub fn try_decode_at(bytes: &[u8], index: usize) -> Option<(VarInt, usize)> {
+        let (value, len) = VarInt::read_at(bytes, index)?;
+        Some((VarInt { value, len }, len))
+    }
+
+    /// Reads a variable integer at a specific index without allocating.
+    ///
+    /// Returns the value and the number of bytes read, or `None`
+    /// under the same conditions as `try_decode_at`.
+    #[inline]
+    pub fn read_at(bytes: &[u8], index: usize) -> Option<(u64, usize)> {
+        let bytes = bytes.get(index..)?;
+
+        // Most varints are field headers and small values of one or two bytes.
+        match bytes {
+            [first, ..] if first >> 7 == 0 => return Some((*first as u64, 1)),
+            [first, second, ..] if second >> 7 == 0 => {
+                return Some(((*first & 0b0111_1111) as u64 | (*second as u64) << 7, 2));
+            }
+            _ => {}
+        }
+
+        let mut value = 0u64;
+        for (i, byte) in bytes.iter().take(10).enumerate() {
+            value |= ((byte & 0b0111_1111) as u64) << (i * 7);
+            if byte >> 7 == 0 {
+                return Some((value, i + 1));
+            }
+        }
+
+        None
     }
 
     /// Reads the bytes of a variable integer.
@@ -69,8 +159,7 @@ impl VarInt {
     /// index: The index to start reading the bytes from.
     pub fn raw_at(bytes: &[u8], index: usize) -> Vec<u8> {
         let mut result = vec![];
-        for i in index..bytes.len() {
-            let byte = bytes[i];
+        for &byte in bytes.iter().skip(index) {
             if byte >> 7 == 1 {
                 result.push(byte);
             } else {
@@ -82,27 +171,41 @@ impl VarInt {
         result
     }
 
+    /// Encodes the varint back into its wire representation.
+    ///
+    /// The encoded length matches the length the varint was decoded from.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = (0..self.len)
+            .map(|i| {
+                let group = self.value.checked_shr(i as u32 * 7).unwrap_or(0) as u8;
+                group | 0b1000_0000
+            })
+            .collect();
+
+        // The last byte must not have the continuation bit set.
+        match bytes.last_mut() {
+            Some(last) => *last &= 0b0111_1111,
+            None => bytes.push(0)
+        }
+
+        bytes
+    }
+
     /// Returns the length of the buffer for the varint.
+    ///
+    /// This is the number of bytes the varint was originally encoded with.
     pub fn length(&self) -> usize {
-        self.0.len()
+        self.len
     }
 
     /// Creates a 32-bit integer representation of the varint.
     pub fn as_i32(&self) -> i32 {
-        let mut value = 0;
-        self.0.iter().for_each(|byte| {
-            value = (value << 7) | *byte as i32;
-        });
-        value
+        self.value as i32
     }
 
     /// Creates a 64-bit integer representation of the varint.
     pub fn as_i64(&self) -> i64 {
-        let mut value = 0;
-        self.0.iter().for_each(|byte| {
-            value = (value << 7) | *byte as i64;
-        });
-        value
+        self.value as i64
     }
 
     /// Creates a 32-bit unsigned integer representation of the varint.
@@ -126,6 +229,56 @@ impl VarInt {
             Some(value as u64)
         }
     }
+
+    /// Offsets the value, wrapping on overflow.
+    ///
+    /// The result keeps the width it was encoded with,
+    /// growing only if the new value no longer fits.
+    fn offset(&self, delta: u64, add: bool) -> VarInt {
+        let value = if add { self.value.wrapping_add(delta) } else { self.value.wrapping_sub(delta) };
+        VarInt { value, len: self.len.max(VarInt::minimal_length(value)) }
+    }
+}
+
+/// Variable integers are equal if they hold the same 64-bit value,
+/// regardless of how many bytes were used to encode them.
+impl PartialEq for VarInt {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_i64() == other.as_i64()
+    }
+}
+
+impl Eq for VarInt {}
+
+/// Variable integers are ordered as signed 64-bit integers.
+impl PartialOrd for VarInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for VarInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_i64().cmp(&other.as_i64())
+    }
+}
+
+/// Variable integers are formatted as the closest fitting number.
+impl fmt::Display for VarInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match Number::closest(self.clone()) {
+            Number::Integer(value) => write!(f, "{value}"),
+            Number::Long(value) => write!(f, "{value}"),
+            Number::UnsignedInteger(value) => write!(f, "{value}"),
+            Number::UnsignedLong(value) => write!(f, "{value}")
+        }
+    }
+}
+
+impl Hash for VarInt {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_i64().hash(state);
+    }
 }
 
 impl Serialize for VarInt {
@@ -137,14 +290,10 @@ impl Serialize for VarInt {
         // Always serialize i32
         let i32 = self.as_i32();
 
-        // Serialize i64 if there are enough bytes (at least 8 bytes)
-        if self.0.len() >= 8 {
+        // Serialize i64 if there are enough bytes (at least 8 bytes), or if the i32
+        // is negative, since a padded negative i32 has 33 bits in only 5 bytes
+        if (self.len >= 8 || i32 < 0) && self.as_i64() != i32 as i64 {
             i64 = Some(self.as_i64());
-
-            // Check if the i64 is the same as the i32
-            if i64.unwrap() == i32 as i64 {
-                i64 = None;
-            }
         }
 
         // Serialize u32 if the value is non-negative
@@ -154,7 +303,7 @@ impl Serialize for VarInt {
                 u32 = Some(u32_val);
 
                 // Serialize u64 if there are enough bytes (at least 8 bytes) and the value is non-negative
-                if self.0.len() >= 8 {
+                if self.len >= 8 {
                     if let Some(u64_val) = self.as_u64() {
                         if u64_val != u32_val as u64 {
                             u64 = Some(u64_val);
@@ -188,7 +337,7 @@ impl<'de> Deserialize<'de> for VarInt {
     where
         D: Deserializer<'de>,
     {
-        deserializer.deserialize_seq(VarIntVisitor)
+        deserializer.deserialize_any(VarIntVisitor)
     }
 }
 
@@ -198,32 +347,35 @@ impl<'de> Visitor<'de> for VarIntVisitor {
     type Value = VarInt;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("a sequence of integers representing a VarInt")
+        formatter.write_str("an integer, or a sequence of integers representing a VarInt")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<VarInt, E> {
+        Ok(VarInt::decode(&VarInt::encode_minimal(value as u64)))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<VarInt, E> {
+        Ok(VarInt::decode(&VarInt::encode_minimal(value)))
     }
 
     fn visit_seq<V>(self, mut seq: V) -> Result<VarInt, V::Error>
     where
         V: SeqAccess<'de>,
     {
-        let mut bytes = vec![];
-
-        if let Some(i32_val) = seq.next_element::<i32>()? {
-            bytes.append(&mut VarInt::encode(i32_val));
-        }
-
-        if let Some(i64_val) = seq.next_element::<i64>()? {
-            bytes.append(&mut VarInt::encode(i64_val as i32));
-        }
-
-        if let Some(u32_val) = seq.next_element::<u32>()? {
-            bytes.append(&mut VarInt::encode(u32_val as i32));
-        }
-
-        if let Some(u64_val) = seq.next_element::<u64>()? {
-            bytes.append(&mut VarInt::encode(u64_val as i32));
-        }
-
-        Ok(VarInt(bytes))
+        let Some(i32_val) = seq.next_element::<i32>()? else {
+            return Err(de::Error::invalid_length(0, &self));
+        };
+
+        // The wider readings are only written when they differ from the narrower ones.
+        let i64_val = seq.next_element::<i64>()?;
+        let u32_val = seq.next_element::<u32>()?;
+        let u64_val = seq.next_element::<u64>()?;
+
+        let value = u64_val
+            .or(u32_val.map(u64::from))
+            .or(i64_val.map(|value| value as u64))
+            .unwrap_or(i32_val as i64 as u64);
+        self.visit_u64(value)
     }
 }
 
@@ -255,7 +407,7 @@ macro_rules! impl_varint {
         $(
             impl From<$target> for VarInt {
                 fn from(value: $target) -> Self {
-                    VarInt(VarInt::$encoder(value))
+                    VarInt::decode(&VarInt::$encoder(value))
                 }
             }
             
@@ -283,6 +435,54 @@ macro_rules! impl_varint {
                         *self == other.[<as_ $target>]()
                     }
                 }
+
+                impl PartialOrd<$target> for VarInt {
+                    fn partial_cmp(&self, other: &$target) -> Option<Ordering> {
+                        self.[<as_ $target>]().partial_cmp(other)
+                    }
+                }
+
+                impl PartialOrd<VarInt> for $target {
+                    fn partial_cmp(&self, other: &VarInt) -> Option<Ordering> {
+                        self.partial_cmp(&other.[<as_ $target>]())
+                    }
+                }
+            }
+        )*
+    };
+}
+
+/// Generates the implementations for adding and subtracting
+/// Rust primitives, keeping the width of the variable integer.
+macro_rules! impl_arithmetic {
+    ($($target:ty => $widen:ty),*) => {
+        $(
+            impl Add<$target> for VarInt {
+                type Output = VarInt;
+
+                fn add(self, rhs: $target) -> VarInt {
+                    self.offset(rhs as $widen as u64, true)
+                }
+            }
+
+            impl Sub<$target> for VarInt {
+                type Output = VarInt;
+
+                fn sub(self, rhs: $target) -> VarInt {
+                    self.offset(rhs as $widen as u64, false)
+                }
+            }
+
+            impl AddAssign<$target> for VarInt {
+                fn add_assign(&mut self, rhs: $target) {
+                    *self = self.offset(rhs as $widen as u64, true);
+                }
+            }
+
+            impl SubAssign<$target> for VarInt {
+                fn sub_assign(&mut self, rhs: $target) {
+                    *self = self.offset(rhs as $widen as u64, false);
+                }
             }
         )*
     };
@@ -291,4 +491,66 @@ macro_rules! impl_varint {
 impl_varint!(
     i32 => encode,
     i64 => encode_long
-);
\ No newline at end of file
+);
+
+impl_arithmetic!(
+    i32 => i64,
+    i64 => i64,
+    u32 => u64,
+    u64 => u64
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn representation() {
+        let padded = VarInt::encode(150);
+        let (varint, len) = VarInt::decode_at(&padded, 0);
+        assert_eq!((varint.as_i32(), len, varint.length()), (150, 5, 5));
+        assert_eq!(varint.to_bytes(), padded);
+
+        let negative = VarInt::from(-1i64);
+        assert_eq!(negative.as_i64(), -1);
+        assert_eq!(negative.as_u64(), None);
+        assert_eq!(VarInt::decode(&negative.to_bytes()), negative);
+
+        assert_eq!(VarInt::try_decode_at(&[0x96, 0x01], 0), Some((150.into(), 2)));
+        assert!(!varint.is_canonical());
+        assert_eq!(varint.canonical().to_bytes(), VarInt::encode_minimal(150));
+        assert_eq!(VarInt::encode_minimal(150), [0x96, 0x01]);
+        assert_eq!(VarInt::encode_minimal(0), [0x00]);
+        assert_eq!(VarInt::encode_minimal(u64::MAX).len(), 10);
+        assert_eq!(VarInt::encode_padded(1, 3), Some(vec![0x81, 0x80, 0x00]));
+        assert_eq!(VarInt::encode_padded(150, 1), None);
+        assert_eq!(serde_json::to_string(&varint).unwrap(), "150");
+
+        let padded = [-1i32, i32::MIN].map(|value| VarInt::decode(&VarInt::encode(value)));
+        for varint in [150, -1, 1 << 40, i64::MIN].map(VarInt::from).into_iter().chain(padded) {
+            let json = serde_json::to_string(&varint).unwrap();
+            assert_eq!(serde_json::from_str::<VarInt>(&json).unwrap(), varint, "{json}");
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        let (counter, _) = VarInt::decode_at(&VarInt::encode(150), 0);
+        assert!(counter > 100 && 200i64 > counter);
+        assert_eq!(VarInt::from(-1i64).max(counter.clone()), counter);
+        assert_eq!(counter.to_string(), "150");
+        assert_eq!(VarInt::from(-5i64).to_string(), "-5");
+
+        // The padded width is kept, so the bytes can be written back in place.
+        let next = counter.clone() + 1u32;
+        assert_eq!((next.as_i32(), next.length()), (151, 5));
+        assert_eq!(next.to_bytes(), VarInt::encode(151));
+        assert_eq!(counter.clone() - 151, -1);
+
+        let mut minimal = VarInt::decode(&[0x7f]);
+        minimal += 1;
+        assert_eq!((minimal.as_i64(), minimal.length()), (128, 2));
+        minimal -= 1i64;
+        assert_eq!((minimal.as_i64(), minimal.length()), (127, 2));
+    }
+}
@@ -0,0 +1,127 @@
+use std::ops::{Bound, RangeBounds};
+use crate::Value;
+
+/// A borrowed view into the payload of a bytes field.
+///
+/// Views remember their offset into the original payload,
+/// so sub-structures can be located without copying.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BytesView<'a> {
+    bytes: &'a [u8],
+    offset: usize
+}
+
+impl<'a> BytesView<'a> {
+    /// Creates a view over the whole of the given bytes.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    /// Returns the viewed bytes.
+    pub fn as_slice(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Returns the offset of the view into the original payload.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the number of viewed bytes.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns true if the view is empty.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Returns a view of a range of this view, relative to its start.
+    ///
+    /// Returns `None` if the range is out of bounds.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> Option<BytesView<'a>> {
+        let start = match range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => start.checked_add(1)?,
+            Bound::Unbounded => 0
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => end.checked_add(1)?,
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => self.bytes.len()
+        };
+
+        let bytes = self.bytes.get(start..end)?;
+        Some(Self { bytes, offset: self.offset + start })
+    }
+
+    /// Reads a little-endian `u16` at the given offset.
+    pub fn read_u16_le(&self, offset: usize) -> Option<u16> {
+        self.read_array(offset).map(u16::from_le_bytes)
+    }
+
+    /// Reads a little-endian `u32` at the given offset.
+    pub fn read_u32_le(&self, offset: usize) -> Option<u32> {
+        self.read_array(offset).map(u32::from_le_bytes)
+    }
+
+    /// Reads a little-endian `u64` at the given offset.
+    pub fn read_u64_le(&self, offset: usize) -> Option<u64> {
+        self.read_array(offset).map(u64::from_le_bytes)
+    }
+
+    /// Returns an iterator over views of `size` bytes.
+    ///
+    /// The last view is shorter if the length is not a multiple of `size`.
+    ///
+    /// Panics if `size` is zero.
+    pub fn iter_chunks(&self, size: usize) -> impl Iterator<Item = BytesView<'a>> {
+        let offset = self.offset;
+        self.bytes.chunks(size)
+            .enumerate()
+            .map(move |(i, bytes)| Self { bytes, offset: offset + i * size })
+    }
+
+    /// Reads a fixed number of bytes at the given offset.
+    fn read_array<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        let end = offset.checked_add(N)?;
+        self.bytes.get(offset..end)?.try_into().ok()
+    }
+}
+
+impl<'a> From<&'a [u8]> for BytesView<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl Value {
+    /// Returns a view of a bytes value, without copying it.
+    pub fn as_bytes_view(&self) -> Option<BytesView<'_>> {
+        match self {
+            Value::Bytes(bytes) => Some(BytesView::new(bytes)),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn views() {
+        let value = Value::Bytes(vec![0xff, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03]);
+        let view = value.as_bytes_view().unwrap();
+
+        let body = view.slice(1..).unwrap();
+        assert_eq!(body.offset(), 1);
+        assert_eq!(body.read_u32_le(4), Some(2));
+        assert_eq!(body.read_u32_le(6), None);
+        assert!(view.slice(5..20).is_none());
+
+        let chunks: Vec<_> = body.iter_chunks(4).map(|chunk| (chunk.offset(), chunk.len())).collect();
+        assert_eq!(chunks, vec![(1, 4), (5, 4), (9, 1)]);
+    }
+}
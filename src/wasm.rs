@@ -0,0 +1,82 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use crate::wellknown::recognize;
+use crate::{decode, FieldPath, Result};
+
+/// A well-known type found in a message, as exposed to JavaScript.
+#[derive(Serialize)]
+struct Annotation {
+    path: FieldPath,
+    type_name: &'static str,
+    value: String
+}
+
+/// Decodes a protobuf message into JSON.
+#[wasm_bindgen(js_name = decodeToJson)]
+pub fn decode_to_json(bytes: &[u8]) -> std::result::Result<String, JsError> {
+    to_json(bytes).map_err(|error| JsError::new(&error.to_string()))
+}
+
+/// Dissects a protobuf message into JSON with the versioned schema of `dissect_to_json`.
+///
+/// `options` is a JSON object of `DissectOptions`, or empty for the defaults.
+#[wasm_bindgen(js_name = dissectToJson)]
+pub fn dissect_to_json(bytes: &[u8], options: &str) -> std::result::Result<String, JsError> {
+    crate::dissect::dissect_to_json(bytes, options).map_err(|error| JsError::new(&error))
+}
+
+/// Decodes a Base64-encoded protobuf message into JSON.
+#[wasm_bindgen(js_name = decodeBase64ToJson)]
+pub fn decode_base64_to_json(text: &str) -> std::result::Result<String, JsError> {
+    let text: String = text.split_whitespace().collect();
+    let bytes = STANDARD.decode(text).map_err(|error| JsError::new(&error.to_string()))?;
+
+    decode_to_json(&bytes)
+}
+
+/// Decodes a protobuf message and lists the well-known types in it as JSON.
+///
+/// Each annotation has a `path`, a `type_name`, and a rendered `value`.
+#[wasm_bindgen(js_name = annotateToJson)]
+pub fn annotate_to_json(bytes: &[u8]) -> std::result::Result<String, JsError> {
+    annotations(bytes).map_err(|error| JsError::new(&error.to_string()))
+}
+
+/// Decodes a message into JSON.
+fn to_json(bytes: &[u8]) -> Result<String> {
+    Ok(serde_json::to_string(&decode(bytes)?)?)
+}
+
+/// Decodes a message and serializes its well-known type annotations.
+fn annotations(bytes: &[u8]) -> Result<String> {
+    let annotations: Vec<_> = recognize(&decode(bytes)?).into_iter()
+        .map(|(path, well_known)| Annotation {
+            path,
+            type_name: well_known.type_name(),
+            value: well_known.to_string()
+        })
+        .collect();
+
+    Ok(serde_json::to_string(&annotations)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProtobufBytes;
+
+    #[test]
+    fn json() {
+        let mut duration = vec![];
+        duration.write_u32(1, 90);
+
+        let mut bytes = vec![];
+        bytes.write_u32(1, 150);
+        bytes.write_bytes(2, &duration);
+
+        assert_eq!(to_json(&bytes).unwrap(), r#"{"1":150,"2":{"1":90}}"#);
+        assert_eq!(annotations(&bytes).unwrap(), r#"[{"path":[2],"type_name":"google.protobuf.Int64Value","value":"90"}]"#);
+    }
+}
@@ -0,0 +1,269 @@
+use std::fmt;
+use crate::{encoding, FieldPath, SerializedMessage, Value};
+
+/// Timestamps before this (2000-01-01) are assumed to be durations.
+const MIN_TIMESTAMP: i64 = 946_684_800;
+
+/// Timestamps after this (2100-01-01) are assumed to be durations.
+const MAX_TIMESTAMP: i64 = 4_102_444_800;
+
+/// A nested message recognized as one of the well-known types by its shape.
+///
+/// Recognition is heuristic; a message with only field 1 is always
+/// recognized as a wrapper, and integer wrappers are indistinguishable.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WellKnown {
+    Timestamp { seconds: i64, nanos: i32 },
+    Duration { seconds: i64, nanos: i32 },
+    DoubleValue(f64),
+    FloatValue(f32),
+    /// Any integer wrapper, including `BoolValue`.
+    Int64Value(i64),
+    StringValue(String),
+    BytesValue(Vec<u8>),
+    FieldMask(Vec<String>)
+}
+
+impl WellKnown {
+    /// Recognizes a message as a well-known type by its shape.
+    pub fn detect(message: &SerializedMessage) -> Option<Self> {
+        let mut fields = message.iter();
+        let (first, second) = (fields.next(), fields.next());
+        if fields.next().is_some() {
+            return None;
+        }
+
+        match (first, second) {
+            (Some((1, Value::VarInt(seconds))), Some((2, Value::VarInt(nanos)))) => {
+                time(seconds.as_i64(), nanos.as_i64())
+            }
+            (Some((1, value)), None) => match value {
+                Value::VarInt(seconds) if is_timestamp(seconds.as_i64()) => time(seconds.as_i64(), 0),
+                Value::VarInt(value) => Some(WellKnown::Int64Value(value.as_i64())),
+                Value::Double(value) => Some(WellKnown::DoubleValue(*value)),
+                Value::Float(value) => Some(WellKnown::FloatValue(*value)),
+                Value::String(value) => Some(WellKnown::StringValue(value.clone())),
+                Value::Bytes(value) => Some(WellKnown::BytesValue(value.clone())),
+                Value::Repeated(values) => field_mask(values),
+                _ => None
+            },
+            // Zero seconds are omitted on the wire.
+            (Some((2, Value::VarInt(nanos))), None) => time(0, nanos.as_i64()),
+            _ => None
+        }
+    }
+
+    /// Returns the fully qualified name of the type.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            WellKnown::Timestamp { .. } => "google.protobuf.Timestamp",
+            WellKnown::Duration { .. } => "google.protobuf.Duration",
+            WellKnown::DoubleValue(_) => "google.protobuf.DoubleValue",
+            WellKnown::FloatValue(_) => "google.protobuf.FloatValue",
+            WellKnown::Int64Value(_) => "google.protobuf.Int64Value",
+            WellKnown::StringValue(_) => "google.protobuf.StringValue",
+            WellKnown::BytesValue(_) => "google.protobuf.BytesValue",
+            WellKnown::FieldMask(_) => "google.protobuf.FieldMask"
+        }
+    }
+}
+
+/// The unit of a Unix timestamp written as a plain integer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EpochUnit {
+    Seconds,
+    Millis,
+    Micros
+}
+
+impl EpochUnit {
+    /// Detects the unit in which an integer is a timestamp from 2000 until 2100, if any.
+    ///
+    /// The ranges of the units do not overlap, so at most one matches.
+    pub fn detect(value: i64) -> Option<Self> {
+        [EpochUnit::Seconds, EpochUnit::Millis, EpochUnit::Micros].into_iter()
+            .find(|unit| is_timestamp(value.div_euclid(unit.per_second())))
+    }
+
+    /// Returns the number of units in a second.
+    pub fn per_second(self) -> i64 {
+        match self {
+            EpochUnit::Seconds => 1,
+            EpochUnit::Millis => 1_000,
+            EpochUnit::Micros => 1_000_000
+        }
+    }
+
+    /// Converts an integer in this unit into a timestamp.
+    pub fn timestamp(self, value: i64) -> WellKnown {
+        let per_second = self.per_second();
+        WellKnown::Timestamp {
+            seconds: value.div_euclid(per_second),
+            nanos: (value.rem_euclid(per_second) * (1_000_000_000 / per_second)) as i32
+        }
+    }
+}
+
+/// Renders the value in the canonical JSON form of the type, without quotes.
+impl fmt::Display for WellKnown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WellKnown::Timestamp { seconds, nanos } => {
+                let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+                let time = seconds.rem_euclid(86_400);
+                write!(f, "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}", time / 3600, time / 60 % 60, time % 60)?;
+                write_nanos(f, *nanos)?;
+                f.write_str("Z")
+            }
+            WellKnown::Duration { seconds, nanos } => {
+                if *seconds == 0 && *nanos < 0 {
+                    f.write_str("-")?;
+                }
+                write!(f, "{seconds}")?;
+                write_nanos(f, nanos.abs())?;
+                f.write_str("s")
+            }
+            WellKnown::DoubleValue(value) => write!(f, "{value}"),
+            WellKnown::FloatValue(value) => write!(f, "{value}"),
+            WellKnown::Int64Value(value) => write!(f, "{value}"),
+            WellKnown::StringValue(value) => f.write_str(value),
+            WellKnown::BytesValue(value) => f.write_str(&encoding::base64_encode(value)),
+            WellKnown::FieldMask(paths) => f.write_str(&paths.join(","))
+        }
+    }
+}
+
+/// Finds every nested message which is recognized as a well-known type.
+///
+/// This is an opt-in pass; decoding never applies it.
+/// The root message itself is not checked.
+pub fn recognize(message: &SerializedMessage) -> Vec<(FieldPath, WellKnown)> {
+    let mut found = vec![];
+    recognize_in(message, &mut vec![], &mut found);
+
+    found
+}
+
+/// Recognizes the nested messages of a message.
+fn recognize_in(message: &SerializedMessage, path: &mut FieldPath, found: &mut Vec<(FieldPath, WellKnown)>) {
+    for (field, value) in message {
+        path.push(*field);
+        recognize_value(value, path, found);
+        path.pop();
+    }
+}
+
+/// Recognizes a value, and any messages nested in it.
+fn recognize_value(value: &Value, path: &mut FieldPath, found: &mut Vec<(FieldPath, WellKnown)>) {
+    match value {
+        Value::Message(message) => match WellKnown::detect(message) {
+            Some(well_known) => found.push((path.clone(), well_known)),
+            None => recognize_in(message, path, found)
+        },
+        Value::Repeated(values) => {
+            for value in values {
+                recognize_value(value, path, found);
+            }
+        }
+        Value::Map(entries) => {
+            for value in entries.values() {
+                recognize_value(value, path, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns true if the seconds are within the range assumed to be timestamps.
+fn is_timestamp(seconds: i64) -> bool {
+    (MIN_TIMESTAMP..MAX_TIMESTAMP).contains(&seconds)
+}
+
+/// Recognizes a time, as a timestamp or duration depending on the seconds.
+fn time(seconds: i64, nanos: i64) -> Option<WellKnown> {
+    let nanos = i32::try_from(nanos).ok().filter(|nanos| nanos.abs() < 1_000_000_000)?;
+
+    if is_timestamp(seconds) && nanos >= 0 {
+        Some(WellKnown::Timestamp { seconds, nanos })
+    } else {
+        Some(WellKnown::Duration { seconds, nanos })
+    }
+}
+
+/// Recognizes repeated strings which look like field paths as a field mask.
+fn field_mask(values: &[Value]) -> Option<WellKnown> {
+    let is_path = |path: &str| !path.is_empty() && path.chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '.');
+
+    values.iter()
+        .map(|value| match value {
+            Value::String(path) if is_path(path) => Some(path.clone()),
+            _ => None
+        })
+        .collect::<Option<Vec<_>>>()
+        .map(WellKnown::FieldMask)
+}
+
+/// Writes the fraction of a second, using 3, 6, or 9 digits as needed.
+fn write_nanos(f: &mut fmt::Formatter<'_>, nanos: i32) -> fmt::Result {
+    match nanos {
+        0 => Ok(()),
+        nanos if nanos % 1_000_000 == 0 => write!(f, ".{:03}", nanos / 1_000_000),
+        nanos if nanos % 1_000 == 0 => write!(f, ".{:06}", nanos / 1_000),
+        nanos => write!(f, ".{nanos:09}")
+    }
+}
+
+/// Converts days since the Unix epoch into a year, month, and day.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn well_known() {
+        let mut timestamp = SerializedMessage::new();
+        timestamp.insert(1, Value::VarInt(1_700_000_000i64.into()));
+        timestamp.insert(2, Value::VarInt(500_000_000.into()));
+
+        let mut duration = SerializedMessage::new();
+        duration.insert(1, Value::VarInt(90.into()));
+        duration.insert(2, Value::VarInt(1_000.into()));
+
+        let mut mask = SerializedMessage::new();
+        mask.insert(1, Value::String("user.name".to_string()));
+        mask.insert(1, Value::String("user.email".to_string()));
+
+        let mut message = SerializedMessage::new();
+        message.insert(1, Value::Message(timestamp));
+        message.insert(2, Value::Message(duration));
+        message.insert(3, Value::Message(mask));
+
+        let rendered: Vec<_> = recognize(&message).into_iter()
+            .map(|(path, well_known)| (path, well_known.type_name(), well_known.to_string()))
+            .collect();
+        assert_eq!(rendered, vec![
+            (vec![1], "google.protobuf.Timestamp", "2023-11-14T22:13:20.500Z".to_string()),
+            (vec![2], "google.protobuf.Duration", "90.000001s".to_string()),
+            (vec![3], "google.protobuf.FieldMask", "user.name,user.email".to_string())
+        ]);
+
+        assert_eq!(EpochUnit::detect(1_700_000_000), Some(EpochUnit::Seconds));
+        assert_eq!(EpochUnit::detect(1_700_000_000_123_456), Some(EpochUnit::Micros));
+        assert_eq!(EpochUnit::detect(1_700_000), None);
+        assert_eq!(EpochUnit::Millis.timestamp(1_700_000_000_250).to_string(), "2023-11-14T22:13:20.250Z");
+    }
+}